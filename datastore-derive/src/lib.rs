@@ -0,0 +1,216 @@
+// Derive macro for `datastore::entity::DatastoreEntity`.
+//
+// The generated implementation maps one field to the entity's key and optionally one field to the
+// key's parent, all other fields become properties which are converted one by one via
+// `serde_ds::to_value` and `serde_ds::from_value`. See the documentation of `DatastoreEntity` for
+// the supported attributes.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitStr, Path, Type};
+
+#[proc_macro_derive(DatastoreEntity, attributes(datastore))]
+pub fn derive_datastore_entity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Property {
+    ident: Ident,
+    name: String,
+    exclude_from_indexes: bool,
+}
+
+#[derive(Default)]
+struct FieldAttributes {
+    key: bool,
+    parent: bool,
+    exclude_from_indexes: bool,
+    rename: Option<String>,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(
+                &input.ident, "DatastoreEntity can only be derived for structs with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(
+            &input.ident, "DatastoreEntity can only be derived for structs")),
+    };
+
+    let (kind, krate) = struct_attributes(input)?;
+    let mut key: Option<(Ident, Type)> = None;
+    let mut parent: Option<Ident> = None;
+    let mut properties = vec![];
+
+    for field in fields {
+        let ident = field.ident.clone().expect("named field without name");
+        let attributes = field_attributes(field)?;
+
+        if attributes.key || attributes.parent {
+            if attributes.key && attributes.parent {
+                return Err(syn::Error::new_spanned(field, "a field can not be both key and parent"));
+            }
+            if attributes.exclude_from_indexes || attributes.rename.is_some() {
+                return Err(syn::Error::new_spanned(
+                    field, "key and parent fields are not stored as properties"));
+            }
+        }
+
+        if attributes.key {
+            if key.is_some() {
+                return Err(syn::Error::new_spanned(field, "only one field can be the key"));
+            }
+            key = Some((ident, field.ty.clone()));
+        } else if attributes.parent {
+            if parent.is_some() {
+                return Err(syn::Error::new_spanned(field, "only one field can be the parent"));
+            }
+            parent = Some(ident);
+        } else {
+            let name = attributes.rename.unwrap_or_else(|| ident.unraw().to_string());
+            if properties.iter().any(|property: &Property| property.name == name) {
+                return Err(syn::Error::new_spanned(field, format!("duplicate property {}", name)));
+            }
+            properties.push(Property {
+                ident,
+                name,
+                exclude_from_indexes: attributes.exclude_from_indexes,
+            });
+        }
+    }
+
+    let (key_ident, key_type) = key.ok_or_else(|| syn::Error::new_spanned(
+        &input.ident, "DatastoreEntity requires a field marked with #[datastore(key)]"))?;
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let parent_fn = match parent {
+        Some(ref parent) => quote! {
+            fn parent(&self) -> ::std::option::Option<&#krate::datastore::Key> {
+                #krate::entity::ParentKey::parent_key(&self.#parent)
+            }
+        },
+        None => quote!(),
+    };
+    let parent_init = match parent {
+        Some(ref parent) => quote! {
+            #parent: #krate::entity::ParentKey::from_parent_key(key.parent())?,
+        },
+        None => quote!(),
+    };
+
+    let to_properties = properties.iter().map(|property| {
+        let ident = &property.ident;
+        let name = &property.name;
+        let value = if property.exclude_from_indexes {
+            quote!(#krate::entity::exclude_from_indexes(#krate::serde_ds::to_value(&self.#ident)?))
+        } else {
+            quote!(#krate::serde_ds::to_value(&self.#ident)?)
+        };
+        quote! {
+            properties.insert(::std::string::String::from(#name), #value);
+        }
+    });
+    let from_properties = properties.iter().map(|property| {
+        let ident = &property.ident;
+        let name = &property.name;
+        quote! {
+            #ident: #krate::entity::take_property(&mut properties, #name)?,
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics #krate::entity::DatastoreEntity for #name #type_generics #where_clause {
+            const KIND: &'static str = #kind;
+
+            type Id = #key_type;
+
+            fn id(&self) -> &Self::Id {
+                &self.#key_ident
+            }
+
+            #parent_fn
+
+            fn to_entity(&self) -> #krate::serde_ds::Result<#krate::datastore::Entity> {
+                let mut properties = ::std::collections::HashMap::new();
+                #(#to_properties)*
+                ::std::result::Result::Ok(#krate::datastore::Entity {
+                    key: ::std::option::Option::Some(#krate::entity::DatastoreEntity::key(self)),
+                    properties,
+                })
+            }
+
+            fn from_entity(entity: #krate::datastore::Entity)
+                           -> #krate::serde_ds::Result<Self> {
+                let (key, mut properties) = #krate::entity::split_entity::<Self>(entity)?;
+                ::std::result::Result::Ok(#name {
+                    #key_ident: #krate::entity::KeyId::from_key(&key)?,
+                    #parent_init
+                    #(#from_properties)*
+                })
+            }
+        }
+    })
+}
+
+// Reads `#[datastore(kind = "...")]` from the struct, defaulting to the name of the struct, and
+// `#[datastore(crate = "...")]`, the path under which the `datastore` crate is reachable. The
+// latter is only needed when the crate is renamed or within the crate itself.
+fn struct_attributes(input: &DeriveInput) -> syn::Result<(LitStr, Path)> {
+    let mut kind = None;
+    let mut krate = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("datastore")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("kind") {
+                kind = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else if meta.path.is_ident("crate") {
+                krate = Some(meta.value()?.parse::<LitStr>()?.parse::<Path>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported datastore attribute, expected `kind` or `crate`"))
+            }
+        })?;
+    }
+
+    let kind = match kind {
+        Some(ref kind) if kind.value().is_empty() =>
+            return Err(syn::Error::new_spanned(kind, "kind must not be empty")),
+        Some(kind) => kind,
+        None => LitStr::new(&input.ident.unraw().to_string(), input.ident.span()),
+    };
+    Ok((kind, krate.unwrap_or_else(|| syn::parse_quote!(::datastore))))
+}
+
+fn field_attributes(field: &syn::Field) -> syn::Result<FieldAttributes> {
+    let mut attributes = FieldAttributes::default();
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("datastore")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("key") {
+                attributes.key = true;
+            } else if meta.path.is_ident("parent") {
+                attributes.parent = true;
+            } else if meta.path.is_ident("exclude_from_indexes") {
+                attributes.exclude_from_indexes = true;
+            } else if meta.path.is_ident("rename") {
+                attributes.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else {
+                return Err(meta.error(
+                    "unsupported datastore attribute, expected `key`, `parent`, `exclude_from_indexes` or `rename`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attributes)
+}
@@ -0,0 +1,181 @@
+// Command line arguments. Options may appear before or after the command.
+
+use std::env;
+use std::path::PathBuf;
+
+use datastore::client::{blocking, BulkOptions, Client, Protocol, Result};
+use datastore::datastore::Key;
+
+pub const USAGE: &str = "\
+Usage: ds [OPTIONS] <COMMAND>
+
+Commands:
+  get <KEY>...          Print the entities with the given keys, e.g. 'User:123 / Order:\"abc\"'
+  query <GQL>           Print the results of a GQL query
+  put                   Upsert the entities read from stdin in wire JSON, one or more
+  delete <KEY>...       Delete the entities with the given keys
+  export [--kind <KIND>] [--output <FILE>]
+                        Write all entities of a kind, or of the namespace, as wire JSON lines
+  dump [--kind <KIND>]  Same as export
+  import [--input <FILE>] [--workers <N>]
+                        Upsert the wire JSON lines of an export in parallel batches
+  help                  Print this help
+
+Options:
+  --project <ID>        Project ID, defaults to $DATASTORE_PROJECT_ID or $GOOGLE_CLOUD_PROJECT
+  --namespace <NS>      Namespace, defaults to the default namespace
+  --endpoint <URL>      API endpoint, defaults to $DATASTORE_EMULATOR_HOST or production
+  --access-token <T>    OAuth2 access token, e.g. from `gcloud auth print-access-token`
+  --grpc                Use gRPC instead of REST
+  --format <FORMAT>     Output format of get and query: json (default), wire or table
+  --checkpoint <FILE>   Record the progress of export or import, and resume from it
+  --batch-size <N>      Entities per page of export and per commit of import, at most 500
+";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    /// Plain JSON objects with the key in `__key__`, one per line.
+    Json,
+
+    /// The JSON representation of the API, one entity per line.
+    Wire,
+
+    /// A table with a column per property.
+    Table,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    Get(Vec<Key>),
+    Query(String),
+    Put,
+    Delete(Vec<Key>),
+    Export { kind: Option<String>, output: Option<PathBuf>, options: BulkOptions },
+    Import { input: Option<PathBuf>, options: BulkOptions },
+    Help,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Args {
+    pub project_id: Option<String>,
+    pub namespace: Option<String>,
+    pub endpoint: Option<String>,
+    pub access_token: Option<String>,
+    pub grpc: bool,
+    pub format: Format,
+    pub command: Command,
+}
+
+impl Args {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> std::result::Result<Args, String> {
+        let mut parsed = Args {
+            project_id: None,
+            namespace: None,
+            endpoint: None,
+            access_token: None,
+            grpc: false,
+            format: Format::Json,
+            command: Command::Help,
+        };
+        let mut kind = None;
+        let (mut input, mut output) = (None, None);
+        let mut options = BulkOptions::default();
+        let mut positional = vec![];
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                positional.push(arg);
+                continue;
+            }
+            let (name, inline) = match arg.find('=') {
+                Some(i) => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+                None => (arg.clone(), None),
+            };
+            if name == "--grpc" {
+                parsed.grpc = true;
+                continue;
+            }
+            let value = inline.or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", name))?;
+            match name.as_str() {
+                "--project" => parsed.project_id = Some(value),
+                "--namespace" => parsed.namespace = Some(value),
+                "--endpoint" => parsed.endpoint = Some(value),
+                "--access-token" => parsed.access_token = Some(value),
+                "--kind" => kind = Some(value),
+                "--input" => input = Some(PathBuf::from(value)),
+                "--output" => output = Some(PathBuf::from(value)),
+                "--checkpoint" => options = options.with_checkpoint(value),
+                "--workers" => options = options.with_workers(number(&name, &value)?),
+                "--batch-size" => options = options.with_batch_size(number(&name, &value)?),
+                "--format" => parsed.format = match value.as_str() {
+                    "json" => Format::Json,
+                    "wire" => Format::Wire,
+                    "table" => Format::Table,
+                    _ => return Err(format!("unknown format `{}`", value)),
+                },
+                _ => return Err(format!("unknown option `{}`", name)),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let command = positional.next().unwrap_or_else(|| "help".to_string());
+        let rest: Vec<String> = positional.collect();
+        let keys = |rest: &[String]| -> std::result::Result<Vec<Key>, String> {
+            if rest.is_empty() {
+                return Err(format!("{} needs at least one key", command));
+            }
+            rest.iter()
+                .map(|text| match text.parse::<Key>() {
+                    Ok(ref key) if !key.is_complete() => Err(format!("`{}` is incomplete", text)),
+                    Ok(key) => Ok(key),
+                    Err(err) => Err(format!("`{}`: {}", text, err)),
+                })
+                .collect()
+        };
+        parsed.command = match command.as_str() {
+            "get" => Command::Get(keys(&rest)?),
+            "delete" => Command::Delete(keys(&rest)?),
+            "query" if rest.len() == 1 => Command::Query(rest[0].clone()),
+            "query" => return Err("query needs exactly one GQL query, quote it".to_string()),
+            "put" if rest.is_empty() => Command::Put,
+            "export" | "dump" if rest.is_empty() => Command::Export { kind, output, options },
+            "import" if rest.is_empty() => Command::Import { input, options },
+            "help" => Command::Help,
+            "put" | "export" | "dump" | "import" => return Err(format!("{} takes no arguments", command)),
+            _ => return Err(format!("unknown command `{}`", command)),
+        };
+        Ok(parsed)
+    }
+
+    pub fn client(&self) -> Result<blocking::Client> {
+        let project_id = self.project_id.clone()
+            .or_else(|| env::var("DATASTORE_PROJECT_ID").ok())
+            .or_else(|| env::var("GOOGLE_CLOUD_PROJECT").ok())
+            .unwrap_or_default();
+
+        let mut builder = Client::builder(project_id);
+        if let Some(ref endpoint) = self.endpoint {
+            builder = builder.endpoint(endpoint.as_str());
+        }
+        if let Some(ref token) = self.access_token {
+            builder = builder.access_token(token.as_str());
+        }
+        if self.grpc {
+            builder = builder.protocol(Protocol::Grpc);
+        }
+        let client = builder.build_blocking()?;
+        Ok(match self.namespace {
+            Some(ref namespace) => client.namespace(namespace.as_str()),
+            None => client,
+        })
+    }
+}
+
+fn number(name: &str, value: &str) -> std::result::Result<usize, String> {
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("{} needs a positive number", name)),
+    }
+}
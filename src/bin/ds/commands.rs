@@ -0,0 +1,155 @@
+// The commands of `ds`.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+
+use datastore::client::blocking::Client;
+use datastore::datastore::{Entity, Key};
+use datastore::datastore::query::{GqlQuery, MoreResultsType};
+use datastore::datastore::rpc::*;
+
+use crate::Result;
+use crate::args::{Args, Command};
+use crate::output::{key_text, print_entities};
+
+/// Number of mutations per commit.
+const MAX_MUTATIONS: usize = 500;
+
+/// Runs a command, reading entities from `input` and printing to `out`. Returns false if some
+/// entities were not found.
+pub fn run<R: BufRead, W: Write>(args: &Args, client: &Client, input: R, out: &mut W) -> Result<bool> {
+    match args.command {
+        Command::Get(ref keys) => {
+            let mut found = vec![];
+            let mut complete = true;
+            for (key, entity) in keys.iter().zip(client.get_entities(keys)?) {
+                match entity {
+                    Some(entity) => found.push(entity),
+                    None => {
+                        eprintln!("ds: not found: {}", key_text(key));
+                        complete = false;
+                    }
+                }
+            }
+            print_entities(args.format, &found, out)?;
+            Ok(complete)
+        }
+
+        Command::Query(ref gql) => {
+            let request = RunQueryRequest {
+                gql_query: Some(GqlQuery { query_string: gql.clone(), allow_literals: true, ..GqlQuery::default() }),
+                ..RunQueryRequest::default()
+            };
+            let mut entities = vec![];
+            run_query(client, request, |page| {
+                entities.extend(page);
+                Ok(())
+            })?;
+            print_entities(args.format, &entities, out)?;
+            Ok(true)
+        }
+
+        Command::Put => {
+            let entities = serde_json::Deserializer::from_reader(input).into_iter::<Entity>();
+            let mut batch = vec![];
+            for entity in entities {
+                batch.push(entity?);
+                if batch.len() == MAX_MUTATIONS {
+                    put(client, batch.split_off(0), out)?;
+                }
+            }
+            put(client, batch, out)?;
+            Ok(true)
+        }
+
+        Command::Delete(ref keys) => {
+            for chunk in keys.chunks(MAX_MUTATIONS) {
+                commit(client, chunk.iter().cloned().map(Mutation::Delete).collect())?;
+            }
+            Ok(true)
+        }
+
+        Command::Export { ref kind, ref output, ref options } => {
+            let exported = match *output {
+                Some(ref path) => {
+                    // A resumed export continues the output of the interrupted one after the last
+                    // page that it checkpointed.
+                    let offset = options.export_offset()?.unwrap_or(0);
+                    let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(path)?;
+                    file.set_len(offset)?;
+                    file.seek(SeekFrom::Start(offset))?;
+                    client.export_jsonl(kind.as_deref(), BufWriter::new(file), options)?
+                }
+                None => client.export_jsonl(kind.as_deref(), &mut *out, options)?,
+            };
+            eprintln!("ds: exported {} entities", exported);
+            Ok(true)
+        }
+
+        Command::Import { input: ref path, ref options } => {
+            let imported = match *path {
+                Some(ref path) => client.import_jsonl(BufReader::new(File::open(path)?), options)?,
+                None => client.import_jsonl(input, options)?,
+            };
+            eprintln!("ds: imported {} entities", imported);
+            Ok(true)
+        }
+
+        Command::Help => {
+            write!(out, "{}", crate::args::USAGE)?;
+            Ok(true)
+        }
+    }
+}
+
+// Runs a query page by page. GQL queries are continued with their parsed form, which the first
+// response contains.
+fn run_query<F: FnMut(Vec<Entity>) -> Result<()>>(client: &Client, mut request: RunQueryRequest,
+                                                  mut handle: F) -> Result<()> {
+    loop {
+        let response = client.run_query(request.clone())?;
+        let batch = response.batch;
+        let fetched = batch.entity_results.len() as i32;
+        handle(batch.entity_results.into_iter().map(|result| result.entity).collect())?;
+
+        let mut query = match response.query.or(request.query) {
+            Some(query) if batch.more_results == MoreResultsType::NotFinished => query,
+            _ => return Ok(()),
+        };
+        query.start_cursor = batch.end_cursor;
+        query.offset = (query.offset - batch.skipped_results).max(0);
+        if let Some(limit) = query.limit {
+            if limit <= fetched {
+                return Ok(());
+            }
+            query.limit = Some(limit - fetched);
+        }
+        request.gql_query = None;
+        request.query = Some(query);
+    }
+}
+
+fn put<W: Write>(client: &Client, entities: Vec<Entity>, out: &mut W) -> Result<()> {
+    if entities.is_empty() {
+        return Ok(());
+    }
+    let keys: Vec<Option<Key>> = entities.iter().map(|entity| entity.key.clone()).collect();
+    let response = commit(client, entities.into_iter().map(Mutation::Upsert).collect())?;
+    // Keys are only returned for entities whose IDs were allocated.
+    for (key, result) in keys.into_iter().zip(response.mutation_results) {
+        if let Some(key) = result.key.or(key) {
+            writeln!(out, "{}", key_text(&key))?;
+        }
+    }
+    Ok(())
+}
+
+fn commit(client: &Client, mutations: Vec<Mutation>) -> Result<CommitResponse> {
+    let request = CommitRequest {
+        project_id: String::new(),
+        mode: CommitMode::NonTransactional,
+        transaction: None,
+        mutations,
+    };
+    Ok(client.commit(request)?)
+}
@@ -0,0 +1,44 @@
+// `ds`, a command line tool that looks up, queries, writes, deletes, exports and imports the
+// entities of the emulator or of production. Run `ds help` for its usage.
+
+use std::env;
+use std::io;
+use std::process;
+
+mod args;
+mod commands;
+mod output;
+
+#[cfg(test)]
+mod tests;
+
+use crate::args::{Args, Command, USAGE};
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("ds: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let result = args.client().map_err(Into::into).and_then(|client| {
+        if args.command != Command::Help && client.project_id().is_empty() {
+            return Err("no project, use --project or set $DATASTORE_PROJECT_ID".into());
+        }
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        commands::run(&args, &client, stdin.lock(), &mut stdout.lock())
+    });
+    match result {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("ds: {}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Printing entities in the output formats of `--format`.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use serde_json::Value as Json;
+
+use datastore::datastore::{Entity, Key, PartitionId};
+use datastore::datastore::json::{entity_to_plain, to_plain};
+
+use crate::Result;
+use crate::args::Format;
+
+/// Widest column of a table, longer values are cut off.
+const MAX_COLUMN_WIDTH: usize = 40;
+
+pub fn print_entities<W: Write>(format: Format, entities: &[Entity], out: &mut W) -> Result<()> {
+    match format {
+        Format::Json => for entity in entities {
+            writeln!(out, "{}", serde_json::to_string(&plain(entity))?)?;
+        },
+        Format::Wire => for entity in entities {
+            writeln!(out, "{}", serde_json::to_string(entity)?)?;
+        },
+        Format::Table => print_table(entities, out)?,
+    }
+    Ok(())
+}
+
+/// The text of a key without its project, which is the same for all keys of a run.
+pub fn key_text(key: &Key) -> String {
+    let partition = PartitionId::new("", key.partition_id().namespace_id());
+    Key::new(partition, key.path().to_vec()).to_string()
+}
+
+// The plain JSON of an entity, with its key in `__key__`.
+fn plain(entity: &Entity) -> Json {
+    let mut json = entity_to_plain(entity);
+    if let (Json::Object(ref mut object), Some(ref key)) = (&mut json, &entity.key) {
+        object.insert("__key__".to_string(), Json::String(key_text(key)));
+    }
+    json
+}
+
+fn print_table<W: Write>(entities: &[Entity], out: &mut W) -> Result<()> {
+    let properties: BTreeSet<&str> = entities.iter()
+        .flat_map(|entity| entity.properties.keys().map(String::as_str))
+        .collect();
+
+    let mut rows = vec![];
+    rows.push(Some("__key__").into_iter().chain(properties.iter().cloned()).map(str::to_string).collect::<Vec<_>>());
+    for entity in entities {
+        let mut row = vec![entity.key.as_ref().map(key_text).unwrap_or_default()];
+        row.extend(properties.iter().map(|name| match entity.properties.get(*name) {
+            Some(value) => cell(&to_plain(value)),
+            None => String::new(),
+        }));
+        rows.push(row);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+    for (i, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = row.iter().zip(&widths)
+            .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+            .collect();
+        writeln!(out, "{}", cells.join("  ").trim_end())?;
+        if i == 0 {
+            let rules: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+            writeln!(out, "{}", rules.join("  "))?;
+        }
+    }
+    Ok(())
+}
+
+// Strings are shown without quotes, everything else as JSON.
+fn cell(json: &Json) -> String {
+    let text = match *json {
+        Json::String(ref string) => string.clone(),
+        ref json => json.to_string(),
+    };
+    let text = text.replace(['\n', '\t'], " ");
+    if text.chars().count() <= MAX_COLUMN_WIDTH {
+        return text;
+    }
+    let cut: String = text.chars().take(MAX_COLUMN_WIDTH - 3).collect();
+    format!("{}...", cut)
+}
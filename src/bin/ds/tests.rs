@@ -0,0 +1,217 @@
+use datastore::client::{BulkOptions, MemoryBackend};
+use datastore::client::blocking::Client;
+use datastore::datastore::*;
+
+use crate::args::{Args, Command, Format};
+use crate::commands::run;
+
+const PROJECT: &str = "test-project";
+
+fn args(args: &[&str]) -> Result<Args, String> {
+    Args::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+// Runs a command line against `client` and returns its output.
+fn ds(client: &Client, command: &[&str], input: &str) -> String {
+    let mut out = vec![];
+    let complete = run(&args(command).unwrap(), client, input.as_bytes(), &mut out).unwrap();
+    assert!(complete, "{:?} did not find everything", command);
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_key_arguments() {
+    let parsed = args(&["get", r#"User:123/Order:"abc""#, "[ns:tenant] User:1"]).unwrap();
+    let expected = vec![
+        Key::new(PartitionId::new("", ""), vec![
+            PathElement::from_id("User", 123),
+            PathElement::from_name("Order", "abc"),
+        ]),
+        Key::new(PartitionId::new("", "tenant"), vec![PathElement::from_id("User", 1)]),
+    ];
+    assert_eq!(Command::Get(expected), parsed.command);
+
+    // Names must be quoted.
+    assert!(args(&["get", "Tag:red"]).is_err());
+}
+
+#[test]
+fn test_parse_args() {
+    let parsed = args(&["--project", "p", "get", "User:1", "User:2", "--format=table", "--grpc"]).unwrap();
+    assert_eq!(Some("p".to_string()), parsed.project_id);
+    assert_eq!(Format::Table, parsed.format);
+    assert!(parsed.grpc);
+    assert!(matches!(parsed.command, Command::Get(ref keys) if keys.len() == 2));
+
+    let parsed = args(&["dump", "--kind", "User", "--namespace", "tenant"]).unwrap();
+    let dump = Command::Export { kind: Some("User".to_string()), output: None, options: BulkOptions::default() };
+    assert_eq!(dump, parsed.command);
+    assert_eq!(Some("tenant".to_string()), parsed.namespace);
+    let parsed = args(&["import", "--input", "users.jsonl", "--workers", "4", "--checkpoint", "users.ckpt"]).unwrap();
+    let options = BulkOptions::default().with_workers(4).with_checkpoint("users.ckpt");
+    assert_eq!(Command::Import { input: Some("users.jsonl".into()), options }, parsed.command);
+    assert_eq!(Command::Query("SELECT * FROM User".to_string()),
+               args(&["query", "SELECT * FROM User"]).unwrap().command);
+    assert_eq!(Command::Help, args(&[]).unwrap().command);
+
+    let invalid: [&[&str]; 8] = [
+        &["get"],
+        &["get", "User"],
+        &["query", "SELECT", "*"],
+        &["put", "extra"],
+        &["list"],
+        &["get", "User:1", "--format", "xml"],
+        &["import", "users.jsonl"],
+        &["export", "--batch-size", "0"],
+    ];
+    for command in &invalid {
+        assert!(args(command).is_err(), "{:?}", command);
+    }
+    assert!(args(&["get", "User:1", "--project"]).is_err());
+}
+
+#[test]
+fn test_put_get_delete() {
+    let client = Client::new(PROJECT, MemoryBackend::new()).unwrap();
+    let input = r#"
+        {"key": {"partitionId": {"projectId": "test-project"}, "path": [{"kind": "User", "id": "1"}]}, "properties": {"name": {"stringValue": "Ann"}}}
+        {"key": {"partitionId": {"projectId": "test-project"}, "path": [{"kind": "User"}]}, "properties": {"name": {"stringValue": "Bob"}, "age": {"integerValue": "42"}}}
+    "#;
+    let keys = ds(&client, &["put"], input);
+    let keys: Vec<&str> = keys.lines().collect();
+    assert_eq!(2, keys.len());
+    assert_eq!("User:1", keys[0]);
+    assert!(keys[1].starts_with("User:"));
+
+    let out = ds(&client, &["get", "User:1"], "");
+    assert_eq!("{\"__key__\":\"User:1\",\"name\":\"Ann\"}\n", out);
+    let out = ds(&client, &["get", keys[1], "--format", "wire"], "");
+    let entity: Entity = serde_json::from_str(&out).unwrap();
+    assert_eq!(Some(&Value::from(42)), entity.properties.get("age"));
+
+    let out = ds(&client, &["get", "User:1", keys[1], "--format", "table"], "");
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(4, lines.len());
+    assert_eq!(vec!["__key__", "age", "name"], lines[0].split_whitespace().collect::<Vec<_>>());
+    assert!(lines[1].starts_with("-------"));
+    assert_eq!(vec!["User:1", "Ann"], lines[2].split_whitespace().collect::<Vec<_>>());
+    assert_eq!(vec!["42", "Bob"], lines[3].split_whitespace().skip(1).collect::<Vec<_>>());
+
+    ds(&client, &["delete", "User:1"], "");
+    let mut out = vec![];
+    let complete = run(&args(&["get", "User:1", keys[1]]).unwrap(), &client, &b""[..], &mut out).unwrap();
+    assert!(!complete);
+    assert_eq!(1, String::from_utf8(out).unwrap().lines().count());
+}
+
+#[test]
+fn test_get_many() {
+    // The backend defers all but 300 keys of a lookup and refuses more than 1000 keys.
+    let client = Client::new(PROJECT, MemoryBackend::new().with_lookup_limit(300)).unwrap();
+    let client = client.namespace("tenant");
+    let mut input = String::new();
+    for i in 1..=1200 {
+        input.push_str(&format!(r#"{{"key": {{"partitionId": {{"projectId": "test-project"}}, "path": [{{"kind": "User", "id": "{}"}}]}}}}"#, i));
+    }
+    ds(&client, &["put"], &input);
+
+    let mut command = vec!["get".to_string()];
+    command.extend((1..=1200).map(|i| format!("User:{}", i)));
+    command.push("User:7".to_string());
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+    let out = ds(&client, &command, "");
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(1201, lines.len());
+    assert_eq!("{\"__key__\":\"[ns:tenant] User:1\"}", lines[0]);
+    assert_eq!(lines[6], lines[1200]);
+}
+
+#[test]
+fn test_dump() {
+    let client = Client::new(PROJECT, MemoryBackend::new()).unwrap();
+    let mut input = String::new();
+    for i in 1..=3 {
+        input.push_str(&format!(r#"{{"key": {{"partitionId": {{"projectId": "test-project"}}, "path": [{{"kind": "User", "id": "{}"}}]}}}}"#, i));
+    }
+    input.push_str(r#"{"key": {"partitionId": {"projectId": "test-project"}, "path": [{"kind": "Order", "name": "o1"}]}}"#);
+    ds(&client, &["put"], &input);
+
+    let out = ds(&client, &["dump", "--kind", "User"], "");
+    let entities: Vec<Entity> = out.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(3, entities.len());
+    assert_eq!(Some("User"), entities[0].key.as_ref().and_then(Key::kind));
+
+    // Without a kind, all entities of the namespace are dumped.
+    assert_eq!(4, ds(&client, &["dump"], "").lines().count());
+    assert_eq!(0, ds(&client.namespace("other"), &["dump"], "").lines().count());
+}
+
+#[test]
+fn test_export_import() {
+    let source = Client::new(PROJECT, MemoryBackend::new()).unwrap();
+    let mut input = String::new();
+    for i in 1..=5 {
+        input.push_str(&format!(r#"{{"key": {{"partitionId": {{"projectId": "test-project"}}, "path": [{{"kind": "User", "id": "{}"}}]}}}}"#, i));
+    }
+    ds(&source, &["put"], &input);
+
+    let path = std::env::temp_dir().join(format!("ds-export-{}.jsonl", std::process::id()));
+    let output = path.to_str().unwrap();
+    assert_eq!("", ds(&source, &["export", "--kind", "User", "--output", output, "--batch-size", "2"], ""));
+    assert_eq!(5, std::fs::read_to_string(&path).unwrap().lines().count());
+
+    // The entities are moved to the project of the importing client.
+    let copy = Client::new("copy-project", MemoryBackend::new()).unwrap();
+    ds(&copy, &["import", "--input", output, "--workers", "2", "--batch-size", "2"], "");
+    std::fs::remove_file(&path).unwrap();
+    let out = ds(&copy, &["get", "User:1", "User:5"], "");
+    assert_eq!(2, out.lines().count());
+}
+
+// Accepts `lines` writes, one per line of an export, and fails after them.
+struct FailingWriter {
+    written: Vec<u8>,
+    lines: usize,
+}
+
+impl std::io::Write for FailingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.lines == 0 {
+            return Err(std::io::Error::other("disk full"));
+        }
+        self.lines -= 1;
+        self.written.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_resume_export() {
+    let client = Client::new(PROJECT, MemoryBackend::new()).unwrap();
+    let mut input = String::new();
+    for i in 1..=5 {
+        input.push_str(&format!(r#"{{"key": {{"partitionId": {{"projectId": "test-project"}}, "path": [{{"kind": "User", "id": "{}"}}]}}}}"#, i));
+    }
+    ds(&client, &["put"], &input);
+
+    let path = std::env::temp_dir().join(format!("ds-resume-{}.jsonl", std::process::id()));
+    let checkpoint = std::env::temp_dir().join(format!("ds-resume-{}.ckpt", std::process::id()));
+    let (output, checkpoint_arg) = (path.to_str().unwrap(), checkpoint.to_str().unwrap());
+    assert_eq!("", ds(&client, &["export", "--kind", "User", "--output", output, "--batch-size", "2"], ""));
+    let uninterrupted = std::fs::read_to_string(&path).unwrap();
+
+    // The interrupted export wrote the first page and a line of the second one.
+    let options = BulkOptions::default().with_batch_size(2).with_checkpoint(&checkpoint);
+    let mut out = FailingWriter { written: vec![], lines: 3 };
+    assert!(client.export_jsonl(Some("User"), &mut out, &options).is_err());
+    std::fs::write(&path, &out.written).unwrap();
+
+    let command = ["export", "--kind", "User", "--output", output, "--batch-size", "2", "--checkpoint", checkpoint_arg];
+    assert_eq!("", ds(&client, &command, ""));
+    assert!(!checkpoint.exists());
+    assert_eq!(uninterrupted, std::fs::read_to_string(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,292 @@
+// Client of the Datastore Admin API: managed exports and imports and composite indexes.
+//
+// Exports, imports and index changes run as long-running operations on the server. Starting one
+// returns an `OperationHandle`, which polls the operation until it is done. The Admin API is only
+// available over REST.
+//
+// See https://cloud.google.com/datastore/docs/reference/admin/rest
+
+use std::sync::Arc;
+use std::time::Duration;
+use http::Method as HttpMethod;
+use tokio::time::Instant;
+
+use crate::client::{Code, Endpoint, Error, RestTransport, Result, RetryPolicy};
+use crate::datastore::admin::*;
+use crate::index::{self, IndexDiff};
+
+/// Time between two polls of an operation or index by default.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+struct Inner {
+    project_id: String,
+    transport: RestTransport,
+    retry_policy: RetryPolicy,
+}
+
+/// An async client of the Admin API. Clones share the underlying connections.
+#[derive(Clone)]
+pub struct AdminClient {
+    inner: Arc<Inner>,
+    poll_interval: Duration,
+}
+
+impl AdminClient {
+    pub fn new<P: Into<String>>(project_id: P, endpoint: Endpoint, access_token: Option<String>)
+                                -> AdminClient {
+        AdminClient::with_retry_policy(project_id, endpoint, access_token, RetryPolicy::default())
+    }
+
+    pub(crate) fn with_retry_policy<P: Into<String>>(project_id: P, endpoint: Endpoint,
+                                                     access_token: Option<String>,
+                                                     retry_policy: RetryPolicy) -> AdminClient {
+        AdminClient {
+            inner: Arc::new(Inner {
+                project_id: project_id.into(),
+                transport: RestTransport::new(endpoint, access_token),
+                retry_policy,
+            }),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets the time between two polls when waiting for operations and indexes, 5 seconds by
+    /// default.
+    pub fn with_poll_interval(mut self, interval: Duration) -> AdminClient {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn project_id(&self) -> &str {
+        &self.inner.project_id
+    }
+
+    /// Starts exporting the entities selected by `filter` to `output_url_prefix`, a Cloud Storage
+    /// location like `gs://bucket/path`.
+    pub async fn export_entities(&self, output_url_prefix: &str, filter: EntityFilter)
+                                 -> Result<OperationHandle> {
+        self.start_export(ExportEntitiesRequest {
+            project_id: String::new(),
+            labels: Default::default(),
+            entity_filter: Some(filter),
+            output_url_prefix: output_url_prefix.to_string(),
+        }).await
+    }
+
+    /// Starts importing the entities selected by `filter` from an export, given by the
+    /// `output_url` of the export operation.
+    pub async fn import_entities(&self, input_url: &str, filter: EntityFilter)
+                                 -> Result<OperationHandle> {
+        self.start_import(ImportEntitiesRequest {
+            project_id: String::new(),
+            labels: Default::default(),
+            input_url: input_url.to_string(),
+            entity_filter: Some(filter),
+        }).await
+    }
+
+    /// Starts an export. Failed requests are not retried, as they may have started an operation.
+    pub async fn start_export(&self, request: ExportEntitiesRequest) -> Result<OperationHandle> {
+        let operation = self.start("export", serde_json::to_value(&request)?).await?;
+        Ok(OperationHandle::new(self.clone(), operation))
+    }
+
+    /// Starts an import, see `start_export`.
+    pub async fn start_import(&self, request: ImportEntitiesRequest) -> Result<OperationHandle> {
+        let operation = self.start("import", serde_json::to_value(&request)?).await?;
+        Ok(OperationHandle::new(self.clone(), operation))
+    }
+
+    async fn start(&self, method: &str, mut body: serde_json::Value) -> Result<Operation> {
+        // The project ID is part of the path, it is not accepted in the body.
+        if let serde_json::Value::Object(ref mut fields) = body {
+            fields.remove("projectId");
+        }
+        let path = format!("/v1/projects/{}:{}", self.inner.project_id, method);
+        self.inner.transport.request(HttpMethod::POST, &path, Some(&body)).await
+    }
+
+    /// Reads the current state of an operation by its name.
+    pub async fn get_operation(&self, name: &str) -> Result<Operation> {
+        let path = format!("/v1/{}", name);
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.request(HttpMethod::GET, &path, None),
+                                    Error::is_retryable).await
+    }
+
+    /// Asks the server to cancel an operation. Cancellation is asynchronous, the operation may
+    /// still finish.
+    pub async fn cancel_operation(&self, name: &str) -> Result<()> {
+        let path = format!("/v1/{}:cancel", name);
+        let body = serde_json::json!({});
+        let transport = &self.inner.transport;
+        let _: serde_json::Value = self.inner.retry_policy
+            .run(|| transport.request(HttpMethod::POST, &path, Some(&body)), Error::is_retryable)
+            .await?;
+        Ok(())
+    }
+
+    /// Lists the composite indexes of the project.
+    pub async fn list_indexes(&self) -> Result<Vec<Index>> {
+        let mut indexes = vec![];
+        let mut page_token = String::new();
+        loop {
+            let mut path = format!("/v1/projects/{}/indexes", self.inner.project_id);
+            if !page_token.is_empty() {
+                path = format!("{}?pageToken={}", path, encode_query_value(&page_token));
+            }
+            let transport = &self.inner.transport;
+            let response: ListIndexesResponse = self.inner.retry_policy
+                .run(|| transport.request(HttpMethod::GET, &path, None), Error::is_retryable)
+                .await?;
+            indexes.extend(response.indexes);
+            if response.next_page_token.is_empty() {
+                return Ok(indexes);
+            }
+            page_token = response.next_page_token;
+        }
+    }
+
+    pub async fn get_index(&self, index_id: &str) -> Result<Index> {
+        let path = format!("/v1/projects/{}/indexes/{}", self.inner.project_id, index_id);
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.request(HttpMethod::GET, &path, None),
+                                    Error::is_retryable).await
+    }
+
+    /// Starts creating an index. The operation's metadata has the ID of the new index. Failed
+    /// requests are not retried.
+    pub async fn create_index(&self, definition: &index::Index) -> Result<OperationHandle> {
+        let path = format!("/v1/projects/{}/indexes", self.inner.project_id);
+        let body = serde_json::to_value(Index::new(definition))?;
+        let operation = self.inner.transport.request(HttpMethod::POST, &path, Some(&body)).await?;
+        Ok(OperationHandle::new(self.clone(), operation))
+    }
+
+    /// Starts deleting an index.
+    pub async fn delete_index(&self, index_id: &str) -> Result<OperationHandle> {
+        let path = format!("/v1/projects/{}/indexes/{}", self.inner.project_id, index_id);
+        let transport = &self.inner.transport;
+        let operation = self.inner.retry_policy
+            .run(|| transport.request(HttpMethod::DELETE, &path, None), Error::is_retryable)
+            .await?;
+        Ok(OperationHandle::new(self.clone(), operation))
+    }
+
+    /// Polls an index until it is `READY` or `timeout` has passed. Fails if building the index
+    /// failed, and with `Error::Timeout` if it is still being built.
+    pub async fn wait_for_index(&self, index_id: &str, timeout: Duration) -> Result<Index> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let index = self.get_index(index_id).await?;
+            match index.state {
+                IndexState::Ready => return Ok(index),
+                IndexState::Error | IndexState::Deleting =>
+                    return Err(Error::api(Code::FailedPrecondition,
+                                          format!("index {} is in state {:?}", index_id, index.state))),
+                _ => (),
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout(format!("index {} is still {:?}", index_id, index.state)));
+            }
+            tokio::time::sleep(self.poll_interval.min(deadline - now)).await;
+        }
+    }
+
+    /// Compares local definitions, e.g. of an `index.yaml` file, with the indexes on the server.
+    pub async fn diff_indexes(&self, local: &[index::Index]) -> Result<IndexDiff> {
+        Ok(index::diff(local, &self.list_indexes().await?))
+    }
+
+    /// Starts creating the missing indexes of a diff and, if `delete_unused`, deleting the unused
+    /// ones. Returns the started operations.
+    pub async fn sync_indexes(&self, diff: &IndexDiff, delete_unused: bool)
+                              -> Result<Vec<OperationHandle>> {
+        let mut operations = vec![];
+        for definition in &diff.missing {
+            operations.push(self.create_index(definition).await?);
+        }
+        if delete_unused {
+            for index in &diff.unused {
+                operations.push(self.delete_index(&index.index_id).await?);
+            }
+        }
+        Ok(operations)
+    }
+}
+
+/// A running export, import or index operation.
+pub struct OperationHandle {
+    client: AdminClient,
+    operation: Operation,
+    poll_interval: Duration,
+}
+
+impl OperationHandle {
+    fn new(client: AdminClient, operation: Operation) -> OperationHandle {
+        let poll_interval = client.poll_interval;
+        OperationHandle { client, operation, poll_interval }
+    }
+
+    /// Sets the time between two polls in `wait`, by default that of the client.
+    pub fn with_poll_interval(mut self, interval: Duration) -> OperationHandle {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.operation.name
+    }
+
+    /// The state of the operation as of the last poll.
+    pub fn operation(&self) -> &Operation {
+        &self.operation
+    }
+
+    /// Reads the current state of the operation.
+    pub async fn poll(&mut self) -> Result<&Operation> {
+        self.operation = self.client.get_operation(&self.operation.name).await?;
+        Ok(&self.operation)
+    }
+
+    /// Polls the operation until it is done or `timeout` has passed. Fails with the operation's
+    /// error if it failed or was cancelled, and with `Error::Timeout` if it is still running.
+    pub async fn wait(&mut self, timeout: Duration) -> Result<&Operation> {
+        let deadline = Instant::now() + timeout;
+        while !self.operation.done {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout(format!("operation {} is still {:?}",
+                                                  self.operation.name, self.operation.state())));
+            }
+            tokio::time::sleep(self.poll_interval.min(deadline - now)).await;
+            self.poll().await?;
+        }
+
+        match self.operation.error {
+            Some(ref status) => Err(Error::api(Code::from_i32(status.code), status.message.clone())),
+            None => Ok(&self.operation),
+        }
+    }
+
+    /// Asks the server to cancel the operation and reads its state afterwards.
+    pub async fn cancel(&mut self) -> Result<&Operation> {
+        self.client.cancel_operation(&self.operation.name).await?;
+        self.poll().await
+    }
+}
+
+/// Percent-encodes a query parameter, e.g. a page token, which may contain `+`, `/` and `=`.
+fn encode_query_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for &b in value.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use crate::client::*;
+use crate::client::stand_in;
+use crate::datastore::admin::*;
+use crate::datastore::query::Direction;
+use crate::index;
+
+const PROJECT: &str = "test-project";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn admin_client(stand_in: &stand_in::StandIn) -> AdminClient {
+    Client::builder(PROJECT).endpoint(stand_in.endpoint()).build_admin().unwrap()
+}
+
+#[tokio::test]
+async fn test_export_import() {
+    let stand_in = stand_in::admin();
+    let client = admin_client(&stand_in);
+
+    let filter = EntityFilter::all().with_kinds(vec!["User"]).with_namespaces(vec!["", "tenant"]);
+    let mut export = client.export_entities("gs://bucket/exports", filter.clone()).await.unwrap()
+        .with_poll_interval(POLL_INTERVAL);
+    assert!(export.name().starts_with("projects/test-project/operations/"));
+    assert!(!export.operation().done);
+    assert_eq!(State::Processing, export.operation().state());
+    assert_eq!(Some(0.0), export.operation().progress().unwrap().fraction());
+
+    let operation = export.wait(TIMEOUT).await.unwrap();
+    assert!(operation.done);
+    assert_eq!(State::Successful, operation.state());
+    assert_eq!(Some(1.0), operation.progress().unwrap().fraction());
+    let metadata = operation.metadata.as_ref().unwrap();
+    assert_eq!(OperationType::ExportEntities, metadata.common.operation_type);
+    assert_eq!(Some(&filter), metadata.entity_filter.as_ref());
+    let output_url = operation.output_url().unwrap().to_string();
+    assert!(output_url.starts_with("gs://bucket/exports/"));
+
+    let mut import = client.import_entities(&output_url, EntityFilter::all()).await.unwrap()
+        .with_poll_interval(POLL_INTERVAL);
+    let operation = import.wait(TIMEOUT).await.unwrap();
+    assert_eq!(State::Successful, operation.state());
+    let metadata = operation.metadata.as_ref().unwrap();
+    assert_eq!(OperationType::ImportEntities, metadata.common.operation_type);
+    assert_eq!(Some(output_url.as_str()), metadata.input_url.as_deref());
+
+    // Operations can be read by name.
+    let operation = client.get_operation(export.name()).await.unwrap();
+    assert_eq!(State::Successful, operation.state());
+}
+
+#[tokio::test]
+async fn test_poll_and_timeout() {
+    let stand_in = stand_in::admin();
+    let client = admin_client(&stand_in);
+
+    let mut export = client.export_entities("gs://bucket", EntityFilter::all()).await.unwrap()
+        .with_poll_interval(Duration::from_secs(60));
+    let err = export.wait(Duration::from_millis(1)).await.unwrap_err();
+    assert!(matches!(err, Error::Timeout(_)), "{:?}", err);
+    assert!(!err.is_retryable());
+
+    let progress = export.poll().await.unwrap().progress().unwrap().clone();
+    assert!(progress.fraction().unwrap() > 0.0);
+}
+
+#[tokio::test]
+async fn test_cancel() {
+    let stand_in = stand_in::admin();
+    let client = admin_client(&stand_in);
+
+    let mut export = client.export_entities("gs://bucket", EntityFilter::all()).await.unwrap()
+        .with_poll_interval(POLL_INTERVAL);
+    let operation = export.cancel().await.unwrap();
+    assert!(operation.done);
+    assert_eq!(State::Cancelled, operation.state());
+    assert_eq!(None, operation.output_url());
+
+    let err = export.wait(TIMEOUT).await.unwrap_err();
+    assert_eq!(Some(Code::Cancelled), err.code());
+}
+
+#[tokio::test]
+async fn test_failures() {
+    let stand_in = stand_in::admin();
+    let client = admin_client(&stand_in);
+
+    // Invalid requests fail when starting the operation.
+    let err = client.export_entities("/tmp/export", EntityFilter::all()).await.err().unwrap();
+    assert_eq!(Some(Code::InvalidArgument), err.code());
+
+    // Failed operations fail when waiting for them.
+    let mut import = client.import_entities("gs://bucket/missing", EntityFilter::all()).await
+        .unwrap()
+        .with_poll_interval(POLL_INTERVAL);
+    let err = import.wait(TIMEOUT).await.unwrap_err();
+    assert_eq!(Some(Code::NotFound), err.code());
+    assert_eq!(State::Failed, import.operation().state());
+
+    let err = client.get_operation("projects/test-project/operations/unknown").await.unwrap_err();
+    assert_eq!(Some(Code::NotFound), err.code());
+}
+
+fn definition(kind: &str, property: &str) -> index::Index {
+    index::Index::new(kind)
+        .with_property("status", Direction::Ascending)
+        .with_property(property, Direction::Descending)
+}
+
+#[tokio::test]
+async fn test_indexes() {
+    let stand_in = stand_in::admin();
+    let client = admin_client(&stand_in).with_poll_interval(POLL_INTERVAL);
+    assert_eq!(Vec::<Index>::new(), client.list_indexes().await.unwrap());
+
+    let mut operation = client.create_index(&definition("Order", "created")).await.unwrap();
+    let index_id = operation.operation().index_id().unwrap().to_string();
+    assert_eq!(IndexState::Creating, client.get_index(&index_id).await.unwrap().state);
+    let index = client.wait_for_index(&index_id, TIMEOUT).await.unwrap();
+    assert_eq!(IndexState::Ready, index.state);
+    assert_eq!(AncestorMode::None, index.ancestor);
+    assert_eq!(definition("Order", "created"), index.definition());
+    let operation = operation.wait(TIMEOUT).await.unwrap();
+    assert_eq!(OperationType::CreateIndex, operation.metadata.as_ref().unwrap().common.operation_type);
+
+    // Indexes are listed across pages.
+    for property in &["total", "region"] {
+        client.create_index(&definition("Order", property)).await.unwrap().wait(TIMEOUT).await.unwrap();
+    }
+    let indexes = client.list_indexes().await.unwrap();
+    assert!(indexes.len() > stand_in::INDEX_PAGE_SIZE);
+    assert_eq!(3, indexes.len());
+
+    let err = client.create_index(&definition("Order", "created")).await.err().unwrap();
+    assert_eq!(Some(Code::AlreadyExists), err.code());
+
+    let mut operation = client.delete_index(&index_id).await.unwrap();
+    assert_eq!(IndexState::Deleting, client.get_index(&index_id).await.unwrap().state);
+    operation.wait(TIMEOUT).await.unwrap();
+    let err = client.get_index(&index_id).await.unwrap_err();
+    assert_eq!(Some(Code::NotFound), err.code());
+    assert_eq!(2, client.list_indexes().await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_failed_index() {
+    let stand_in = stand_in::admin();
+    let client = admin_client(&stand_in).with_poll_interval(POLL_INTERVAL);
+
+    let operation = client.create_index(&definition("FailingKind", "created")).await.unwrap();
+    let index_id = operation.operation().index_id().unwrap();
+    let err = client.wait_for_index(index_id, TIMEOUT).await.unwrap_err();
+    assert_eq!(Some(Code::FailedPrecondition), err.code());
+    assert_eq!(IndexState::Error, client.get_index(index_id).await.unwrap().state);
+
+    let operation = client.create_index(&definition("Order", "created")).await.unwrap();
+    let err = client.wait_for_index(operation.operation().index_id().unwrap(), Duration::from_millis(1))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Timeout(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn test_sync_indexes() {
+    let stand_in = stand_in::admin();
+    let client = admin_client(&stand_in).with_poll_interval(POLL_INTERVAL);
+    client.create_index(&definition("Order", "created")).await.unwrap().wait(TIMEOUT).await.unwrap();
+    client.create_index(&definition("Order", "total")).await.unwrap().wait(TIMEOUT).await.unwrap();
+
+    let local = vec![definition("Order", "created"), definition("Line", "qty")];
+    let diff = client.diff_indexes(&local).await.unwrap();
+    assert_eq!(vec![definition("Line", "qty")], diff.missing);
+    assert_eq!(vec![definition("Order", "total")],
+               diff.unused.iter().map(Index::definition).collect::<Vec<_>>());
+    assert_eq!(1, diff.existing.len());
+
+    // Unused indexes are kept unless asked otherwise.
+    for mut operation in client.sync_indexes(&diff, false).await.unwrap() {
+        operation.wait(TIMEOUT).await.unwrap();
+    }
+    let diff = client.diff_indexes(&local).await.unwrap();
+    assert!(diff.missing.is_empty());
+    assert_eq!(1, diff.unused.len());
+
+    for mut operation in client.sync_indexes(&diff, true).await.unwrap() {
+        operation.wait(TIMEOUT).await.unwrap();
+    }
+    assert!(client.diff_indexes(&local).await.unwrap().is_empty());
+}
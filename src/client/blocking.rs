@@ -0,0 +1,245 @@
+// Blocking facade over the async client, for command line tools and scripts.
+//
+// Every call runs the corresponding async operation to completion on a runtime owned by the
+// client. Clones share the runtime and the connections of the async client and may be used from
+// several threads at once.
+
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::client::{self, BulkOptions, ClientBuilder, Result, Transport};
+use crate::datastore::{Blob, Entity, Key};
+use crate::datastore::metadata::Property;
+use crate::datastore::query::{AggregationQuery, AggregationResult, Query};
+use crate::datastore::rpc::*;
+use crate::datastore::stats::Stat;
+use crate::entity::DatastoreEntity;
+
+#[derive(Clone)]
+pub struct Client {
+    client: client::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    pub fn builder<P: Into<String>>(project_id: P) -> ClientBuilder {
+        client::Client::builder(project_id)
+    }
+
+    /// Creates a client that uses the given transport, e.g. a `MemoryBackend`.
+    pub fn new<P: Into<String>, T: Transport + 'static>(project_id: P, transport: T) -> Result<Client> {
+        Client::wrap(client::Client::new(project_id, transport))
+    }
+
+    /// Wraps an async client, sharing its connections.
+    pub fn wrap(client: client::Client) -> Result<Client> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Client { client, runtime: Arc::new(runtime) })
+    }
+
+    /// The async client wrapped by this client.
+    pub fn as_async(&self) -> &client::Client {
+        &self.client
+    }
+
+    pub fn project_id(&self) -> &str {
+        self.client.project_id()
+    }
+
+    /// Returns a client confined to `namespace`, see `client::Client::namespace`.
+    pub fn namespace<N: Into<String>>(&self, namespace: N) -> Client {
+        Client { client: self.client.namespace(namespace), runtime: self.runtime.clone() }
+    }
+
+    pub fn namespace_id(&self) -> Option<&str> {
+        self.client.namespace_id()
+    }
+
+    pub fn lookup(&self, request: LookupRequest) -> Result<LookupResponse> {
+        self.runtime.block_on(self.client.lookup(request))
+    }
+
+    pub fn run_query(&self, request: RunQueryRequest) -> Result<RunQueryResponse> {
+        self.runtime.block_on(self.client.run_query(request))
+    }
+
+    pub fn run_aggregation_query(&self, request: RunAggregationQueryRequest)
+                                 -> Result<RunAggregationQueryResponse> {
+        self.runtime.block_on(self.client.run_aggregation_query(request))
+    }
+
+    pub fn aggregate(&self, query: AggregationQuery) -> Result<AggregationResult> {
+        self.runtime.block_on(self.client.aggregate(query))
+    }
+
+    pub fn begin_transaction(&self, request: BeginTransactionRequest)
+                             -> Result<BeginTransactionResponse> {
+        self.runtime.block_on(self.client.begin_transaction(request))
+    }
+
+    pub fn commit(&self, request: CommitRequest) -> Result<CommitResponse> {
+        self.runtime.block_on(self.client.commit(request))
+    }
+
+    pub fn rollback(&self, request: RollbackRequest) -> Result<RollbackResponse> {
+        self.runtime.block_on(self.client.rollback(request))
+    }
+
+    pub fn allocate_ids(&self, request: AllocateIdsRequest) -> Result<AllocateIdsResponse> {
+        self.runtime.block_on(self.client.allocate_ids(request))
+    }
+
+    pub fn reserve_ids(&self, request: ReserveIdsRequest) -> Result<ReserveIdsResponse> {
+        self.runtime.block_on(self.client.reserve_ids(request))
+    }
+
+    pub fn get_entity(&self, key: &Key) -> Result<Option<Entity>> {
+        self.runtime.block_on(self.client.get_entity(key))
+    }
+
+    pub fn get_entities(&self, keys: &[Key]) -> Result<Vec<Option<Entity>>> {
+        self.runtime.block_on(self.client.get_entities(keys))
+    }
+
+    pub fn put_entity(&self, entity: Entity) -> Result<()> {
+        self.runtime.block_on(self.client.put_entity(entity))
+    }
+
+    pub fn delete(&self, key: Key) -> Result<()> {
+        self.runtime.block_on(self.client.delete(key))
+    }
+
+    pub fn read<T: DeserializeOwned>(&self, key: &Key) -> Result<Option<T>> {
+        self.runtime.block_on(self.client.read(key))
+    }
+
+    pub fn write<T: Serialize>(&self, key: Key, value: &T) -> Result<()> {
+        self.runtime.block_on(self.client.write(key, value))
+    }
+
+    pub fn get<T: DatastoreEntity>(&self, id: impl Into<T::Id>) -> Result<Option<T>> {
+        self.runtime.block_on(self.client.get(id))
+    }
+
+    pub fn get_by_key<T: DatastoreEntity>(&self, key: &Key) -> Result<Option<T>> {
+        self.runtime.block_on(self.client.get_by_key(key))
+    }
+
+    pub fn query_keys(&self, query: Query) -> Result<Vec<Key>> {
+        self.runtime.block_on(self.client.query_keys(query))
+    }
+
+    pub fn allocate_keys(&self, keys: &[Key]) -> Result<Vec<Key>> {
+        self.runtime.block_on(self.client.allocate_keys(keys))
+    }
+
+    pub fn reserve_keys(&self, keys: &[Key]) -> Result<()> {
+        self.runtime.block_on(self.client.reserve_keys(keys))
+    }
+
+    pub fn namespaces(&self) -> Result<Vec<String>> {
+        self.runtime.block_on(self.client.namespaces())
+    }
+
+    pub fn kinds(&self, namespace: &str) -> Result<Vec<String>> {
+        self.runtime.block_on(self.client.kinds(namespace))
+    }
+
+    pub fn properties(&self, namespace: &str, kind: Option<&str>) -> Result<Vec<Property>> {
+        self.runtime.block_on(self.client.properties(namespace, kind))
+    }
+
+    pub fn latest_stats<T: Stat>(&self, namespace: &str) -> Result<Vec<T>> {
+        self.runtime.block_on(self.client.latest_stats(namespace))
+    }
+
+    pub fn export_jsonl<W: Write>(&self, kind: Option<&str>, out: W, options: &BulkOptions) -> Result<u64> {
+        self.runtime.block_on(self.client.export_jsonl(kind, out, options))
+    }
+
+    pub fn import_jsonl<R: BufRead>(&self, input: R, options: &BulkOptions) -> Result<u64> {
+        self.runtime.block_on(self.client.import_jsonl(input, options))
+    }
+
+    pub fn put<T: DatastoreEntity>(&self, value: &T) -> Result<Key> {
+        self.runtime.block_on(self.client.put(value))
+    }
+
+    pub fn transaction(&self) -> Result<Transaction> {
+        let transaction = self.runtime.block_on(self.client.transaction())?;
+        Ok(Transaction { transaction, runtime: self.runtime.clone() })
+    }
+
+    pub fn read_only_transaction(&self) -> Result<Transaction> {
+        let transaction = self.runtime.block_on(self.client.read_only_transaction())?;
+        Ok(Transaction { transaction, runtime: self.runtime.clone() })
+    }
+}
+
+/// A transaction of the blocking client, see `client::Transaction`.
+pub struct Transaction {
+    transaction: client::Transaction,
+    runtime: Arc<Runtime>,
+}
+
+impl Transaction {
+    pub fn id(&self) -> &Blob {
+        self.transaction.id()
+    }
+
+    pub fn lookup(&self, keys: Vec<Key>) -> Result<LookupResponse> {
+        self.runtime.block_on(self.transaction.lookup(keys))
+    }
+
+    pub fn run_query(&self, request: RunQueryRequest) -> Result<RunQueryResponse> {
+        self.runtime.block_on(self.transaction.run_query(request))
+    }
+
+    pub fn run_aggregation_query(&self, request: RunAggregationQueryRequest)
+                                 -> Result<RunAggregationQueryResponse> {
+        self.runtime.block_on(self.transaction.run_aggregation_query(request))
+    }
+
+    pub fn aggregate(&self, query: AggregationQuery) -> Result<AggregationResult> {
+        self.runtime.block_on(self.transaction.aggregate(query))
+    }
+
+    pub fn get_entity(&self, key: &Key) -> Result<Option<Entity>> {
+        self.runtime.block_on(self.transaction.get_entity(key))
+    }
+
+    pub fn read<T: DeserializeOwned>(&self, key: &Key) -> Result<Option<T>> {
+        self.runtime.block_on(self.transaction.read(key))
+    }
+
+    pub fn insert(&mut self, entity: Entity) {
+        self.transaction.insert(entity)
+    }
+
+    pub fn update(&mut self, entity: Entity) {
+        self.transaction.update(entity)
+    }
+
+    pub fn upsert(&mut self, entity: Entity) {
+        self.transaction.upsert(entity)
+    }
+
+    pub fn delete(&mut self, key: Key) {
+        self.transaction.delete(key)
+    }
+
+    pub fn write<T: Serialize>(&mut self, key: Key, value: &T) -> Result<()> {
+        self.transaction.write(key, value)
+    }
+
+    pub fn commit(self) -> Result<CommitResponse> {
+        self.runtime.block_on(self.transaction.commit())
+    }
+
+    pub fn rollback(self) -> Result<()> {
+        self.runtime.block_on(self.transaction.rollback())
+    }
+}
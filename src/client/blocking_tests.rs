@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use std::thread;
+
+use crate::client::{blocking, stand_in, Code, MemoryBackend, Protocol};
+use crate::datastore::*;
+use crate::datastore::query::Query;
+use crate::datastore::rpc::*;
+
+const PROJECT: &str = "test-project";
+
+fn user_key(name: &str) -> Key {
+    Key::new(PartitionId::new(PROJECT, ""), vec![PathElement::from_name("User", name)])
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct User {
+    name: String,
+    age: i64,
+}
+
+fn alice() -> User {
+    User { name: "alice".to_string(), age: 31 }
+}
+
+fn scenario(client: &blocking::Client) {
+    client.write(user_key("alice"), &alice()).expect("Write failed");
+    assert_eq!(Some(alice()), client.read::<User>(&user_key("alice")).unwrap());
+
+    let mut transaction = client.transaction().unwrap();
+    let mut user: User = transaction.read(&user_key("alice")).unwrap().unwrap();
+    user.age += 1;
+    transaction.write(user_key("alice"), &user).unwrap();
+    transaction.commit().expect("Commit failed");
+    assert_eq!(32, client.read::<User>(&user_key("alice")).unwrap().unwrap().age);
+
+    let mut transaction = client.transaction().unwrap();
+    transaction.get_entity(&user_key("alice")).unwrap();
+    client.delete(user_key("alice")).unwrap();
+    transaction.delete(user_key("alice"));
+    assert_eq!(Some(Code::Aborted), transaction.commit().unwrap_err().code());
+    assert_eq!(None, client.get_entity(&user_key("alice")).unwrap());
+}
+
+#[test]
+fn test_blocking_memory_backend() {
+    let client = blocking::Client::new(PROJECT, MemoryBackend::new()).unwrap();
+    scenario(&client);
+}
+
+#[test]
+fn test_blocking_rest_transport() {
+    let stand_in = stand_in::rest(Arc::new(MemoryBackend::new()));
+    let client = blocking::Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .build_blocking()
+        .unwrap();
+    scenario(&client);
+    assert_eq!(1, stand_in.connections());
+}
+
+#[test]
+fn test_blocking_grpc_transport() {
+    let stand_in = stand_in::grpc(Arc::new(MemoryBackend::new()));
+    let client = blocking::Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Grpc)
+        .build_blocking()
+        .unwrap();
+    scenario(&client);
+}
+
+#[test]
+fn test_blocking_clones_across_threads() {
+    let stand_in = stand_in::grpc(Arc::new(MemoryBackend::new()));
+    let client = blocking::Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Grpc)
+        .build_blocking()
+        .unwrap();
+
+    let threads: Vec<_> = (0..4).map(|i| {
+        let client = client.clone();
+        thread::spawn(move || {
+            let user = User { name: format!("user-{}", i), age: i };
+            client.write(user_key(&user.name), &user).unwrap();
+        })
+    }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let request = RunQueryRequest { query: Some(Query::new("User")), ..RunQueryRequest::default() };
+    assert_eq!(4, client.run_query(request).unwrap().batch.entity_results.len());
+}
@@ -0,0 +1,335 @@
+// Bulk export and import of entities as JSON lines.
+//
+// Entities are written in the JSON representation of the API, one per line, as `ds dump` prints
+// them. Exports page through a query with cursors, imports upsert batches of lines on several
+// tasks at once. Both can record their progress in a checkpoint file, so that a run that was
+// interrupted continues where it stopped instead of starting over. The checkpoint of an export
+// holds the cursor after the last page that was written and the length of the output up to it, the
+// one of an import the line up to which all entities were committed. Checkpoint files are removed
+// when a run completes.
+//
+// Pages and batches are checkpointed once they are written, so a run that is interrupted in
+// between repeats at most one page or the batches in flight. This is harmless for imports, which
+// upsert. An export repeats the page after its output was truncated to the checkpointed length,
+// which drops what was written of the page before.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use tokio::task::JoinSet;
+
+use crate::client::{Client, Error, Result};
+use crate::datastore::{Blob, Entity, Key, PartitionId, Value};
+use crate::datastore::query::{MoreResultsType, Query};
+use crate::datastore::rpc::{CommitMode, CommitRequest, Mutation, RunQueryRequest};
+
+/// How `Client::export_jsonl` and `Client::import_jsonl` run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkOptions {
+    workers: usize,
+    batch_size: usize,
+    checkpoint: Option<PathBuf>,
+}
+
+impl Default for BulkOptions {
+    fn default() -> BulkOptions {
+        BulkOptions { workers: 8, batch_size: 500, checkpoint: None }
+    }
+}
+
+impl BulkOptions {
+    /// Sets how many batches an import commits at the same time.
+    pub fn with_workers(mut self, workers: usize) -> BulkOptions {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Sets the number of entities per page of an export and per commit of an import. Commits are
+    /// limited to 500 mutations.
+    pub fn with_batch_size(mut self, batch_size: usize) -> BulkOptions {
+        self.batch_size = batch_size.clamp(1, 500);
+        self
+    }
+
+    /// Records progress in a file, and resumes from it if it exists.
+    pub fn with_checkpoint<P: Into<PathBuf>>(mut self, path: P) -> BulkOptions {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn checkpoint(&self) -> Option<&Path> {
+        self.checkpoint.as_deref()
+    }
+
+    /// Returns the length of the output of an interrupted export at its checkpoint, or `None` if
+    /// there is no checkpoint file. The output has to be truncated to it before the export resumes.
+    pub fn export_offset(&self) -> Result<Option<u64>> {
+        match self.checkpoint() {
+            Some(path) if path.exists() => Ok(Some(Checkpoint::load(Some(path))?.offset)),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Checkpoint {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cursor: Option<Blob>,
+
+    #[serde(default)]
+    line: u64,
+
+    #[serde(default)]
+    offset: u64,
+}
+
+impl Checkpoint {
+    fn load(path: Option<&Path>) -> Result<Checkpoint> {
+        match path {
+            Some(path) if path.exists() => Ok(serde_json::from_slice(&fs::read(path)?)?),
+            _ => Ok(Checkpoint::default()),
+        }
+    }
+
+    // Writes the checkpoint to a temporary file first, so that an interruption never leaves a
+    // truncated checkpoint behind.
+    fn save(&self, path: Option<&Path>) -> Result<()> {
+        if let Some(path) = path {
+            let mut temporary = path.as_os_str().to_owned();
+            temporary.push(".tmp");
+            fs::write(&temporary, serde_json::to_vec(self)?)?;
+            fs::rename(&temporary, path)?;
+        }
+        Ok(())
+    }
+
+    fn remove(path: Option<&Path>) -> Result<()> {
+        match path {
+            Some(path) if path.exists() => Ok(fs::remove_file(path)?),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Client {
+    /// Writes the entities of a kind, or of the whole namespace if `kind` is `None`, to `out` as
+    /// JSON lines and returns how many were written. Namespace exports leave out the statistics
+    /// and metadata kinds, whose names start with `__`.
+    ///
+    /// With a checkpoint, the export continues after the last page of an earlier run, and `out`
+    /// should append to that run's output truncated to `BulkOptions::export_offset`.
+    pub async fn export_jsonl<W: Write>(&self, kind: Option<&str>, mut out: W, options: &BulkOptions)
+                                        -> Result<u64> {
+        let path = options.checkpoint();
+        let mut checkpoint = Checkpoint::load(path)?;
+        let mut query = match kind {
+            Some(kind) => Query::new(kind),
+            None => Query::default(),
+        };
+        query.limit = Some(options.batch_size as i32);
+
+        let mut exported = 0;
+        loop {
+            query.start_cursor = checkpoint.cursor.clone();
+            let request = RunQueryRequest { query: Some(query.clone()), ..RunQueryRequest::default() };
+            let batch = self.run_query(request).await?.batch;
+            let fetched = batch.entity_results.len();
+            for result in batch.entity_results {
+                if kind.is_none() && is_internal(&result.entity) {
+                    continue;
+                }
+                let mut line = serde_json::to_vec(&result.entity)?;
+                line.push(b'\n');
+                out.write_all(&line)?;
+                checkpoint.offset += line.len() as u64;
+                exported += 1;
+            }
+            out.flush()?;
+
+            // Batches may be empty without being the last one, when the query stopped after
+            // scanning many entities.
+            let last = match batch.more_results {
+                MoreResultsType::NoMoreResults => true,
+                MoreResultsType::MoreResultsAfterLimit => fetched < options.batch_size,
+                _ => false,
+            };
+            if last {
+                break;
+            }
+            checkpoint.cursor = match batch.end_cursor {
+                Some(cursor) => Some(cursor),
+                None => return Err(Error::UnexpectedResponse("unfinished query without cursor".to_string())),
+            };
+            checkpoint.save(path)?;
+        }
+        Checkpoint::remove(path)?;
+        Ok(exported)
+    }
+
+    /// Upserts the entities read from `input`, JSON lines as written by `export_jsonl`, and returns
+    /// how many were imported. Blank lines are skipped.
+    ///
+    /// Keys of the project that the entities were exported from are moved to the project of the
+    /// client, including keys in properties, so that entities can be copied between projects.
+    /// Entities with incomplete keys get new IDs, which makes a repeated import of them create
+    /// them again. With a checkpoint, the import skips the lines that an earlier run committed.
+    pub async fn import_jsonl<R: BufRead>(&self, input: R, options: &BulkOptions) -> Result<u64> {
+        let path = options.checkpoint();
+        let committed = Checkpoint::load(path)?.line;
+        let mut import = Import {
+            client: self,
+            workers: options.workers,
+            checkpoint: path,
+            running: JoinSet::new(),
+            finished: BTreeMap::new(),
+            committed,
+            imported: 0,
+        };
+
+        let mut batch = vec![];
+        let mut first = committed + 1;
+        let mut number = 0;
+        for line in input.lines() {
+            let line = line?;
+            number += 1;
+            if number <= committed {
+                continue;
+            }
+            if let Some(entity) = parse_line(number, &line)? {
+                batch.push(Mutation::Upsert(self.to_own_project(entity)));
+            }
+            if batch.len() == options.batch_size {
+                import.start(first, number, batch.split_off(0)).await?;
+                first = number + 1;
+            }
+        }
+        if !batch.is_empty() {
+            import.start(first, number, batch).await?;
+        }
+        while !import.running.is_empty() {
+            import.join_next().await?;
+        }
+        Checkpoint::remove(path)?;
+        Ok(import.imported)
+    }
+
+    fn to_own_project(&self, mut entity: Entity) -> Entity {
+        let source = match entity.key {
+            Some(ref key) => key.partition_id().project_id().to_string(),
+            None => return entity,
+        };
+        if source != self.project_id() {
+            move_keys(&mut entity, &source, self.project_id());
+        }
+        entity
+    }
+}
+
+// The batches of an import that are being committed. Batches cover consecutive ranges of lines
+// and may finish in any order, the checkpoint only moves past lines whose batches all finished.
+struct Import<'a> {
+    client: &'a Client,
+    workers: usize,
+    checkpoint: Option<&'a Path>,
+    running: JoinSet<Result<(u64, u64, u64)>>,
+
+    /// The last lines of the batches that finished before an earlier batch, by their first line.
+    finished: BTreeMap<u64, u64>,
+
+    /// The line up to which all entities were committed.
+    committed: u64,
+    imported: u64,
+}
+
+impl<'a> Import<'a> {
+    // Starts committing the batch of the lines `first..=last`, once fewer than `workers` batches
+    // are running.
+    async fn start(&mut self, first: u64, last: u64, mutations: Vec<Mutation>) -> Result<()> {
+        while self.running.len() >= self.workers {
+            self.join_next().await?;
+        }
+        let client = self.client.clone();
+        self.running.spawn(async move {
+            let count = mutations.len() as u64;
+            let request = CommitRequest {
+                project_id: String::new(),
+                mode: CommitMode::NonTransactional,
+                transaction: None,
+                mutations,
+            };
+            client.commit(request).await.map(|_| (first, last, count))
+        });
+        Ok(())
+    }
+
+    // Waits for the next batch to finish. A failed batch fails the import, the batches still
+    // running are cancelled when the import is dropped.
+    async fn join_next(&mut self) -> Result<()> {
+        let (first, last, count) = match self.running.join_next().await {
+            Some(joined) => {
+                joined.map_err(|err| Error::UnexpectedResponse(format!("import task failed: {}", err)))??
+            }
+            None => return Ok(()),
+        };
+        self.imported += count;
+        self.finished.insert(first, last);
+        let before = self.committed;
+        while let Some(last) = self.finished.remove(&(self.committed + 1)) {
+            self.committed = last;
+        }
+        if self.committed != before {
+            Checkpoint { line: self.committed, ..Checkpoint::default() }.save(self.checkpoint)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(number: u64, line: &str) -> Result<Option<Entity>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(line).map(Some).map_err(|err| {
+        Error::Io(io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number, err)))
+    })
+}
+
+fn is_internal(entity: &Entity) -> bool {
+    entity.key.as_ref().and_then(Key::kind).is_some_and(|kind| kind.starts_with("__"))
+}
+
+fn move_keys(entity: &mut Entity, from: &str, to: &str) {
+    if let Some(ref mut key) = entity.key {
+        move_key(key, from, to);
+    }
+    for value in entity.properties.values_mut() {
+        move_value(value, from, to);
+    }
+}
+
+fn move_key(key: &mut Key, from: &str, to: &str) {
+    if key.partition_id().project_id() == from {
+        let partition = PartitionId::new(to, key.partition_id().namespace_id());
+        *key = Key::new(partition, key.path().to_vec());
+    }
+}
+
+fn move_value(value: &mut Value, from: &str, to: &str) {
+    match *value {
+        Value::KeyValue { ref mut key_value, .. } => move_key(key_value, from, to),
+        Value::EntityValue { ref mut entity_value, .. } => move_keys(entity_value, from, to),
+        Value::Array { ref mut array_value, .. } => for value in &mut array_value.values {
+            move_value(value, from, to);
+        },
+        _ => (),
+    }
+}
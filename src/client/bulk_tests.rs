@@ -0,0 +1,143 @@
+use std::fs;
+use std::io::{self, Write};
+
+use crate::client::*;
+use crate::datastore::*;
+
+fn user_key(project: &str, id: i64) -> Key {
+    Key::new(PartitionId::new(project, ""), vec![PathElement::from_id("User", id)])
+}
+
+// A client of the project `source` with seven users and an order that refers to the first one.
+async fn source() -> Client {
+    let client = Client::new("source", MemoryBackend::new());
+    for id in 1..=7 {
+        let mut user = Entity { key: Some(user_key("source", id)), properties: Default::default() };
+        user.properties.insert("age".to_string(), Value::from(20 + id));
+        client.put_entity(user).await.unwrap();
+    }
+    let mut order = Entity {
+        key: Some(Key::new(PartitionId::new("source", ""), vec![PathElement::from_name("Order", "o1")])),
+        properties: Default::default(),
+    };
+    order.properties.insert("buyer".to_string(), Value::from(user_key("source", 1)));
+    client.put_entity(order).await.unwrap();
+    client
+}
+
+fn checkpoint_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("bulk-{}-{}.json", name, std::process::id()))
+}
+
+// Fails writes once it holds `lines` lines.
+struct FailingWriter {
+    written: Vec<u8>,
+    lines: usize,
+}
+
+impl Write for FailingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written.iter().filter(|&&b| b == b'\n').count() >= self.lines {
+            return Err(io::Error::other("disk full"));
+        }
+        self.written.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_export_import() {
+    let client = source().await;
+    let options = BulkOptions::default().with_batch_size(2).with_workers(3);
+
+    let mut users = vec![];
+    assert_eq!(7, client.export_jsonl(Some("User"), &mut users, &options).await.unwrap());
+    assert_eq!(7, users.split(|&b| b == b'\n').filter(|line| !line.is_empty()).count());
+    let first: Entity = serde_json::from_slice(users.split(|&b| b == b'\n').next().unwrap()).unwrap();
+    assert_eq!(Some(&Value::from(21)), first.properties.get("age"));
+
+    let mut all = vec![];
+    assert_eq!(8, client.export_jsonl(None, &mut all, &options).await.unwrap());
+
+    // Keys are moved to the project of the importing client.
+    let copy = Client::new("copy", MemoryBackend::new());
+    let mut input = all.clone();
+    input.extend_from_slice(b"\n\n");
+    assert_eq!(8, copy.import_jsonl(&input[..], &options).await.unwrap());
+    let order_key = Key::new(PartitionId::new("copy", ""), vec![PathElement::from_name("Order", "o1")]);
+    let order = copy.get_entity(&order_key).await.unwrap().expect("Order was not imported");
+    assert_eq!(Some(&Value::from(user_key("copy", 1))), order.properties.get("buyer"));
+    assert_eq!(7, copy.query_keys(query::Query::new("User")).await.unwrap().len());
+
+    let invalid = b"{\"key\": {\"partitionId\": {\"projectId\": \"source\"}, \"path\": [{\"kind\": \"User\", \"id\": \"9\"}]}}\nnot json\n";
+    match copy.import_jsonl(&invalid[..], &options).await {
+        Err(Error::Io(err)) => assert!(err.to_string().starts_with("line 2:"), "{}", err),
+        result => panic!("Expected an error for line 2, got {:?}", result),
+    }
+}
+
+#[tokio::test]
+async fn test_export_empty_batches() {
+    let client = Client::new("source", MemoryBackend::new().with_empty_batches(2));
+    for id in 1..=3 {
+        client.put_entity(Entity { key: Some(user_key("source", id)), properties: Default::default() })
+            .await
+            .unwrap();
+    }
+
+    let mut out = vec![];
+    let options = BulkOptions::default().with_batch_size(2);
+    assert_eq!(3, client.export_jsonl(Some("User"), &mut out, &options).await.unwrap());
+    assert_eq!(3, out.split(|&b| b == b'\n').filter(|line| !line.is_empty()).count());
+}
+
+#[tokio::test]
+async fn test_resume_export() {
+    let client = source().await;
+    let path = checkpoint_path("export");
+    let options = BulkOptions::default().with_batch_size(2).with_checkpoint(&path);
+
+    // The export fails in the second page, after the first page was checkpointed.
+    let mut out = FailingWriter { written: vec![], lines: 3 };
+    assert!(client.export_jsonl(Some("User"), &mut out, &options).await.is_err());
+    assert!(path.exists());
+
+    // The resumed export continues the output where the checkpoint was taken.
+    let mut written = out.written;
+    written.truncate(options.export_offset().unwrap().unwrap() as usize);
+    assert_eq!(2, written.split(|&b| b == b'\n').filter(|line| !line.is_empty()).count());
+    assert_eq!(5, client.export_jsonl(Some("User"), &mut written, &options).await.unwrap());
+    assert!(!path.exists());
+    assert_eq!(None, options.export_offset().unwrap());
+
+    let mut uninterrupted = vec![];
+    client.export_jsonl(Some("User"), &mut uninterrupted, &options).await.unwrap();
+    assert_eq!(String::from_utf8(uninterrupted).unwrap(), String::from_utf8(written).unwrap());
+}
+
+#[tokio::test]
+async fn test_resume_import() {
+    let client = source().await;
+    let mut lines = vec![];
+    client.export_jsonl(Some("User"), &mut lines, &BulkOptions::default()).await.unwrap();
+    let lines: Vec<&[u8]> = lines.split(|&b| b == b'\n').filter(|line| !line.is_empty()).collect();
+
+    let path = checkpoint_path("import");
+    let options = BulkOptions::default().with_batch_size(2).with_workers(1).with_checkpoint(&path);
+    let copy = Client::new("copy", MemoryBackend::new());
+    let mut broken = lines[..5].join(&b'\n');
+    broken.extend_from_slice(b"\n{\n");
+    assert!(copy.import_jsonl(&broken[..], &options).await.is_err());
+    let checkpoint = fs::read_to_string(&path).unwrap();
+    assert!(checkpoint.contains("\"line\":"), "{}", checkpoint);
+
+    // The second run skips the committed lines and imports the rest.
+    let input = lines.join(&b'\n');
+    let imported = copy.import_jsonl(&input[..], &options).await.unwrap();
+    assert!(imported < 7, "{} entities imported again", imported);
+    assert!(!path.exists());
+    assert_eq!(7, copy.query_keys(query::Query::new("User")).await.unwrap().len());
+}
@@ -0,0 +1,129 @@
+// Connection establishment shared by the transports: endpoint parsing, TCP and TLS.
+
+use std::convert::TryFrom;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use http::Uri;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+
+use crate::client::{Error, Result};
+
+/// The production endpoint, which serves both the REST and the gRPC API.
+pub const DEFAULT_ENDPOINT: &str = "https://datastore.googleapis.com";
+
+/// Base URL of an API endpoint, e.g. `https://datastore.googleapis.com` or
+/// `http://localhost:8081` for the emulator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    tls: bool,
+    host: String,
+    port: u16,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Endpoint> {
+        let uri: Uri = url.parse().map_err(|_| Error::Transport(format!("invalid endpoint: {}", url)))?;
+
+        let tls = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(Error::Transport(format!("unsupported endpoint scheme: {}", url))),
+        };
+
+        let host = uri.host()
+            .ok_or_else(|| Error::Transport(format!("endpoint has no host: {}", url)))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+
+        Ok(Endpoint { tls, host, port })
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        if self.tls { "https" } else { "http" }
+    }
+
+    /// The `host:port` authority used in request URIs and `Host` headers.
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Connects to the endpoint, negotiating `alpn` as the application protocol if TLS is used.
+    pub async fn connect(&self, alpn: &[u8]) -> Result<Stream> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        tcp.set_nodelay(true)?;
+
+        if !self.tls {
+            return Ok(Stream::Plain(tcp));
+        }
+
+        let server_name = ServerName::try_from(self.host.clone())
+            .map_err(|_| Error::Transport(format!("invalid TLS server name: {}", self.host)))?;
+        let tls = TlsConnector::from(tls_config(alpn)?).connect(server_name, tcp).await?;
+        Ok(Stream::Tls(Box::new(tls)))
+    }
+}
+
+fn tls_config(alpn: &[u8]) -> Result<Arc<ClientConfig>> {
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let mut config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::Transport(e.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![alpn.to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+/// A connection to an endpoint, with or without TLS.
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            Stream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            Stream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_flush(cx),
+            Stream::Tls(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            Stream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
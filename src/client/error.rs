@@ -0,0 +1,240 @@
+use std::fmt::{self, Display};
+use std::convert::TryFrom;
+use std::io;
+
+use crate::proto;
+use crate::serde_ds;
+
+/// Canonical error codes of Google APIs (`google.rpc.Code`). They are used as gRPC status codes
+/// and appear as the `status` of REST error responses.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Code {
+    Ok,
+    Cancelled,
+    Unknown,
+    InvalidArgument,
+    DeadlineExceeded,
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    ResourceExhausted,
+    FailedPrecondition,
+    Aborted,
+    OutOfRange,
+    Unimplemented,
+    Internal,
+    Unavailable,
+    DataLoss,
+    Unauthenticated,
+}
+
+const CODES: [Code; 17] = [
+    Code::Ok,
+    Code::Cancelled,
+    Code::Unknown,
+    Code::InvalidArgument,
+    Code::DeadlineExceeded,
+    Code::NotFound,
+    Code::AlreadyExists,
+    Code::PermissionDenied,
+    Code::ResourceExhausted,
+    Code::FailedPrecondition,
+    Code::Aborted,
+    Code::OutOfRange,
+    Code::Unimplemented,
+    Code::Internal,
+    Code::Unavailable,
+    Code::DataLoss,
+    Code::Unauthenticated,
+];
+
+impl Code {
+    /// Converts a numeric code, unknown values are mapped to `Unknown`.
+    pub fn from_i32(code: i32) -> Code {
+        usize::try_from(code).ok().and_then(|i| CODES.get(i)).cloned().unwrap_or(Code::Unknown)
+    }
+
+    pub fn as_i32(self) -> i32 {
+        self as i32
+    }
+
+    /// The HTTP status that the REST API uses for this code.
+    pub fn http_status(self) -> u16 {
+        match self {
+            Code::Ok => 200,
+            Code::Cancelled => 499,
+            Code::Unknown | Code::Internal | Code::DataLoss => 500,
+            Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => 400,
+            Code::DeadlineExceeded => 504,
+            Code::NotFound => 404,
+            Code::AlreadyExists | Code::Aborted => 409,
+            Code::PermissionDenied => 403,
+            Code::ResourceExhausted => 429,
+            Code::Unimplemented => 501,
+            Code::Unavailable => 503,
+            Code::Unauthenticated => 401,
+        }
+    }
+
+    /// Whether a request that failed with this code may succeed when it is sent again. Whether it
+    /// is safe to send it again depends on the request, see `RetryPolicy`.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, Code::Aborted
+                       | Code::Unavailable
+                       | Code::DeadlineExceeded
+                       | Code::ResourceExhausted
+                       | Code::Internal)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The connection to the endpoint could not be established or broke down.
+    Io(io::Error),
+
+    /// The endpoint violated the HTTP or gRPC protocol.
+    Transport(String),
+
+    /// The endpoint answered with an HTTP error that is not an API error, e.g. from a proxy.
+    Http { status: u16, body: String },
+
+    Json(serde_json::Error),
+    Proto(proto::Error),
+
+    /// A value could not be converted to or from an entity.
+    Serde(serde_ds::Error),
+
+    /// The API rejected the request.
+    Api { code: Code, message: String },
+
+    /// A request of a namespace-scoped client referred to another namespace, see
+    /// `Client::namespace`. The request was not sent.
+    CrossNamespace { expected: String, found: String },
+
+    /// A long-running operation did not finish within the time that was waited for it. The
+    /// operation itself continues.
+    Timeout(String),
+
+    /// The API answered with a response that breaks its contract, e.g. a lookup that returned
+    /// an entity that was not asked for, or kept deferring keys.
+    UnexpectedResponse(String),
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    pub fn api<M: Into<String>>(code: Code, message: M) -> Error {
+        Error::Api { code, message: message.into() }
+    }
+
+    /// Returns the API error code, if the error was reported by the API.
+    pub fn code(&self) -> Option<Code> {
+        match *self {
+            Error::Api { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Whether the failure is transient, i.e. sending the request again may succeed. Broken
+    /// connections and gateway errors are transient as well as the API codes listed by
+    /// `Code::is_retryable`.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Io(_) => true,
+            Error::Http { status: 429 | 500 | 502 | 503 | 504, .. } => true,
+            Error::Api { code, .. } => code.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl From<proto::Error> for Error {
+    fn from(err: proto::Error) -> Self {
+        Error::Proto(err)
+    }
+}
+
+impl From<serde_ds::Error> for Error {
+    fn from(err: serde_ds::Error) -> Self {
+        Error::Serde(err)
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Self {
+        // Connections that broke down, e.g. pooled connections that the server closed in the
+        // meantime, are I/O errors, so that the request is retried.
+        let kind = if err.is_incomplete_message() {
+            Some(io::ErrorKind::UnexpectedEof)
+        } else if err.is_closed() || err.is_canceled() {
+            Some(io::ErrorKind::ConnectionAborted)
+        } else {
+            io_source(&err).map(io::Error::kind)
+        };
+        match kind {
+            Some(kind) => Error::Io(io::Error::new(kind, err)),
+            None => Error::Transport(err.to_string()),
+        }
+    }
+}
+
+// The I/O error that caused an error, if any.
+fn io_source<'a>(err: &'a (dyn ::std::error::Error + 'static)) -> Option<&'a io::Error> {
+    let mut source = err.source();
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<io::Error>() {
+            return Some(io);
+        }
+        source = err.source();
+    }
+    None
+}
+
+impl From<h2::Error> for Error {
+    fn from(err: h2::Error) -> Self {
+        if err.is_io() {
+            Error::Io(err.into_io().expect("error is an I/O error"))
+        } else {
+            Error::Transport(err.to_string())
+        }
+    }
+}
+
+impl From<http::Error> for Error {
+    fn from(err: http::Error) -> Self {
+        Error::Transport(err.to_string())
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(fmt, "I/O error: {}", err),
+            Error::Transport(ref msg) => write!(fmt, "transport error: {}", msg),
+            Error::Http { status, ref body } => write!(fmt, "HTTP status {}: {}", status, body),
+            Error::Json(ref err) => write!(fmt, "invalid JSON: {}", err),
+            Error::Proto(ref err) => write!(fmt, "invalid protobuf: {}", err),
+            Error::Serde(ref err) => write!(fmt, "entity conversion failed: {}", err),
+            Error::Api { code, ref message } => write!(fmt, "{:?}: {}", code, message),
+            Error::CrossNamespace { ref expected, ref found } =>
+                write!(fmt, "request for namespace {:?} refers to namespace {:?}", expected, found),
+            Error::Timeout(ref msg) => write!(fmt, "timed out: {}", msg),
+            Error::UnexpectedResponse(ref msg) => write!(fmt, "unexpected response: {}", msg),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
@@ -0,0 +1,172 @@
+// gRPC transport: protobuf over HTTP/2.
+//
+// All requests share one HTTP/2 connection, which is established on first use and re-established
+// if it breaks down. Each message is sent with the 5 byte gRPC prefix (compression flag and
+// length), the outcome of a call is reported in the `grpc-status` and `grpc-message` trailers.
+//
+// See https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+
+use bytes::{Buf, Bytes, BytesMut};
+use h2::client::{self, SendRequest};
+use http::{HeaderMap, Request};
+use tokio::sync::Mutex;
+
+use crate::client::{BoxFuture, Call, Code, Endpoint, Error, Method, Result};
+use crate::proto::v1::{self, Message};
+
+const SERVICE: &str = "google.datastore.v1.Datastore";
+
+/// Sends requests to the gRPC API.
+pub struct GrpcTransport {
+    endpoint: Endpoint,
+    access_token: Option<String>,
+    connection: Mutex<Option<SendRequest<Bytes>>>,
+}
+
+impl GrpcTransport {
+    pub fn new(endpoint: Endpoint, access_token: Option<String>) -> GrpcTransport {
+        GrpcTransport { endpoint, access_token, connection: Mutex::new(None) }
+    }
+
+    async fn connect(&self) -> Result<SendRequest<Bytes>> {
+        let stream = self.endpoint.connect(b"h2").await?;
+        let (sender, connection) = client::handshake(stream).await?;
+        tokio::spawn(async move {
+            // Errors of the connection surface in the requests that use it.
+            let _ = connection.await;
+        });
+        Ok(sender)
+    }
+
+    /// Returns a sender that is ready for a new request, reconnecting if the cached connection
+    /// is gone.
+    async fn sender(&self) -> Result<SendRequest<Bytes>> {
+        let mut connection = self.connection.lock().await;
+
+        if let Some(sender) = connection.take() {
+            if let Ok(sender) = sender.ready().await {
+                *connection = Some(sender.clone());
+                return Ok(sender);
+            }
+        }
+
+        let sender = self.connect().await?.ready().await?;
+        *connection = Some(sender.clone());
+        Ok(sender)
+    }
+
+    async fn send<M: Method>(&self, request: M) -> Result<M::Response> {
+        let message = v1::encode(&request)?;
+
+        let uri = format!("{}://{}/{}/{}",
+                          self.endpoint.scheme(), self.endpoint.authority(), SERVICE, M::GRPC);
+        let mut builder = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/grpc")
+            .header("te", "trailers")
+            .header("x-goog-request-params", format!("project_id={}", request.project_id()));
+        if let Some(ref token) = self.access_token {
+            builder = builder.header("authorization", format!("Bearer {}", token));
+        }
+        let http_request = builder.body(())?;
+
+        let mut sender = self.sender().await?;
+        let (response, mut stream) = sender.send_request(http_request, false)?;
+        stream.send_data(frame(&message), true)?;
+
+        let response = response.await?;
+        let status = response.status();
+        let (parts, mut body) = response.into_parts();
+
+        // A call that fails immediately is answered with the status in the headers only.
+        if let Some(result) = status_of(&parts.headers) {
+            result?;
+        }
+        if status != http::StatusCode::OK {
+            return Err(Error::Http { status: status.as_u16(), body: String::new() });
+        }
+
+        let mut data = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            body.flow_control().release_capacity(chunk.len())?;
+            data.extend_from_slice(&chunk);
+        }
+
+        let trailers = body.trailers().await?.unwrap_or_default();
+        status_of(&trailers)
+            .unwrap_or_else(|| Err(Error::Transport("response has no grpc-status".to_string())))?;
+
+        let message = unframe(data.freeze())?;
+        Ok(M::Response::decode(&message)?)
+    }
+}
+
+impl Call for GrpcTransport {
+    fn call<M: Method>(&self, request: M) -> BoxFuture<'_, Result<M::Response>> {
+        Box::pin(self.send(request))
+    }
+}
+
+/// Prefixes a message with the uncompressed flag and its length.
+pub(crate) fn frame(message: &[u8]) -> Bytes {
+    let mut framed = BytesMut::with_capacity(message.len() + 5);
+    framed.extend_from_slice(&[0]);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed.freeze()
+}
+
+/// Extracts the single message of a unary call from its framed representation.
+pub(crate) fn unframe(mut data: Bytes) -> Result<Bytes> {
+    if data.len() < 5 {
+        return Err(Error::Transport("truncated gRPC message".to_string()));
+    }
+    if data.get_u8() != 0 {
+        return Err(Error::Transport("compressed gRPC messages are not supported".to_string()));
+    }
+
+    let length = data.get_u32() as usize;
+    if data.len() != length {
+        return Err(Error::Transport("gRPC message length mismatch".to_string()));
+    }
+    Ok(data)
+}
+
+/// Reads the outcome of a call from `grpc-status` and `grpc-message`, if present.
+fn status_of(headers: &HeaderMap) -> Option<Result<()>> {
+    let status = headers.get("grpc-status")?;
+    let code = status.to_str().ok().and_then(|s| s.parse().ok()).map(Code::from_i32)
+        .unwrap_or(Code::Unknown);
+    if code == Code::Ok {
+        return Some(Ok(()));
+    }
+
+    let message = headers.get("grpc-message")
+        .map(|m| decode_message(m.as_bytes()))
+        .unwrap_or_default();
+    Some(Err(Error::api(code, message)))
+}
+
+/// Decodes a percent-encoded `grpc-message`, leaving invalid escapes as they are.
+pub(crate) fn decode_message(message: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(message.len());
+    let mut i = 0;
+    while i < message.len() {
+        let escaped = message.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(b) if message[i] == b'%' => {
+                decoded.push(b);
+                i += 3;
+            }
+            _ => {
+                decoded.push(message[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,149 @@
+// Pre-allocated IDs.
+//
+// An `IdPool` keeps a buffer of allocated IDs per kind and parent, so that new entities can get
+// complete keys up front, e.g. to build the keys of their children, without a round trip each.
+// When a buffer runs low it is refilled by a background task on the current tokio runtime. Only
+// taking an ID from an empty buffer waits for an allocation, which is shared by all callers that
+// find the buffer empty while it is in flight.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::sync::Notify;
+
+use crate::client::{Client, Code, Error, Result};
+use crate::datastore::{Key, PartitionId, PathElement};
+
+/// Number of IDs that are allocated at once by default.
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+#[derive(Default)]
+struct Buffer {
+    keys: VecDeque<Key>,
+    refilling: bool,
+    // Woken once a refill finished, whether it succeeded or not.
+    refilled: Arc<Notify>,
+}
+
+struct Inner {
+    client: Client,
+    batch_size: usize,
+    low_water: usize,
+    // Buffers by the incomplete key that their keys complete.
+    buffers: Mutex<HashMap<Key, Buffer>>,
+}
+
+/// Hands out complete keys with pre-allocated IDs. Clones share the buffers.
+#[derive(Clone)]
+pub struct IdPool {
+    inner: Arc<Inner>,
+}
+
+impl IdPool {
+    /// Creates a pool that allocates 100 IDs at once.
+    pub fn new(client: Client) -> IdPool {
+        IdPool::with_batch_size(client, DEFAULT_BATCH_SIZE)
+    }
+
+    /// Creates a pool that allocates `batch_size` IDs at once and refills a buffer once fewer
+    /// than a fifth of that remain, and at the latest once it is empty.
+    pub fn with_batch_size(client: Client, batch_size: usize) -> IdPool {
+        let batch_size = batch_size.max(1);
+        IdPool {
+            inner: Arc::new(Inner {
+                client,
+                batch_size,
+                low_water: (batch_size / 5).max(1),
+                buffers: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Returns the key of a new entity of `kind`, a child of `parent` if given.
+    pub async fn next_key(&self, kind: &str, parent: Option<&Key>) -> Result<Key> {
+        let element = PathElement::incomplete(kind);
+        let key = match parent {
+            Some(parent) => parent.child(element),
+            None => Key::new(PartitionId::new("", ""), vec![element]),
+        };
+        self.complete(&key).await
+    }
+
+    /// Completes an incomplete key with a pre-allocated ID. If the buffer is empty, waits for the
+    /// refill that is in flight or starts one, and reports its failure.
+    pub async fn complete(&self, key: &Key) -> Result<Key> {
+        if key.is_complete() {
+            return Err(Error::api(Code::InvalidArgument, format!("key {} is already complete", key)));
+        }
+
+        loop {
+            let refilled = {
+                let mut buffers = self.inner.buffers();
+                let buffer = buffers.entry(key.clone()).or_default();
+                if let Some(complete) = buffer.keys.pop_front() {
+                    self.refill_if_low(key, buffer);
+                    return Ok(complete);
+                }
+                if buffer.refilling {
+                    // Created while the lock is held, so that the wakeup can not be missed.
+                    Some(buffer.refilled.clone().notified_owned())
+                } else {
+                    buffer.refilling = true;
+                    None
+                }
+            };
+
+            match refilled {
+                Some(refilled) => refilled.await,
+                None => {
+                    // Spawned, so that the waiters are woken even if this call is cancelled.
+                    let refill = tokio::spawn(refill(self.inner.clone(), key.clone()));
+                    refill.await
+                        .map_err(|err| Error::UnexpectedResponse(format!("refill task failed: {}", err)))??;
+                }
+            }
+        }
+    }
+
+    /// The number of buffered IDs for an incomplete key.
+    pub fn available(&self, key: &Key) -> usize {
+        self.inner.buffers().get(key).map_or(0, |buffer| buffer.keys.len())
+    }
+
+    fn refill_if_low(&self, key: &Key, buffer: &mut Buffer) {
+        if buffer.keys.len() < self.inner.low_water && !buffer.refilling {
+            buffer.refilling = true;
+            // Failed refills are not reported here, the next caller that finds the buffer empty
+            // starts another one and reports its failure.
+            tokio::spawn(refill(self.inner.clone(), key.clone()));
+        }
+    }
+}
+
+impl Inner {
+    fn buffers(&self) -> MutexGuard<'_, HashMap<Key, Buffer>> {
+        self.buffers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    async fn allocate(&self, key: &Key) -> Result<Vec<Key>> {
+        self.client.allocate_keys(&vec![key.clone(); self.batch_size]).await
+    }
+}
+
+// Allocates a batch for the buffer of `key`, which must have been marked as refilling, and wakes
+// the callers waiting for it.
+async fn refill(inner: Arc<Inner>, key: Key) -> Result<()> {
+    let result = match inner.allocate(&key).await {
+        Ok(ref keys) if keys.is_empty() => {
+            Err(Error::UnexpectedResponse("allocateIds returned no keys".to_string()))
+        }
+        result => result,
+    };
+    let mut buffers = inner.buffers();
+    let buffer = buffers.entry(key).or_default();
+    buffer.refilling = false;
+    // The waiters only look at the buffer once the lock is released.
+    buffer.refilled.notify_waiters();
+    buffer.keys.extend(result?);
+    Ok(())
+}
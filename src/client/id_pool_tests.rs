@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::client::*;
+use crate::client::stand_in;
+use crate::datastore::*;
+
+const PROJECT: &str = "test-project";
+
+fn incomplete(kind: &str) -> Key {
+    Key::new(PartitionId::new("", ""), vec![PathElement::incomplete(kind)])
+}
+
+fn user(id: i64) -> Key {
+    Key::new(PartitionId::new("", ""), vec![PathElement::from_id("User", id)])
+}
+
+// ID allocation, the same expectations hold for every transport.
+async fn allocation(client: &Client) {
+    let order = user(1).child(PathElement::incomplete("Order"));
+    let keys = client.allocate_keys(&[incomplete("User"), order.clone(), incomplete("User")]).await
+        .unwrap();
+    assert_eq!(3, keys.len());
+    assert!(keys.iter().all(Key::is_complete));
+    assert_eq!(Some("Order"), keys[1].kind());
+    assert_eq!(user(1).path(), &keys[1].path()[..1]);
+    assert_ne!(keys[0], keys[2]);
+
+    // Reserved IDs are skipped.
+    let next = keys[2].path()[0].id().unwrap() + 1;
+    client.reserve_keys(&[user(next)]).await.unwrap();
+    let key = client.allocate_keys(&[incomplete("User")]).await.unwrap().remove(0);
+    assert_ne!(Some(next), key.path()[0].id());
+
+    // Complete keys can not be allocated, incomplete ones not reserved.
+    let err = client.allocate_keys(&[user(5)]).await.unwrap_err();
+    assert_eq!(Some(Code::InvalidArgument), err.code());
+    let err = client.reserve_keys(&[incomplete("User")]).await.unwrap_err();
+    assert_eq!(Some(Code::InvalidArgument), err.code());
+
+    // Inserting an entity with an incomplete key allocates an ID.
+    let response = client.commit(CommitRequest {
+        project_id: String::new(),
+        mode: CommitMode::NonTransactional,
+        transaction: None,
+        mutations: vec![Mutation::Insert(Entity {
+            key: Some(incomplete("User")),
+            properties: Default::default(),
+        })],
+    }).await.unwrap();
+    let key = response.mutation_results[0].key.clone().expect("No key allocated");
+    assert!(key.is_complete());
+    assert!(client.get_entity(&key).await.unwrap().is_some());
+
+    // Allocated IDs do not collide with the keys of other mutations of the same commit.
+    let next = user(key.path()[0].id().unwrap() + 1);
+    let entity = |key: Key| Entity { key: Some(key), properties: Default::default() };
+    let response = client.commit(CommitRequest {
+        project_id: String::new(),
+        mode: CommitMode::NonTransactional,
+        transaction: None,
+        mutations: vec![Mutation::Upsert(entity(next.clone())), Mutation::Upsert(entity(incomplete("User")))],
+    }).await.unwrap();
+    let allocated = response.mutation_results[1].key.clone().expect("No key allocated");
+    assert_ne!(next.path(), allocated.path());
+}
+
+#[tokio::test]
+async fn test_allocation() {
+    allocation(&Client::new(PROJECT, MemoryBackend::new())).await;
+
+    let stand_in = stand_in::rest(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT).endpoint(stand_in.endpoint()).build().unwrap();
+    allocation(&client).await;
+
+    let stand_in = stand_in::grpc(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Grpc)
+        .build()
+        .unwrap();
+    allocation(&client).await;
+}
+
+#[tokio::test]
+async fn test_id_pool() {
+    let pool = IdPool::with_batch_size(Client::new(PROJECT, MemoryBackend::new()), 10);
+    let mut seen = HashSet::new();
+    for _ in 0..25 {
+        let key = pool.next_key("User", None).await.unwrap();
+        assert!(key.is_complete());
+        assert!(seen.insert(key));
+    }
+
+    // Each kind and parent has its own buffer.
+    let order = pool.next_key("Order", Some(&user(1))).await.unwrap();
+    assert_eq!(Some(user(1).path()), order.parent().as_ref().map(Key::path));
+    assert_eq!(9, pool.available(&user(1).child(PathElement::incomplete("Order"))));
+
+    // Running low starts a refill in the background.
+    let key = incomplete("Invoice");
+    pool.complete(&key).await.unwrap();
+    for _ in 0..8 {
+        pool.complete(&key).await.unwrap();
+    }
+    assert_eq!(1, pool.available(&key));
+    tokio::task::yield_now().await;
+    assert_eq!(11, pool.available(&key));
+}
+
+#[tokio::test]
+async fn test_small_batches_are_refilled() {
+    let pool = IdPool::with_batch_size(Client::new(PROJECT, MemoryBackend::new()), 3);
+    let key = incomplete("User");
+    pool.complete(&key).await.unwrap();
+    pool.complete(&key).await.unwrap();
+    assert_eq!(1, pool.available(&key));
+    pool.complete(&key).await.unwrap();
+    assert_eq!(0, pool.available(&key));
+    tokio::task::yield_now().await;
+    assert_eq!(3, pool.available(&key));
+
+    // A buffer of a single ID is refilled as soon as it was allocated.
+    let pool = IdPool::with_batch_size(Client::new(PROJECT, MemoryBackend::new()), 1);
+    pool.complete(&key).await.unwrap();
+    tokio::task::yield_now().await;
+    assert_eq!(1, pool.available(&key));
+}
+
+#[tokio::test]
+async fn test_concurrent_callers_share_a_refill() {
+    // Allocations over HTTP are in flight for a while, unlike those of the memory backend.
+    let stand_in = stand_in::rest(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT).endpoint(stand_in.endpoint()).build().unwrap();
+    let pool = IdPool::with_batch_size(client, 10);
+    let key = incomplete("User");
+    let (a, b, c, d) = tokio::join!(pool.complete(&key), pool.complete(&key), pool.complete(&key),
+                                    pool.complete(&key));
+    let keys: HashSet<Key> = vec![a.unwrap(), b.unwrap(), c.unwrap(), d.unwrap()].into_iter().collect();
+    assert_eq!(4, keys.len());
+    assert_eq!(6, pool.available(&key));
+}
+
+#[tokio::test]
+async fn test_complete_keys_are_rejected() {
+    let pool = IdPool::new(Client::new(PROJECT, MemoryBackend::new()));
+    let err = pool.complete(&user(1)).await.unwrap_err();
+    assert_eq!(Some(Code::InvalidArgument), err.code());
+    assert_eq!(0, pool.available(&user(1)));
+}
+
+#[test]
+fn test_incomplete_keys() {
+    let key = incomplete("User");
+    assert!(!key.is_complete());
+    assert!(!Key::new(PartitionId::new("", ""), vec![]).is_complete());
+    assert!(key.with_id(7).is_complete());
+    assert_eq!(user(7), key.with_id(7));
+
+    let json = serde_json::to_string(&key).unwrap();
+    assert_eq!(r#"{"partitionId":{"projectId":""},"path":[{"kind":"User"}]}"#, json);
+    assert_eq!(key, serde_json::from_str(&json).unwrap());
+}
@@ -0,0 +1,104 @@
+// Metadata entities of the in-memory backend.
+//
+// Queries for the pseudo-kinds `__namespace__`, `__kind__` and `__property__` run over entities
+// that are derived from the stored entities when the query runs.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::datastore::metadata::*;
+use crate::datastore::{Entity, Key, PartitionId, Value};
+
+pub fn is_metadata_kind(kind: &str) -> bool {
+    kind == NAMESPACE_KIND || kind == KIND_KIND || kind == PROPERTY_KIND
+}
+
+/// The metadata entities of `kind` in `partition`, derived from all stored entities of the
+/// partition's project. Namespaces are listed in the default namespace only.
+pub fn entities<'a, I>(kind: &str, partition: &PartitionId, stored: I) -> Vec<Entity>
+    where I: Iterator<Item = &'a Entity>
+{
+    let project = partition.project_id();
+    let stored = stored.filter(|entity| entity.key.as_ref()
+        .is_some_and(|key| key.partition_id().project_id() == project));
+    let in_partition = |key: &Key| key.partition_id() == partition;
+
+    let keys: Vec<Key> = match kind {
+        NAMESPACE_KIND if partition.namespace_id().is_empty() => stored
+            .filter_map(|entity| entity.key.as_ref())
+            .map(|key| key.partition_id().namespace_id().to_string())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| Namespace::new(name).key())
+            .collect(),
+        KIND_KIND => stored
+            .filter_map(|entity| entity.key.as_ref().filter(|key| in_partition(key)))
+            .filter_map(|key| key.kind().map(str::to_string))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| Kind::new(partition.namespace_id(), name).key())
+            .collect(),
+        PROPERTY_KIND => {
+            let stored = stored.filter(|entity| entity.key.as_ref().is_some_and(in_partition));
+            return properties(stored, partition);
+        }
+        _ => vec![],
+    };
+
+    keys.into_iter()
+        .map(|key| Entity { key: Some(with_project(key, partition)), properties: HashMap::new() })
+        .collect()
+}
+
+// One entity per indexed property of each kind, listing the representations of its values.
+fn properties<'a, I>(stored: I, partition: &PartitionId) -> Vec<Entity>
+    where I: Iterator<Item = &'a Entity>
+{
+    let mut found: BTreeMap<(String, String), BTreeSet<String>> = BTreeMap::new();
+    for entity in stored {
+        let kind = match entity.key.as_ref().and_then(Key::kind) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        for (name, value) in &entity.properties {
+            collect(&mut found, kind, name, value);
+        }
+    }
+
+    found.into_iter().map(|((kind, name), representations)| {
+        let property = Property {
+            namespace: partition.namespace_id().to_string(),
+            kind,
+            name,
+            representations: vec![],
+        };
+        let representations: Vec<Value> = representations.into_iter().map(Value::from).collect();
+        let mut properties = HashMap::new();
+        properties.insert(PROPERTY_REPRESENTATION.to_string(), Value::from(representations));
+        Entity { key: Some(with_project(property.key(), partition)), properties }
+    }).collect()
+}
+
+// Records the representations of a value's index entries, under dotted names for the properties of
+// embedded entities.
+fn collect(found: &mut BTreeMap<(String, String), BTreeSet<String>>, kind: &str, name: &str,
+           value: &Value) {
+    match *value {
+        Value::Array { ref array_value, .. } => for element in &array_value.values {
+            collect(found, kind, name, element);
+        },
+        Value::EntityValue { ref entity_value, .. } => for (child, value) in &entity_value.properties {
+            collect(found, kind, &format!("{}.{}", name, child), value);
+        },
+        ref value if value.exclude_from_indexes() => {}
+        ref value => if let Some(representation) = Representation::of(value) {
+            found.entry((kind.to_string(), name.to_string()))
+                .or_default()
+                .insert(representation.as_str().to_string());
+        },
+    }
+}
+
+fn with_project(key: Key, partition: &PartitionId) -> Key {
+    let namespace = key.partition_id().namespace_id().to_string();
+    Key::new(PartitionId::new(partition.project_id(), namespace), key.path().to_vec())
+}
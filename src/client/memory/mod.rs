@@ -0,0 +1,474 @@
+// In-memory implementation of the Datastore API, used as a test double and as the backend of local
+// stand-in servers.
+//
+// Transactions use optimistic concurrency control: committing a transaction fails with `ABORTED`
+// if an entity that it read or writes was changed by another commit after the transaction began.
+// Deleted entities are kept as tombstones so that their deletion is noticed as well.
+//
+// IDs are allocated from a single sequence, skipping IDs that are in use or reserved.
+
+use std::collections::{HashMap, HashSet};
+use std::future;
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::client::{BoxFuture, Code, Error, Result, Transport};
+use crate::datastore::{Blob, Entity, Key, PartitionId, PathElement, Value};
+use crate::datastore::query::{AggregationResultBatch, EntityResult, Filter, MoreResultsType, Query,
+                              QueryResultBatch};
+use crate::datastore::rpc::*;
+
+mod metadata;
+pub mod query;
+
+/// Number of keys that a single lookup may contain.
+const MAX_LOOKUP_KEYS: usize = 1000;
+
+struct Record {
+    entity: Option<Entity>,
+    version: i64,
+}
+
+struct Transaction {
+    read_only: bool,
+    snapshot: i64,
+    reads: HashSet<Key>,
+}
+
+#[derive(Default)]
+struct State {
+    entities: HashMap<Key, Record>,
+    version: i64,
+    next_transaction: u64,
+    transactions: HashMap<Vec<u8>, Transaction>,
+    next_id: i64,
+    reserved: HashSet<Key>,
+}
+
+/// A Datastore that keeps all entities in memory.
+#[derive(Default)]
+pub struct MemoryBackend {
+    state: Mutex<State>,
+    lookup_limit: Option<usize>,
+    deferred_lookups: AtomicUsize,
+    empty_batches: AtomicUsize,
+    aborted_commits: AtomicUsize,
+}
+
+// Decrements a counter unless it is zero, returning whether it was decremented.
+fn count_down(counter: &AtomicUsize) -> bool {
+    counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok()
+}
+
+fn invalid<M: Into<String>>(message: M) -> Error {
+    Error::api(Code::InvalidArgument, message)
+}
+
+/// Checks that a key is complete and belongs to the project. Keys without a project ID refer to
+/// the project of the request.
+fn check_key(key: &Key, project_id: &str) -> Result<Key> {
+    if key.path().is_empty() {
+        return Err(invalid("key path is empty"));
+    }
+    if !key.is_complete() {
+        return Err(invalid("key path is incomplete"));
+    }
+    check_partition(key, project_id)
+}
+
+/// Checks a key for ID allocation, whose last element must be incomplete.
+fn check_incomplete_key(key: &Key, project_id: &str) -> Result<Key> {
+    match key.path().split_last() {
+        Some((last, ancestors)) if !last.is_complete() && ancestors.iter().all(PathElement::is_complete) =>
+            check_partition(key, project_id),
+        _ => Err(invalid("keys for ID allocation must be incomplete")),
+    }
+}
+
+fn check_partition(key: &Key, project_id: &str) -> Result<Key> {
+    let partition = key.partition_id();
+    if partition.project_id().is_empty() {
+        let partition = PartitionId::new(project_id, partition.namespace_id());
+        return Ok(Key::new(partition, key.path().to_vec()));
+    }
+    if partition.project_id() != project_id {
+        return Err(invalid(format!("key belongs to project {}", partition.project_id())));
+    }
+
+    Ok(key.clone())
+}
+
+/// Checks the key values that a filter compares with, like `check_key`.
+fn check_filter(filter: &mut Filter, project_id: &str) -> Result<()> {
+    match *filter {
+        Filter::CompositeFilter(ref mut composite) => {
+            for filter in &mut composite.filters {
+                check_filter(filter, project_id)?;
+            }
+        }
+        Filter::PropertyFilter(ref mut filter) => {
+            if let Value::KeyValue { ref mut key_value, .. } = filter.value {
+                *key_value = check_key(key_value, project_id)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl State {
+    fn begin(&mut self, options: Option<&TransactionOptions>) -> Blob {
+        self.next_transaction += 1;
+        let id = format!("transaction-{}", self.next_transaction).into_bytes();
+        self.transactions.insert(id.clone(), Transaction {
+            read_only: matches!(options, Some(&TransactionOptions::ReadOnly(_))),
+            snapshot: self.version,
+            reads: HashSet::new(),
+        });
+        Blob(id)
+    }
+
+    // Resolves the transaction a read takes part in, beginning a new one if requested. Returns the
+    // transaction ID and whether it was newly created.
+    fn read_transaction(&mut self, options: Option<&ReadOptions>) -> Result<Option<(Blob, bool)>> {
+        match options {
+            None | Some(&ReadOptions::ReadConsistency(_)) => Ok(None),
+            Some(ReadOptions::Transaction(id)) => {
+                if !self.transactions.contains_key(&id.0) {
+                    return Err(invalid("unknown transaction"));
+                }
+                Ok(Some((id.clone(), false)))
+            }
+            Some(ReadOptions::NewTransaction(options)) => Ok(Some((self.begin(Some(options)), true))),
+            Some(&ReadOptions::ReadTime(_)) => Err(Error::api(
+                Code::Unimplemented, "reads at a timestamp are not supported by the in-memory backend")),
+        }
+    }
+
+    fn record_reads<'a, I: Iterator<Item = &'a Key>>(&mut self, transaction: &Option<(Blob, bool)>, keys: I) {
+        if let Some((ref id, _)) = *transaction {
+            if let Some(transaction) = self.transactions.get_mut(&id.0) {
+                transaction.reads.extend(keys.cloned());
+            }
+        }
+    }
+
+    fn lookup(&mut self, request: LookupRequest, limit: Option<usize>) -> Result<LookupResponse> {
+        if request.keys.len() > MAX_LOOKUP_KEYS {
+            return Err(invalid(format!("a lookup may contain at most {} keys", MAX_LOOKUP_KEYS)));
+        }
+        let transaction = self.read_transaction(request.read_options.as_ref())?;
+        let keys = request.keys.iter()
+            .map(|key| check_key(key, &request.project_id))
+            .collect::<Result<Vec<_>>>()?;
+        self.record_reads(&transaction, keys.iter());
+
+        let mut response = LookupResponse::default();
+        let limit = limit.unwrap_or(keys.len());
+        for (i, key) in keys.into_iter().enumerate() {
+            if i >= limit {
+                response.deferred.push(key);
+                continue;
+            }
+            match self.entities.get(&key) {
+                Some(&Record { entity: Some(ref entity), version }) => response.found.push(EntityResult {
+                    entity: entity.clone(),
+                    version: Some(version.into()),
+                    cursor: None,
+                }),
+                _ => response.missing.push(EntityResult {
+                    entity: Entity { key: Some(key), properties: HashMap::new() },
+                    version: Some(self.version.into()),
+                    cursor: None,
+                }),
+            }
+        }
+
+        response.transaction = transaction.and_then(|(id, new)| if new { Some(id) } else { None });
+        Ok(response)
+    }
+
+    // Runs a query in the partition of a request, returning the results and the transaction that
+    // the query took part in.
+    fn query(&mut self, project_id: &str, partition_id: Option<&PartitionId>,
+             read_options: Option<&ReadOptions>, mut query: Query)
+             -> Result<(QueryResultBatch, Option<(Blob, bool)>)> {
+        if let Some(ref mut filter) = query.filter {
+            check_filter(filter, project_id)?;
+        }
+
+        let transaction = self.read_transaction(read_options)?;
+        let namespace = partition_id.map(PartitionId::namespace_id).unwrap_or("");
+        let partition = PartitionId::new(project_id, namespace);
+
+        let kind = query.kind.first().map_or("", |kind| kind.name.as_str());
+        let derived;
+        let candidates = if metadata::is_metadata_kind(kind) {
+            let stored = self.entities.values().filter_map(|record| record.entity.as_ref());
+            derived = metadata::entities(kind, &partition, stored);
+            derived.iter().map(|entity| query::Candidate { entity, version: self.version }).collect()
+        } else {
+            self.entities.iter()
+                .filter(|(key, _)| *key.partition_id() == partition)
+                .filter_map(|(_, record)| record.entity.as_ref().map(|entity| query::Candidate {
+                    entity,
+                    version: record.version,
+                }))
+                .collect()
+        };
+
+        let mut batch = query::run(candidates, &query)?;
+        batch.snapshot_version = Some(self.version.into());
+        self.record_reads(&transaction,
+                          batch.entity_results.iter().filter_map(|r| r.entity.key.as_ref()));
+        Ok((batch, transaction))
+    }
+
+    fn run_query(&mut self, request: RunQueryRequest, empty: bool) -> Result<RunQueryResponse> {
+        if request.gql_query.is_some() {
+            return Err(Error::api(Code::Unimplemented,
+                                  "GQL queries are not supported by the in-memory backend"));
+        }
+        let query = request.query.ok_or_else(|| invalid("a query is required"))?;
+        let start_cursor = query.start_cursor.clone();
+        let (mut batch, transaction) = self.query(&request.project_id, request.partition_id.as_ref(),
+                                                  request.read_options.as_ref(), query)?;
+        if empty {
+            // Continuing from the end cursor runs the same query again.
+            batch = QueryResultBatch {
+                skipped_results: 0,
+                skipped_cursor: None,
+                entity_results: vec![],
+                end_cursor: Some(start_cursor.unwrap_or_else(|| query::encode_cursor(0))),
+                more_results: MoreResultsType::NotFinished,
+                ..batch
+            };
+        }
+
+        Ok(RunQueryResponse {
+            batch,
+            query: None,
+            transaction: transaction.and_then(|(id, new)| if new { Some(id) } else { None }),
+        })
+    }
+
+    fn run_aggregation_query(&mut self, request: RunAggregationQueryRequest)
+                             -> Result<RunAggregationQueryResponse> {
+        if request.gql_query.is_some() {
+            return Err(Error::api(Code::Unimplemented,
+                                  "GQL queries are not supported by the in-memory backend"));
+        }
+        let query = request.aggregation_query.ok_or_else(|| invalid("a query is required"))?;
+        let (batch, transaction) = self.query(&request.project_id, request.partition_id.as_ref(),
+                                              request.read_options.as_ref(), query.nested_query)?;
+
+        Ok(RunAggregationQueryResponse {
+            batch: AggregationResultBatch {
+                aggregation_results: vec![query::aggregate(&batch.entity_results, &query.aggregations)?],
+                more_results: MoreResultsType::NoMoreResults,
+                read_time: None,
+            },
+            query: None,
+            transaction: transaction.and_then(|(id, new)| if new { Some(id) } else { None }),
+        })
+    }
+
+    fn commit(&mut self, request: CommitRequest) -> Result<CommitResponse> {
+        let transaction = match (request.mode, request.transaction) {
+            (CommitMode::Transactional, Some(id)) => Some(
+                self.transactions.remove(&id.0).ok_or_else(|| invalid("unknown transaction"))?),
+            (CommitMode::Transactional, None) =>
+                return Err(invalid("transactional commits require a transaction")),
+            (CommitMode::NonTransactional, Some(_)) =>
+                return Err(invalid("non-transactional commits must not specify a transaction")),
+            (CommitMode::NonTransactional, None) => None,
+        };
+
+        // IDs allocated for incomplete keys must not collide with the keys of other mutations.
+        let project_id = &request.project_id;
+        let named: HashSet<Key> = request.mutations.iter()
+            .filter_map(|mutation| match *mutation {
+                Mutation::Insert(ref entity) | Mutation::Update(ref entity) | Mutation::Upsert(ref entity) =>
+                    entity.key.as_ref(),
+                Mutation::Delete(ref key) => Some(key),
+            })
+            .filter(|key| key.is_complete())
+            .filter_map(|key| check_partition(key, project_id).ok())
+            .collect();
+
+        // All mutations are validated before any of them is applied, commits are atomic.
+        let mut writes: Vec<(Key, Option<Entity>, bool)> = vec![];
+        for mutation in request.mutations {
+            let (key, entity, exists) = match mutation {
+                Mutation::Insert(entity) => (entity.key.clone(), Some(entity), Some(false)),
+                Mutation::Update(entity) => (entity.key.clone(), Some(entity), Some(true)),
+                Mutation::Upsert(entity) => (entity.key.clone(), Some(entity), None),
+                Mutation::Delete(key) => (Some(key), None, None),
+            };
+
+            // Inserted entities with incomplete keys get a newly allocated ID.
+            let key = key.ok_or_else(|| invalid("entity has no key"))?;
+            let allocated = !key.is_complete() && entity.is_some() && exists != Some(true);
+            let key = if allocated {
+                let key = check_incomplete_key(&key, &request.project_id)?;
+                self.allocate_id(&key, &named)
+            } else {
+                check_key(&key, &request.project_id)?
+            };
+            if writes.iter().any(|(k, _, _)| *k == key) {
+                return Err(invalid("a commit may not contain multiple mutations of the same entity"));
+            }
+
+            let stored = self.entities.get(&key).is_some_and(|r| r.entity.is_some());
+            match exists {
+                Some(false) if stored => return Err(Error::api(Code::AlreadyExists, "entity already exists")),
+                Some(true) if !stored => return Err(Error::api(Code::NotFound, "no entity to update")),
+                _ => {}
+            }
+
+            let entity = entity.map(|entity| Entity { key: Some(key.clone()), ..entity });
+            writes.push((key, entity, allocated));
+        }
+
+        if let Some(transaction) = transaction {
+            if transaction.read_only && !writes.is_empty() {
+                return Err(invalid("read-only transactions can not write"));
+            }
+
+            let changed = transaction.reads.iter().chain(writes.iter().map(|(k, _, _)| k))
+                .any(|key| self.entities.get(key).is_some_and(|r| r.version > transaction.snapshot));
+            if changed {
+                return Err(Error::api(Code::Aborted, "too much contention on these datastore entities"));
+            }
+        }
+
+        self.version += 1;
+        let version = self.version;
+        let mut mutation_results = vec![];
+        for (key, entity, allocated) in writes {
+            self.entities.insert(key.clone(), Record { entity, version });
+            mutation_results.push(MutationResult {
+                key: if allocated { Some(key) } else { None },
+                version: version.into(),
+                conflict_detected: false,
+            });
+        }
+
+        Ok(CommitResponse { mutation_results, index_updates: 0, commit_time: None })
+    }
+
+    fn rollback(&mut self, request: RollbackRequest) -> Result<RollbackResponse> {
+        self.transactions.remove(&request.transaction.0).ok_or_else(|| invalid("unknown transaction"))?;
+        Ok(RollbackResponse {})
+    }
+
+    fn allocate_ids(&mut self, request: AllocateIdsRequest) -> Result<AllocateIdsResponse> {
+        let keys = request.keys.iter()
+            .map(|key| check_incomplete_key(key, &request.project_id))
+            .collect::<Result<Vec<_>>>()?;
+        let taken = HashSet::new();
+        Ok(AllocateIdsResponse { keys: keys.iter().map(|key| self.allocate_id(key, &taken)).collect() })
+    }
+
+    fn reserve_ids(&mut self, request: ReserveIdsRequest) -> Result<ReserveIdsResponse> {
+        let keys = request.keys.iter()
+            .map(|key| check_key(key, &request.project_id))
+            .collect::<Result<Vec<_>>>()?;
+        if keys.iter().any(|key| key.path().last().and_then(PathElement::id).is_none()) {
+            return Err(invalid("only numeric IDs can be reserved"));
+        }
+        self.reserved.extend(keys);
+        Ok(ReserveIdsResponse {})
+    }
+
+    // Completes an incomplete key with an ID that is neither used, reserved nor `taken`.
+    fn allocate_id(&mut self, key: &Key, taken: &HashSet<Key>) -> Key {
+        loop {
+            self.next_id += 1;
+            let key = key.with_id(self.next_id);
+            if !self.entities.contains_key(&key) && !self.reserved.contains(&key) && !taken.contains(&key) {
+                return key;
+            }
+        }
+    }
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+
+    /// Answers at most `limit` keys of every lookup and defers the others, as the API does when
+    /// a response would get too large.
+    pub fn with_lookup_limit(mut self, limit: usize) -> MemoryBackend {
+        self.lookup_limit = Some(limit);
+        self
+    }
+
+    /// Defers all keys of the first `count` lookups.
+    pub fn with_deferred_lookups(self, count: usize) -> MemoryBackend {
+        self.deferred_lookups.store(count, Ordering::SeqCst);
+        self
+    }
+
+    /// Answers the first `count` queries with an empty batch that is not finished, as the API does
+    /// when a query scanned many entities without finding a result.
+    pub fn with_empty_batches(self, count: usize) -> MemoryBackend {
+        self.empty_batches.store(count, Ordering::SeqCst);
+        self
+    }
+
+    /// Fails the first `count` commits of transactions with `ABORTED`, as if another commit had
+    /// changed an entity that they read.
+    pub fn with_aborted_commits(self, count: usize) -> MemoryBackend {
+        self.aborted_commits.store(count, Ordering::SeqCst);
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // A panic while holding the lock can not leave the state inconsistent, as every operation
+        // validates its input before modifying anything.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Transport for MemoryBackend {
+    fn lookup(&self, request: LookupRequest) -> BoxFuture<'_, Result<LookupResponse>> {
+        let limit = if count_down(&self.deferred_lookups) { Some(0) } else { self.lookup_limit };
+        Box::pin(future::ready(self.state().lookup(request, limit)))
+    }
+
+    fn run_query(&self, request: RunQueryRequest) -> BoxFuture<'_, Result<RunQueryResponse>> {
+        Box::pin(future::ready(self.state().run_query(request, count_down(&self.empty_batches))))
+    }
+
+    fn run_aggregation_query(&self, request: RunAggregationQueryRequest)
+                             -> BoxFuture<'_, Result<RunAggregationQueryResponse>> {
+        Box::pin(future::ready(self.state().run_aggregation_query(request)))
+    }
+
+    fn begin_transaction(&self, request: BeginTransactionRequest)
+                         -> BoxFuture<'_, Result<BeginTransactionResponse>> {
+        let transaction = self.state().begin(request.transaction_options.as_ref());
+        Box::pin(future::ready(Ok(BeginTransactionResponse { transaction })))
+    }
+
+    fn commit(&self, request: CommitRequest) -> BoxFuture<'_, Result<CommitResponse>> {
+        if request.transaction.is_some() && count_down(&self.aborted_commits) {
+            return Box::pin(future::ready(Err(Error::api(Code::Aborted, "too much contention"))));
+        }
+        Box::pin(future::ready(self.state().commit(request)))
+    }
+
+    fn rollback(&self, request: RollbackRequest) -> BoxFuture<'_, Result<RollbackResponse>> {
+        Box::pin(future::ready(self.state().rollback(request)))
+    }
+
+    fn allocate_ids(&self, request: AllocateIdsRequest)
+                    -> BoxFuture<'_, Result<AllocateIdsResponse>> {
+        Box::pin(future::ready(self.state().allocate_ids(request)))
+    }
+
+    fn reserve_ids(&self, request: ReserveIdsRequest) -> BoxFuture<'_, Result<ReserveIdsResponse>> {
+        Box::pin(future::ready(self.state().reserve_ids(request)))
+    }
+}
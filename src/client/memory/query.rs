@@ -0,0 +1,496 @@
+// Query evaluation for the in-memory backend.
+//
+// Values are compared like Datastore orders them in its indexes: first by type (null, integers and
+// timestamps, booleans, blobs, strings, doubles, geo points, keys), then by value. Inequality
+// filters only match values of the same type. Array properties match a filter if any of their
+// elements matches, and are sorted by their smallest (ascending) or largest (descending) element.
+// Unindexed values are invisible to queries.
+//
+// Projection queries return values as they are stored in indexes, one row per index entry.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::client::{Code, Error, Result};
+use crate::datastore::{meaning, Blob, Entity, Key, PathElement, Value, ValueMeta};
+use crate::datastore::query::*;
+
+/// A stored entity together with its version.
+pub struct Candidate<'a> {
+    pub entity: &'a Entity,
+    pub version: i64,
+}
+
+fn type_rank(value: &Value) -> u8 {
+    match *value {
+        Value::Null { .. } => 0,
+        Value::Integer { .. } | Value::Timestamp { .. } => 1,
+        Value::Boolean { .. } => 2,
+        Value::Blob { .. } => 3,
+        Value::String { .. } => 4,
+        Value::Double { .. } => 5,
+        Value::GeoPoint { .. } => 6,
+        Value::KeyValue { .. } => 7,
+        Value::EntityValue { .. } => 8,
+        Value::Array { .. } => 9,
+    }
+}
+
+// Integers and timestamps share an index range, timestamps are compared as microseconds.
+fn fixed_point(value: &Value) -> i64 {
+    match *value {
+        Value::Integer { ref integer_value, .. } => integer_value.parse().unwrap_or(0),
+        Value::Timestamp { ref timestamp_value, .. } => timestamp_value.timestamp_micros(),
+        _ => 0,
+    }
+}
+
+fn compare_path_elements(a: &PathElement, b: &PathElement) -> Ordering {
+    a.kind().cmp(b.kind()).then_with(|| match (a.id(), b.id(), a.name(), b.name()) {
+        (Some(a), Some(b), _, _) => a.cmp(&b),
+        (Some(_), None, _, _) => Ordering::Less,
+        (None, Some(_), _, _) => Ordering::Greater,
+        (None, None, a, b) => a.cmp(&b),
+    })
+}
+
+/// Orders keys by partition and then element-wise by path, ancestors before their descendants.
+pub fn compare_keys(a: &Key, b: &Key) -> Ordering {
+    let pa = a.partition_id();
+    let pb = b.partition_id();
+    pa.project_id().cmp(pb.project_id())
+        .then_with(|| pa.namespace_id().cmp(pb.namespace_id()))
+        .then_with(|| {
+            a.path().iter().zip(b.path())
+                .map(|(a, b)| compare_path_elements(a, b))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or_else(|| a.path().len().cmp(&b.path().len()))
+        })
+}
+
+/// Compares two values, ignoring their meaning and index exclusion.
+pub fn compare_values(a: &Value, b: &Value) -> Ordering {
+    let rank = type_rank(a).cmp(&type_rank(b));
+    if rank != Ordering::Equal {
+        return rank;
+    }
+
+    match (a, b) {
+        (&Value::Null { .. }, _) => Ordering::Equal,
+        (&Value::Integer { .. }, _) | (&Value::Timestamp { .. }, _) =>
+            fixed_point(a).cmp(&fixed_point(b)),
+        (&Value::Boolean { boolean_value: a, .. }, &Value::Boolean { boolean_value: b, .. }) =>
+            a.cmp(&b),
+        (&Value::Blob { blob_value: Blob(ref a), .. }, &Value::Blob { blob_value: Blob(ref b), .. }) =>
+            a.cmp(b),
+        (Value::String { string_value: a, .. }, Value::String { string_value: b, .. }) =>
+            a.cmp(b),
+        (&Value::Double { double_value: a, .. }, &Value::Double { double_value: b, .. }) =>
+            a.total_cmp(&b),
+        (Value::GeoPoint { geo_point_value: a, .. },
+         Value::GeoPoint { geo_point_value: b, .. }) =>
+            a.latitude().total_cmp(&b.latitude())
+                .then_with(|| a.longitude().total_cmp(&b.longitude())),
+        (Value::KeyValue { key_value: a, .. }, Value::KeyValue { key_value: b, .. }) =>
+            compare_keys(a, b),
+        (Value::EntityValue { entity_value: a, .. },
+         Value::EntityValue { entity_value: b, .. }) => compare_entities(a, b),
+        (Value::Array { array_value: a, .. }, Value::Array { array_value: b, .. }) =>
+            a.values.iter().zip(&b.values)
+                .map(|(a, b)| compare_values(a, b))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or_else(|| a.values.len().cmp(&b.values.len())),
+        _ => unreachable!("values of the same rank have the same type"),
+    }
+}
+
+fn compare_entities(a: &Entity, b: &Entity) -> Ordering {
+    let mut names_a: Vec<&String> = a.properties.keys().collect();
+    let mut names_b: Vec<&String> = b.properties.keys().collect();
+    names_a.sort();
+    names_b.sort();
+
+    names_a.cmp(&names_b).then_with(|| {
+        names_a.iter()
+            .map(|name| compare_values(&a.properties[*name], &b.properties[*name]))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// Returns the indexed values of a property, with array properties flattened into their elements.
+/// Properties of embedded entities are addressed with dotted paths, e.g. `address.city`.
+pub fn indexed_values(entity: &Entity, name: &str) -> Vec<Value> {
+    if name == KEY_PROPERTY {
+        return entity.key.iter().cloned().map(Value::from).collect();
+    }
+
+    let mut values = vec![];
+    collect_values(entity, name, &mut values);
+    values
+}
+
+fn collect_values(entity: &Entity, name: &str, out: &mut Vec<Value>) {
+    let (head, rest) = match entity.properties.get(name) {
+        Some(value) => (value, None),
+        None => match name.find('.') {
+            Some(pos) => match entity.properties.get(&name[..pos]) {
+                Some(value) => (value, Some(&name[pos + 1..])),
+                None => return,
+            },
+            None => return,
+        },
+    };
+
+    let elements = match *head {
+        Value::Array { ref array_value, .. } => array_value.values.iter().collect(),
+        ref value => vec![value],
+    };
+
+    for element in elements {
+        match (rest, element) {
+            (None, value) if !value.exclude_from_indexes() => out.push(value.clone()),
+            (Some(rest), Value::EntityValue { entity_value, .. }) =>
+                collect_values(entity_value, rest, out),
+            _ => {}
+        }
+    }
+}
+
+fn is_ancestor(ancestor: &Key, key: &Key) -> bool {
+    ancestor.partition_id() == key.partition_id()
+        && ancestor.path().len() <= key.path().len()
+        && ancestor.path() == &key.path()[..ancestor.path().len()]
+}
+
+fn array_elements(value: &Value) -> Result<&[Value]> {
+    match *value {
+        Value::Array { ref array_value, .. } => Ok(&array_value.values),
+        _ => Err(Error::api(Code::InvalidArgument, "IN and NOT_IN filters require an array value")),
+    }
+}
+
+fn matches_property(entity: &Entity, filter: &PropertyFilter) -> Result<bool> {
+    let values = indexed_values(entity, &filter.property.name);
+    let target = &filter.value;
+    let same_type = |v: &Value| type_rank(v) == type_rank(target);
+
+    Ok(match filter.op {
+        PropertyOperator::Equal =>
+            values.iter().any(|v| compare_values(v, target) == Ordering::Equal),
+        PropertyOperator::NotEqual =>
+            values.iter().any(|v| compare_values(v, target) != Ordering::Equal),
+        PropertyOperator::LessThan =>
+            values.iter().any(|v| same_type(v) && compare_values(v, target) == Ordering::Less),
+        PropertyOperator::LessThanOrEqual =>
+            values.iter().any(|v| same_type(v) && compare_values(v, target) != Ordering::Greater),
+        PropertyOperator::GreaterThan =>
+            values.iter().any(|v| same_type(v) && compare_values(v, target) == Ordering::Greater),
+        PropertyOperator::GreaterThanOrEqual =>
+            values.iter().any(|v| same_type(v) && compare_values(v, target) != Ordering::Less),
+        PropertyOperator::In => {
+            let options = array_elements(target)?;
+            values.iter().any(|v| options.iter().any(|o| compare_values(v, o) == Ordering::Equal))
+        }
+        PropertyOperator::NotIn => {
+            let options = array_elements(target)?;
+            values.iter().any(|v| options.iter().all(|o| compare_values(v, o) != Ordering::Equal))
+        }
+        PropertyOperator::HasAncestor => match (target, entity.key.as_ref()) {
+            (Value::KeyValue { key_value, .. }, Some(key)) => is_ancestor(key_value, key),
+            _ => return Err(Error::api(Code::InvalidArgument,
+                                       "HAS_ANCESTOR requires a key value")),
+        },
+    })
+}
+
+pub fn matches(entity: &Entity, filter: &Filter) -> Result<bool> {
+    match *filter {
+        Filter::PropertyFilter(ref filter) => matches_property(entity, filter),
+        Filter::CompositeFilter(ref composite) => {
+            for filter in &composite.filters {
+                let matched = matches(entity, filter)?;
+                match composite.op {
+                    CompositeOperator::And if !matched => return Ok(false),
+                    CompositeOperator::Or if matched => return Ok(true),
+                    _ => {}
+                }
+            }
+            Ok(composite.op == CompositeOperator::And)
+        }
+    }
+}
+
+// The value an entity is sorted by for one order, `None` if the entity lacks the property.
+fn sort_value(entity: &Entity, order: &PropertyOrder) -> Option<Value> {
+    let values = indexed_values(entity, &order.property.name).into_iter();
+    match order.direction {
+        Direction::Ascending => values.min_by(compare_values),
+        Direction::Descending => values.max_by(compare_values),
+    }
+}
+
+pub(super) fn encode_cursor(position: usize) -> Blob {
+    Blob((position as u64).to_be_bytes().to_vec())
+}
+
+fn decode_cursor(cursor: &Blob) -> Result<usize> {
+    if cursor.0.len() != 8 {
+        return Err(Error::api(Code::InvalidArgument, "invalid query cursor"));
+    }
+
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&cursor.0);
+    Ok(u64::from_be_bytes(bytes) as usize)
+}
+
+// A row of the result set: a whole entity, or for projection queries an entity with one
+// combination of the projected values.
+struct Row {
+    entity: Entity,
+    version: i64,
+    sort_values: Vec<Value>,
+}
+
+/// Converts a value to the form in which it is stored in indexes, which is what projection queries
+/// return: timestamps become microseconds with meaning `GD_WHEN`, all other metadata is dropped.
+fn index_value(value: &Value) -> Value {
+    match *value {
+        Value::Timestamp { ref timestamp_value, .. } =>
+            Value::from(timestamp_value.timestamp_micros()).with_meaning(meaning::GD_WHEN),
+        ref value => {
+            let mut value = value.clone();
+            *value.meta_mut() = ValueMeta::default();
+            value
+        }
+    }
+}
+
+// Projects an entity onto the given properties. Like index entries, an entity yields one row per
+// combination of distinct array elements, and none if it lacks an indexed value of a property.
+fn project(entity: &Entity, projection: &[&str]) -> Vec<Entity> {
+    let mut rows = vec![Entity { key: entity.key.clone(), properties: HashMap::new() }];
+    for &name in projection.iter().filter(|name| **name != KEY_PROPERTY) {
+        let mut values: Vec<Value> = vec![];
+        for value in indexed_values(entity, name) {
+            if !values.iter().any(|v| compare_values(v, &value) == Ordering::Equal) {
+                values.push(value);
+            }
+        }
+
+        rows = rows.into_iter()
+            .flat_map(|row| values.iter().map(move |value| {
+                let mut row = row.clone();
+                row.properties.insert(name.to_string(), index_value(value));
+                row
+            }))
+            .collect();
+    }
+    rows
+}
+
+/// Runs a query over all entities of a partition. Cursors are positions in the sorted result set,
+/// which is sufficient for a backend whose contents only change between test steps.
+pub fn run(candidates: Vec<Candidate>, query: &Query) -> Result<QueryResultBatch> {
+    if query.kind.len() > 1 {
+        return Err(Error::api(Code::InvalidArgument, "queries may specify at most one kind"));
+    }
+
+    let projection: Vec<&str> = query.projection.iter().map(|p| p.property.name.as_str()).collect();
+    for distinct in &query.distinct_on {
+        if !projection.contains(&distinct.name.as_str()) {
+            return Err(Error::api(Code::InvalidArgument,
+                                  format!("distinct_on property {} is not projected", distinct.name)));
+        }
+    }
+
+    let kind = query.kind.first().map(|k| k.name.as_str());
+    let mut rows = vec![];
+    for candidate in candidates {
+        let entity = candidate.entity;
+        if kind.is_some() && entity.key.as_ref().and_then(Key::kind) != kind {
+            continue;
+        }
+        if let Some(ref filter) = query.filter {
+            if !matches(entity, filter)? {
+                continue;
+            }
+        }
+
+        let projected = if projection.is_empty() { vec![entity.clone()] } else { project(entity, &projection) };
+        for row in projected {
+            // Rows of a projection are sorted by their own value of a projected property.
+            let sort_values: Option<Vec<Value>> = query.order.iter()
+                .map(|order| if projection.contains(&order.property.name.as_str()) {
+                    sort_value(&row, order)
+                } else {
+                    sort_value(entity, order)
+                })
+                .collect();
+            if let Some(sort_values) = sort_values {
+                rows.push(Row { entity: row, version: candidate.version, sort_values });
+            }
+        }
+    }
+
+    rows.sort_by(|a, b| {
+        query.order.iter().zip(a.sort_values.iter().zip(&b.sort_values))
+            .map(|(order, (a, b))| match order.direction {
+                Direction::Ascending => compare_values(a, b),
+                Direction::Descending => compare_values(b, a),
+            })
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or_else(|| match (a.entity.key.as_ref(), b.entity.key.as_ref()) {
+                (Some(a), Some(b)) => compare_keys(a, b),
+                _ => Ordering::Equal,
+            })
+    });
+
+    if !query.distinct_on.is_empty() {
+        rows = distinct(rows, &query.distinct_on);
+    }
+
+    let start = match query.start_cursor {
+        Some(ref cursor) => decode_cursor(cursor)?.min(rows.len()),
+        None => 0,
+    };
+    let end = match query.end_cursor {
+        Some(ref cursor) => decode_cursor(cursor)?.min(rows.len()).max(start),
+        None => rows.len(),
+    };
+
+    let skipped = (query.offset.max(0) as usize).min(end - start);
+    let first = start + skipped;
+    let last = match query.limit {
+        Some(limit) => (first + limit.max(0) as usize).min(end),
+        None => end,
+    };
+
+    let entity_results = rows.drain(first..last).enumerate()
+        .map(|(i, row)| EntityResult {
+            entity: row.entity,
+            version: Some(row.version.into()),
+            cursor: Some(encode_cursor(first + i + 1)),
+        })
+        .collect();
+
+    let more_results = if last < end {
+        MoreResultsType::MoreResultsAfterLimit
+    } else {
+        MoreResultsType::NoMoreResults
+    };
+
+    Ok(QueryResultBatch {
+        skipped_results: skipped as i32,
+        skipped_cursor: if skipped > 0 { Some(encode_cursor(first)) } else { None },
+        entity_result_type: if query.is_keys_only() {
+            ResultType::KeyOnly
+        } else if projection.is_empty() {
+            ResultType::Full
+        } else {
+            ResultType::Projection
+        },
+        entity_results,
+        end_cursor: Some(encode_cursor(last)),
+        more_results,
+        snapshot_version: None,
+        read_time: None,
+    })
+}
+
+// Keeps the first row of every combination of values of the `distinct_on` properties.
+fn distinct(rows: Vec<Row>, distinct_on: &[PropertyReference]) -> Vec<Row> {
+    let mut seen: Vec<Vec<Value>> = vec![];
+    let mut result = vec![];
+    for row in rows {
+        let values: Vec<Value> = distinct_on.iter()
+            .map(|p| row.entity.properties.get(&p.name).cloned().unwrap_or_else(|| Value::from(())))
+            .collect();
+        let duplicate = seen.iter().any(|other| {
+            other.iter().zip(&values).all(|(a, b)| compare_values(a, b) == Ordering::Equal)
+        });
+        if !duplicate {
+            seen.push(values);
+            result.push(row);
+        }
+    }
+    result
+}
+
+/// Number of aggregations that a query may contain.
+const MAX_AGGREGATIONS: usize = 5;
+
+/// Computes aggregations over the results of their nested query.
+pub fn aggregate(results: &[EntityResult], aggregations: &[Aggregation]) -> Result<AggregationResult> {
+    if aggregations.len() > MAX_AGGREGATIONS {
+        return Err(Error::api(Code::InvalidArgument,
+                              format!("at most {} aggregations are allowed", MAX_AGGREGATIONS)));
+    }
+
+    let mut result = AggregationResult::default();
+    for (i, aggregation) in aggregations.iter().enumerate() {
+        let alias = match aggregation.alias.as_str() {
+            "" => format!("field_{}", i + 1),
+            alias => alias.to_string(),
+        };
+        let value = match aggregation.operator {
+            AggregationOperator::Count(ref count) => {
+                let up_to = match count.up_to {
+                    Some(ref up_to) => up_to.parse::<i64>()
+                        .map_err(|_| Error::api(Code::InvalidArgument, "invalid up_to"))?,
+                    None => i64::MAX,
+                };
+                Value::from((results.len() as i64).min(up_to))
+            }
+            AggregationOperator::Sum { ref property } => sum(&numbers(results, &property.name)),
+            AggregationOperator::Avg { ref property } => {
+                let numbers = numbers(results, &property.name);
+                match sum(&numbers) {
+                    _ if numbers.is_empty() => Value::from(()),
+                    Value::Integer { ref integer_value, .. } =>
+                        Value::from(integer_value.parse::<i64>().unwrap_or(0) as f64 / numbers.len() as f64),
+                    Value::Double { double_value, .. } => Value::from(double_value / numbers.len() as f64),
+                    _ => unreachable!("sums are numeric"),
+                }
+            }
+        };
+
+        if result.aggregate_properties.insert(alias.clone(), value).is_some() {
+            return Err(Error::api(Code::InvalidArgument, format!("duplicate alias {}", alias)));
+        }
+    }
+    Ok(result)
+}
+
+// The numeric values of a property in the results, other values are ignored by aggregations.
+fn numbers(results: &[EntityResult], name: &str) -> Vec<Value> {
+    results.iter()
+        .flat_map(|result| indexed_values(&result.entity, name))
+        .filter(|value| matches!(*value, Value::Integer { .. } | Value::Double { .. }))
+        .collect()
+}
+
+// Sums up integers exactly as long as possible, and as doubles once a double is involved or the
+// sum overflows.
+fn sum(numbers: &[Value]) -> Value {
+    let mut integer: Option<i64> = Some(0);
+    let mut double = 0.0;
+    for value in numbers {
+        match *value {
+            Value::Integer { ref integer_value, .. } => {
+                let i: i64 = integer_value.parse().unwrap_or(0);
+                integer = integer.and_then(|sum| sum.checked_add(i));
+                double += i as f64;
+            }
+            Value::Double { double_value, .. } => {
+                integer = None;
+                double += double_value;
+            }
+            _ => {}
+        }
+    }
+    match integer {
+        Some(sum) => Value::from(sum),
+        None => Value::from(double),
+    }
+}
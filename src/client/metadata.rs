@@ -0,0 +1,50 @@
+// Listing namespaces, kinds and properties via metadata queries, see `datastore::metadata`.
+
+use crate::client::{repo, Client, Result};
+use crate::datastore::{Entity, Key, PartitionId};
+use crate::datastore::metadata::*;
+use crate::datastore::query::{Filter, Query};
+use crate::serde_ds;
+
+impl Client {
+    /// Lists the namespaces of the project that contain entities, the default namespace as the
+    /// empty string.
+    pub async fn namespaces(&self) -> Result<Vec<String>> {
+        let query = Query::new(NAMESPACE_KIND).keys_only();
+        let namespaces = repo::fetch_all(self, query, |entity| {
+            decode(entity, Namespace::from_key, "__namespace__ key")
+        }).await?;
+        Ok(namespaces.into_iter().map(|namespace| namespace.name).collect())
+    }
+
+    /// Lists the kinds that have entities in a namespace.
+    pub async fn kinds(&self, namespace: &str) -> Result<Vec<String>> {
+        let query = Query::new(KIND_KIND).keys_only();
+        let kinds = repo::fetch_all_in(self, partition(namespace), query, |entity| {
+            decode(entity, Kind::from_key, "__kind__ key")
+        }).await?;
+        Ok(kinds.into_iter().map(|kind| kind.name).collect())
+    }
+
+    /// Lists the indexed properties in a namespace, of all kinds or of one kind, together with the
+    /// representations of their values.
+    pub async fn properties(&self, namespace: &str, kind: Option<&str>) -> Result<Vec<Property>> {
+        let mut query = Query::new(PROPERTY_KIND);
+        if let Some(kind) = kind {
+            query = query.with_filter(Filter::has_ancestor(Kind::new(namespace, kind).key()));
+        }
+        repo::fetch_all_in(self, partition(namespace), query, |entity| {
+            Property::from_entity(&entity).ok_or(serde_ds::Error::ExpectedType("__property__ entity"))
+        }).await
+    }
+}
+
+fn partition(namespace: &str) -> Option<PartitionId> {
+    Some(PartitionId::new("", namespace))
+}
+
+fn decode<T, F>(entity: Entity, from_key: F, expected: &'static str) -> serde_ds::Result<T>
+    where F: Fn(&Key) -> Option<T>
+{
+    entity.key.as_ref().and_then(from_key).ok_or(serde_ds::Error::ExpectedType(expected))
+}
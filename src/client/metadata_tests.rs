@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use crate::client::*;
+use crate::client::stand_in;
+use crate::datastore::*;
+use crate::datastore::metadata::*;
+
+const PROJECT: &str = "test-project";
+
+fn key(namespace: &str, kind: &str, name: &str) -> Key {
+    Key::new(PartitionId::new("", namespace), vec![PathElement::from_name(kind, name)])
+}
+
+// Metadata queries, the same expectations hold for every transport.
+async fn metadata(client: &Client) {
+    let address = Entity {
+        key: None,
+        properties: hashmap! { "city".to_string() => Value::from("Berlin") },
+    };
+    for entity in [
+        Entity {
+            key: Some(key("", "User", "alice")),
+            properties: hashmap! {
+                "age".to_string() => Value::from(31),
+                "bio".to_string() => Value::from("unindexed").unindexed(),
+                "address".to_string() => Value::from(address),
+            },
+        },
+        Entity {
+            key: Some(key("", "User", "bob")),
+            properties: hashmap! { "age".to_string() => Value::from("unknown") },
+        },
+        Entity {
+            key: Some(key("", "Order", "A-1")),
+            properties: hashmap! {
+                "tags".to_string() => Value::from(vec![Value::from(true), Value::from(1.5)]),
+            },
+        },
+        Entity { key: Some(key("tenant", "Invoice", "1")), properties: hashmap!() },
+    ] {
+        client.put_entity(entity).await.unwrap();
+    }
+
+    assert_eq!(vec!["".to_string(), "tenant".to_string()], client.namespaces().await.unwrap());
+    assert_eq!(vec!["Order".to_string(), "User".to_string()], client.kinds("").await.unwrap());
+    assert_eq!(vec!["Invoice".to_string()], client.kinds("tenant").await.unwrap());
+
+    let properties = client.properties("", Some("User")).await.unwrap();
+    let names: Vec<&str> = properties.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(vec!["address.city", "age"], names);
+    assert_eq!(vec![Representation::Int64, Representation::String], properties[1].representations);
+    assert_eq!("User", properties[1].kind);
+
+    let properties = client.properties("", None).await.unwrap();
+    assert_eq!(3, properties.len());
+    assert_eq!(vec![Representation::Boolean, Representation::Double], properties[0].representations);
+    assert!(client.properties("tenant", None).await.unwrap().is_empty());
+
+    // Keys of the default namespace use ID 1.
+    let response = client.run_query(RunQueryRequest {
+        query: Some(query::Query::new(NAMESPACE_KIND).keys_only()),
+        ..RunQueryRequest::default()
+    }).await.unwrap();
+    let first = response.batch.entity_results[0].entity.key.clone().unwrap();
+    assert_eq!(Some(DEFAULT_NAMESPACE_ID), first.path()[0].id());
+}
+
+#[tokio::test]
+async fn test_metadata() {
+    metadata(&Client::new(PROJECT, MemoryBackend::new())).await;
+
+    let stand_in = stand_in::rest(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT).endpoint(stand_in.endpoint()).build().unwrap();
+    metadata(&client).await;
+
+    let stand_in = stand_in::grpc(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Grpc)
+        .build()
+        .unwrap();
+    metadata(&client).await;
+}
+
+#[test]
+fn test_metadata_keys() {
+    assert_eq!(Some(Namespace::new("")), Namespace::from_key(&Namespace::new("").key()));
+    assert_eq!(Some(Namespace::new("tenant")), Namespace::from_key(&Namespace::new("tenant").key()));
+    let kind = Kind::new("tenant", "User");
+    assert_eq!("tenant", kind.key().partition_id().namespace_id());
+    assert_eq!(Some(kind.clone()), Kind::from_key(&kind.key()));
+    assert_eq!(None, Namespace::from_key(&kind.key()));
+
+    let property = Property {
+        namespace: "tenant".to_string(),
+        kind: "User".to_string(),
+        name: "age".to_string(),
+        representations: vec![],
+    };
+    assert_eq!(Some(kind.key()), property.key().parent());
+    assert_eq!(Some(property.clone()), Property::from_key(&property.key()));
+    assert_eq!(None, Kind::from_key(&property.key()));
+
+    let entity = Entity {
+        key: Some(property.key()),
+        properties: hashmap! {
+            PROPERTY_REPRESENTATION.to_string() => Value::from(vec!["INT64", "GEO"]),
+        },
+    };
+    assert_eq!(vec![Representation::Int64, Representation::Other("GEO".to_string())],
+               Property::from_entity(&entity).unwrap().representations);
+}
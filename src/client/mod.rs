@@ -0,0 +1,639 @@
+// Client for the Datastore API.
+//
+// All API operations are defined by the `Transport` trait. It is implemented by the REST transport
+// (JSON over HTTP/1.1), the gRPC transport (protobuf over HTTP/2) and an in-memory backend for
+// tests. Which transport a client uses is chosen when it is constructed, application code only
+// ever sees the `Client`.
+//
+// `Client` is async and cheap to clone, all clones share the transport and its connections. The
+// `blocking` module wraps it for code that does not run on an async runtime.
+
+use std::collections::HashSet;
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::datastore::{Entity, Key, Value};
+use crate::datastore::query::{AggregationQuery, AggregationResult, Query};
+use crate::datastore::rpc::*;
+use crate::entity::DatastoreEntity;
+use crate::proto::v1::Message;
+use crate::serde_ds;
+
+mod error;
+mod connect;
+mod rest;
+mod grpc;
+mod retry;
+mod transaction;
+mod repo;
+mod id_pool;
+mod admin;
+mod metadata;
+mod namespace;
+mod stats;
+mod bulk;
+pub mod memory;
+pub mod blocking;
+
+pub use self::error::{Code, Error, Result};
+pub use self::connect::{Endpoint, DEFAULT_ENDPOINT};
+pub use self::rest::RestTransport;
+pub use self::grpc::GrpcTransport;
+pub use self::memory::MemoryBackend;
+pub use self::retry::RetryPolicy;
+pub use self::transaction::Transaction;
+pub use self::repo::{Page, Repo, RepoQuery};
+pub use self::id_pool::IdPool;
+pub use self::admin::{AdminClient, OperationHandle};
+pub use self::bulk::BulkOptions;
+
+use self::namespace::Scoped;
+
+/// Number of keys that a single lookup may contain.
+const MAX_LOOKUP_KEYS: usize = 1000;
+
+/// Number of times that a lookup is sent before giving up on keys whose lookup the API deferred.
+/// The rounds are spaced out like retries, see `RetryPolicy`.
+const MAX_LOOKUP_ROUNDS: u32 = 10;
+
+#[cfg(test)]
+mod stand_in;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(test)]
+mod blocking_tests;
+
+#[cfg(test)]
+mod retry_tests;
+
+#[cfg(test)]
+mod repo_tests;
+
+#[cfg(test)]
+mod metadata_tests;
+
+#[cfg(test)]
+mod stats_tests;
+
+#[cfg(test)]
+mod namespace_tests;
+
+#[cfg(test)]
+mod id_pool_tests;
+
+#[cfg(test)]
+mod bulk_tests;
+
+#[cfg(test)]
+mod admin_tests;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The operations of the Datastore API.
+pub trait Transport: Send + Sync {
+    fn lookup(&self, request: LookupRequest) -> BoxFuture<'_, Result<LookupResponse>>;
+
+    fn run_query(&self, request: RunQueryRequest) -> BoxFuture<'_, Result<RunQueryResponse>>;
+
+    fn run_aggregation_query(&self, request: RunAggregationQueryRequest)
+                             -> BoxFuture<'_, Result<RunAggregationQueryResponse>>;
+
+    fn begin_transaction(&self, request: BeginTransactionRequest)
+                         -> BoxFuture<'_, Result<BeginTransactionResponse>>;
+
+    fn commit(&self, request: CommitRequest) -> BoxFuture<'_, Result<CommitResponse>>;
+
+    fn rollback(&self, request: RollbackRequest) -> BoxFuture<'_, Result<RollbackResponse>>;
+
+    fn allocate_ids(&self, request: AllocateIdsRequest)
+                    -> BoxFuture<'_, Result<AllocateIdsResponse>>;
+
+    fn reserve_ids(&self, request: ReserveIdsRequest) -> BoxFuture<'_, Result<ReserveIdsResponse>>;
+}
+
+/// Request messages of the API methods, with the names under which the transports address them.
+pub(crate) trait Method: Message + Serialize + Send + 'static {
+    type Response: Message + DeserializeOwned + Send;
+
+    /// Name of the REST method, e.g. `lookup` in `/v1/projects/{projectId}:lookup`.
+    const REST: &'static str;
+
+    /// Name of the gRPC method, e.g. `Lookup` in `/google.datastore.v1.Datastore/Lookup`.
+    const GRPC: &'static str;
+
+    fn project_id(&self) -> &str;
+}
+
+macro_rules! method {
+    ($request:ident, $response:ident, $rest:expr, $grpc:expr) => {
+        impl Method for $request {
+            type Response = $response;
+            const REST: &'static str = $rest;
+            const GRPC: &'static str = $grpc;
+
+            fn project_id(&self) -> &str {
+                &self.project_id
+            }
+        }
+    };
+}
+
+method!(LookupRequest, LookupResponse, "lookup", "Lookup");
+method!(RunQueryRequest, RunQueryResponse, "runQuery", "RunQuery");
+method!(RunAggregationQueryRequest, RunAggregationQueryResponse, "runAggregationQuery",
+        "RunAggregationQuery");
+method!(BeginTransactionRequest, BeginTransactionResponse, "beginTransaction", "BeginTransaction");
+method!(CommitRequest, CommitResponse, "commit", "Commit");
+method!(RollbackRequest, RollbackResponse, "rollback", "Rollback");
+method!(AllocateIdsRequest, AllocateIdsResponse, "allocateIds", "AllocateIds");
+method!(ReserveIdsRequest, ReserveIdsResponse, "reserveIds", "ReserveIds");
+
+/// Transports that send every request in the same way, only addressed differently per method.
+pub(crate) trait Call: Send + Sync {
+    fn call<M: Method>(&self, request: M) -> BoxFuture<'_, Result<M::Response>>;
+}
+
+impl<T: Call> Transport for T {
+    fn lookup(&self, request: LookupRequest) -> BoxFuture<'_, Result<LookupResponse>> {
+        self.call(request)
+    }
+
+    fn run_query(&self, request: RunQueryRequest) -> BoxFuture<'_, Result<RunQueryResponse>> {
+        self.call(request)
+    }
+
+    fn run_aggregation_query(&self, request: RunAggregationQueryRequest)
+                             -> BoxFuture<'_, Result<RunAggregationQueryResponse>> {
+        self.call(request)
+    }
+
+    fn begin_transaction(&self, request: BeginTransactionRequest)
+                         -> BoxFuture<'_, Result<BeginTransactionResponse>> {
+        self.call(request)
+    }
+
+    fn commit(&self, request: CommitRequest) -> BoxFuture<'_, Result<CommitResponse>> {
+        self.call(request)
+    }
+
+    fn rollback(&self, request: RollbackRequest) -> BoxFuture<'_, Result<RollbackResponse>> {
+        self.call(request)
+    }
+
+    fn allocate_ids(&self, request: AllocateIdsRequest)
+                    -> BoxFuture<'_, Result<AllocateIdsResponse>> {
+        self.call(request)
+    }
+
+    fn reserve_ids(&self, request: ReserveIdsRequest) -> BoxFuture<'_, Result<ReserveIdsResponse>> {
+        self.call(request)
+    }
+}
+
+/// The wire protocol used to talk to the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// JSON over HTTP/1.1.
+    Rest,
+
+    /// Protobuf over HTTP/2.
+    Grpc,
+}
+
+/// Configures a `Client` that talks to a Datastore endpoint.
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    project_id: String,
+    endpoint: Option<String>,
+    protocol: Protocol,
+    access_token: Option<String>,
+    retry_policy: RetryPolicy,
+}
+
+impl ClientBuilder {
+    /// Sets the base URL of the API. If no endpoint is set, the emulator from
+    /// `DATASTORE_EMULATOR_HOST` is used if that variable is set and the production endpoint
+    /// otherwise.
+    pub fn endpoint<E: Into<String>>(mut self, endpoint: E) -> ClientBuilder {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Selects the transport, the default is `Protocol::Rest`.
+    pub fn protocol(mut self, protocol: Protocol) -> ClientBuilder {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Sets an OAuth2 access token that is sent with every request.
+    pub fn access_token<T: Into<String>>(mut self, token: T) -> ClientBuilder {
+        self.access_token = Some(token.into());
+        self
+    }
+
+    /// Sets how failed requests are retried, the default is `RetryPolicy::default()`.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> ClientBuilder {
+        self.retry_policy = policy;
+        self
+    }
+
+    fn parse_endpoint(&self) -> Result<Endpoint> {
+        let endpoint = match self.endpoint {
+            Some(ref endpoint) => endpoint.clone(),
+            None => match env::var("DATASTORE_EMULATOR_HOST") {
+                Ok(host) => format!("http://{}", host),
+                Err(_) => DEFAULT_ENDPOINT.to_string(),
+            },
+        };
+        Endpoint::parse(&endpoint)
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let endpoint = self.parse_endpoint()?;
+
+        let client = match self.protocol {
+            Protocol::Rest => Client::new(self.project_id, RestTransport::new(endpoint, self.access_token)),
+            Protocol::Grpc => Client::new(self.project_id, GrpcTransport::new(endpoint, self.access_token)),
+        };
+        Ok(client.with_retry_policy(self.retry_policy))
+    }
+
+    /// Builds a client of the Admin API, which always uses REST.
+    pub fn build_admin(self) -> Result<AdminClient> {
+        let endpoint = self.parse_endpoint()?;
+        Ok(AdminClient::with_retry_policy(self.project_id, endpoint, self.access_token, self.retry_policy))
+    }
+
+    /// Builds a client for use outside of an async runtime.
+    pub fn build_blocking(self) -> Result<blocking::Client> {
+        blocking::Client::wrap(self.build()?)
+    }
+}
+
+struct Inner {
+    project_id: String,
+    transport: Arc<dyn Transport>,
+    retry_policy: RetryPolicy,
+}
+
+/// An async Datastore client. Requests that do not specify a project ID are sent for the client's
+/// project.
+///
+/// Clones share the underlying transport, so a single client can serve a whole application.
+/// Transient failures are retried according to the client's `RetryPolicy`.
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+    namespace: Option<String>,
+}
+
+impl Client {
+    pub fn builder<P: Into<String>>(project_id: P) -> ClientBuilder {
+        ClientBuilder {
+            project_id: project_id.into(),
+            endpoint: None,
+            protocol: Protocol::Rest,
+            access_token: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Creates a client that uses the given transport, e.g. a `MemoryBackend`.
+    pub fn new<P: Into<String>, T: Transport + 'static>(project_id: P, transport: T) -> Client {
+        Client {
+            inner: Arc::new(Inner {
+                project_id: project_id.into(),
+                transport: Arc::new(transport),
+                retry_policy: RetryPolicy::default(),
+            }),
+            namespace: None,
+        }
+    }
+
+    /// Returns a client that shares this client's transport but retries according to `policy`.
+    pub fn with_retry_policy(&self, policy: RetryPolicy) -> Client {
+        Client {
+            inner: Arc::new(Inner {
+                project_id: self.inner.project_id.clone(),
+                transport: self.inner.transport.clone(),
+                retry_policy: policy,
+            }),
+            namespace: self.namespace.clone(),
+        }
+    }
+
+    /// Returns a client that shares this client's transport but confines all requests to
+    /// `namespace`. Keys and queries without a namespace are moved into it, requests that refer to
+    /// another namespace fail with `Error::CrossNamespace` without being sent.
+    pub fn namespace<N: Into<String>>(&self, namespace: N) -> Client {
+        Client { inner: self.inner.clone(), namespace: Some(namespace.into()) }
+    }
+
+    /// The namespace that this client is confined to, if any.
+    pub fn namespace_id(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.inner.retry_policy
+    }
+
+    pub fn project_id(&self) -> &str {
+        &self.inner.project_id
+    }
+
+    fn project(&self, project_id: &mut String) {
+        if project_id.is_empty() {
+            project_id.push_str(&self.inner.project_id);
+        }
+    }
+
+    fn scope<R: Scoped>(&self, request: &mut R) -> Result<()> {
+        match self.namespace {
+            Some(ref namespace) => request.scope(namespace),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn lookup(&self, mut request: LookupRequest) -> Result<LookupResponse> {
+        self.project(&mut request.project_id);
+        self.scope(&mut request)?;
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.lookup(request.clone()), Error::is_retryable).await
+    }
+
+    pub async fn run_query(&self, mut request: RunQueryRequest) -> Result<RunQueryResponse> {
+        self.project(&mut request.project_id);
+        self.scope(&mut request)?;
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.run_query(request.clone()), Error::is_retryable).await
+    }
+
+    pub async fn run_aggregation_query(&self, mut request: RunAggregationQueryRequest)
+                                       -> Result<RunAggregationQueryResponse> {
+        self.project(&mut request.project_id);
+        self.scope(&mut request)?;
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.run_aggregation_query(request.clone()),
+                                    Error::is_retryable).await
+    }
+
+    pub async fn begin_transaction(&self, mut request: BeginTransactionRequest)
+                                   -> Result<BeginTransactionResponse> {
+        self.project(&mut request.project_id);
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.begin_transaction(request.clone()), Error::is_retryable).await
+    }
+
+    pub async fn commit(&self, mut request: CommitRequest) -> Result<CommitResponse> {
+        self.project(&mut request.project_id);
+        self.scope(&mut request)?;
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.commit(request.clone()),
+                                    |err| retry::commit_retryable(&request, err)).await
+    }
+
+    pub async fn rollback(&self, mut request: RollbackRequest) -> Result<RollbackResponse> {
+        self.project(&mut request.project_id);
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.rollback(request.clone()), Error::is_retryable).await
+    }
+
+    pub async fn allocate_ids(&self, mut request: AllocateIdsRequest) -> Result<AllocateIdsResponse> {
+        self.project(&mut request.project_id);
+        self.scope(&mut request)?;
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.allocate_ids(request.clone()), Error::is_retryable).await
+    }
+
+    pub async fn reserve_ids(&self, mut request: ReserveIdsRequest) -> Result<ReserveIdsResponse> {
+        self.project(&mut request.project_id);
+        self.scope(&mut request)?;
+        let transport = &self.inner.transport;
+        self.inner.retry_policy.run(|| transport.reserve_ids(request.clone()), Error::is_retryable).await
+    }
+
+    // Convenience functions for single entities:
+
+    /// Looks up a single entity.
+    pub async fn get_entity(&self, key: &Key) -> Result<Option<Entity>> {
+        get_entity(self, key, None).await
+    }
+
+    /// Looks up entities, returning them in the order of the keys with `None` for missing ones.
+    /// Keys may repeat. More than 1000 keys are looked up in several requests, and keys whose
+    /// lookup the API deferred are looked up again after a backoff, up to ten times.
+    pub async fn get_entities(&self, keys: &[Key]) -> Result<Vec<Option<Entity>>> {
+        get_entities(self, keys, None).await
+    }
+
+    /// Inserts or replaces a single entity, outside of any transaction.
+    pub async fn put_entity(&self, entity: Entity) -> Result<()> {
+        self.mutate(Mutation::Upsert(entity)).await
+    }
+
+    /// Deletes a single entity, outside of any transaction. Deleting a missing entity succeeds.
+    pub async fn delete(&self, key: Key) -> Result<()> {
+        self.mutate(Mutation::Delete(key)).await
+    }
+
+    /// Looks up a single entity and deserialises it via `serde_ds`.
+    pub async fn read<T: DeserializeOwned>(&self, key: &Key) -> Result<Option<T>> {
+        match self.get_entity(key).await? {
+            Some(entity) => Ok(Some(from_entity(entity)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Serialises a value via `serde_ds` and stores it as the entity with the given key.
+    pub async fn write<T: Serialize>(&self, key: Key, value: &T) -> Result<()> {
+        self.put_entity(to_entity(key, value)?).await
+    }
+
+    // Convenience functions for typed entities:
+
+    /// Looks up the root entity of type `T` with the given ID or name.
+    pub async fn get<T: DatastoreEntity>(&self, id: impl Into<T::Id>) -> Result<Option<T>> {
+        self.get_by_key(&T::key_for(None, &id.into())).await
+    }
+
+    /// Looks up the entity of type `T` with the given key, e.g. one that has a parent.
+    pub async fn get_by_key<T: DatastoreEntity>(&self, key: &Key) -> Result<Option<T>> {
+        match self.get_entity(key).await? {
+            Some(entity) => Ok(Some(T::from_entity(entity)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Inserts or replaces a typed entity, outside of any transaction, and returns its key.
+    pub async fn put<T: DatastoreEntity>(&self, value: &T) -> Result<Key> {
+        let entity = value.to_entity()?;
+        let key = value.key();
+        self.put_entity(entity).await?;
+        Ok(key)
+    }
+
+    /// Runs an aggregation query and returns its result.
+    pub async fn aggregate(&self, query: AggregationQuery) -> Result<AggregationResult> {
+        aggregate(self, query, None).await
+    }
+
+    /// Runs a query as a keys-only query to completion and returns the keys of the results.
+    pub async fn query_keys(&self, query: Query) -> Result<Vec<Key>> {
+        repo::fetch_all(self, query.keys_only(), repo::key_of).await
+    }
+
+    /// Allocates IDs for incomplete keys and returns the completed keys in the same order.
+    pub async fn allocate_keys(&self, keys: &[Key]) -> Result<Vec<Key>> {
+        let request = AllocateIdsRequest { project_id: String::new(), keys: keys.to_vec() };
+        let response = self.allocate_ids(request).await?;
+        if response.keys.len() != keys.len() {
+            return Err(Error::UnexpectedResponse(format!("allocateIds returned {} keys for {}",
+                                                         response.keys.len(), keys.len())));
+        }
+        Ok(response.keys)
+    }
+
+    /// Reserves the numeric IDs of complete keys, so that they are never allocated, e.g. before
+    /// importing entities whose IDs were allocated elsewhere.
+    pub async fn reserve_keys(&self, keys: &[Key]) -> Result<()> {
+        self.reserve_ids(ReserveIdsRequest { project_id: String::new(), keys: keys.to_vec() }).await?;
+        Ok(())
+    }
+
+    /// A repository for the entities of type `T`.
+    pub fn repo<T: DatastoreEntity>(&self) -> Repo<T> {
+        Repo::new(self.clone())
+    }
+
+    async fn mutate(&self, mutation: Mutation) -> Result<()> {
+        self.commit(CommitRequest {
+            project_id: String::new(),
+            mode: CommitMode::NonTransactional,
+            transaction: None,
+            mutations: vec![mutation],
+        }).await?;
+        Ok(())
+    }
+
+    /// Begins a read-write transaction.
+    pub async fn transaction(&self) -> Result<Transaction> {
+        Transaction::begin(self.clone(), TransactionOptions::ReadWrite(ReadWrite::default())).await
+    }
+
+    /// Begins a read-only transaction, which reads a consistent snapshot.
+    pub async fn read_only_transaction(&self) -> Result<Transaction> {
+        Transaction::begin(self.clone(), TransactionOptions::ReadOnly(ReadOnly::default())).await
+    }
+}
+
+async fn get_entity(client: &Client, key: &Key, read_options: Option<ReadOptions>)
+                    -> Result<Option<Entity>> {
+    for round in 0..MAX_LOOKUP_ROUNDS {
+        if round > 0 {
+            client.retry_policy().pause(round).await;
+        }
+        let request = LookupRequest {
+            project_id: String::new(),
+            read_options: read_options.clone(),
+            keys: vec![key.clone()],
+        };
+        let mut response = client.lookup(request).await?;
+        if response.deferred.is_empty() {
+            return Ok(response.found.pop().map(|result| result.entity));
+        }
+    }
+    Err(deferred_too_often())
+}
+
+async fn get_entities(client: &Client, keys: &[Key], read_options: Option<ReadOptions>)
+                      -> Result<Vec<Option<Entity>>> {
+    // Keys in the response carry the namespace of a scoped client, which the keys may omit.
+    let mut scoped = LookupRequest { keys: keys.to_vec(), ..LookupRequest::default() };
+    client.scope(&mut scoped)?;
+    let keys = &scoped.keys;
+
+    let mut unique: Vec<Key> = Vec::with_capacity(keys.len());
+    let mut seen = HashSet::new();
+    for key in keys {
+        if seen.insert(key) {
+            unique.push(key.clone());
+        }
+    }
+    let mut found = Vec::with_capacity(unique.len());
+    for chunk in unique.chunks(MAX_LOOKUP_KEYS) {
+        let mut keys = chunk.to_vec();
+        for round in 0..MAX_LOOKUP_ROUNDS {
+            if keys.is_empty() {
+                break;
+            }
+            if round > 0 {
+                client.retry_policy().pause(round).await;
+            }
+            let request = LookupRequest { project_id: String::new(), read_options: read_options.clone(), keys };
+            let response = client.lookup(request).await?;
+            found.extend(response.found.into_iter().map(|result| result.entity));
+            keys = response.deferred;
+        }
+        if !keys.is_empty() {
+            return Err(deferred_too_often());
+        }
+    }
+
+    // Keys in the response carry the project, which the requested keys may omit.
+    let mut results: Vec<Option<Entity>> = keys.iter().map(|_| None).collect();
+    for entity in found {
+        let mut matched = false;
+        if let Some(ref key) = entity.key {
+            for (result, _) in results.iter_mut().zip(keys).filter(|(_, k)| same_entity(k, key)) {
+                *result = Some(entity.clone());
+                matched = true;
+            }
+        }
+        if !matched {
+            return Err(Error::UnexpectedResponse("lookup returned an unexpected entity".to_string()));
+        }
+    }
+    Ok(results)
+}
+
+fn deferred_too_often() -> Error {
+    Error::UnexpectedResponse(format!("lookup was deferred {} times", MAX_LOOKUP_ROUNDS))
+}
+
+// Whether two keys refer to the same entity, where a key without project ID refers to any project.
+fn same_entity(a: &Key, b: &Key) -> bool {
+    let (pa, pb) = (a.partition_id(), b.partition_id());
+    a.path() == b.path()
+        && pa.namespace_id() == pb.namespace_id()
+        && (pa.project_id().is_empty() || pb.project_id().is_empty() || pa.project_id() == pb.project_id())
+}
+
+async fn aggregate(client: &Client, query: AggregationQuery, read_options: Option<ReadOptions>)
+                   -> Result<AggregationResult> {
+    let request = RunAggregationQueryRequest {
+        read_options,
+        aggregation_query: Some(query),
+        ..RunAggregationQueryRequest::default()
+    };
+    let mut response = client.run_aggregation_query(request).await?;
+    response.batch.aggregation_results.pop()
+        .ok_or_else(|| Error::UnexpectedResponse("aggregation query returned no result".to_string()))
+}
+
+fn from_entity<T: DeserializeOwned>(entity: Entity) -> Result<T> {
+    Ok(serde_ds::from_value(Value::from(entity))?)
+}
+
+fn to_entity<T: Serialize>(key: Key, value: &T) -> Result<Entity> {
+    match serde_ds::to_value(value)? {
+        Value::EntityValue { entity_value, .. } => Ok(Entity { key: Some(key), ..entity_value }),
+        _ => Err(serde_ds::Error::ExpectedType("entity").into()),
+    }
+}
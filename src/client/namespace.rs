@@ -0,0 +1,227 @@
+// Namespace-scoped clients.
+//
+// A client returned by `Client::namespace` confines every request to one namespace before it is
+// sent: keys, queries and ID allocations without a namespace are moved into it, and requests that
+// refer to another namespace are refused with `Error::CrossNamespace`. This covers keys in key
+// values of properties and filters as well, so that one tenant's entities can not reference
+// another's. Keys in the default namespace count as having no namespace, so they are moved too.
+// Key literals of GQL queries can not be moved, their `NAMESPACE` must be the client's.
+
+use crate::client::{Error, Result};
+use crate::datastore::{Entity, Key, PartitionId, Value};
+use crate::datastore::query::{Filter, GqlQuery, GqlQueryParameter, Query};
+use crate::datastore::rpc::*;
+
+/// Requests that can be confined to a namespace.
+pub(crate) trait Scoped {
+    fn scope(&mut self, namespace: &str) -> Result<()>;
+}
+
+impl Scoped for LookupRequest {
+    fn scope(&mut self, namespace: &str) -> Result<()> {
+        scope_keys(&mut self.keys, namespace)
+    }
+}
+
+impl Scoped for RunQueryRequest {
+    fn scope(&mut self, namespace: &str) -> Result<()> {
+        scope_partition(&mut self.partition_id, namespace)?;
+        if let Some(ref mut query) = self.query {
+            scope_query(query, namespace)?;
+        }
+        if let Some(ref mut query) = self.gql_query {
+            scope_gql_query(query, namespace)?;
+        }
+        Ok(())
+    }
+}
+
+impl Scoped for RunAggregationQueryRequest {
+    fn scope(&mut self, namespace: &str) -> Result<()> {
+        scope_partition(&mut self.partition_id, namespace)?;
+        if let Some(ref mut query) = self.aggregation_query {
+            scope_query(&mut query.nested_query, namespace)?;
+        }
+        if let Some(ref mut query) = self.gql_query {
+            scope_gql_query(query, namespace)?;
+        }
+        Ok(())
+    }
+}
+
+impl Scoped for CommitRequest {
+    fn scope(&mut self, namespace: &str) -> Result<()> {
+        for mutation in &mut self.mutations {
+            match *mutation {
+                Mutation::Insert(ref mut entity)
+                | Mutation::Update(ref mut entity)
+                | Mutation::Upsert(ref mut entity) => scope_entity(entity, namespace)?,
+                Mutation::Delete(ref mut key) => scope_key(key, namespace)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Scoped for AllocateIdsRequest {
+    fn scope(&mut self, namespace: &str) -> Result<()> {
+        scope_keys(&mut self.keys, namespace)
+    }
+}
+
+impl Scoped for ReserveIdsRequest {
+    fn scope(&mut self, namespace: &str) -> Result<()> {
+        scope_keys(&mut self.keys, namespace)
+    }
+}
+
+fn cross_namespace(expected: &str, found: &str) -> Error {
+    Error::CrossNamespace { expected: expected.to_string(), found: found.to_string() }
+}
+
+fn scope_key(key: &mut Key, namespace: &str) -> Result<()> {
+    let partition = key.partition_id();
+    match partition.namespace_id() {
+        found if found == namespace => Ok(()),
+        "" => {
+            let partition = PartitionId::new(partition.project_id(), namespace);
+            *key = Key::new(partition, key.path().to_vec());
+            Ok(())
+        }
+        found => Err(cross_namespace(namespace, found)),
+    }
+}
+
+fn scope_keys(keys: &mut [Key], namespace: &str) -> Result<()> {
+    keys.iter_mut().try_for_each(|key| scope_key(key, namespace))
+}
+
+fn scope_partition(partition: &mut Option<PartitionId>, namespace: &str) -> Result<()> {
+    let project_id = match *partition {
+        Some(ref partition) if partition.namespace_id() == namespace => return Ok(()),
+        Some(ref partition) if !partition.namespace_id().is_empty() =>
+            return Err(cross_namespace(namespace, partition.namespace_id())),
+        Some(ref partition) => partition.project_id().to_string(),
+        None => String::new(),
+    };
+    *partition = Some(PartitionId::new(project_id, namespace));
+    Ok(())
+}
+
+fn scope_entity(entity: &mut Entity, namespace: &str) -> Result<()> {
+    if let Some(ref mut key) = entity.key {
+        scope_key(key, namespace)?;
+    }
+    entity.properties.values_mut().try_for_each(|value| scope_value(value, namespace))
+}
+
+fn scope_value(value: &mut Value, namespace: &str) -> Result<()> {
+    match *value {
+        Value::KeyValue { ref mut key_value, .. } => scope_key(key_value, namespace),
+        Value::EntityValue { ref mut entity_value, .. } => scope_entity(entity_value, namespace),
+        Value::Array { ref mut array_value, .. } =>
+            array_value.values.iter_mut().try_for_each(|value| scope_value(value, namespace)),
+        _ => Ok(()),
+    }
+}
+
+fn scope_query(query: &mut Query, namespace: &str) -> Result<()> {
+    match query.filter {
+        Some(ref mut filter) => scope_filter(filter, namespace),
+        None => Ok(()),
+    }
+}
+
+fn scope_filter(filter: &mut Filter, namespace: &str) -> Result<()> {
+    match *filter {
+        Filter::CompositeFilter(ref mut composite) =>
+            composite.filters.iter_mut().try_for_each(|filter| scope_filter(filter, namespace)),
+        Filter::PropertyFilter(ref mut filter) => scope_value(&mut filter.value, namespace),
+    }
+}
+
+fn scope_gql_query(query: &mut GqlQuery, namespace: &str) -> Result<()> {
+    if query.allow_literals {
+        scope_gql_literals(&query.query_string, namespace)?;
+    }
+    query.named_bindings.values_mut()
+        .chain(query.positional_bindings.iter_mut())
+        .try_for_each(|binding| match *binding {
+            GqlQueryParameter::Value(ref mut value) => scope_value(value, namespace),
+            GqlQueryParameter::Cursor(_) => Ok(()),
+        })
+}
+
+// Refuses key literals whose `NAMESPACE(...)` names another namespace. Literals can not be moved
+// into the namespace like keys, so `NAMESPACE('')` is refused as well, and so is any `NAMESPACE`
+// whose argument is not a plain string. A `namespace` that is not followed by an argument list is
+// an identifier and passes.
+fn scope_gql_literals(query: &str, namespace: &str) -> Result<()> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\'' || c == '"' || c == '`' {
+            i = gql_string(&chars, i).map(|(_, end)| end).unwrap_or(chars.len());
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            // Without an opening parenthesis the word is an identifier, e.g. a property name.
+            let word: String = chars[start..i].iter().collect();
+            let open = match skip_whitespace(&chars, i) {
+                Some(open) if chars[open] == '(' && word.eq_ignore_ascii_case("NAMESPACE") => open,
+                _ => continue,
+            };
+            let argument = skip_whitespace(&chars, open + 1)
+                .and_then(|quote| gql_string(&chars, quote))
+                .filter(|&(_, end)| skip_whitespace(&chars, end).is_some_and(|close| chars[close] == ')'));
+            match argument {
+                Some((ref found, _)) if found == namespace => {}
+                Some((found, _)) => return Err(cross_namespace(namespace, &found)),
+                None => {
+                    let found: String = chars[start..].iter().take_while(|&&c| c != ')').collect();
+                    return Err(cross_namespace(namespace, &found));
+                }
+            }
+        } else {
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+// The position of the next character that is not whitespace.
+fn skip_whitespace(chars: &[char], from: usize) -> Option<usize> {
+    (from..chars.len()).find(|&i| !chars[i].is_whitespace())
+}
+
+// Reads the quoted string that starts at `start`, returning its content and the position after
+// it. Quotes are escaped with a backslash or by doubling them.
+fn gql_string(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let quote = chars[start];
+    if quote != '\'' && quote != '"' && quote != '`' {
+        return None;
+    }
+    let mut content = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                content.push(*chars.get(i + 1)?);
+                i += 2;
+            }
+            c if c == quote && chars.get(i + 1) == Some(&quote) => {
+                content.push(quote);
+                i += 2;
+            }
+            c if c == quote => return Some((content, i + 1)),
+            c => {
+                content.push(c);
+                i += 1;
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,126 @@
+use crate::client::*;
+use crate::datastore::*;
+use crate::datastore::query::*;
+use crate::entity::DatastoreEntity;
+
+#[derive(Debug, Clone, PartialEq, DatastoreEntity)]
+#[datastore(crate = "crate")]
+struct User {
+    #[datastore(key)]
+    name: String,
+    age: i64,
+}
+
+fn key(namespace: &str, name: &str) -> Key {
+    Key::new(PartitionId::new("", namespace), vec![PathElement::from_name("User", name)])
+}
+
+fn assert_cross_namespace<T: std::fmt::Debug>(result: Result<T>) {
+    match result {
+        Err(Error::CrossNamespace { ref expected, ref found }) => {
+            assert_eq!("tenant-a", expected);
+            assert_eq!("tenant-b", found);
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_scoped_requests() {
+    let client = Client::new("test-project", MemoryBackend::new());
+    let tenant = client.namespace("tenant-a");
+    assert_eq!(Some("tenant-a"), tenant.namespace_id());
+    assert_eq!(None, client.namespace_id());
+
+    // Typed entities build keys without a namespace, they are moved into the tenant's namespace.
+    let users = tenant.repo::<User>();
+    users.put(&User { name: "alice".to_string(), age: 31 }).await.unwrap();
+    assert!(client.get_entity(&key("tenant-a", "alice")).await.unwrap().is_some());
+    assert!(client.get_entity(&key("", "alice")).await.unwrap().is_none());
+    assert_eq!(Some(31), users.get("alice").await.unwrap().map(|user| user.age));
+    assert!(tenant.get_entity(&key("tenant-a", "alice")).await.unwrap().is_some());
+
+    // Queries run in the tenant's namespace only.
+    client.put_entity(Entity { key: Some(key("", "bob")), properties: Default::default() })
+        .await
+        .unwrap();
+    assert_eq!(1, users.query().fetch().await.unwrap().len());
+    assert_eq!(1, tenant.query_keys(Query::new("User")).await.unwrap().len());
+    assert_eq!(Some(1), tenant.aggregate(AggregationQuery::new(Query::new("User")).count("n"))
+        .await
+        .unwrap()
+        .integer("n"));
+}
+
+#[tokio::test]
+async fn test_cross_namespace_requests_are_refused() {
+    let client = Client::new("test-project", MemoryBackend::new());
+    client.put_entity(Entity { key: Some(key("tenant-b", "mallory")), properties: Default::default() })
+        .await
+        .unwrap();
+    let tenant = client.namespace("tenant-a");
+
+    assert_cross_namespace(tenant.get_entity(&key("tenant-b", "mallory")).await);
+    assert_cross_namespace(tenant.delete(key("tenant-b", "mallory")).await);
+    assert!(client.get_entity(&key("tenant-b", "mallory")).await.unwrap().is_some());
+
+    // Key values in properties and filters must not point at other tenants either.
+    let reference = Entity {
+        key: Some(key("", "eve")),
+        properties: hashmap! {
+            "friends".to_string() => Value::from(vec![Value::from(key("tenant-b", "mallory"))]),
+        },
+    };
+    assert_cross_namespace(tenant.put_entity(reference).await);
+    let query = Query::new("User")
+        .with_filter(Filter::property("friend", PropertyOperator::Equal, key("tenant-b", "mallory")));
+    assert_cross_namespace(tenant.query_keys(query).await);
+
+    let request = rpc::RunQueryRequest {
+        partition_id: Some(PartitionId::new("", "tenant-b")),
+        query: Some(Query::new("User")),
+        ..rpc::RunQueryRequest::default()
+    };
+    assert_cross_namespace(tenant.run_query(request).await);
+
+    let request = rpc::ReserveIdsRequest { project_id: String::new(), keys: vec![key("tenant-b", "x")] };
+    assert_cross_namespace(tenant.reserve_ids(request).await);
+}
+
+#[tokio::test]
+async fn test_gql_literals_are_checked() {
+    let client = Client::new("test-project", MemoryBackend::new());
+    let tenant = client.namespace("tenant-a");
+    let gql = |query: &str| rpc::RunQueryRequest {
+        gql_query: Some(GqlQuery {
+            query_string: query.to_string(),
+            allow_literals: true,
+            named_bindings: Default::default(),
+            positional_bindings: vec![],
+        }),
+        ..rpc::RunQueryRequest::default()
+    };
+
+    let query = "SELECT * FROM User WHERE __key__ = KEY(Namespace('tenant-b'), 'User', 'mallory')";
+    assert_cross_namespace(tenant.run_query(gql(query)).await);
+    let query = "SELECT * FROM User WHERE name = 'x' AND __key__ = KEY(NAMESPACE ( \"tenant-b\" ), 'User', 1)";
+    assert_cross_namespace(tenant.run_query(gql(query)).await);
+
+    // Arguments that are not plain strings and the default namespace are refused too.
+    for query in &["SELECT * FROM User WHERE __key__ = KEY(NAMESPACE(@ns), 'User', 1)",
+                   "SELECT * FROM User WHERE __key__ = KEY(NAMESPACE(''), 'User', 1)"] {
+        match tenant.run_query(gql(query)).await {
+            Err(Error::CrossNamespace { .. }) => {}
+            other => panic!("Unexpected result for {}: {:?}", query, other),
+        }
+    }
+
+    // The query's own namespace and mentions inside strings pass and reach the backend.
+    for query in &["SELECT * FROM User WHERE __key__ = KEY(NAMESPACE('tenant-a'), 'User', 'alice')",
+                   "SELECT * FROM User WHERE bio = 'KEY(NAMESPACE(''tenant-b''), ''User'', 1)'",
+                   "SELECT * FROM User WHERE __key__ = KEY('User', 'alice')",
+                   "SELECT * FROM Tenant WHERE namespace = @ns AND `namespace` > 'a'"] {
+        let err = tenant.run_query(gql(query)).await.unwrap_err();
+        assert_eq!(Some(Code::Unimplemented), err.code(), "{}", query);
+    }
+}
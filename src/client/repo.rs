@@ -0,0 +1,285 @@
+// Typed access to the entities of one kind.
+//
+// A `Repo<T>` builds keys and queries from `T`'s kind and key mapping and converts all results via
+// `T::from_entity`, so that code working with typed entities never touches raw entities. All
+// failures, including entities that can not be converted, are reported as `client::Error`.
+
+use std::marker::PhantomData;
+use serde::de::DeserializeOwned;
+
+use crate::client::{Client, Error, Result};
+use crate::datastore::{Blob, Entity, Key, PartitionId};
+use crate::datastore::query::*;
+use crate::datastore::rpc::*;
+use crate::entity::{self, DatastoreEntity};
+use crate::serde_ds;
+
+/// Number of mutations that a single commit may contain.
+const MAX_MUTATIONS: usize = 500;
+
+/// Reads and writes entities of type `T`.
+pub struct Repo<T> {
+    client: Client,
+    entity: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Repo<T> {
+    fn clone(&self) -> Repo<T> {
+        Repo { client: self.client.clone(), entity: PhantomData }
+    }
+}
+
+impl<T: DatastoreEntity> Repo<T> {
+    pub fn new(client: Client) -> Repo<T> {
+        Repo { client, entity: PhantomData }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Looks up the root entity with the given ID or name.
+    pub async fn get(&self, id: impl Into<T::Id>) -> Result<Option<T>> {
+        self.client.get_by_key(&T::key_for(None, &id.into())).await
+    }
+
+    /// Looks up the entity with the given key, e.g. one that has a parent.
+    pub async fn get_by_key(&self, key: &Key) -> Result<Option<T>> {
+        self.client.get_by_key(key).await
+    }
+
+    /// Looks up the root entities with the given IDs or names. The results are in the order of the
+    /// IDs, with `None` for missing entities.
+    pub async fn get_many<I>(&self, ids: I) -> Result<Vec<Option<T>>>
+        where I: IntoIterator,
+              I::Item: Into<T::Id>
+    {
+        let keys: Vec<Key> = ids.into_iter().map(|id| T::key_for(None, &id.into())).collect();
+        self.get_many_by_key(&keys).await
+    }
+
+    /// Looks up the entities with the given keys, see `get_many`. Keys may repeat.
+    pub async fn get_many_by_key(&self, keys: &[Key]) -> Result<Vec<Option<T>>> {
+        self.client.get_entities(keys).await?.into_iter()
+            .map(|entity| Ok(entity.map(T::from_entity).transpose()?))
+            .collect()
+    }
+
+    /// Inserts or replaces an entity and returns its key.
+    pub async fn put(&self, value: &T) -> Result<Key> {
+        self.client.put(value).await
+    }
+
+    /// Inserts or replaces entities and returns their keys. Up to 500 entities are written in one
+    /// commit, larger batches are split into several commits of which some may fail while others
+    /// succeed.
+    pub async fn put_many(&self, values: &[T]) -> Result<Vec<Key>> {
+        let mut keys = Vec::with_capacity(values.len());
+        for chunk in values.chunks(MAX_MUTATIONS) {
+            let mut mutations = Vec::with_capacity(chunk.len());
+            for value in chunk {
+                keys.push(value.key());
+                mutations.push(Mutation::Upsert(value.to_entity()?));
+            }
+            self.commit(mutations).await?;
+        }
+        Ok(keys)
+    }
+
+    /// Deletes the root entity with the given ID or name. Deleting a missing entity succeeds.
+    pub async fn delete(&self, id: impl Into<T::Id>) -> Result<()> {
+        self.client.delete(T::key_for(None, &id.into())).await
+    }
+
+    /// Deletes the entity with the given key.
+    pub async fn delete_by_key(&self, key: Key) -> Result<()> {
+        self.client.delete(key).await
+    }
+
+    async fn commit(&self, mutations: Vec<Mutation>) -> Result<CommitResponse> {
+        self.client.commit(CommitRequest {
+            project_id: String::new(),
+            mode: CommitMode::NonTransactional,
+            transaction: None,
+            mutations,
+        }).await
+    }
+
+    /// A query for all entities of this kind.
+    pub fn query(&self) -> RepoQuery<T> {
+        RepoQuery { client: self.client.clone(), query: Query::new(T::KIND), entity: PhantomData }
+    }
+
+    /// A query for the entities of this kind that descend from `parent`.
+    pub fn ancestor_query(&self, parent: Key) -> RepoQuery<T> {
+        self.query().filter(Filter::has_ancestor(parent))
+    }
+}
+
+/// A page of query results.
+#[derive(Debug)]
+pub struct Page<T> {
+    pub results: Vec<T>,
+
+    /// Cursor after the last result, to continue the query with `RepoQuery::start_cursor`.
+    pub cursor: Option<Blob>,
+
+    /// Whether the query may have more results after this page.
+    pub more: bool,
+}
+
+/// A query for entities of type `T`, created by `Repo::query`.
+pub struct RepoQuery<T> {
+    client: Client,
+    query: Query,
+    entity: PhantomData<fn() -> T>,
+}
+
+impl<T: DatastoreEntity> RepoQuery<T> {
+    /// Adds a filter, which has to hold in addition to all previous filters.
+    pub fn filter(mut self, filter: Filter) -> RepoQuery<T> {
+        self.query.filter = Some(match self.query.filter.take() {
+            None => filter,
+            Some(Filter::CompositeFilter(CompositeFilter { op: CompositeOperator::And, mut filters })) => {
+                filters.push(filter);
+                Filter::and(filters)
+            }
+            Some(previous) => Filter::and(vec![previous, filter]),
+        });
+        self
+    }
+
+    pub fn order<P: Into<String>>(mut self, property: P, direction: Direction) -> RepoQuery<T> {
+        self.query = self.query.with_order(property, direction);
+        self
+    }
+
+    pub fn limit(mut self, limit: i32) -> RepoQuery<T> {
+        self.query = self.query.with_limit(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i32) -> RepoQuery<T> {
+        self.query = self.query.with_offset(offset);
+        self
+    }
+
+    pub fn start_cursor(mut self, cursor: Blob) -> RepoQuery<T> {
+        self.query = self.query.with_start_cursor(cursor);
+        self
+    }
+
+    /// Returns only the given properties, see `Query::project`. The results have to be fetched
+    /// with `fetch_as` or `fetch_page_as`.
+    pub fn project<P: AsRef<str>>(mut self, properties: &[P]) -> RepoQuery<T> {
+        self.query = self.query.project(properties);
+        self
+    }
+
+    pub fn distinct_on<P: AsRef<str>>(mut self, properties: &[P]) -> RepoQuery<T> {
+        self.query = self.query.distinct_on(properties);
+        self
+    }
+
+    /// The query that is sent.
+    pub fn as_query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Runs the query once and returns the first batch of results.
+    pub async fn fetch_page(&self) -> Result<Page<T>> {
+        fetch_page(&self.client, self.query.clone(), T::from_entity).await
+    }
+
+    /// Runs the query to completion, continuing it as long as the API reports that it is not
+    /// finished yet.
+    pub async fn fetch(&self) -> Result<Vec<T>> {
+        fetch_all(&self.client, self.query.clone(), T::from_entity).await
+    }
+
+    /// Like `fetch_page`, but deserialises the results of a projection into `P`.
+    pub async fn fetch_page_as<P: DeserializeOwned>(&self) -> Result<Page<P>> {
+        fetch_page(&self.client, self.query.clone(), entity::from_projection).await
+    }
+
+    /// Like `fetch`, but deserialises the results of a projection into `P`.
+    pub async fn fetch_as<P: DeserializeOwned>(&self) -> Result<Vec<P>> {
+        fetch_all(&self.client, self.query.clone(), entity::from_projection).await
+    }
+
+    /// Runs the query as a keys-only query to completion and returns the keys of the results.
+    pub async fn fetch_keys(&self) -> Result<Vec<Key>> {
+        fetch_all(&self.client, self.query.clone().keys_only(), key_of).await
+    }
+
+    /// Like `fetch_keys`, but returns only the first batch of keys.
+    pub async fn fetch_keys_page(&self) -> Result<Page<Key>> {
+        fetch_page(&self.client, self.query.clone().keys_only(), key_of).await
+    }
+
+    /// Counts the results of the query with an aggregation query, without fetching them.
+    pub async fn count(&self) -> Result<i64> {
+        let result = self.client.aggregate(AggregationQuery::new(self.query.clone()).count("count"))
+            .await?;
+        result.integer("count")
+            .ok_or_else(|| Error::UnexpectedResponse("aggregation query returned no count".to_string()))
+    }
+}
+
+/// Runs a query once and converts the first batch of results.
+pub(crate) async fn fetch_page<R, F>(client: &Client, query: Query, convert: F) -> Result<Page<R>>
+    where F: Fn(Entity) -> serde_ds::Result<R>
+{
+    let batch = run(client, None, query).await?;
+    let more = batch.more_results != MoreResultsType::NoMoreResults;
+    Ok(Page { results: convert_all(batch.entity_results, &convert)?, cursor: batch.end_cursor, more })
+}
+
+/// Runs a query to completion and converts all results.
+pub(crate) async fn fetch_all<R, F>(client: &Client, query: Query, convert: F) -> Result<Vec<R>>
+    where F: Fn(Entity) -> serde_ds::Result<R>
+{
+    fetch_all_in(client, None, query, convert).await
+}
+
+/// Like `fetch_all`, but runs the query in the given partition instead of the default namespace.
+pub(crate) async fn fetch_all_in<R, F>(client: &Client, partition_id: Option<PartitionId>,
+                                       mut query: Query, convert: F) -> Result<Vec<R>>
+    where F: Fn(Entity) -> serde_ds::Result<R>
+{
+    let mut results = vec![];
+    loop {
+        let batch = run(client, partition_id.clone(), query.clone()).await?;
+        let count = batch.entity_results.len() as i32;
+        results.extend(convert_all(batch.entity_results, &convert)?);
+
+        if batch.more_results != MoreResultsType::NotFinished {
+            return Ok(results);
+        }
+        query.start_cursor = match batch.end_cursor {
+            Some(cursor) => Some(cursor),
+            None => return Err(Error::UnexpectedResponse("unfinished query without cursor".to_string())),
+        };
+        query.offset = (query.offset - batch.skipped_results).max(0);
+        query.limit = query.limit.map(|limit| limit - count);
+        if query.limit == Some(0) {
+            return Ok(results);
+        }
+    }
+}
+
+async fn run(client: &Client, partition_id: Option<PartitionId>, query: Query)
+             -> Result<QueryResultBatch> {
+    let request = RunQueryRequest { partition_id, query: Some(query), ..RunQueryRequest::default() };
+    Ok(client.run_query(request).await?.batch)
+}
+
+pub(crate) fn key_of(entity: Entity) -> serde_ds::Result<Key> {
+    entity.key.ok_or(serde_ds::Error::ExpectedType("entity with key"))
+}
+
+fn convert_all<R, F>(results: Vec<EntityResult>, convert: F) -> Result<Vec<R>>
+    where F: Fn(Entity) -> serde_ds::Result<R>
+{
+    results.into_iter().map(|result| Ok(convert(result.entity)?)).collect()
+}
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::client::*;
+use crate::client::stand_in;
+use crate::datastore::*;
+use crate::datastore::query::*;
+use crate::entity::DatastoreEntity;
+
+const PROJECT: &str = "test-project";
+
+#[derive(Debug, Clone, PartialEq, DatastoreEntity)]
+#[datastore(crate = "crate")]
+struct User {
+    #[datastore(key)]
+    name: String,
+    age: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, DatastoreEntity)]
+#[datastore(crate = "crate")]
+struct Order {
+    #[datastore(parent)]
+    user: Key,
+    #[datastore(key)]
+    id: i64,
+    total: i64,
+}
+
+fn user(name: &str, age: i64) -> User {
+    User { name: name.to_string(), age }
+}
+
+fn repo_client() -> Client {
+    Client::new(PROJECT, MemoryBackend::new())
+}
+
+#[tokio::test]
+async fn test_crud() {
+    let users = repo_client().repo::<User>();
+    assert_eq!(None, users.get("alice").await.unwrap());
+
+    users.put(&user("alice", 31)).await.unwrap();
+    let keys = users.put_many(&[user("bob", 25), user("carol", 42)]).await.unwrap();
+    assert_eq!(User::key_for(None, &"bob".to_string()), keys[0]);
+
+    assert_eq!(Some(user("alice", 31)), users.get("alice").await.unwrap());
+    assert_eq!(vec![Some(user("carol", 42)), None, Some(user("alice", 31))],
+               users.get_many(vec!["carol", "mallory", "alice"]).await.unwrap());
+    assert_eq!(vec![Some(user("alice", 31)), None, Some(user("alice", 31))],
+               users.get_many(vec!["alice", "mallory", "alice"]).await.unwrap());
+
+    users.delete("alice").await.unwrap();
+    assert_eq!(None, users.get("alice").await.unwrap());
+    users.delete("alice").await.expect("Deleting a missing entity failed");
+}
+
+#[tokio::test]
+async fn test_put_many_splits_commits() {
+    let users = repo_client().repo::<User>();
+    let many: Vec<User> = (0..1200).map(|i| user(&format!("user-{:04}", i), i)).collect();
+    assert_eq!(1200, users.put_many(&many).await.unwrap().len());
+
+    let names: Vec<String> = (0..1200).map(|i| format!("user-{:04}", i)).collect();
+    let found = users.get_many(names).await.unwrap();
+    assert!(found.iter().all(Option::is_some));
+    assert_eq!(1200, users.query().fetch().await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_queries() {
+    let client = repo_client();
+    let users = client.repo::<User>();
+    users.put_many(&[user("alice", 31), user("bob", 25), user("carol", 42), user("dave", 25)])
+        .await
+        .unwrap();
+
+    let younger = users.query()
+        .filter(Filter::property("age", PropertyOperator::LessThan, 40))
+        .order("age", Direction::Descending)
+        .order("__key__", Direction::Ascending);
+    assert_eq!(vec![user("alice", 31), user("bob", 25), user("dave", 25)],
+               younger.fetch().await.unwrap());
+
+    let page = users.query().order("age", Direction::Ascending).limit(3).fetch_page().await.unwrap();
+    assert_eq!(3, page.results.len());
+    assert!(page.more);
+    let rest = users.query()
+        .order("age", Direction::Ascending)
+        .start_cursor(page.cursor.unwrap())
+        .fetch_page()
+        .await
+        .unwrap();
+    assert_eq!(vec![user("carol", 42)], rest.results);
+    assert!(!rest.more);
+
+    // Entities of other kinds are not returned.
+    client.put_entity(Entity {
+        key: Some(Key::new(PartitionId::new("", ""), vec![PathElement::from_name("Admin", "root")])),
+        properties: Default::default(),
+    }).await.unwrap();
+    assert_eq!(4, users.query().fetch().await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_ancestor_query() {
+    let client = repo_client();
+    let alice = client.put(&user("alice", 31)).await.unwrap();
+    let bob = client.put(&user("bob", 25)).await.unwrap();
+
+    let orders = client.repo::<Order>();
+    orders.put_many(&[
+        Order { user: alice.clone(), id: 1, total: 100 },
+        Order { user: alice.clone(), id: 2, total: 250 },
+        Order { user: bob.clone(), id: 3, total: 75 },
+    ]).await.unwrap();
+
+    let totals: Vec<i64> = orders.ancestor_query(alice.clone())
+        .filter(Filter::property("total", PropertyOperator::GreaterThan, 50))
+        .order("total", Direction::Descending)
+        .fetch()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|order| order.total)
+        .collect();
+    assert_eq!(vec![250, 100], totals);
+
+    let key = Order::key_for(Some(&bob), &3);
+    let order = orders.get_by_key(&key).await.unwrap().unwrap();
+    assert_eq!(75, order.total);
+    assert_eq!(bob.path(), order.user.path());
+}
+
+#[tokio::test]
+async fn test_conversion_errors() {
+    let client = repo_client();
+    client.put_entity(Entity {
+        key: Some(User::key_for(None, &"alice".to_string())),
+        properties: hashmap! { "age".to_string() => Value::from("old") },
+    }).await.unwrap();
+
+    let users = client.repo::<User>();
+    match users.get("alice").await {
+        Err(Error::Serde(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    match users.query().fetch().await {
+        Err(Error::Serde(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Listing {
+    email: String,
+    created: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Tagged {
+    tag: String,
+}
+
+fn account(name: &str, day: u32, tags: &[&str]) -> Entity {
+    let tags: Vec<Value> = tags.iter().map(|tag| Value::from(*tag)).collect();
+    Entity {
+        key: Some(User::key_for(None, &name.to_string())),
+        properties: hashmap! {
+            "email".to_string() => Value::from(format!("{}@example.com", name)),
+            "created".to_string() => Value::from(Utc.with_ymd_and_hms(2018, 3, day, 0, 0, 0).unwrap()),
+            "tag".to_string() => Value::from(tags),
+            "bio".to_string() => Value::from("unindexed").unindexed(),
+        },
+    }
+}
+
+// Projections return index entries, the same expectations hold for every transport.
+async fn projections(client: &Client) {
+    for entity in [account("alice", 3, &["admin", "dev"]), account("bob", 1, &["dev"]),
+                       account("carol", 2, &[])] {
+        client.put_entity(entity).await.unwrap();
+    }
+    let users = client.repo::<User>();
+
+    let listings: Vec<Listing> = users.query()
+        .project(&["email", "created"])
+        .order("created", Direction::Ascending)
+        .fetch_as()
+        .await
+        .unwrap();
+    let emails: Vec<&str> = listings.iter().map(|l| l.email.as_str()).collect();
+    assert_eq!(vec!["bob@example.com", "carol@example.com", "alice@example.com"], emails);
+    assert_eq!(Utc.with_ymd_and_hms(2018, 3, 1, 0, 0, 0).unwrap(), listings[0].created);
+
+    let page = users.query().project(&["email", "created"]).limit(1).fetch_page_as::<Listing>()
+        .await
+        .unwrap();
+    assert_eq!(1, page.results.len());
+    assert!(page.more);
+
+    // Timestamps arrive as integers with meaning 7.
+    let response = client.run_query(RunQueryRequest {
+        query: Some(Query::new("User").project(&["created"]).with_limit(1)),
+        ..RunQueryRequest::default()
+    }).await.unwrap();
+    assert_eq!(ResultType::Projection, response.batch.entity_result_type);
+    let created = &response.batch.entity_results[0].entity.properties["created"];
+    assert_eq!(Some(meaning::GD_WHEN), created.meaning());
+
+    // Arrays yield one row per element, entities without indexed values none.
+    let tags: Vec<Tagged> = users.query()
+        .project(&["tag"])
+        .order("tag", Direction::Ascending)
+        .fetch_as()
+        .await
+        .unwrap();
+    let tags: Vec<&str> = tags.iter().map(|t| t.tag.as_str()).collect();
+    assert_eq!(vec!["admin", "dev", "dev"], tags);
+
+    let distinct: Vec<Tagged> = users.query()
+        .project(&["tag"])
+        .distinct_on(&["tag"])
+        .order("tag", Direction::Ascending)
+        .fetch_as()
+        .await
+        .unwrap();
+    assert_eq!(2, distinct.len());
+
+    assert!(users.query().project(&["bio"]).fetch_as::<HashMap<String, String>>().await.unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_projections() {
+    projections(&repo_client()).await;
+
+    let stand_in = stand_in::rest(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT).endpoint(stand_in.endpoint()).build().unwrap();
+    projections(&client).await;
+
+    let stand_in = stand_in::grpc(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Grpc)
+        .build()
+        .unwrap();
+    projections(&client).await;
+}
+
+#[tokio::test]
+async fn test_distinct_on_requires_projection() {
+    let client = repo_client();
+    let err = client.repo::<User>().query().distinct_on(&["age"]).fetch().await.unwrap_err();
+    assert_eq!(Some(Code::InvalidArgument), err.code());
+}
+
+#[derive(Debug, Clone, PartialEq, DatastoreEntity)]
+#[datastore(crate = "crate")]
+struct Job {
+    #[datastore(key)]
+    id: i64,
+    shard: i64,
+}
+
+// Key-range scans, the same expectations hold for every transport.
+async fn key_ranges(client: &Client) {
+    let jobs = client.repo::<Job>();
+    let all: Vec<Job> = (1..=10).map(|id| Job { id, shard: id % 2 }).collect();
+    jobs.put_many(&all).await.unwrap();
+
+    let key = |id: i64| Job::key_for(None, &id);
+    let range = Query::new("Job")
+        .with_filter(Filter::and(vec![
+            Filter::key(PropertyOperator::GreaterThanOrEqual, key(3)),
+            Filter::key(PropertyOperator::LessThan, key(7)),
+        ]))
+        .with_key_order(Direction::Descending);
+    let ids: Vec<i64> = client.query_keys(range.clone()).await.unwrap().iter()
+        .map(|key| key.path()[0].id().unwrap())
+        .collect();
+    assert_eq!(vec![6, 5, 4, 3], ids);
+
+    let response = client.run_query(RunQueryRequest {
+        query: Some(range.keys_only()),
+        ..RunQueryRequest::default()
+    }).await.unwrap();
+    assert_eq!(ResultType::KeyOnly, response.batch.entity_result_type);
+    assert!(response.batch.entity_results.iter().all(|r| r.entity.properties.is_empty()));
+    assert_eq!(4, response.batch.keys().count());
+
+    let page = jobs.query()
+        .filter(Filter::property("shard", PropertyOperator::Equal, 0))
+        .filter(Filter::key(PropertyOperator::GreaterThan, key(4)))
+        .order("__key__", Direction::Ascending)
+        .limit(2)
+        .fetch_keys_page()
+        .await
+        .unwrap();
+    assert_eq!(vec![6, 8], page.results.iter().map(|k| k.path()[0].id().unwrap()).collect::<Vec<_>>());
+    assert!(page.more);
+    assert_eq!(1, jobs.query().filter(Filter::key(PropertyOperator::Equal, key(10))).fetch_keys()
+        .await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_key_ranges() {
+    key_ranges(&repo_client()).await;
+
+    let stand_in = stand_in::rest(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT).endpoint(stand_in.endpoint()).build().unwrap();
+    key_ranges(&client).await;
+
+    let stand_in = stand_in::grpc(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Grpc)
+        .build()
+        .unwrap();
+    key_ranges(&client).await;
+}
+
+#[derive(Debug, Clone, PartialEq, DatastoreEntity)]
+#[datastore(crate = "crate")]
+struct Sale {
+    #[datastore(key)]
+    id: i64,
+    region: String,
+    amount: f64,
+    units: i64,
+}
+
+// Aggregations, the same expectations hold for every transport.
+async fn aggregations(client: &Client) {
+    let sales = client.repo::<Sale>();
+    sales.put_many(&[
+        Sale { id: 1, region: "eu".to_string(), amount: 10.5, units: 3 },
+        Sale { id: 2, region: "eu".to_string(), amount: 4.5, units: 1 },
+        Sale { id: 3, region: "us".to_string(), amount: 20.0, units: 8 },
+    ]).await.unwrap();
+
+    let query = AggregationQuery::new(Query::new("Sale"))
+        .count("n")
+        .count_up_to("capped", 2)
+        .sum("units", "units")
+        .sum("amount", "amount")
+        .avg("avg_units", "units");
+    let result = client.aggregate(query).await.unwrap();
+    assert_eq!(Some(3), result.integer("n"));
+    assert_eq!(Some(2), result.integer("capped"));
+    assert_eq!(Some(12), result.integer("units"));
+    assert_eq!(Some(35.0), result.double("amount"));
+    assert_eq!(Some(4.0), result.double("avg_units"));
+
+    let eu = Query::new("Sale").with_filter(Filter::property("region", PropertyOperator::Equal, "eu"));
+    let result = client.aggregate(AggregationQuery::new(eu).sum("units", "units")).await.unwrap();
+    assert_eq!(Some(4), result.integer("units"));
+
+    // The average of no values is null, the sum zero.
+    let none = Query::new("Sale").with_filter(Filter::property("region", PropertyOperator::Equal, "ap"));
+    let result = client.aggregate(AggregationQuery::new(none).avg("a", "units").sum("s", "units"))
+        .await
+        .unwrap();
+    assert_eq!(Some(&Value::from(())), result.get("a"));
+    assert_eq!(Some(0), result.integer("s"));
+
+    assert_eq!(2, sales.query().limit(2).count().await.unwrap());
+    assert_eq!(1, sales.query().filter(Filter::property("units", PropertyOperator::GreaterThan, 5))
+        .count().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_aggregations() {
+    aggregations(&repo_client()).await;
+
+    let stand_in = stand_in::rest(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT).endpoint(stand_in.endpoint()).build().unwrap();
+    aggregations(&client).await;
+
+    let stand_in = stand_in::grpc(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Grpc)
+        .build()
+        .unwrap();
+    aggregations(&client).await;
+}
+
+#[tokio::test]
+async fn test_duplicate_aliases() {
+    let query = AggregationQuery::new(Query::new("Sale")).count("n").sum("n", "units");
+    let err = repo_client().aggregate(query).await.unwrap_err();
+    assert_eq!(Some(Code::InvalidArgument), err.code());
+}
@@ -0,0 +1,153 @@
+// REST transport: JSON over HTTP/1.1.
+//
+// Connections are kept alive and reused: a request takes an idle connection from the pool or
+// opens a new one, and returns it to the pool once the response has been read.
+//
+// See https://cloud.google.com/datastore/docs/reference/rest
+
+use std::sync::Mutex;
+use bytes::Bytes;
+use http::{header, Method as HttpMethod, Request, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::client::conn::http1;
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
+
+use crate::client::{BoxFuture, Call, Code, Endpoint, Error, Method, Result};
+
+/// Number of idle connections that are kept open at most.
+const MAX_IDLE_CONNECTIONS: usize = 16;
+
+type Sender = http1::SendRequest<Full<Bytes>>;
+
+/// Sends requests to the REST API.
+pub struct RestTransport {
+    endpoint: Endpoint,
+    access_token: Option<String>,
+    idle: Mutex<Vec<Sender>>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorStatus,
+}
+
+#[derive(Deserialize)]
+struct ErrorStatus {
+    #[serde(default)]
+    message: String,
+    status: Option<Code>,
+}
+
+impl RestTransport {
+    pub fn new(endpoint: Endpoint, access_token: Option<String>) -> RestTransport {
+        RestTransport { endpoint, access_token, idle: Mutex::new(vec![]) }
+    }
+
+    /// Returns a connection that is ready for a request, preferring idle connections.
+    async fn sender(&self) -> Result<Sender> {
+        loop {
+            let idle = self.idle.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop();
+            let mut sender = match idle {
+                Some(sender) => sender,
+                None => break,
+            };
+            if sender.ready().await.is_ok() {
+                return Ok(sender);
+            }
+        }
+
+        let stream = self.endpoint.connect(b"http/1.1").await?;
+        let (sender, connection) = http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(connection);
+        Ok(sender)
+    }
+
+    fn release(&self, sender: Sender) {
+        if sender.is_closed() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(sender);
+        }
+    }
+
+    async fn send<M: Method>(&self, request: M) -> Result<M::Response> {
+        let path = format!("/v1/projects/{}:{}", request.project_id(), M::REST);
+
+        // The project ID is part of the path, it is not accepted in the body.
+        let mut body = serde_json::to_value(&request)?;
+        if let JsonValue::Object(ref mut fields) = body {
+            fields.remove("projectId");
+        }
+        self.request(HttpMethod::POST, &path, Some(&body)).await
+    }
+
+    /// Sends a request to a path of the API, e.g. for the methods of the Admin API, which do not
+    /// follow the `projects/{projectId}:{method}` pattern.
+    pub(crate) async fn request<T: DeserializeOwned>(&self, method: HttpMethod, path: &str,
+                                                     body: Option<&JsonValue>) -> Result<T> {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(header::HOST, self.endpoint.authority());
+        if body.is_some() {
+            builder = builder.header(header::CONTENT_TYPE, "application/json");
+        }
+        if let Some(ref token) = self.access_token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let body = match body {
+            Some(body) => Bytes::from(serde_json::to_vec(body)?),
+            None => Bytes::new(),
+        };
+        let http_request = builder.body(Full::new(body))?;
+
+        let mut sender = self.sender().await?;
+        let response = sender.send_request(http_request).await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        self.release(sender);
+
+        if status == StatusCode::OK {
+            return Ok(serde_json::from_slice(&body)?);
+        }
+
+        match serde_json::from_slice::<ErrorResponse>(&body) {
+            Ok(ErrorResponse { error }) => {
+                let code = error.status.unwrap_or_else(|| code_for_status(status));
+                Err(Error::api(code, error.message))
+            }
+            Err(_) => Err(Error::Http {
+                status: status.as_u16(),
+                body: String::from_utf8_lossy(&body).into_owned(),
+            }),
+        }
+    }
+}
+
+/// Maps an HTTP status to the code it most likely stands for, for error responses without status.
+fn code_for_status(status: StatusCode) -> Code {
+    match status.as_u16() {
+        400 => Code::InvalidArgument,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        409 => Code::Aborted,
+        429 => Code::ResourceExhausted,
+        499 => Code::Cancelled,
+        500 => Code::Internal,
+        501 => Code::Unimplemented,
+        503 => Code::Unavailable,
+        504 => Code::DeadlineExceeded,
+        _ => Code::Unknown,
+    }
+}
+
+impl Call for RestTransport {
+    fn call<M: Method>(&self, request: M) -> BoxFuture<'_, Result<M::Response>> {
+        Box::pin(self.send(request))
+    }
+}
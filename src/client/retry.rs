@@ -0,0 +1,166 @@
+// Retrying of transient failures.
+//
+// Failed requests are sent again if the error is transient (see `Error::is_retryable`) and sending
+// the request again can not apply a change twice. Reads can always be retried. Commits are only
+// retried if they belong to a transaction, which the API commits at most once, or if they consist
+// of upserts of complete keys only, which have the same outcome no matter how often they are
+// applied. An upsert of an incomplete key makes the server allocate a new ID on every attempt.
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+use crate::client::{Code, Error, Result};
+use crate::datastore::Key;
+use crate::datastore::rpc::{CommitRequest, Mutation};
+
+/// Controls how often and how long failed requests are retried.
+///
+/// The delay before the n-th retry is `initial_backoff * multiplier^(n-1)`, capped at
+/// `max_backoff`, of which a random amount of up to a half is skipped so that clients that failed
+/// at the same time do not retry in lockstep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    deadline: Option<Duration>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            deadline: Some(Duration::from_secs(60)),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that sends every request once.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy::default().with_max_attempts(1)
+    }
+
+    /// Sets how often a request is sent at most, including the first attempt.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> RetryPolicy {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Limits the total time spent on a request including all retries, `None` for no limit. A
+    /// request that runs out of time fails with `Code::DeadlineExceeded`.
+    pub fn with_deadline(mut self, deadline: Option<Duration>) -> RetryPolicy {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Sets the delay before the first retry and the upper limit of the delay.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> RetryPolicy {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Sets the factor by which the delay grows with every retry.
+    pub fn with_multiplier(mut self, multiplier: f64) -> RetryPolicy {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// The delay before the given retry (starting at 1) without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.saturating_sub(1).min(64) as i32);
+        let backoff = self.initial_backoff.as_secs_f64() * factor;
+        Duration::from_secs_f64(backoff.min(self.max_backoff.as_secs_f64()))
+    }
+
+    fn jittered_backoff(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        backoff - backoff.mul_f64(random_fraction() / 2.0)
+    }
+
+    /// Waits before the given retry (starting at 1) of a request that the API answered only in
+    /// part, e.g. a lookup that deferred keys.
+    pub(crate) async fn pause(&self, retry: u32) {
+        time::sleep(self.jittered_backoff(retry)).await;
+    }
+
+    /// Runs `attempt` until it succeeds, fails permanently or the policy is exhausted.
+    /// `retryable` decides whether a failed attempt may be repeated.
+    pub(crate) async fn run<T, F, Fut, R>(&self, mut attempt: F, retryable: R) -> Result<T>
+        where F: FnMut() -> Fut,
+              Fut: Future<Output = Result<T>>,
+              R: Fn(&Error) -> bool
+    {
+        let deadline = self.deadline.map(|deadline| Instant::now() + deadline);
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+            let result = match deadline {
+                Some(deadline) => match time::timeout_at(deadline, attempt()).await {
+                    Ok(result) => result,
+                    Err(_) => return Err(deadline_exceeded(attempts)),
+                },
+                None => attempt().await,
+            };
+
+            let err = match result {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            if attempts >= self.max_attempts || !retryable(&err) {
+                return Err(err);
+            }
+
+            let wake = Instant::now() + self.jittered_backoff(attempts);
+            if deadline.is_some_and(|deadline| wake >= deadline) {
+                return Err(err);
+            }
+            time::sleep_until(wake).await;
+        }
+    }
+}
+
+fn deadline_exceeded(attempts: u32) -> Error {
+    Error::api(Code::DeadlineExceeded, format!("retry deadline exceeded after {} attempts", attempts))
+}
+
+/// A random number in `[0, 1)`, good enough for spreading out retries.
+fn random_fraction() -> f64 {
+    // Every `RandomState` is seeded differently, so hashing nothing yields a new number each time.
+    let hasher = RandomState::new().build_hasher();
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether a failed commit may be sent again.
+pub(crate) fn commit_retryable(request: &CommitRequest, err: &Error) -> bool {
+    if !err.is_retryable() {
+        return false;
+    }
+
+    if request.transaction.is_some() {
+        // An aborted transaction can not be committed anymore, it has to be run again as a whole.
+        return err.code() != Some(Code::Aborted);
+    }
+
+    request.mutations.iter().all(|mutation| match *mutation {
+        Mutation::Upsert(ref entity) => entity.key.as_ref().is_some_and(Key::is_complete),
+        _ => false,
+    })
+}
@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+use std::future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::client::*;
+use crate::datastore::*;
+use crate::datastore::query::Query;
+
+const PROJECT: &str = "test-project";
+
+// A fault that is injected into the next request.
+struct Fault {
+    error: Error,
+
+    // Whether the request is applied before the error is reported, like a response that got lost.
+    applied: bool,
+}
+
+#[derive(Default)]
+struct Faults {
+    pending: Mutex<VecDeque<Fault>>,
+    attempts: Mutex<usize>,
+}
+
+impl Faults {
+    fn inject(&self, count: usize, error: fn() -> Error, applied: bool) {
+        let mut pending = self.pending.lock().unwrap();
+        for _ in 0..count {
+            pending.push_back(Fault { error: error(), applied });
+        }
+    }
+
+    fn attempts(&self) -> usize {
+        *self.attempts.lock().unwrap()
+    }
+
+    fn reset_attempts(&self) {
+        *self.attempts.lock().unwrap() = 0;
+    }
+}
+
+// Transport that fails requests as instructed and passes all others on to a memory backend.
+struct FaultyTransport {
+    backend: MemoryBackend,
+    faults: Arc<Faults>,
+}
+
+impl FaultyTransport {
+    fn handle<'a, T, F>(&'a self, request: F) -> BoxFuture<'a, Result<T>>
+        where T: Send + 'a,
+              F: FnOnce() -> BoxFuture<'a, Result<T>>
+    {
+        *self.faults.attempts.lock().unwrap() += 1;
+        let fault = self.faults.pending.lock().unwrap().pop_front();
+        match fault {
+            None => request(),
+            Some(Fault { error, applied: false }) => Box::pin(future::ready(Err(error))),
+            Some(Fault { error, applied: true }) => {
+                let request = request();
+                Box::pin(async move {
+                    let _ = request.await;
+                    Err(error)
+                })
+            }
+        }
+    }
+}
+
+impl Transport for FaultyTransport {
+    fn lookup(&self, request: LookupRequest) -> BoxFuture<'_, Result<LookupResponse>> {
+        self.handle(|| self.backend.lookup(request))
+    }
+
+    fn run_query(&self, request: RunQueryRequest) -> BoxFuture<'_, Result<RunQueryResponse>> {
+        self.handle(|| self.backend.run_query(request))
+    }
+
+    fn run_aggregation_query(&self, request: RunAggregationQueryRequest)
+                             -> BoxFuture<'_, Result<RunAggregationQueryResponse>> {
+        self.handle(|| self.backend.run_aggregation_query(request))
+    }
+
+    fn begin_transaction(&self, request: BeginTransactionRequest)
+                         -> BoxFuture<'_, Result<BeginTransactionResponse>> {
+        self.handle(|| self.backend.begin_transaction(request))
+    }
+
+    fn commit(&self, request: CommitRequest) -> BoxFuture<'_, Result<CommitResponse>> {
+        self.handle(|| self.backend.commit(request))
+    }
+
+    fn rollback(&self, request: RollbackRequest) -> BoxFuture<'_, Result<RollbackResponse>> {
+        self.handle(|| self.backend.rollback(request))
+    }
+
+    fn allocate_ids(&self, request: AllocateIdsRequest)
+                    -> BoxFuture<'_, Result<AllocateIdsResponse>> {
+        self.handle(|| self.backend.allocate_ids(request))
+    }
+
+    fn reserve_ids(&self, request: ReserveIdsRequest) -> BoxFuture<'_, Result<ReserveIdsResponse>> {
+        self.handle(|| self.backend.reserve_ids(request))
+    }
+}
+
+fn faulty_client(policy: RetryPolicy) -> (Client, Arc<Faults>) {
+    let faults = Arc::new(Faults::default());
+    let transport = FaultyTransport { backend: MemoryBackend::new(), faults: faults.clone() };
+    (Client::new(PROJECT, transport).with_retry_policy(policy), faults)
+}
+
+fn unavailable() -> Error {
+    Error::api(Code::Unavailable, "try again")
+}
+
+fn user_key(name: &str) -> Key {
+    Key::new(PartitionId::new(PROJECT, ""), vec![PathElement::from_name("User", name)])
+}
+
+fn user(name: &str) -> Entity {
+    Entity {
+        key: Some(user_key(name)),
+        properties: hashmap! { "name".to_string() => Value::from(name) },
+    }
+}
+
+async fn commit(client: &Client, mutations: Vec<Mutation>) -> Result<CommitResponse> {
+    client.commit(CommitRequest {
+        project_id: String::new(),
+        mode: CommitMode::NonTransactional,
+        transaction: None,
+        mutations,
+    }).await
+}
+
+#[test]
+fn test_error_classification() {
+    for &code in &[Code::Aborted, Code::Unavailable, Code::DeadlineExceeded, Code::ResourceExhausted,
+                   Code::Internal] {
+        assert!(Error::api(code, "").is_retryable(), "{:?} should be retryable", code);
+    }
+    for &code in &[Code::InvalidArgument, Code::NotFound, Code::AlreadyExists, Code::PermissionDenied,
+                   Code::FailedPrecondition, Code::Unauthenticated, Code::Unknown] {
+        assert!(!Error::api(code, "").is_retryable(), "{:?} should be permanent", code);
+    }
+
+    assert!(Error::Http { status: 503, body: String::new() }.is_retryable());
+    assert!(Error::Http { status: 502, body: String::new() }.is_retryable());
+    assert!(!Error::Http { status: 404, body: String::new() }.is_retryable());
+    assert!(Error::Io(std::io::ErrorKind::ConnectionReset.into()).is_retryable());
+    assert!(!Error::Transport("garbage".to_string()).is_retryable());
+}
+
+#[test]
+fn test_backoff() {
+    let policy = RetryPolicy::default()
+        .with_backoff(Duration::from_millis(100), Duration::from_secs(1))
+        .with_multiplier(3.0);
+    assert_eq!(Duration::from_millis(100), policy.backoff(1));
+    assert_eq!(Duration::from_millis(300), policy.backoff(2));
+    assert_eq!(Duration::from_millis(900), policy.backoff(3));
+    assert_eq!(Duration::from_secs(1), policy.backoff(4));
+    assert_eq!(Duration::from_secs(1), policy.backoff(100));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_reads_are_retried() {
+    let (client, faults) = faulty_client(RetryPolicy::default().with_max_attempts(4));
+
+    faults.inject(3, unavailable, false);
+    let started = Instant::now();
+    assert_eq!(None, client.get_entity(&user_key("alice")).await.unwrap());
+    assert_eq!(4, faults.attempts());
+
+    // Retries wait 100, 200 and 400ms, of which up to a half is skipped.
+    let waited = started.elapsed();
+    assert!(waited >= Duration::from_millis(350), "waited {:?}", waited);
+    assert!(waited <= Duration::from_millis(700), "waited {:?}", waited);
+
+    faults.reset_attempts();
+    faults.inject(4, unavailable, false);
+    let err = client.get_entity(&user_key("alice")).await.unwrap_err();
+    assert_eq!(Some(Code::Unavailable), err.code());
+    assert_eq!(4, faults.attempts());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_permanent_errors_are_not_retried() {
+    let (client, faults) = faulty_client(RetryPolicy::default());
+    faults.inject(1, || Error::api(Code::PermissionDenied, "no"), false);
+
+    let err = client.get_entity(&user_key("alice")).await.unwrap_err();
+    assert_eq!(Some(Code::PermissionDenied), err.code());
+    assert_eq!(1, faults.attempts());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_deadline() {
+    let policy = RetryPolicy::default()
+        .with_max_attempts(100)
+        .with_backoff(Duration::from_secs(1), Duration::from_secs(1))
+        .with_deadline(Some(Duration::from_secs(5)));
+    let (client, faults) = faulty_client(policy);
+    faults.inject(100, || Error::api(Code::ResourceExhausted, "slow down"), false);
+
+    let started = Instant::now();
+    let err = client.get_entity(&user_key("alice")).await.unwrap_err();
+    assert_eq!(Some(Code::ResourceExhausted), err.code());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(faults.attempts() >= 6, "only {} attempts", faults.attempts());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_upserts_are_retried() {
+    let (client, faults) = faulty_client(RetryPolicy::default());
+
+    // The first attempt is applied but its response is lost, applying it again is harmless.
+    faults.inject(1, unavailable, true);
+    faults.inject(1, || Error::api(Code::Aborted, "contention"), false);
+    client.put_entity(user("alice")).await.expect("Upsert failed");
+    assert_eq!(3, faults.attempts());
+    assert_eq!(Some(user("alice")), client.get_entity(&user_key("alice")).await.unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_non_idempotent_commits_are_not_retried() {
+    let (client, faults) = faulty_client(RetryPolicy::default());
+
+    faults.inject(1, unavailable, true);
+    let err = commit(&client, vec![Mutation::Insert(user("alice"))]).await.unwrap_err();
+    assert_eq!(Some(Code::Unavailable), err.code());
+    assert_eq!(1, faults.attempts());
+
+    // Retrying would have failed the insert with ALREADY_EXISTS, although it was applied.
+    assert!(client.get_entity(&user_key("alice")).await.unwrap().is_some());
+
+    faults.reset_attempts();
+    faults.inject(1, unavailable, false);
+    let err = commit(&client, vec![Mutation::Upsert(user("bob")), Mutation::Delete(user_key("alice"))])
+        .await
+        .unwrap_err();
+    assert_eq!(Some(Code::Unavailable), err.code());
+    assert_eq!(1, faults.attempts());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_upserts_of_incomplete_keys_are_not_retried() {
+    let (client, faults) = faulty_client(RetryPolicy::default());
+
+    faults.inject(1, unavailable, true);
+    let entity = Entity {
+        key: Some(Key::new(PartitionId::new(PROJECT, ""), vec![PathElement::incomplete("User")])),
+        properties: hashmap! { "name".to_string() => Value::from("carol") },
+    };
+    let err = commit(&client, vec![Mutation::Upsert(entity)]).await.unwrap_err();
+    assert_eq!(Some(Code::Unavailable), err.code());
+    assert_eq!(1, faults.attempts());
+
+    // Retrying would have stored the entity a second time under another allocated ID.
+    assert_eq!(1, client.query_keys(Query::new("User")).await.unwrap().len());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_transactional_commits_are_retried() {
+    let (client, faults) = faulty_client(RetryPolicy::default());
+
+    let mut transaction = client.transaction().await.unwrap();
+    transaction.insert(user("alice"));
+    faults.reset_attempts();
+    faults.inject(2, || Error::Http { status: 503, body: String::new() }, false);
+    transaction.commit().await.expect("Commit failed");
+    assert_eq!(3, faults.attempts());
+
+    // An aborted transaction can not be committed again.
+    let mut transaction = client.transaction().await.unwrap();
+    transaction.update(user("alice"));
+    faults.reset_attempts();
+    faults.inject(1, || Error::api(Code::Aborted, "contention"), false);
+    let err = transaction.commit().await.unwrap_err();
+    assert_eq!(Some(Code::Aborted), err.code());
+    assert_eq!(1, faults.attempts());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_no_retries() {
+    let (client, faults) = faulty_client(RetryPolicy::none());
+    faults.inject(1, unavailable, false);
+
+    assert!(client.get_entity(&user_key("alice")).await.is_err());
+    assert_eq!(1, faults.attempts());
+}
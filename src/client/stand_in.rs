@@ -0,0 +1,493 @@
+// Local stand-ins for the REST and gRPC endpoints, serving the API of any `Transport`, and for the
+// Admin API.
+//
+// Each server runs on its own thread with its own runtime, so that it can be used by blocking
+// clients as well as from async tests.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use bytes::{Bytes, BytesMut};
+use http::{HeaderMap, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Builder;
+
+use crate::client::{Code, Error, Result, Transport};
+use crate::client::grpc::{decode_message, frame, unframe};
+use crate::datastore::admin::*;
+use crate::proto::v1::{self, Message};
+
+type Backend = Arc<dyn Transport>;
+
+/// Calls the backend method named `$name`, converting the request and response with the given
+/// functions.
+macro_rules! dispatch {
+    ($backend:expr, $name:expr, $decode:ident, $encode:ident, $input:expr,
+     $($method:ident: $rest:expr, $grpc:expr;)*) => {
+        match $name {
+            $(
+                $rest | $grpc => {
+                    let request = $decode($input)?;
+                    $encode(&$backend.$method(request).await?)
+                }
+            )*
+            _ => Err(Error::api(Code::Unimplemented, format!("unknown method {}", $name))),
+        }
+    };
+}
+
+macro_rules! dispatch_all {
+    ($backend:expr, $name:expr, $decode:ident, $encode:ident, $input:expr) => {
+        dispatch!($backend, $name, $decode, $encode, $input,
+                  lookup: "lookup", "Lookup";
+                  run_query: "runQuery", "RunQuery";
+                  run_aggregation_query: "runAggregationQuery", "RunAggregationQuery";
+                  begin_transaction: "beginTransaction", "BeginTransaction";
+                  commit: "commit", "Commit";
+                  rollback: "rollback", "Rollback";
+                  allocate_ids: "allocateIds", "AllocateIds";
+                  reserve_ids: "reserveIds", "ReserveIds";)
+    };
+}
+
+/// A running stand-in server.
+pub struct StandIn {
+    pub addr: SocketAddr,
+    connections: Arc<AtomicUsize>,
+}
+
+impl StandIn {
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Number of connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+fn spawn_server<F, Fut>(serve: F) -> StandIn
+    where F: FnOnce(TcpStream) -> Fut + Clone + Send + 'static,
+          Fut: std::future::Future<Output = ()> + Send + 'static
+{
+    // The listener is bound before the thread starts, so that connections can be made right away.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let counter = connections.clone();
+    thread::spawn(move || {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async move {
+            let listener = TcpListener::from_std(listener).unwrap();
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve.clone()(stream));
+            }
+        })
+    });
+
+    StandIn { addr, connections }
+}
+
+/// Starts a REST endpoint backed by `backend`.
+pub fn rest(backend: Backend) -> StandIn {
+    flaky_rest(backend, 0)
+}
+
+/// Starts a REST endpoint backed by `backend` that closes its first `drops` connections as soon
+/// as a request arrives on them, without answering it.
+pub fn flaky_rest(backend: Backend, drops: usize) -> StandIn {
+    let drops = Arc::new(AtomicUsize::new(drops));
+    spawn_server(move |mut stream| async move {
+        if drops.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+            let _ = stream.read(&mut [0; 64]).await;
+            return;
+        }
+        let service = service_fn(move |request| {
+            let backend = backend.clone();
+            async move { Ok::<_, Infallible>(serve_rest(backend, request).await) }
+        });
+        let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+    })
+}
+
+async fn serve_rest(backend: Backend, request: Request<Incoming>) -> Response<Full<Bytes>> {
+    let result = async {
+        // Paths have the form `/v1/projects/{projectId}:{method}`.
+        let path = request.uri().path().to_string();
+        let (project_id, method) = path.strip_prefix("/v1/projects/")
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(|| Error::Transport(format!("unknown path {}", path)))?;
+
+        let body = request.into_body().collect().await?.to_bytes();
+        let mut json: JsonValue = serde_json::from_slice(&body)?;
+        if let JsonValue::Object(ref mut fields) = json {
+            fields.insert("projectId".to_string(), project_id.into());
+        }
+
+        dispatch_all!(backend, method, from_json, to_json, json)
+    }.await;
+
+    let (status, body) = match result {
+        Ok(body) => (StatusCode::OK, body),
+        Err(Error::Api { code, message }) => {
+            let body = json!({"error": {"code": code.http_status(), "message": message, "status": code}});
+            (StatusCode::from_u16(code.http_status()).unwrap(), serde_json::to_vec(&body).unwrap())
+        }
+        Err(err) => (StatusCode::BAD_REQUEST, err.to_string().into_bytes()),
+    };
+
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+fn from_json<T: DeserializeOwned>(json: JsonValue) -> Result<T> {
+    Ok(serde_json::from_value(json)?)
+}
+
+fn to_json<T: Serialize>(response: &T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(response)?)
+}
+
+/// Starts a gRPC endpoint backed by `backend`.
+pub fn grpc(backend: Backend) -> StandIn {
+    spawn_server(move |stream| async move {
+        let mut connection = match h2::server::handshake(stream).await {
+            Ok(connection) => connection,
+            Err(_) => return,
+        };
+        while let Some(Ok((request, respond))) = connection.accept().await {
+            tokio::spawn(serve_grpc(backend.clone(), request, respond));
+        }
+    })
+}
+
+async fn serve_grpc(backend: Backend, request: Request<h2::RecvStream>,
+                    mut respond: h2::server::SendResponse<Bytes>) {
+    // Paths have the form `/google.datastore.v1.Datastore/{Method}`.
+    let method = request.uri().path().rsplit('/').next().unwrap_or("").to_string();
+
+    let result = async {
+        let mut body = request.into_body();
+        let mut data = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            body.flow_control().release_capacity(chunk.len())?;
+            data.extend_from_slice(&chunk);
+        }
+        let message = unframe(data.freeze())?;
+
+        dispatch_all!(backend, method.as_str(), from_proto, to_proto, &message)
+    }.await;
+
+    let response = Response::builder()
+        .header("content-type", "application/grpc")
+        .body(())
+        .unwrap();
+    let mut stream = match respond.send_response(response, false) {
+        Ok(stream) => stream,
+        Err(_) => return,
+    };
+
+    let mut trailers = HeaderMap::new();
+    match result {
+        Ok(message) => {
+            let _ = stream.send_data(frame(&message), false);
+            trailers.insert("grpc-status", "0".parse().unwrap());
+        }
+        Err(err) => {
+            let (code, message) = match err {
+                Error::Api { code, message } => (code, message),
+                err => (Code::Internal, err.to_string()),
+            };
+            trailers.insert("grpc-status", code.as_i32().to_string().parse().unwrap());
+            trailers.insert("grpc-message", encode_message(&message).parse().unwrap());
+        }
+    }
+    let _ = stream.send_trailers(trailers);
+}
+
+fn from_proto<T: Message>(bytes: &[u8]) -> Result<T> {
+    Ok(T::decode(bytes)?)
+}
+
+fn to_proto<T: Message>(response: &T) -> Result<Vec<u8>> {
+    Ok(v1::encode(response)?)
+}
+
+/// Percent-encodes a status message for the `grpc-message` trailer.
+fn encode_message(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+    for &b in message.as_bytes() {
+        if (0x20..0x7f).contains(&b) && b != b'%' {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Number of polls after which operations of the Admin API stand-in finish.
+pub const OPERATION_POLLS: i64 = 3;
+
+/// Number of indexes per page of the Admin API stand-in's index listing.
+pub const INDEX_PAGE_SIZE: usize = 2;
+
+/// Starts a stand-in of the Admin API's export, import, index and operations endpoints.
+/// Operations make progress whenever they or their index are read and finish after
+/// `OPERATION_POLLS` reads. Imports from URLs containing `missing` and indexes of kinds starting
+/// with `Failing` fail.
+pub fn admin() -> StandIn {
+    let state = Arc::new(Mutex::new(AdminState::default()));
+    spawn_server(move |stream| async move {
+        let service = service_fn(move |request| {
+            let state = state.clone();
+            async move { Ok::<_, Infallible>(serve_admin(&state, request).await) }
+        });
+        let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+    })
+}
+
+#[derive(Default)]
+struct AdminState {
+    operations: HashMap<String, Operation>,
+    indexes: BTreeMap<u64, Index>,
+    next_index_id: u64,
+}
+
+async fn serve_admin(state: &Mutex<AdminState>, request: Request<Incoming>) -> Response<Full<Bytes>> {
+    let method = request.method().clone();
+    let path = request.uri().path().trim_start_matches("/v1/projects/").to_string();
+    // Page tokens contain characters that must be percent-encoded, a `+` stands for a space.
+    let page_token = request.uri().query()
+        .and_then(|query| query.strip_prefix("pageToken="))
+        .map(|token| decode_message(token.replace('+', " ").as_bytes()))
+        .map(|token| token.strip_prefix("page+").and_then(|token| token.strip_suffix("/="))
+            .and_then(|offset| offset.parse().ok()));
+    let body = match request.into_body().collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(_) => Bytes::new(),
+    };
+    let mut state = state.lock().unwrap();
+
+    let split = path.find(['/', ':']).unwrap_or(path.len());
+    let (project, resource) = path.split_at(split);
+    let result = match (method, resource) {
+        (http::Method::POST, ":export") | (http::Method::POST, ":import") =>
+            start_transfer(&mut state, project, &resource[1..], &body),
+        (http::Method::GET, "/indexes") => match page_token.unwrap_or(Some(0)) {
+            Some(offset) => {
+                let indexes: Vec<Index> = state.indexes.values().skip(offset).take(INDEX_PAGE_SIZE)
+                    .cloned()
+                    .collect();
+                let next = offset + indexes.len();
+                let next_page_token = if next < state.indexes.len() {
+                    format!("page+{}/=", next)
+                } else {
+                    String::new()
+                };
+                Ok(serde_json::to_value(ListIndexesResponse { indexes, next_page_token }).unwrap())
+            }
+            None => Err(Error::api(Code::InvalidArgument, "invalid page token")),
+        },
+        (http::Method::POST, "/indexes") => create_index(&mut state, project, &body),
+        (method, resource) if resource.starts_with("/indexes/") => {
+            let id = resource["/indexes/".len()..].parse().unwrap_or(0);
+            match (method, state.indexes.get_mut(&id)) {
+                (http::Method::GET, Some(_)) => {
+                    if let Some(name) = pending_operation(&state, id) {
+                        advance(&mut state, &name);
+                    }
+                    Ok(serde_json::to_value(&state.indexes[&id]).unwrap())
+                }
+                (http::Method::DELETE, Some(index)) if index.state != IndexState::Deleting => {
+                    index.state = IndexState::Deleting;
+                    Ok(start_operation(&mut state, project, OperationType::DeleteIndex, |metadata| {
+                        metadata.index_id = Some(id.to_string());
+                    }))
+                }
+                _ => Err(Error::api(Code::NotFound, format!("no index {}", id))),
+            }
+        }
+        (http::Method::POST, resource) if resource.starts_with("/operations/") && resource.ends_with(":cancel") => {
+            let name = format!("projects/{}", path.trim_end_matches(":cancel"));
+            match state.operations.get_mut(&name) {
+                Some(operation) => {
+                    if !operation.done {
+                        finish(operation, State::Cancelled, Some((Code::Cancelled, "operation was cancelled")));
+                    }
+                    Ok(json!({}))
+                }
+                None => Err(Error::api(Code::NotFound, format!("no operation {}", name))),
+            }
+        }
+        (http::Method::GET, resource) if resource.starts_with("/operations/") => {
+            let name = format!("projects/{}", path);
+            if state.operations.contains_key(&name) {
+                advance(&mut state, &name);
+                Ok(serde_json::to_value(&state.operations[&name]).unwrap())
+            } else {
+                Err(Error::api(Code::NotFound, format!("no operation {}", name)))
+            }
+        }
+        _ => Err(Error::api(Code::NotFound, format!("unknown resource {}", path))),
+    };
+
+    let (status, body) = match result {
+        Ok(body) => (StatusCode::OK, body),
+        Err(err) => {
+            let code = err.code().unwrap_or(Code::Internal);
+            let body = json!({"error": {"code": code.http_status(), "message": err.to_string(), "status": code}});
+            (StatusCode::from_u16(code.http_status()).unwrap(), body)
+        }
+    };
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from(serde_json::to_vec(&body).unwrap())))
+        .unwrap()
+}
+
+fn start_operation<F: FnOnce(&mut OperationMetadata)>(state: &mut AdminState, project: &str,
+                                                      operation_type: OperationType, init: F)
+                                                      -> JsonValue {
+    let type_name = match operation_type {
+        OperationType::ExportEntities => "ExportEntitiesMetadata",
+        OperationType::ImportEntities => "ImportEntitiesMetadata",
+        _ => "IndexOperationMetadata",
+    };
+    let mut metadata = OperationMetadata {
+        type_url: format!("type.googleapis.com/google.datastore.admin.v1.{}", type_name),
+        common: CommonMetadata { operation_type, state: State::Processing, ..CommonMetadata::default() },
+        progress_entities: Some(Progress { work_completed: Some(0.into()), work_estimated: Some(OPERATION_POLLS.into()) }),
+        ..OperationMetadata::default()
+    };
+    init(&mut metadata);
+
+    let name = format!("projects/{}/operations/{}", project, state.operations.len() + 1);
+    let operation = Operation { name: name.clone(), metadata: Some(metadata), ..Operation::default() };
+    let json = serde_json::to_value(&operation).unwrap();
+    state.operations.insert(name, operation);
+    json
+}
+
+fn start_transfer(state: &mut AdminState, project: &str, method: &str, body: &[u8]) -> Result<JsonValue> {
+    if method == "export" {
+        let request: ExportEntitiesRequest = serde_json::from_slice(body)?;
+        if !request.output_url_prefix.starts_with("gs://") {
+            return Err(Error::api(Code::InvalidArgument, "outputUrlPrefix must be a gs:// URL"));
+        }
+        Ok(start_operation(state, project, OperationType::ExportEntities, |metadata| {
+            metadata.common.labels = request.labels;
+            metadata.entity_filter = request.entity_filter;
+            metadata.output_url_prefix = Some(request.output_url_prefix);
+        }))
+    } else {
+        let request: ImportEntitiesRequest = serde_json::from_slice(body)?;
+        Ok(start_operation(state, project, OperationType::ImportEntities, |metadata| {
+            metadata.common.labels = request.labels;
+            metadata.entity_filter = request.entity_filter;
+            metadata.input_url = Some(request.input_url);
+        }))
+    }
+}
+
+fn create_index(state: &mut AdminState, project: &str, body: &[u8]) -> Result<JsonValue> {
+    let mut index: Index = serde_json::from_slice(body)?;
+    if index.kind.is_empty() || index.properties.is_empty() {
+        return Err(Error::api(Code::InvalidArgument, "an index needs a kind and properties"));
+    }
+    let exists = state.indexes.values()
+        .any(|other| other.state != IndexState::Deleting && other.definition() == index.definition());
+    if exists {
+        return Err(Error::api(Code::AlreadyExists, "index already exists"));
+    }
+
+    state.next_index_id += 1;
+    let id = state.next_index_id;
+    index.project_id = project.to_string();
+    index.index_id = id.to_string();
+    index.state = IndexState::Creating;
+    state.indexes.insert(id, index);
+    Ok(start_operation(state, project, OperationType::CreateIndex, |metadata| {
+        metadata.index_id = Some(id.to_string());
+    }))
+}
+
+// The running operation that creates or deletes an index.
+fn pending_operation(state: &AdminState, id: u64) -> Option<String> {
+    let id = id.to_string();
+    state.operations.values()
+        .find(|operation| !operation.done && operation.index_id() == Some(id.as_str()))
+        .map(|operation| operation.name.clone())
+}
+
+// Completes one unit of work of a running operation.
+fn advance(state: &mut AdminState, name: &str) {
+    let operation = state.operations.get_mut(name).unwrap();
+    if operation.done {
+        return;
+    }
+    let metadata = operation.metadata.as_mut().unwrap();
+    let progress = metadata.progress_entities.as_mut().unwrap();
+    let completed = progress.work_completed.as_ref().unwrap().parse::<i64>().unwrap() + 1;
+    progress.work_completed = Some(completed.into());
+    if completed < OPERATION_POLLS {
+        return;
+    }
+
+    let index_id = metadata.index_id.as_ref().and_then(|id| id.parse::<u64>().ok());
+    match (metadata.common.operation_type, index_id) {
+        (OperationType::CreateIndex, Some(id)) => {
+            let index = state.indexes.get_mut(&id).unwrap();
+            if index.kind.starts_with("Failing") {
+                index.state = IndexState::Error;
+                finish(operation, State::Failed, Some((Code::FailedPrecondition, "index build failed")));
+            } else {
+                index.state = IndexState::Ready;
+                finish(operation, State::Successful, None);
+            }
+        }
+        (OperationType::DeleteIndex, Some(id)) => {
+            state.indexes.remove(&id);
+            finish(operation, State::Successful, None);
+        }
+        _ if metadata.input_url.as_ref().is_some_and(|url| url.contains("missing")) =>
+            finish(operation, State::Failed, Some((Code::NotFound, "export not found"))),
+        _ => {
+            let output_url = metadata.output_url_prefix.as_ref()
+                .map(|prefix| format!("{}/export.overall_export_metadata", prefix));
+            finish(operation, State::Successful, None);
+            operation.response = Some(OperationResponse {
+                type_url: "type.googleapis.com/google.datastore.admin.v1.ExportEntitiesResponse".to_string(),
+                output_url,
+            });
+        }
+    }
+}
+
+fn finish(operation: &mut Operation, state: State, error: Option<(Code, &str)>) {
+    operation.done = true;
+    operation.metadata.as_mut().unwrap().common.state = state;
+    operation.error = error.map(|(code, message)| Status { code: code.as_i32(), message: message.to_string() });
+}
@@ -0,0 +1,34 @@
+// Reading the latest snapshot of statistics, see `datastore::stats`.
+
+use crate::client::{repo, Client, Result};
+use crate::datastore::{Entity, PartitionId, Value};
+use crate::datastore::query::{Direction, Filter, PropertyOperator, Query};
+use crate::datastore::stats::{stat_kind, Stat, StatTotal};
+use crate::serde_ds;
+
+impl Client {
+    /// Reads the statistics of type `T` from the most recent snapshot in a namespace, the empty
+    /// string for the default namespace. Returns no statistics if no snapshot was taken yet.
+    ///
+    /// Snapshots are identified by the timestamp of the newest `__Stat_Total__` entity, so that all
+    /// statistics come from the same snapshot even while a new one is being written.
+    pub async fn latest_stats<T: Stat>(&self, namespace: &str) -> Result<Vec<T>> {
+        let partition = Some(PartitionId::new("", namespace));
+        let newest = Query::new(stat_kind(StatTotal::KIND, namespace))
+            .with_order("timestamp", Direction::Descending)
+            .with_limit(1);
+        let totals: Vec<StatTotal> = repo::fetch_all_in(self, partition.clone(), newest, read).await?;
+        let timestamp = match totals.first() {
+            Some(total) => total.timestamp,
+            None => return Ok(vec![]),
+        };
+
+        let snapshot = Query::new(stat_kind(T::KIND, namespace))
+            .with_filter(Filter::property("timestamp", PropertyOperator::Equal, timestamp));
+        repo::fetch_all_in(self, partition, snapshot, read).await
+    }
+}
+
+fn read<T: Stat>(entity: Entity) -> serde_ds::Result<T> {
+    serde_ds::from_value(Value::from(entity))
+}
@@ -0,0 +1,116 @@
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::client::*;
+use crate::datastore::*;
+use crate::datastore::stats::*;
+
+fn day(day: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2018, 3, day, 0, 0, 0).unwrap()
+}
+
+// The key of a statistics entity of the given kind, which is renamed in namespaces.
+fn stat_key(namespace: &str, kind: &str, name: &str) -> Key {
+    let kind = stat_kind(kind, namespace);
+    Key::new(PartitionId::new("", namespace), vec![PathElement::from_name(kind, name)])
+}
+
+#[tokio::test]
+async fn test_latest_stats() {
+    let client = Client::new("test-project", MemoryBackend::new());
+    assert!(client.latest_stats::<StatKind>("").await.unwrap().is_empty());
+
+    for entity in [
+        Entity {
+            key: Some(stat_key("", StatTotal::KIND, "old")),
+            properties: hashmap! {
+                "count".to_string() => Value::from(5),
+                "bytes".to_string() => Value::from(500),
+                "entity_bytes".to_string() => Value::from(400),
+                "timestamp".to_string() => Value::from(day(1)),
+            },
+        },
+        Entity {
+            key: Some(stat_key("", StatKind::KIND, "User-1")),
+            properties: hashmap! {
+                "count".to_string() => Value::from(5),
+                "bytes".to_string() => Value::from(500),
+                "entity_bytes".to_string() => Value::from(400),
+                "timestamp".to_string() => Value::from(day(1)),
+                "kind_name".to_string() => Value::from("User"),
+            },
+        },
+        Entity {
+            key: Some(stat_key("", StatTotal::KIND, "new")),
+            properties: hashmap! {
+                "count".to_string() => Value::from(7),
+                "bytes".to_string() => Value::from(700),
+                "entity_bytes".to_string() => Value::from(560),
+                "timestamp".to_string() => Value::from(day(2)),
+            },
+        },
+        Entity {
+            key: Some(stat_key("", StatKind::KIND, "User-2")),
+            properties: hashmap! {
+                "count".to_string() => Value::from(4),
+                "bytes".to_string() => Value::from(400),
+                "entity_bytes".to_string() => Value::from(320),
+                "timestamp".to_string() => Value::from(day(2)),
+                "kind_name".to_string() => Value::from("User"),
+            },
+        },
+        Entity {
+            key: Some(stat_key("", StatKind::KIND, "Order-2")),
+            properties: hashmap! {
+                "count".to_string() => Value::from(3),
+                "bytes".to_string() => Value::from(300),
+                "entity_bytes".to_string() => Value::from(240),
+                "timestamp".to_string() => Value::from(day(2)),
+                "kind_name".to_string() => Value::from("Order"),
+            },
+        },
+        Entity {
+            key: Some(stat_key("tenant", StatTotal::KIND, "new")),
+            properties: hashmap! {
+                "count".to_string() => Value::from(2),
+                "bytes".to_string() => Value::from(200),
+                "entity_bytes".to_string() => Value::from(160),
+                "timestamp".to_string() => Value::from(day(2)),
+            },
+        },
+        Entity {
+            key: Some(stat_key("tenant", StatKind::KIND, "Invoice-2")),
+            properties: hashmap! {
+                "count".to_string() => Value::from(2),
+                "bytes".to_string() => Value::from(200),
+                "entity_bytes".to_string() => Value::from(160),
+                "timestamp".to_string() => Value::from(day(2)),
+                "kind_name".to_string() => Value::from("Invoice"),
+            },
+        },
+    ] {
+        client.put_entity(entity).await.unwrap();
+    }
+
+    let totals = client.latest_stats::<StatTotal>("").await.unwrap();
+    assert_eq!(1, totals.len());
+    assert_eq!(7, totals[0].count);
+    assert_eq!(0, totals[0].composite_index_count);
+    assert_eq!(day(2), totals[0].timestamp());
+
+    let mut kinds = client.latest_stats::<StatKind>("").await.unwrap();
+    kinds.sort_by(|a, b| a.kind_name.cmp(&b.kind_name));
+    let counts: Vec<(&str, i64)> = kinds.iter().map(|k| (k.kind_name.as_str(), k.count)).collect();
+    assert_eq!(vec![("Order", 3), ("User", 4)], counts);
+    assert_eq!(400, kinds[1].bytes);
+
+    let kinds = client.latest_stats::<StatKind>("tenant").await.unwrap();
+    assert_eq!(vec!["Invoice"], kinds.iter().map(|k| k.kind_name.as_str()).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_stat_kinds() {
+    assert_eq!("__Stat_Kind__", stat_kind(StatKind::KIND, ""));
+    assert_eq!("__Stat_Ns_Kind__", stat_kind(StatKind::KIND, "tenant"));
+    assert_eq!("__Stat_Ns_PropertyType_PropertyName_Kind__",
+               stat_kind(StatPropertyTypePropertyNameKind::KIND, "tenant"));
+}
@@ -0,0 +1,356 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client::*;
+use crate::client::stand_in;
+use crate::datastore::*;
+use crate::datastore::query::*;
+
+const PROJECT: &str = "test-project";
+
+fn user_key(name: &str) -> Key {
+    Key::new(PartitionId::new(PROJECT, ""), vec![PathElement::from_name("User", name)])
+}
+
+fn user(name: &str, age: i64) -> Entity {
+    Entity {
+        key: Some(user_key(name)),
+        properties: hashmap! {
+            "name".to_string() => Value::from(name),
+            "age".to_string() => Value::from(age),
+        },
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct User {
+    name: String,
+    age: i64,
+}
+
+fn rest_client() -> (Client, stand_in::StandIn) {
+    let stand_in = stand_in::rest(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Rest)
+        .build()
+        .unwrap();
+    (client, stand_in)
+}
+
+fn grpc_client() -> (Client, stand_in::StandIn) {
+    let stand_in = stand_in::grpc(Arc::new(MemoryBackend::new()));
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Grpc)
+        .build()
+        .unwrap();
+    (client, stand_in)
+}
+
+async fn commit(client: &Client, mutations: Vec<Mutation>) -> Result<CommitResponse> {
+    client.commit(CommitRequest {
+        project_id: String::new(),
+        mode: CommitMode::NonTransactional,
+        transaction: None,
+        mutations,
+    }).await
+}
+
+async fn lookup(client: &Client, read_options: Option<ReadOptions>, keys: Vec<Key>) -> LookupResponse {
+    let request = LookupRequest { project_id: String::new(), read_options, keys };
+    client.lookup(request).await.expect("Lookup failed")
+}
+
+async fn query(client: &Client, query: Query) -> QueryResultBatch {
+    let request = RunQueryRequest { query: Some(query), ..RunQueryRequest::default() };
+    client.run_query(request).await.expect("Query failed").batch
+}
+
+fn names(batch: &QueryResultBatch) -> Vec<&str> {
+    batch.entity_results.iter()
+        .map(|result| result.entity.key.as_ref().unwrap().path()[0].name().unwrap())
+        .collect()
+}
+
+// Exercises all operations, the same expectations hold for every transport.
+async fn scenario(client: &Client) {
+    let users = vec![user("alice", 31), user("bob", 25), user("carol", 42), user("dave", 25)];
+    let response = commit(client, users.into_iter().map(Mutation::Upsert).collect()).await
+        .expect("Upsert failed");
+    assert_eq!(4, response.mutation_results.len());
+
+    // Lookup
+    let response = lookup(client, None, vec![user_key("alice"), user_key("mallory")]).await;
+    assert_eq!(1, response.found.len());
+    assert_eq!(user("alice", 31), response.found[0].entity);
+    assert_eq!(1, response.missing.len());
+    assert_eq!(Some(user_key("mallory")), response.missing[0].entity.key);
+
+    // Inserting an existing entity is rejected
+    let err = commit(client, vec![Mutation::Insert(user("bob", 26))]).await.unwrap_err();
+    assert_eq!(Some(Code::AlreadyExists), err.code());
+
+    // Queries with filter, order, limit and cursors
+    let younger = Query::new("User")
+        .with_filter(Filter::property("age", PropertyOperator::LessThan, 40))
+        .with_order("age", Direction::Ascending)
+        .with_limit(2);
+    let batch = query(client, younger.clone()).await;
+    assert_eq!(vec!["bob", "dave"], names(&batch));
+    assert_eq!(MoreResultsType::MoreResultsAfterLimit, batch.more_results);
+
+    let cursor = batch.end_cursor.expect("No end cursor");
+    let batch = query(client, younger.with_start_cursor(cursor)).await;
+    assert_eq!(vec!["alice"], names(&batch));
+    assert_eq!(MoreResultsType::NoMoreResults, batch.more_results);
+
+    // A transaction that commits
+    let mut transaction = client.transaction().await.expect("Begin transaction failed");
+    let carol = transaction.get_entity(&user_key("carol")).await.unwrap();
+    assert_eq!(Some(user("carol", 42)), carol);
+    transaction.update(user("carol", 43));
+    transaction.commit().await.expect("Transactional commit failed");
+
+    // A transaction that conflicts with a concurrent write
+    let mut transaction = client.transaction().await.expect("Begin transaction failed");
+    transaction.lookup(vec![user_key("carol")]).await.unwrap();
+    commit(client, vec![Mutation::Upsert(user("carol", 44))]).await.expect("Concurrent write failed");
+    transaction.update(user("carol", 45));
+    let err = transaction.commit().await.unwrap_err();
+    assert_eq!(Some(Code::Aborted), err.code());
+
+    // A transaction that is rolled back
+    let transaction = client.transaction().await.expect("Begin transaction failed");
+    let id = transaction.id().clone();
+    transaction.rollback().await.expect("Rollback failed");
+    let err = client.rollback(RollbackRequest { project_id: String::new(), transaction: id }).await
+        .unwrap_err();
+    assert_eq!(Some(Code::InvalidArgument), err.code());
+
+    let response = lookup(client, None, vec![user_key("carol")]).await;
+    assert_eq!(user("carol", 44), response.found[0].entity);
+
+    // Delete
+    client.delete(user_key("dave")).await.expect("Delete failed");
+    assert_eq!(None, client.get_entity(&user_key("dave")).await.unwrap());
+    let batch = query(client, Query::new("User").with_order("age", Direction::Ascending)).await;
+    assert_eq!(vec!["bob", "alice", "carol"], names(&batch));
+
+    // Conversion via serde_ds
+    let erin = User { name: "erin".to_string(), age: 29 };
+    client.write(user_key("erin"), &erin).await.expect("Write failed");
+    assert_eq!(Some(user("erin", 29)), client.get_entity(&user_key("erin")).await.unwrap());
+    assert_eq!(Some(erin), client.read::<User>(&user_key("erin")).await.unwrap());
+    assert_eq!(None, client.read::<User>(&user_key("mallory")).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_memory_backend() {
+    let client = Client::new(PROJECT, MemoryBackend::new());
+    scenario(&client).await;
+}
+
+#[tokio::test]
+async fn test_rest_transport() {
+    let (client, _stand_in) = rest_client();
+    scenario(&client).await;
+}
+
+#[tokio::test]
+async fn test_grpc_transport() {
+    let (client, _stand_in) = grpc_client();
+    scenario(&client).await;
+}
+
+#[tokio::test]
+async fn test_grpc_error_message() {
+    let (client, _stand_in) = grpc_client();
+
+    let other = Key::new(PartitionId::new("projekt-ö", ""), vec![PathElement::from_id("User", 1)]);
+    let err = client.lookup(LookupRequest { keys: vec![other], ..LookupRequest::default() }).await
+        .unwrap_err();
+    match err {
+        Error::Api { code, message } => {
+            assert_eq!(Code::InvalidArgument, code);
+            assert_eq!("key belongs to project projekt-ö", message);
+        }
+        err => panic!("Unexpected error: {}", err),
+    }
+}
+
+#[tokio::test]
+async fn test_rest_reuses_connections() {
+    let (client, stand_in) = rest_client();
+    for _ in 0..5 {
+        assert_eq!(None, client.get_entity(&user_key("alice")).await.unwrap());
+    }
+    assert_eq!(1, stand_in.connections());
+
+    // Concurrent requests need connections of their own, which are kept for later requests.
+    let tasks: Vec<_> = (0..3).map(|_| {
+        let client = client.clone();
+        tokio::spawn(async move { client.get_entity(&user_key("alice")).await })
+    }).collect();
+    for task in tasks {
+        assert_eq!(None, task.await.unwrap().unwrap());
+    }
+    let connections = stand_in.connections();
+    assert!(connections <= 3);
+
+    client.get_entity(&user_key("alice")).await.unwrap();
+    assert_eq!(connections, stand_in.connections());
+}
+
+#[tokio::test]
+async fn test_grpc_shares_connection() {
+    let (client, stand_in) = grpc_client();
+    let clone = client.clone();
+    commit(&client, vec![Mutation::Upsert(user("alice", 31))]).await.unwrap();
+    assert_eq!(1, lookup(&clone, None, vec![user_key("alice")]).await.found.len());
+    assert_eq!(1, stand_in.connections());
+}
+
+#[tokio::test]
+async fn test_clones_share_state() {
+    let client = Client::new(PROJECT, MemoryBackend::new());
+    let tasks: Vec<_> = (0..10).map(|i| {
+        let client = client.clone();
+        tokio::spawn(async move { client.put_entity(user(&format!("user-{}", i), i)).await })
+    }).collect();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+
+    let batch = query(&client, Query::new("User")).await;
+    assert_eq!(10, batch.entity_results.len());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_deferred_lookup() {
+    let client = Client::new(PROJECT, MemoryBackend::new().with_deferred_lookups(2));
+    client.put_entity(user("alice", 30)).await.unwrap();
+    let started = tokio::time::Instant::now();
+    assert_eq!(Some(user("alice", 30)), client.get_entity(&user_key("alice")).await.unwrap());
+
+    // Rounds are spaced out like retries, 100 and 200ms of which up to a half is skipped.
+    let waited = started.elapsed();
+    assert!(waited >= Duration::from_millis(150), "waited {:?}", waited);
+    assert!(waited <= Duration::from_millis(300), "waited {:?}", waited);
+
+    let client = Client::new(PROJECT, MemoryBackend::new().with_deferred_lookups(usize::MAX));
+    match client.get_entity(&user_key("alice")).await {
+        Err(Error::UnexpectedResponse(_)) => {}
+        result => panic!("Expected the lookup to give up, got {:?}", result),
+    }
+    match client.get_entities(&[user_key("alice"), user_key("bob")]).await {
+        Err(Error::UnexpectedResponse(_)) => {}
+        result => panic!("Expected the lookup to give up, got {:?}", result),
+    }
+}
+
+#[tokio::test]
+async fn test_read_only_transaction() {
+    let client = Client::new(PROJECT, MemoryBackend::new());
+    client.put_entity(user("alice", 31)).await.unwrap();
+
+    let transaction = client.read_only_transaction().await.unwrap();
+    let alice: Option<User> = transaction.read(&user_key("alice")).await.unwrap();
+    assert_eq!(Some(User { name: "alice".to_string(), age: 31 }), alice);
+    transaction.commit().await.unwrap();
+
+    let mut transaction = client.read_only_transaction().await.unwrap();
+    transaction.delete(user_key("alice"));
+    let err = transaction.commit().await.unwrap_err();
+    assert_eq!(Some(Code::InvalidArgument), err.code());
+}
+
+#[tokio::test]
+async fn test_write_requires_struct() {
+    let client = Client::new(PROJECT, MemoryBackend::new());
+    match client.write(user_key("alice"), &42).await {
+        Err(Error::Serde(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_unreachable_endpoint() {
+    for &protocol in &[Protocol::Rest, Protocol::Grpc] {
+        let client = Client::builder(PROJECT)
+            .endpoint("http://127.0.0.1:1")
+            .protocol(protocol)
+            .retry_policy(RetryPolicy::none())
+            .build()
+            .unwrap();
+        match client.lookup(LookupRequest::default()).await {
+            Err(Error::Io(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_rest_retries_dropped_connections() {
+    let stand_in = stand_in::flaky_rest(Arc::new(MemoryBackend::new()), 1);
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Rest)
+        .retry_policy(RetryPolicy::default().with_backoff(Duration::from_millis(1), Duration::from_millis(10)))
+        .build()
+        .unwrap();
+    commit(&client, vec![Mutation::Upsert(user("alice", 31))]).await.expect("Upsert was not retried");
+    assert_eq!(2, stand_in.connections());
+    assert_eq!(Some(user("alice", 31)), client.get_entity(&user_key("alice")).await.unwrap());
+
+    // Commits that must not be applied twice are not retried.
+    let stand_in = stand_in::flaky_rest(Arc::new(MemoryBackend::new()), 1);
+    let client = Client::builder(PROJECT)
+        .endpoint(stand_in.endpoint())
+        .protocol(Protocol::Rest)
+        .build()
+        .unwrap();
+    match commit(&client, vec![Mutation::Insert(user("bob", 25))]).await {
+        Err(err @ Error::Io(_)) => assert!(err.is_retryable()),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert_eq!(1, stand_in.connections());
+}
+
+#[tokio::test]
+async fn test_commit_is_atomic() {
+    let client = Client::new(PROJECT, MemoryBackend::new());
+    commit(&client, vec![Mutation::Upsert(user("alice", 31))]).await.unwrap();
+
+    let err = commit(&client, vec![
+        Mutation::Upsert(user("bob", 25)),
+        Mutation::Insert(user("alice", 32)),
+    ]).await.unwrap_err();
+    assert_eq!(Some(Code::AlreadyExists), err.code());
+    assert!(lookup(&client, None, vec![user_key("bob")]).await.found.is_empty());
+}
+
+#[test]
+fn test_endpoint_parse() {
+    let endpoint = Endpoint::parse(DEFAULT_ENDPOINT).unwrap();
+    assert_eq!("https", endpoint.scheme());
+    assert_eq!("datastore.googleapis.com:443", endpoint.authority());
+
+    let endpoint = Endpoint::parse("http://[::1]:8081").unwrap();
+    assert_eq!("http", endpoint.scheme());
+    assert_eq!("[::1]:8081", endpoint.authority());
+
+    assert!(Endpoint::parse("ftp://localhost").is_err());
+    assert!(Endpoint::parse("localhost:8081").is_err());
+}
+
+#[test]
+fn test_codes() {
+    for i in 0..17 {
+        assert_eq!(i, Code::from_i32(i).as_i32());
+    }
+    assert_eq!(Code::Unknown, Code::from_i32(17));
+    assert_eq!(Code::Unknown, Code::from_i32(-1));
+    assert_eq!(409, Code::Aborted.http_status());
+    assert_eq!("\"RESOURCE_EXHAUSTED\"", serde_json::to_string(&Code::ResourceExhausted).unwrap());
+}
@@ -0,0 +1,114 @@
+// Transactions of the async client.
+//
+// Reads are sent immediately, mutations are buffered and sent together by `commit`. A transaction
+// that is dropped without being committed or rolled back is not rolled back explicitly, the API
+// releases it after a timeout.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::client::{aggregate, from_entity, get_entities, get_entity, to_entity, Client, Result};
+use crate::datastore::{Blob, Entity, Key};
+use crate::datastore::query::{AggregationQuery, AggregationResult};
+use crate::datastore::rpc::*;
+
+pub struct Transaction {
+    client: Client,
+    id: Blob,
+    mutations: Vec<Mutation>,
+}
+
+impl Transaction {
+    pub(crate) async fn begin(client: Client, options: TransactionOptions) -> Result<Transaction> {
+        let request = BeginTransactionRequest {
+            project_id: String::new(),
+            transaction_options: Some(options),
+        };
+        let id = client.begin_transaction(request).await?.transaction;
+        Ok(Transaction { client, id, mutations: vec![] })
+    }
+
+    pub fn id(&self) -> &Blob {
+        &self.id
+    }
+
+    fn read_options(&self) -> Option<ReadOptions> {
+        Some(ReadOptions::Transaction(self.id.clone()))
+    }
+
+    pub async fn lookup(&self, keys: Vec<Key>) -> Result<LookupResponse> {
+        let request = LookupRequest { project_id: String::new(), read_options: self.read_options(), keys };
+        self.client.lookup(request).await
+    }
+
+    /// Runs a query in this transaction, replacing the read options of the request.
+    pub async fn run_query(&self, mut request: RunQueryRequest) -> Result<RunQueryResponse> {
+        request.read_options = self.read_options();
+        self.client.run_query(request).await
+    }
+
+    /// Runs an aggregation query in this transaction, replacing the read options of the request.
+    pub async fn run_aggregation_query(&self, mut request: RunAggregationQueryRequest)
+                                       -> Result<RunAggregationQueryResponse> {
+        request.read_options = self.read_options();
+        self.client.run_aggregation_query(request).await
+    }
+
+    pub async fn aggregate(&self, query: AggregationQuery) -> Result<AggregationResult> {
+        aggregate(&self.client, query, self.read_options()).await
+    }
+
+    pub async fn get_entity(&self, key: &Key) -> Result<Option<Entity>> {
+        get_entity(&self.client, key, self.read_options()).await
+    }
+
+    /// Looks up entities in this transaction, see `Client::get_entities`.
+    pub async fn get_entities(&self, keys: &[Key]) -> Result<Vec<Option<Entity>>> {
+        get_entities(&self.client, keys, self.read_options()).await
+    }
+
+    pub async fn read<T: DeserializeOwned>(&self, key: &Key) -> Result<Option<T>> {
+        match self.get_entity(key).await? {
+            Some(entity) => Ok(Some(from_entity(entity)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity) {
+        self.mutations.push(Mutation::Insert(entity));
+    }
+
+    pub fn update(&mut self, entity: Entity) {
+        self.mutations.push(Mutation::Update(entity));
+    }
+
+    pub fn upsert(&mut self, entity: Entity) {
+        self.mutations.push(Mutation::Upsert(entity));
+    }
+
+    pub fn delete(&mut self, key: Key) {
+        self.mutations.push(Mutation::Delete(key));
+    }
+
+    /// Serialises a value via `serde_ds` and upserts it as the entity with the given key.
+    pub fn write<T: Serialize>(&mut self, key: Key, value: &T) -> Result<()> {
+        self.upsert(to_entity(key, value)?);
+        Ok(())
+    }
+
+    /// Applies all buffered mutations atomically. Fails with `Code::Aborted` if an entity read or
+    /// written by the transaction was changed concurrently.
+    pub async fn commit(self) -> Result<CommitResponse> {
+        self.client.commit(CommitRequest {
+            project_id: String::new(),
+            mode: CommitMode::Transactional,
+            transaction: Some(self.id),
+            mutations: self.mutations,
+        }).await
+    }
+
+    pub async fn rollback(self) -> Result<()> {
+        self.client.rollback(RollbackRequest { project_id: String::new(), transaction: self.id }).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+// Messages of the Datastore Admin API's managed export and import and index methods and the
+// long-running operations that they start.
+//
+// See https://cloud.google.com/datastore/docs/reference/admin/rest
+
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+
+use crate::datastore::Int;
+use crate::index::{self, IndexedProperty};
+
+/// Selects the entities of an export or import. Empty lists select all kinds or namespaces, the
+/// default namespace is selected by the empty string.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityFilter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub namespace_ids: Vec<String>,
+}
+
+impl EntityFilter {
+    /// Selects all entities.
+    pub fn all() -> EntityFilter {
+        EntityFilter::default()
+    }
+
+    pub fn with_kinds<K: Into<String>>(mut self, kinds: Vec<K>) -> EntityFilter {
+        self.kinds = kinds.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_namespaces<N: Into<String>>(mut self, namespaces: Vec<N>) -> EntityFilter {
+        self.namespace_ids = namespaces.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportEntitiesRequest {
+    #[serde(default)]
+    pub project_id: String,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_filter: Option<EntityFilter>,
+
+    /// A Cloud Storage location like `gs://bucket/path`, under which the export is written.
+    pub output_url_prefix: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportEntitiesRequest {
+    #[serde(default)]
+    pub project_id: String,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+
+    /// The overall metadata file of an export, as returned in `outputUrl`.
+    pub input_url: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_filter: Option<EntityFilter>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OperationType {
+    #[default]
+    OperationTypeUnspecified,
+    ExportEntities,
+    ImportEntities,
+    CreateIndex,
+    DeleteIndex,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum State {
+    #[default]
+    StateUnspecified,
+    Initializing,
+    Processing,
+    Cancelling,
+    Finalizing,
+    Successful,
+    Failed,
+    Cancelled,
+}
+
+impl State {
+    /// Whether the operation has ended, successfully or not.
+    pub fn is_final(self) -> bool {
+        matches!(self, State::Successful | State::Failed | State::Cancelled)
+    }
+}
+
+/// Metadata common to all operations.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CommonMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<DateTime<Utc>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub operation_type: OperationType,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+
+    #[serde(default)]
+    pub state: State,
+}
+
+/// Progress of an operation, in entities or bytes. The estimate may be missing.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub work_completed: Option<Int>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub work_estimated: Option<Int>,
+}
+
+impl Progress {
+    /// The completed fraction of the work between 0 and 1, if the work was estimated.
+    pub fn fraction(&self) -> Option<f64> {
+        let completed: i64 = self.work_completed.as_ref().map_or(Some(0), |c| c.parse().ok())?;
+        let estimated: i64 = self.work_estimated.as_ref()?.parse().ok()?;
+        if estimated <= 0 {
+            return None;
+        }
+        Some((completed as f64 / estimated as f64).min(1.0))
+    }
+}
+
+/// Metadata of export, import and index operations, `ExportEntitiesMetadata`,
+/// `ImportEntitiesMetadata` and `IndexOperationMetadata`.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationMetadata {
+    #[serde(rename = "@type", default)]
+    pub type_url: String,
+
+    #[serde(default)]
+    pub common: CommonMetadata,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_entities: Option<Progress>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_bytes: Option<Progress>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_filter: Option<EntityFilter>,
+
+    /// Set for exports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_url_prefix: Option<String>,
+
+    /// Set for imports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_url: Option<String>,
+
+    /// Set for index creations and deletions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_id: Option<String>,
+}
+
+/// The result of a successful operation, e.g. `ExportEntitiesResponse`.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationResponse {
+    #[serde(rename = "@type", default)]
+    pub type_url: String,
+
+    /// The overall metadata file of a finished export, to be passed to an import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_url: Option<String>,
+}
+
+/// The error of a failed operation, a `google.rpc.Status`.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Status {
+    #[serde(default)]
+    pub code: i32,
+
+    #[serde(default)]
+    pub message: String,
+}
+
+/// A long-running operation, `google.longrunning.Operation`.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    /// The resource name, `projects/{project}/operations/{id}`.
+    pub name: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<OperationMetadata>,
+
+    #[serde(default)]
+    pub done: bool,
+
+    /// Set if the operation is done and failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<Status>,
+
+    /// Set if the operation is done and succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<OperationResponse>,
+}
+
+impl Operation {
+    pub fn state(&self) -> State {
+        self.metadata.as_ref().map_or(State::StateUnspecified, |metadata| metadata.common.state)
+    }
+
+    /// Progress in entities, if reported yet.
+    pub fn progress(&self) -> Option<&Progress> {
+        self.metadata.as_ref().and_then(|metadata| metadata.progress_entities.as_ref())
+    }
+
+    /// The overall metadata file of a finished export.
+    pub fn output_url(&self) -> Option<&str> {
+        self.response.as_ref().and_then(|response| response.output_url.as_deref())
+    }
+
+    /// The ID of the index that an index operation creates or deletes.
+    pub fn index_id(&self) -> Option<&str> {
+        self.metadata.as_ref().and_then(|metadata| metadata.index_id.as_deref())
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AncestorMode {
+    #[default]
+    AncestorModeUnspecified,
+    None,
+    AllAncestors,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IndexState {
+    #[default]
+    StateUnspecified,
+    Creating,
+    Ready,
+    Deleting,
+    Error,
+}
+
+/// A composite index on the server, see `index::Index` for its definition.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub project_id: String,
+
+    /// Assigned by the server.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub index_id: String,
+
+    pub kind: String,
+
+    #[serde(default)]
+    pub ancestor: AncestorMode,
+
+    #[serde(default)]
+    pub properties: Vec<IndexedProperty>,
+
+    /// Set by the server.
+    #[serde(default, skip_serializing_if = "is_unspecified")]
+    pub state: IndexState,
+}
+
+fn is_unspecified(state: &IndexState) -> bool {
+    *state == IndexState::StateUnspecified
+}
+
+impl Index {
+    /// The index to create for a definition.
+    pub fn new(definition: &index::Index) -> Index {
+        let ancestor = if definition.ancestor { AncestorMode::AllAncestors } else { AncestorMode::None };
+        Index {
+            kind: definition.kind.clone(),
+            ancestor,
+            properties: definition.properties.clone(),
+            ..Index::default()
+        }
+    }
+
+    pub fn definition(&self) -> index::Index {
+        index::Index {
+            kind: self.kind.clone(),
+            ancestor: self.ancestor == AncestorMode::AllAncestors,
+            properties: self.properties.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ListIndexesResponse {
+    #[serde(default)]
+    pub indexes: Vec<Index>,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub next_page_token: String,
+}
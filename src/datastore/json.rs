@@ -0,0 +1,414 @@
+// Conversions between Datastore entities / values and `serde_json::Value`.
+//
+// Two representations are supported:
+//
+// * The *plain* representation maps every Datastore value to its most natural JSON counterpart,
+//   e.g. timestamps become RFC3339 strings and blobs become base64 strings. This is useful for
+//   displaying and logging entities, but type information is lost along the way.
+//
+// * The *annotated* representation keeps everything needed to restore the original value. Values
+//   that have no JSON counterpart are wrapped in single-key objects whose key names the type:
+//
+//   {"$integer": "42"}, {"$double": 1.5}, {"$timestamp": "2017-09-21T05:41:33Z"},
+//   {"$blob": "UnVzdCE="}, {"$geo": {"latitude": 1.0, "longitude": 2.0}},
+//   {"$key": <key in wire format>}, {"$entity": <annotated entity>}
+//
+//   Values carrying a legacy meaning or excluded from indexes are wrapped once more:
+//
+//   {"$value": <annotated value>, "$meaning": 15, "$excludeFromIndexes": true}
+//
+//   Annotated entities are objects of the form {"key": <key>, "properties": {...}}, which means
+//   that property names can never clash with the type annotations.
+//
+// Plain JSON can be converted back into entities, in which case the Datastore type of each value
+// is inferred from the JSON type unless a type hint for the property path says otherwise.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{self, Map, Number};
+use serde_json::Value as Json;
+
+use crate::datastore::{Blob, Entity, Key, LatLng, Value, ValueMeta};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// An annotated object did not have exactly one recognised `$type` key.
+    UnknownAnnotation(String),
+
+    /// The JSON value did not have the shape required for the Datastore type.
+    ExpectedType(&'static str),
+
+    /// A value had the right JSON type, but its contents could not be parsed.
+    InvalidValue(&'static str, String),
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownAnnotation(ref a) =>
+                write!(fmt, "unknown type annotation: {}", a),
+            Error::ExpectedType(t) =>
+                write!(fmt, "unexpected JSON type: expected {}", t),
+            Error::InvalidValue(t, ref msg) =>
+                write!(fmt, "invalid {} value: {}", t, msg),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// Type hints that override the type inferred from plain JSON. Hints are keyed by property path,
+/// with nested entity properties separated by dots (e.g. `status.updated`). Hints on array
+/// properties apply to each element of the array.
+pub type TypeHints = HashMap<String, TypeHint>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TypeHint {
+    String,
+    Integer,
+    Double,
+    Timestamp,
+    Blob,
+    GeoPoint,
+    Key,
+}
+
+// Plain (lossy) representation:
+
+/// Converts a value into plain JSON. Non-finite doubles, which JSON can not represent, become
+/// `null`.
+pub fn to_plain(value: &Value) -> Json {
+    match *value {
+        Value::Null { .. } => Json::Null,
+        Value::String { ref string_value, .. } => Json::String(string_value.clone()),
+        Value::Boolean { boolean_value, .. } => Json::Bool(boolean_value),
+        Value::Integer { ref integer_value, .. } => match integer_value.parse::<i64>() {
+            Ok(i) => Json::Number(Number::from(i)),
+            Err(_) => Json::String(integer_value.0.clone()),
+        },
+        Value::Double { double_value, .. } => Number::from_f64(double_value)
+            .map(Json::Number)
+            .unwrap_or(Json::Null),
+        Value::Array { ref array_value, .. } =>
+            Json::Array(array_value.values.iter().map(to_plain).collect()),
+        Value::GeoPoint { ref geo_point_value, .. } => geo_point_json(geo_point_value),
+        Value::EntityValue { ref entity_value, .. } => entity_to_plain(entity_value),
+        Value::KeyValue { ref key_value, .. } => key_json(key_value),
+        Value::Blob { ref blob_value, .. } => Json::String(base64::encode(&blob_value.0)),
+        Value::Timestamp { ref timestamp_value, .. } => Json::String(timestamp_string(timestamp_value)),
+    }
+}
+
+/// Converts the properties of an entity into a plain JSON object. The entity key is dropped.
+pub fn entity_to_plain(entity: &Entity) -> Json {
+    let properties = entity.properties.iter()
+        .map(|(name, value)| (name.clone(), to_plain(value)))
+        .collect();
+
+    Json::Object(properties)
+}
+
+/// Converts plain JSON into a value, inferring the Datastore type from the JSON type. Integral
+/// numbers become integers and all other numbers become doubles.
+pub fn from_plain(json: &Json) -> Result<Value> {
+    from_plain_at(json, "", &TypeHints::new())
+}
+
+/// Converts a plain JSON object into an entity without a key, using the given type hints where the
+/// inferred type is not sufficient.
+pub fn entity_from_plain(json: &Json, hints: &TypeHints) -> Result<Entity> {
+    entity_from_plain_at(json, "", hints)
+}
+
+fn entity_from_plain_at(json: &Json, path: &str, hints: &TypeHints) -> Result<Entity> {
+    let object = json.as_object().ok_or(Error::ExpectedType("object"))?;
+    let mut properties = HashMap::new();
+
+    for (name, value) in object {
+        let property_path = if path.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", path, name)
+        };
+
+        properties.insert(name.clone(), from_plain_at(value, &property_path, hints)?);
+    }
+
+    Ok(Entity { key: None, properties })
+}
+
+fn from_plain_at(json: &Json, path: &str, hints: &TypeHints) -> Result<Value> {
+    // Null values and arrays are structural and therefore never subject to hints.
+    match *json {
+        Json::Null => return Ok(Value::from(())),
+        Json::Array(ref values) => {
+            let mut out = vec![];
+            for value in values {
+                out.push(from_plain_at(value, path, hints)?);
+            }
+            return Ok(Value::from(out));
+        }
+        _ => {}
+    }
+
+    match hints.get(path) {
+        Some(hint) => from_plain_hinted(json, *hint),
+        None => match *json {
+            Json::Bool(b) => Ok(Value::from(b)),
+            Json::String(ref s) => Ok(Value::from(s.clone())),
+            Json::Number(ref n) => Ok(number_value(n)),
+            Json::Object(_) => entity_from_plain_at(json, path, hints).map(Value::from),
+            Json::Null | Json::Array(_) => unreachable!(),
+        },
+    }
+}
+
+fn from_plain_hinted(json: &Json, hint: TypeHint) -> Result<Value> {
+    match hint {
+        TypeHint::String => match *json {
+            Json::String(ref s) => Ok(Value::from(s.clone())),
+            _ => Err(Error::ExpectedType("string")),
+        },
+        TypeHint::Integer => match *json {
+            Json::Number(ref n) => n.as_i64()
+                .map(Value::from)
+                .ok_or_else(|| Error::InvalidValue("integer", n.to_string())),
+            Json::String(ref s) => parse_integer(s),
+            _ => Err(Error::ExpectedType("integer")),
+        },
+        TypeHint::Double => match *json {
+            Json::Number(ref n) => Ok(Value::from(n.as_f64().unwrap_or(f64::NAN))),
+            Json::String(ref s) => parse_double(s),
+            _ => Err(Error::ExpectedType("double")),
+        },
+        TypeHint::Timestamp => parse_timestamp(json),
+        TypeHint::Blob => parse_blob(json),
+        TypeHint::GeoPoint => parse_geo_point(json),
+        TypeHint::Key => parse_key(json),
+    }
+}
+
+// Annotated (lossless) representation:
+
+/// Converts a value into its annotated JSON representation.
+pub fn to_annotated(value: &Value) -> Json {
+    let json = annotated_value(value);
+    let meta = value.meta();
+
+    if *meta == ValueMeta::default() {
+        return json;
+    }
+
+    let mut object = Map::new();
+    object.insert("$value".to_string(), json);
+    if let Some(meaning) = meta.meaning {
+        object.insert("$meaning".to_string(), Json::Number(Number::from(meaning)));
+    }
+    if meta.exclude_from_indexes {
+        object.insert("$excludeFromIndexes".to_string(), Json::Bool(true));
+    }
+
+    Json::Object(object)
+}
+
+fn annotated_value(value: &Value) -> Json {
+    match *value {
+        Value::Null { .. } => Json::Null,
+        Value::String { ref string_value, .. } => Json::String(string_value.clone()),
+        Value::Boolean { boolean_value, .. } => Json::Bool(boolean_value),
+        Value::Integer { ref integer_value, .. } =>
+            annotated("$integer", Json::String(integer_value.0.clone())),
+        Value::Double { double_value, .. } => {
+            // Non-finite doubles are represented by their Rust string representation.
+            let double = Number::from_f64(double_value)
+                .map(Json::Number)
+                .unwrap_or_else(|| Json::String(format!("{}", double_value)));
+            annotated("$double", double)
+        }
+        Value::Array { ref array_value, .. } =>
+            Json::Array(array_value.values.iter().map(to_annotated).collect()),
+        Value::GeoPoint { ref geo_point_value, .. } =>
+            annotated("$geo", geo_point_json(geo_point_value)),
+        Value::EntityValue { ref entity_value, .. } =>
+            annotated("$entity", entity_to_annotated(entity_value)),
+        Value::KeyValue { ref key_value, .. } => annotated("$key", key_json(key_value)),
+        Value::Blob { ref blob_value, .. } =>
+            annotated("$blob", Json::String(base64::encode(&blob_value.0))),
+        Value::Timestamp { ref timestamp_value, .. } =>
+            annotated("$timestamp", Json::String(timestamp_string(timestamp_value))),
+    }
+}
+
+/// Converts an entity, including its key, into its annotated JSON representation.
+pub fn entity_to_annotated(entity: &Entity) -> Json {
+    let mut object = Map::new();
+
+    if let Some(ref key) = entity.key {
+        object.insert("key".to_string(), key_json(key));
+    }
+
+    let properties = entity.properties.iter()
+        .map(|(name, value)| (name.clone(), to_annotated(value)))
+        .collect();
+    object.insert("properties".to_string(), Json::Object(properties));
+
+    Json::Object(object)
+}
+
+/// Restores a value from its annotated JSON representation.
+pub fn from_annotated(json: &Json) -> Result<Value> {
+    match *json {
+        Json::Null => Ok(Value::from(())),
+        Json::Bool(b) => Ok(Value::from(b)),
+        Json::String(ref s) => Ok(Value::from(s.clone())),
+        Json::Number(_) => Err(Error::ExpectedType("annotated number")),
+        Json::Array(ref values) => {
+            let mut out = vec![];
+            for value in values {
+                out.push(from_annotated(value)?);
+            }
+            Ok(Value::from(out))
+        }
+        Json::Object(ref object) if object.contains_key("$value") => {
+            let mut value = from_annotated(&object["$value"])?;
+
+            for (annotation, inner) in object {
+                match annotation.as_str() {
+                    "$value" => {}
+                    "$meaning" => {
+                        let meaning = inner.as_i64()
+                            .and_then(|meaning| i32::try_from(meaning).ok())
+                            .ok_or(Error::ExpectedType("meaning"))?;
+                        value.meta_mut().meaning = Some(meaning);
+                    }
+                    "$excludeFromIndexes" => {
+                        let exclude = inner.as_bool()
+                            .ok_or(Error::ExpectedType("excludeFromIndexes"))?;
+                        value.meta_mut().exclude_from_indexes = exclude;
+                    }
+                    other => return Err(Error::UnknownAnnotation(other.to_string())),
+                }
+            }
+
+            Ok(value)
+        }
+        Json::Object(ref object) => {
+            if object.len() != 1 {
+                return Err(Error::UnknownAnnotation(format!("{} keys", object.len())));
+            }
+
+            let (annotation, inner) = object.iter().next().unwrap();
+            match annotation.as_str() {
+                "$integer" => match *inner {
+                    Json::String(ref s) => parse_integer(s),
+                    _ => Err(Error::ExpectedType("integer string")),
+                },
+                "$double" => match *inner {
+                    Json::Number(ref n) => Ok(Value::from(n.as_f64().unwrap_or(f64::NAN))),
+                    Json::String(ref s) => parse_double(s),
+                    _ => Err(Error::ExpectedType("double")),
+                },
+                "$timestamp" => parse_timestamp(inner),
+                "$blob" => parse_blob(inner),
+                "$geo" => parse_geo_point(inner),
+                "$key" => parse_key(inner),
+                "$entity" => entity_from_annotated(inner).map(Value::from),
+                other => Err(Error::UnknownAnnotation(other.to_string())),
+            }
+        }
+    }
+}
+
+/// Restores an entity from its annotated JSON representation.
+pub fn entity_from_annotated(json: &Json) -> Result<Entity> {
+    let object = json.as_object().ok_or(Error::ExpectedType("object"))?;
+
+    let key = match object.get("key") {
+        None | Some(&Json::Null) => None,
+        Some(key) => Some(key_from_json(key)?),
+    };
+
+    let mut properties = HashMap::new();
+    if let Some(props) = object.get("properties") {
+        let props = props.as_object().ok_or(Error::ExpectedType("properties object"))?;
+        for (name, value) in props {
+            properties.insert(name.clone(), from_annotated(value)?);
+        }
+    }
+
+    Ok(Entity { key, properties })
+}
+
+// Helpers shared by both representations:
+
+fn annotated(annotation: &str, value: Json) -> Json {
+    let mut object = Map::new();
+    object.insert(annotation.to_string(), value);
+    Json::Object(object)
+}
+
+// Numbers that do not fit into Datastore's 64-bit integers are stored as doubles.
+fn number_value(n: &Number) -> Value {
+    match n.as_i64() {
+        Some(i) => Value::from(i),
+        None => Value::from(n.as_f64().unwrap_or(f64::NAN)),
+    }
+}
+
+fn timestamp_string(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn geo_point_json(lat_lng: &LatLng) -> Json {
+    serde_json::to_value(lat_lng).expect("LatLng serialisation can not fail")
+}
+
+fn key_json(key: &Key) -> Json {
+    serde_json::to_value(key).expect("Key serialisation can not fail")
+}
+
+fn key_from_json(json: &Json) -> Result<Key> {
+    serde_json::from_value(json.clone())
+        .map_err(|e| Error::InvalidValue("key", format!("{}", e)))
+}
+
+fn parse_integer(s: &str) -> Result<Value> {
+    s.parse::<i64>()
+        .map(Value::from)
+        .map_err(|e| Error::InvalidValue("integer", format!("{}", e)))
+}
+
+fn parse_double(s: &str) -> Result<Value> {
+    s.parse::<f64>()
+        .map(Value::from)
+        .map_err(|e| Error::InvalidValue("double", format!("{}", e)))
+}
+
+fn parse_timestamp(json: &Json) -> Result<Value> {
+    let s = json.as_str().ok_or(Error::ExpectedType("timestamp string"))?;
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| Value::from(t.with_timezone(&Utc)))
+        .map_err(|e| Error::InvalidValue("timestamp", format!("{}", e)))
+}
+
+fn parse_blob(json: &Json) -> Result<Value> {
+    let s = json.as_str().ok_or(Error::ExpectedType("base64 string"))?;
+    base64::decode(s)
+        .map(|bytes| Value::from(Blob(bytes)))
+        .map_err(|e| Error::InvalidValue("blob", format!("{:?}", e)))
+}
+
+fn parse_geo_point(json: &Json) -> Result<Value> {
+    serde_json::from_value::<LatLng>(json.clone())
+        .map(Value::from)
+        .map_err(|e| Error::InvalidValue("geo point", format!("{}", e)))
+}
+
+fn parse_key(json: &Json) -> Result<Value> {
+    key_from_json(json).map(Value::from)
+}
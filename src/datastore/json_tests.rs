@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::PathBuf;
+use chrono::{TimeZone, Utc};
+use crate::datastore::*;
+use crate::datastore::json::{self, Error, TypeHint, TypeHints};
+
+fn test_entity() -> Entity {
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("resources/entity-test.json");
+    let file = File::open(d).expect("Could not open test file");
+    serde_json::from_reader(file).expect("Deserialisation failed")
+}
+
+fn test_key() -> Key {
+    serde_json::from_str(r#"{
+        "partitionId": {"projectId": "test-project"},
+        "path": [{"kind": "User", "id": "123"}]
+    }"#).expect("Key deserialisation failed")
+}
+
+#[test]
+fn test_entity_to_plain() {
+    let plain = json::entity_to_plain(&test_entity());
+    let expected: serde_json::Value = serde_json::from_str(r#"{
+        "status": {"scoringStatus": "Accepted"},
+        "email": "mags@mag",
+        "signingId": null,
+        "availableProducts": ["creditline"],
+        "companyCountry": "NO",
+        "created": "2017-09-21T05:41:33Z"
+    }"#).unwrap();
+
+    assert_eq!(expected, plain, "Plain JSON should match expectations");
+}
+
+#[test]
+fn test_plain_scalars() {
+    assert_eq!(json!(42), json::to_plain(&Value::from(42)));
+    assert_eq!(json!(1.5), json::to_plain(&Value::from(1.5)));
+    assert_eq!(json!(null), json::to_plain(&Value::from(f64::NAN)));
+    assert_eq!(json!("UnVzdCE="), json::to_plain(&Value::from(Blob::from(&b"Rust!"[..]))));
+}
+
+#[test]
+fn test_entity_from_plain_inference() {
+    let input = json!({
+        "name": "Rust",
+        "age": 7,
+        "score": 9.5,
+        "tags": ["fast", "safe"],
+        "nested": {"flag": true}
+    });
+
+    let entity = json::entity_from_plain(&input, &TypeHints::new()).expect("conversion failed");
+
+    let nested = Entity {
+        key: None,
+        properties: hashmap!("flag".to_string() => Value::from(true)),
+    };
+
+    let expected = Entity {
+        key: None,
+        properties: hashmap!(
+            "name".to_string() => Value::from("Rust"),
+            "age".to_string() => Value::from(7),
+            "score".to_string() => Value::from(9.5),
+            "tags".to_string() => Value::from(vec!["fast", "safe"]),
+            "nested".to_string() => Value::from(nested),
+        ),
+    };
+
+    assert_eq!(expected, entity);
+}
+
+#[test]
+fn test_entity_from_plain_with_hints() {
+    let input = json!({
+        "created": "2017-09-21T05:41:33Z",
+        "ratio": 2,
+        "ids": ["1", "2"],
+        "status": {"updated": "2017-09-21T05:41:33Z"}
+    });
+
+    let hints: TypeHints = hashmap!(
+        "created".to_string() => TypeHint::Timestamp,
+        "ratio".to_string() => TypeHint::Double,
+        "ids".to_string() => TypeHint::Integer,
+        "status.updated".to_string() => TypeHint::Timestamp,
+    );
+
+    let entity = json::entity_from_plain(&input, &hints).expect("conversion failed");
+    let time = Utc.with_ymd_and_hms(2017, 9, 21, 5, 41, 33).unwrap();
+
+    assert_eq!(Some(&Value::from(time)), entity.properties.get("created"));
+    assert_eq!(Some(&Value::from(2.0)), entity.properties.get("ratio"));
+    assert_eq!(Some(&Value::from(vec![1, 2])), entity.properties.get("ids"));
+
+    let status = Entity {
+        key: None,
+        properties: hashmap!("updated".to_string() => Value::from(time)),
+    };
+    assert_eq!(Some(&Value::from(status)), entity.properties.get("status"));
+}
+
+#[test]
+fn test_hint_type_mismatch() {
+    let hints: TypeHints = hashmap!("created".to_string() => TypeHint::Timestamp);
+    let result = json::entity_from_plain(&json!({"created": true}), &hints);
+
+    assert_eq!(Err(Error::ExpectedType("timestamp string")), result);
+}
+
+#[test]
+fn test_annotated_values() {
+    assert_eq!(json!({"$integer": "42"}), json::to_annotated(&Value::from(42)));
+    assert_eq!(json!({"$double": 42.0}), json::to_annotated(&Value::from(42.0)));
+    assert_eq!(json!({"$double": "inf"}), json::to_annotated(&Value::from(f64::INFINITY)));
+    assert_eq!(json!({"$blob": "UnVzdCE="}),
+               json::to_annotated(&Value::from(Blob::from(&b"Rust!"[..]))));
+
+    let key_value = Value::from(test_key());
+    let expected_key = json!({"$key": {
+        "partitionId": {"projectId": "test-project"},
+        "path": [{"kind": "User", "id": "123"}]
+    }});
+    assert_eq!(expected_key, json::to_annotated(&key_value));
+}
+
+#[test]
+fn test_annotated_roundtrip() {
+    let mut entity = test_entity();
+    entity.key = Some(test_key());
+
+    let mut properties: HashMap<String, Value> = HashMap::new();
+    properties.insert("int".to_string(), Value::from(i64::MIN));
+    properties.insert("double".to_string(), Value::from(1.0));
+    properties.insert("infinity".to_string(), Value::from(f64::NEG_INFINITY));
+    properties.insert("blob".to_string(), Value::from(Blob(vec![0, 1, 2, 255])));
+    properties.insert("key".to_string(), Value::from(test_key()));
+    properties.insert("geo".to_string(), Value::from(LatLng::new(59.9, 10.7)));
+    properties.insert("text".to_string(), Value::from("long text").with_meaning(15).unindexed());
+    entity.properties.insert("extra".to_string(), Value::from(Entity { key: None, properties }));
+
+    let annotated = json::entity_to_annotated(&entity);
+    let restored = json::entity_from_annotated(&annotated).expect("restoring entity failed");
+
+    assert_eq!(entity, restored, "Entity should survive annotated round-trip");
+}
+
+#[test]
+fn test_annotated_meta() {
+    let value = Value::from(Blob(vec![1])).unindexed();
+    let expected = json!({"$value": {"$blob": "AQ=="}, "$excludeFromIndexes": true});
+    assert_eq!(expected, json::to_annotated(&value));
+    assert_eq!(Ok(value), json::from_annotated(&expected));
+}
+
+#[test]
+fn test_unknown_annotation() {
+    let result = json::from_annotated(&json!({"$foo": 1}));
+    assert_eq!(Err(Error::UnknownAnnotation("$foo".to_string())), result);
+}
+
+#[test]
+fn test_meaning_out_of_range() {
+    let result = json::from_annotated(&json!({"$value": true, "$meaning": 4294967296i64}));
+    assert_eq!(Err(Error::ExpectedType("meaning")), result);
+}
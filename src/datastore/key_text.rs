@@ -0,0 +1,284 @@
+// The text syntax of keys, for logs, command line arguments and configuration files.
+//
+//   [project:my-project ns:tenant] Kind:123 / Child:"name"
+//
+// The bracketed partition is left out where the project and namespace are empty, unless the path
+// is empty as well, so that a key without path elements is written as `[]`. Path elements
+// are separated by slashes, numeric IDs are written as numbers and names are always quoted, so
+// that the name "123" stays distinct from the ID 123. Incomplete elements are written as their
+// kind alone. Kinds and partition values are quoted unless they consist of letters, digits, `_`,
+// `.` and `-`. Quoted strings escape `"`, `\` and control characters with a backslash.
+//
+// `Display` writes the canonical form, which `FromStr` reads back into an equal key. Parsing is
+// lenient about whitespace around separators.
+
+use std::fmt::{self, Display, Write};
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use crate::datastore::{Key, PartitionId, PathElement};
+
+/// Why a key could not be parsed, at which byte offset of the text.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseKeyError {
+    position: usize,
+    message: String,
+}
+
+impl ParseKeyError {
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl Display for ParseKeyError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "invalid key at offset {}: {}", self.position, self.message)
+    }
+}
+
+impl ::std::error::Error for ParseKeyError {}
+
+impl Display for Key {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let partition = &self.partition_id;
+        let mut attributes = vec![];
+        if !partition.project_id().is_empty() {
+            attributes.push(format!("project:{}", quote_token(partition.project_id())));
+        }
+        if !partition.namespace_id().is_empty() {
+            attributes.push(format!("ns:{}", quote_token(partition.namespace_id())));
+        }
+        if !attributes.is_empty() || self.path.is_empty() {
+            write!(fmt, "[{}]", attributes.join(" "))?;
+            if !self.path.is_empty() {
+                fmt.write_char(' ')?;
+            }
+        }
+
+        for (i, element) in self.path.iter().enumerate() {
+            if i > 0 {
+                fmt.write_str(" / ")?;
+            }
+            fmt.write_str(&quote_token(element.kind()))?;
+            match *element {
+                PathElement::Id { ref id, .. } => write!(fmt, ":{}", id)?,
+                PathElement::Name { ref name, .. } => write!(fmt, ":{}", quote(name))?,
+                PathElement::Incomplete { .. } => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParseKeyError;
+
+    fn from_str(text: &str) -> Result<Key, ParseKeyError> {
+        let mut parser = Parser { text, chars: text.char_indices().peekable() };
+        let key = parser.key()?;
+        parser.skip_whitespace();
+        match parser.chars.peek() {
+            Some(&(position, _)) => Err(parser.error_at(position, "expected `/` between path elements")),
+            None => Ok(key),
+        }
+    }
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '-'
+}
+
+// Kinds and partition values are only quoted where necessary.
+fn quote_token(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(is_token_char) {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{{{:x}}}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn error_at(&self, position: usize, message: &str) -> ParseKeyError {
+        ParseKeyError { position, message: message.to_string() }
+    }
+
+    fn error(&mut self, message: &str) -> ParseKeyError {
+        let position = self.position();
+        self.error_at(position, message)
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.text.len(), |&(i, _)| i)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|&(_, c)| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.chars.peek().is_some_and(|&(_, c)| c == expected) {
+            self.chars.next();
+            return true;
+        }
+        false
+    }
+
+    fn key(&mut self) -> Result<Key, ParseKeyError> {
+        self.skip_whitespace();
+        let bracketed = self.eat('[');
+        let partition = if bracketed { self.partition()? } else { PartitionId::new("", "") };
+
+        // Only a partition can stand on its own, as the text of a key without path elements.
+        self.skip_whitespace();
+        if bracketed && self.chars.peek().is_none() {
+            return Ok(Key::new(partition, vec![]));
+        }
+
+        let mut path = vec![];
+        loop {
+            self.skip_whitespace();
+            path.push(self.element()?);
+            self.skip_whitespace();
+            if !self.eat('/') {
+                return Ok(Key::new(partition, path));
+            }
+        }
+    }
+
+    fn partition(&mut self) -> Result<PartitionId, ParseKeyError> {
+        let (mut project, mut namespace) = (None, None);
+        loop {
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(PartitionId::new(project.unwrap_or_default(), namespace.unwrap_or_default()));
+            }
+
+            let position = self.position();
+            let name = self.token()?;
+            if !self.eat(':') {
+                return Err(self.error("expected `:` after the partition attribute"));
+            }
+            let value = self.string()?;
+            let slot = match name.as_str() {
+                "project" => &mut project,
+                "ns" => &mut namespace,
+                _ => return Err(self.error_at(position, "unknown partition attribute, expected project or ns")),
+            };
+            if slot.replace(value).is_some() {
+                return Err(self.error_at(position, "duplicate partition attribute"));
+            }
+        }
+    }
+
+    fn element(&mut self) -> Result<PathElement, ParseKeyError> {
+        let kind = self.string()?;
+        if !self.eat(':') {
+            return Ok(PathElement::incomplete(kind));
+        }
+
+        if self.chars.peek().is_some_and(|&(_, c)| c == '"') {
+            return Ok(PathElement::from_name(kind, self.quoted()?));
+        }
+        let position = self.position();
+        let id = self.token()?;
+        match id.parse::<i64>() {
+            Ok(id) => Ok(PathElement::from_id(kind, id)),
+            Err(_) => Err(self.error_at(position, "expected a numeric ID or a quoted name")),
+        }
+    }
+
+    // A quoted string or a token.
+    fn string(&mut self) -> Result<String, ParseKeyError> {
+        if self.chars.peek().is_some_and(|&(_, c)| c == '"') {
+            self.quoted()
+        } else {
+            self.token()
+        }
+    }
+
+    fn token(&mut self) -> Result<String, ParseKeyError> {
+        let mut token = String::new();
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !is_token_char(c) {
+                break;
+            }
+            token.push(c);
+            self.chars.next();
+        }
+        if token.is_empty() {
+            return Err(self.error("expected a name"));
+        }
+        Ok(token)
+    }
+
+    fn quoted(&mut self) -> Result<String, ParseKeyError> {
+        let start = self.position();
+        self.chars.next();
+        let mut value = String::new();
+        loop {
+            let c = match self.chars.next() {
+                Some((_, '"')) => return Ok(value),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => '"',
+                    Some((_, '\\')) => '\\',
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, 'u')) => self.unicode_escape()?,
+                    Some((position, _)) => return Err(self.error_at(position, "unknown escape")),
+                    None => return Err(self.error_at(start, "unterminated string")),
+                },
+                Some((_, c)) => c,
+                None => return Err(self.error_at(start, "unterminated string")),
+            };
+            value.push(c);
+        }
+    }
+
+    // Reads the `{hex}` of a `\u{hex}` escape.
+    fn unicode_escape(&mut self) -> Result<char, ParseKeyError> {
+        let position = self.position();
+        let invalid = |parser: &Self| parser.error_at(position, "invalid unicode escape");
+        if !self.eat('{') {
+            return Err(invalid(self));
+        }
+        let mut hex = String::new();
+        while let Some((_, c)) = self.chars.next() {
+            if c == '}' {
+                return u32::from_str_radix(&hex, 16).ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| invalid(self));
+            }
+            hex.push(c);
+        }
+        Err(invalid(self))
+    }
+}
@@ -0,0 +1,207 @@
+// Metadata about the namespaces, kinds and properties of a project.
+//
+// Datastore exposes metadata as entities of the pseudo-kinds `__namespace__`, `__kind__` and
+// `__property__`, which are queried like any other kind but encode the metadata in their keys:
+//
+// * `__namespace__` entities are named after the namespace, the default namespace has ID 1.
+// * `__kind__` entities are named after the kind and live in the partition of the namespace.
+// * `__property__` entities are children of the `__kind__` entity, named after the property. Their
+//   `property_representation` property lists how the property's values are stored in indexes.
+//
+// Only indexed properties appear as `__property__` entities.
+
+use std::fmt;
+
+use crate::datastore::{Entity, Key, PartitionId, PathElement, Value};
+
+pub const NAMESPACE_KIND: &str = "__namespace__";
+pub const KIND_KIND: &str = "__kind__";
+pub const PROPERTY_KIND: &str = "__property__";
+
+/// The property of `__property__` entities listing the representations.
+pub const PROPERTY_REPRESENTATION: &str = "property_representation";
+
+/// The ID of the `__namespace__` key of the default namespace.
+pub const DEFAULT_NAMESPACE_ID: i64 = 1;
+
+/// A namespace, the empty string for the default namespace.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Namespace {
+    pub name: String,
+}
+
+impl Namespace {
+    pub fn new<N: Into<String>>(name: N) -> Namespace {
+        Namespace { name: name.into() }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// The key of the namespace's `__namespace__` entity.
+    pub fn key(&self) -> Key {
+        let element = if self.is_default() {
+            PathElement::from_id(NAMESPACE_KIND, DEFAULT_NAMESPACE_ID)
+        } else {
+            PathElement::from_name(NAMESPACE_KIND, self.name.as_str())
+        };
+        Key::new(PartitionId::new("", ""), vec![element])
+    }
+
+    /// Decodes the key of a `__namespace__` entity, `None` for keys of other shapes.
+    pub fn from_key(key: &Key) -> Option<Namespace> {
+        match key.path() {
+            [element] if element.kind() == NAMESPACE_KIND => match (element.id(), element.name()) {
+                (Some(DEFAULT_NAMESPACE_ID), _) => Some(Namespace::new("")),
+                (_, Some(name)) => Some(Namespace::new(name)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A kind within a namespace.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Kind {
+    pub namespace: String,
+    pub name: String,
+}
+
+impl Kind {
+    pub fn new<N: Into<String>, K: Into<String>>(namespace: N, name: K) -> Kind {
+        Kind { namespace: namespace.into(), name: name.into() }
+    }
+
+    /// The key of the kind's `__kind__` entity.
+    pub fn key(&self) -> Key {
+        Key::new(PartitionId::new("", self.namespace.as_str()),
+                 vec![PathElement::from_name(KIND_KIND, self.name.as_str())])
+    }
+
+    /// Decodes the key of a `__kind__` entity, `None` for keys of other shapes.
+    pub fn from_key(key: &Key) -> Option<Kind> {
+        match key.path() {
+            [element] if element.kind() == KIND_KIND => Some(Kind::new(
+                key.partition_id().namespace_id(), element.name()?)),
+            _ => None,
+        }
+    }
+}
+
+/// An indexed property of a kind.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Property {
+    pub namespace: String,
+    pub kind: String,
+    pub name: String,
+
+    /// How the values of the property are stored, empty if the property was read with a keys-only
+    /// query.
+    pub representations: Vec<Representation>,
+}
+
+impl Property {
+    /// The key of the property's `__property__` entity.
+    pub fn key(&self) -> Key {
+        Kind::new(self.namespace.as_str(), self.kind.as_str()).key()
+            .child(PathElement::from_name(PROPERTY_KIND, self.name.as_str()))
+    }
+
+    /// Decodes the key of a `__property__` entity, `None` for keys of other shapes.
+    pub fn from_key(key: &Key) -> Option<Property> {
+        match key.path() {
+            [kind, property] if kind.kind() == KIND_KIND && property.kind() == PROPERTY_KIND => {
+                Some(Property {
+                    namespace: key.partition_id().namespace_id().to_string(),
+                    kind: kind.name()?.to_string(),
+                    name: property.name()?.to_string(),
+                    representations: vec![],
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Decodes a `__property__` entity including its representations.
+    pub fn from_entity(entity: &Entity) -> Option<Property> {
+        let mut property = Property::from_key(entity.key.as_ref()?)?;
+        property.representations = match entity.properties.get(PROPERTY_REPRESENTATION) {
+            Some(Value::Array { array_value, .. }) => array_value.values.iter()
+                .filter_map(|value| match *value {
+                    Value::String { ref string_value, .. } => Some(Representation::from(string_value.as_str())),
+                    _ => None,
+                })
+                .collect(),
+            Some(Value::String { string_value, .. }) => vec![Representation::from(string_value.as_str())],
+            _ => vec![],
+        };
+        Some(property)
+    }
+}
+
+/// How values are stored in indexes. Several value types share a representation, e.g. integers and
+/// timestamps are both `Int64`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Representation {
+    Null,
+    Int64,
+    Boolean,
+    String,
+    Double,
+    Point,
+    Reference,
+    Other(String),
+}
+
+impl Representation {
+    /// The representation of an indexed value, `None` for arrays and embedded entities, whose
+    /// elements and properties are indexed instead.
+    pub fn of(value: &Value) -> Option<Representation> {
+        match *value {
+            Value::Null { .. } => Some(Representation::Null),
+            Value::Integer { .. } | Value::Timestamp { .. } => Some(Representation::Int64),
+            Value::Boolean { .. } => Some(Representation::Boolean),
+            Value::String { .. } | Value::Blob { .. } => Some(Representation::String),
+            Value::Double { .. } => Some(Representation::Double),
+            Value::GeoPoint { .. } => Some(Representation::Point),
+            Value::KeyValue { .. } => Some(Representation::Reference),
+            Value::EntityValue { .. } | Value::Array { .. } => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Representation::Null => "NULL",
+            Representation::Int64 => "INT64",
+            Representation::Boolean => "BOOLEAN",
+            Representation::String => "STRING",
+            Representation::Double => "DOUBLE",
+            Representation::Point => "POINT",
+            Representation::Reference => "REFERENCE",
+            Representation::Other(ref other) => other,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Representation {
+    fn from(s: &'a str) -> Representation {
+        match s {
+            "NULL" => Representation::Null,
+            "INT64" => Representation::Int64,
+            "BOOLEAN" => Representation::Boolean,
+            "STRING" => Representation::String,
+            "DOUBLE" => Representation::Double,
+            "POINT" => Representation::Point,
+            "REFERENCE" => Representation::Reference,
+            other => Representation::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Representation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
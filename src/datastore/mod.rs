@@ -1,33 +1,198 @@
 use std::collections::HashMap;
 use serde::de::Error;
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
-use base64;
 use chrono::{DateTime, Utc};
 use std::str::FromStr;
-use std::convert::Into;
+use std::convert::{Into, TryFrom};
+
+pub mod admin;
+pub mod json;
+pub mod metadata;
+pub mod query;
+pub mod rpc;
+pub mod stats;
+
+mod key_text;
+
+pub use self::key_text::ParseKeyError;
 
 #[cfg(test)]
+#[allow(deprecated, clippy::zero_prefixed_literal)]
 mod tests;
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[cfg(test)]
+mod json_tests;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct PartitionId {
     project_id: String,
+
+    // The default namespace is represented by omitting the namespace ID.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     namespace_id: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
-#[serde(untagged)]
+impl PartitionId {
+    /// Creates a partition ID. An empty namespace ID refers to the default namespace.
+    pub fn new<P: Into<String>, N: Into<String>>(project_id: P, namespace_id: N) -> PartitionId {
+        PartitionId {
+            project_id: project_id.into(),
+            namespace_id: namespace_id.into(),
+        }
+    }
+
+    pub fn project_id(&self) -> &str {
+        &self.project_id
+    }
+
+    pub fn namespace_id(&self) -> &str {
+        &self.namespace_id
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[serde(untagged, try_from = "RawPathElement")]
 pub enum PathElement {
     Id { kind: String, id: String },
     Name { kind: String, name: String },
+
+    /// An element without ID or name, which the API completes with a newly allocated ID.
+    Incomplete { kind: String },
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+// The wire format of a path element. IDs are sent as strings by the API, but numbers are accepted
+// as well since they are the natural way of writing them by hand.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPathElement {
+    kind: String,
+    id: Option<RawId>,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawId {
+    Number(i64),
+    Text(String),
+}
+
+impl TryFrom<RawPathElement> for PathElement {
+    type Error = String;
+
+    fn try_from(raw: RawPathElement) -> Result<PathElement, String> {
+        let RawPathElement { kind, id, name } = raw;
+        match (id, name) {
+            (Some(_), Some(_)) => Err(format!("path element of kind {} has both an ID and a name", kind)),
+            (Some(RawId::Number(id)), None) => Ok(PathElement::from_id(kind, id)),
+            (Some(RawId::Text(id)), None) => Ok(PathElement::Id { kind, id }),
+            (None, Some(name)) => Ok(PathElement::Name { kind, name }),
+            (None, None) => Ok(PathElement::Incomplete { kind }),
+        }
+    }
+}
+
+impl PathElement {
+    pub fn from_id<K: Into<String>>(kind: K, id: i64) -> PathElement {
+        PathElement::Id { kind: kind.into(), id: format!("{}", id) }
+    }
+
+    pub fn from_name<K: Into<String>, N: Into<String>>(kind: K, name: N) -> PathElement {
+        PathElement::Name { kind: kind.into(), name: name.into() }
+    }
+
+    /// An element that identifies a new entity whose ID is allocated by the API.
+    pub fn incomplete<K: Into<String>>(kind: K) -> PathElement {
+        PathElement::Incomplete { kind: kind.into() }
+    }
+
+    pub fn kind(&self) -> &str {
+        match *self {
+            PathElement::Id { ref kind, .. }
+            | PathElement::Name { ref kind, .. }
+            | PathElement::Incomplete { ref kind } => kind,
+        }
+    }
+
+    /// Returns the numeric ID of this element, if it has one. IDs are transmitted as strings, an
+    /// ID that is not a valid 64-bit integer is treated as absent.
+    pub fn id(&self) -> Option<i64> {
+        match *self {
+            PathElement::Id { ref id, .. } => id.parse().ok(),
+            PathElement::Name { .. } | PathElement::Incomplete { .. } => None,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match *self {
+            PathElement::Name { ref name, .. } => Some(name),
+            PathElement::Id { .. } | PathElement::Incomplete { .. } => None,
+        }
+    }
+
+    /// Whether the element has an ID or a name.
+    pub fn is_complete(&self) -> bool {
+        !matches!(*self, PathElement::Incomplete { .. })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Key {
     partition_id: PartitionId,
     path: Vec<PathElement>,
 }
 
+impl Key {
+    pub fn new(partition_id: PartitionId, path: Vec<PathElement>) -> Key {
+        Key { partition_id, path }
+    }
+
+    pub fn partition_id(&self) -> &PartitionId {
+        &self.partition_id
+    }
+
+    pub fn path(&self) -> &[PathElement] {
+        &self.path
+    }
+
+    /// The kind of the entity identified by this key, i.e. the kind of the last path element.
+    pub fn kind(&self) -> Option<&str> {
+        self.path.last().map(PathElement::kind)
+    }
+
+    /// The key of the parent entity in the same partition, `None` for root entities.
+    pub fn parent(&self) -> Option<Key> {
+        match self.path.len() {
+            0 | 1 => None,
+            len => Some(Key::new(self.partition_id.clone(), self.path[..len - 1].to_vec())),
+        }
+    }
+
+    /// The key of a child entity in the same partition.
+    pub fn child(&self, element: PathElement) -> Key {
+        let mut path = self.path.clone();
+        path.push(element);
+        Key::new(self.partition_id.clone(), path)
+    }
+
+    /// Whether every element of the path has an ID or a name, i.e. the key identifies an entity.
+    /// Keys whose last element is incomplete are completed by allocating an ID.
+    pub fn is_complete(&self) -> bool {
+        !self.path.is_empty() && self.path.iter().all(PathElement::is_complete)
+    }
+
+    /// Completes the last element of the key with a numeric ID.
+    pub fn with_id(&self, id: i64) -> Key {
+        let mut path = self.path.clone();
+        if let Some(last) = path.pop() {
+            path.push(PathElement::from_id(last.kind(), id));
+        }
+        Key::new(self.partition_id.clone(), path)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct ArrayValue {
     pub values: Vec<Value>,
@@ -39,6 +204,20 @@ pub struct LatLng {
     longitude: f64,
 }
 
+impl LatLng {
+    pub fn new(latitude: f64, longitude: f64) -> LatLng {
+        LatLng { latitude, longitude }
+    }
+
+    pub fn latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    pub fn longitude(&self) -> f64 {
+        self.longitude
+    }
+}
+
 /// This newtype around a byte vector provides base64-based (de-)serialisation for use in Datastore.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Blob(pub Vec<u8>);
@@ -69,7 +248,7 @@ impl<'de> Deserialize<'de> for Blob {
             D: Deserializer<'de>,
     {
         let str = String::deserialize(deserializer)?;
-        base64::decode(&str).map(|vec| Blob(vec)).map_err(|e| {
+        base64::decode(&str).map(Blob).map_err(|e| {
             D::Error::custom(format!("base64-decoding failed: {:?}", e))
         })
     }
@@ -103,6 +282,36 @@ impl From<i32> for Int { fn from(v: i32) -> Self { Int(format!("{}", v)) } }
 
 impl From<i64> for Int { fn from(v: i64) -> Self { Int(format!("{}", v)) } }
 
+/// Additional information that may be attached to any value. The `meaning` is a legacy type
+/// annotation (e.g. 15 for long text) that Datastore still reports for entities written by older
+/// APIs, while values excluded from indexes can not be used in queries.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meaning: Option<i32>,
+
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub exclude_from_indexes: bool,
+}
+
+/// Legacy meaning codes that may appear in `ValueMeta::meaning`.
+pub mod meaning {
+    pub const GD_WHEN: i32 = 7;
+    pub const GEORSS_POINT: i32 = 9;
+    pub const RATING: i32 = 13;
+    pub const BLOB: i32 = 14;
+    pub const TEXT: i32 = 15;
+    pub const BYTESTRING: i32 = 16;
+    pub const ENTITY_PROTO: i32 = 19;
+    pub const USER: i32 = 20;
+    pub const EMPTY_LIST: i32 = 24;
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
 // Currently the many nested attributes are needed because of
 // https://github.com/serde-rs/serde/issues/1061
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
@@ -111,51 +320,131 @@ pub enum Value {
     Null {
         #[serde(rename = "nullValue")]
         null_value: (),
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     String {
         #[serde(rename = "stringValue")]
         string_value: String,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     Boolean {
         #[serde(rename = "booleanValue")]
         boolean_value: bool,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     Integer {
         #[serde(rename = "integerValue")]
         integer_value: Int,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     Double {
         #[serde(rename = "doubleValue")]
         double_value: f64,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     Array {
         #[serde(rename = "arrayValue")]
         array_value: ArrayValue,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     GeoPoint {
         #[serde(rename = "geoPointValue")]
         geo_point_value: LatLng,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     EntityValue {
         #[serde(rename = "entityValue")]
         entity_value: Entity,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     KeyValue {
         #[serde(rename = "keyValue")]
         key_value: Key,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     Blob {
         #[serde(rename = "blobValue")]
         blob_value: Blob,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
     Timestamp {
         #[serde(rename = "timestampValue")]
         timestamp_value: DateTime<Utc>,
+        #[serde(flatten)]
+        meta: ValueMeta,
     },
 }
 
+impl Value {
+    pub fn meta(&self) -> &ValueMeta {
+        match *self {
+            Value::Null { ref meta, .. } |
+            Value::String { ref meta, .. } |
+            Value::Boolean { ref meta, .. } |
+            Value::Integer { ref meta, .. } |
+            Value::Double { ref meta, .. } |
+            Value::Array { ref meta, .. } |
+            Value::GeoPoint { ref meta, .. } |
+            Value::EntityValue { ref meta, .. } |
+            Value::KeyValue { ref meta, .. } |
+            Value::Blob { ref meta, .. } |
+            Value::Timestamp { ref meta, .. } => meta,
+        }
+    }
+
+    pub fn meta_mut(&mut self) -> &mut ValueMeta {
+        match *self {
+            Value::Null { ref mut meta, .. } |
+            Value::String { ref mut meta, .. } |
+            Value::Boolean { ref mut meta, .. } |
+            Value::Integer { ref mut meta, .. } |
+            Value::Double { ref mut meta, .. } |
+            Value::Array { ref mut meta, .. } |
+            Value::GeoPoint { ref mut meta, .. } |
+            Value::EntityValue { ref mut meta, .. } |
+            Value::KeyValue { ref mut meta, .. } |
+            Value::Blob { ref mut meta, .. } |
+            Value::Timestamp { ref mut meta, .. } => meta,
+        }
+    }
+
+    pub fn meaning(&self) -> Option<i32> {
+        self.meta().meaning
+    }
+
+    pub fn exclude_from_indexes(&self) -> bool {
+        self.meta().exclude_from_indexes
+    }
+
+    pub fn with_meaning(mut self, meaning: i32) -> Value {
+        self.meta_mut().meaning = Some(meaning);
+        self
+    }
+
+    /// Marks this value as excluded from indexes. For arrays this must be set on the elements
+    /// instead of the array value itself.
+    pub fn unindexed(mut self) -> Value {
+        self.meta_mut().exclude_from_indexes = true;
+        self
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct Entity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<Key>,
+
+    #[serde(default)]
     pub properties: HashMap<String, Value>,
 }
 
@@ -163,43 +452,43 @@ pub struct Entity {
 
 impl<'a> From<&'a str> for Value {
     fn from(s: &'a str) -> Self {
-        Value::String { string_value: s.to_string() }
+        Value::String { string_value: s.to_string(), meta: ValueMeta::default() }
     }
 }
 
 impl From<String> for Value {
     fn from(string_value: String) -> Self {
-        Value::String { string_value }
+        Value::String { string_value, meta: ValueMeta::default() }
     }
 }
 
 impl From<bool> for Value {
     fn from(boolean_value: bool) -> Self {
-        Value::Boolean { boolean_value }
+        Value::Boolean { boolean_value, meta: ValueMeta::default() }
     }
 }
 
 impl From<Entity> for Value {
     fn from(entity_value: Entity) -> Self {
-        Value::EntityValue { entity_value }
+        Value::EntityValue { entity_value, meta: ValueMeta::default() }
     }
 }
 
 impl From<()> for Value {
     fn from(_: ()) -> Self {
-        Value::Null { null_value: () }
+        Value::Null { null_value: (), meta: ValueMeta::default() }
     }
 }
 
 impl From<f64> for Value {
     fn from(double_value: f64) -> Self {
-        Value::Double { double_value }
+        Value::Double { double_value, meta: ValueMeta::default() }
     }
 }
 
 impl<T> From<T> for Value where T: Into<Int> {
     fn from(i: T) -> Self {
-        Value::Integer { integer_value: i.into() }
+        Value::Integer { integer_value: i.into(), meta: ValueMeta::default() }
     }
 }
 
@@ -210,18 +499,33 @@ impl<T> From<Vec<T>> for Value where T: Into<Value> {
             out.push(value.into())
         }
 
-        return Value::Array { array_value: ArrayValue { values: out } }
+        Value::Array {
+            array_value: ArrayValue { values: out },
+            meta: ValueMeta::default(),
+        }
     }
 }
 
 impl From<Blob> for Value {
     fn from(blob_value: Blob) -> Self {
-        Value::Blob { blob_value }
+        Value::Blob { blob_value, meta: ValueMeta::default() }
     }
 }
 
 impl From<DateTime<Utc>> for Value {
     fn from(timestamp_value: DateTime<Utc>) -> Self {
-        Value::Timestamp { timestamp_value }
+        Value::Timestamp { timestamp_value, meta: ValueMeta::default() }
+    }
+}
+
+impl From<LatLng> for Value {
+    fn from(geo_point_value: LatLng) -> Self {
+        Value::GeoPoint { geo_point_value, meta: ValueMeta::default() }
+    }
+}
+
+impl From<Key> for Value {
+    fn from(key_value: Key) -> Self {
+        Value::KeyValue { key_value, meta: ValueMeta::default() }
     }
 }
@@ -0,0 +1,427 @@
+// Query types used by the `runQuery` method.
+//
+// See https://cloud.google.com/datastore/docs/reference/rest/v1/projects/runQuery#Query
+
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+
+use crate::datastore::{Blob, Entity, Int, Key, Value};
+
+/// The pseudo-property that refers to the key of an entity in filters, orders and projections.
+pub const KEY_PROPERTY: &str = "__key__";
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Query {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub projection: Vec<Projection>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kind: Vec<KindExpression>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<PropertyOrder>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub distinct_on: Vec<PropertyReference>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_cursor: Option<Blob>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_cursor: Option<Blob>,
+
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub offset: i32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i32>,
+}
+
+fn is_zero(n: &i32) -> bool {
+    *n == 0
+}
+
+impl Query {
+    /// Creates a query for all entities of the given kind.
+    pub fn new<K: Into<String>>(kind: K) -> Query {
+        Query {
+            kind: vec![KindExpression { name: kind.into() }],
+            ..Query::default()
+        }
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Query {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn with_order<P: Into<String>>(mut self, property: P, direction: Direction) -> Query {
+        self.order.push(PropertyOrder { property: PropertyReference::new(property), direction });
+        self
+    }
+
+    pub fn with_limit(mut self, limit: i32) -> Query {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: i32) -> Query {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_start_cursor(mut self, cursor: Blob) -> Query {
+        self.start_cursor = Some(cursor);
+        self
+    }
+
+    /// Returns only the given properties, read from an index instead of the entities. Each entity
+    /// yields one result per indexed value of an array property, and timestamps are returned as
+    /// integers (microseconds) with meaning `GD_WHEN`, see `entity::from_projection`.
+    pub fn project<P: AsRef<str>>(mut self, properties: &[P]) -> Query {
+        self.projection.extend(properties.iter().map(|p| Projection {
+            property: PropertyReference::new(p.as_ref()),
+        }));
+        self
+    }
+
+    /// Returns only the keys of the results, which is a projection on `__key__`. Any previous
+    /// projection is replaced.
+    pub fn keys_only(mut self) -> Query {
+        self.projection = vec![Projection { property: PropertyReference::new(KEY_PROPERTY) }];
+        self
+    }
+
+    /// Whether this query returns only keys.
+    pub fn is_keys_only(&self) -> bool {
+        self.projection.len() == 1 && self.projection[0].property.name == KEY_PROPERTY
+    }
+
+    /// Orders the results by key, e.g. to scan key ranges in order.
+    pub fn with_key_order(self, direction: Direction) -> Query {
+        self.with_order(KEY_PROPERTY, direction)
+    }
+
+    /// Returns only the first result of each combination of values of the given properties, which
+    /// must be projected.
+    pub fn distinct_on<P: AsRef<str>>(mut self, properties: &[P]) -> Query {
+        self.distinct_on.extend(properties.iter().map(|p| PropertyReference::new(p.as_ref())));
+        self
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct PropertyReference {
+    pub name: String,
+}
+
+impl PropertyReference {
+    pub fn new<N: Into<String>>(name: N) -> PropertyReference {
+        PropertyReference { name: name.into() }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct KindExpression {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Projection {
+    pub property: PropertyReference,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Direction {
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct PropertyOrder {
+    pub property: PropertyReference,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum Filter {
+    CompositeFilter(CompositeFilter),
+    PropertyFilter(PropertyFilter),
+}
+
+impl Filter {
+    pub fn property<P: Into<String>, V: Into<Value>>(property: P, op: PropertyOperator, value: V)
+                                                     -> Filter {
+        Filter::PropertyFilter(PropertyFilter {
+            property: PropertyReference::new(property),
+            op,
+            value: value.into(),
+        })
+    }
+
+    /// Compares the keys of entities with `key`, e.g. to scan a key range. Keys are ordered by
+    /// their path, with an entity's descendants following the entity itself.
+    pub fn key(op: PropertyOperator, key: Key) -> Filter {
+        Filter::property(KEY_PROPERTY, op, key)
+    }
+
+    /// Restricts a query to the descendants of `ancestor`, including the ancestor itself.
+    pub fn has_ancestor(ancestor: Key) -> Filter {
+        Filter::key(PropertyOperator::HasAncestor, ancestor)
+    }
+
+    pub fn and(filters: Vec<Filter>) -> Filter {
+        Filter::CompositeFilter(CompositeFilter { op: CompositeOperator::And, filters })
+    }
+
+    pub fn or(filters: Vec<Filter>) -> Filter {
+        Filter::CompositeFilter(CompositeFilter { op: CompositeOperator::Or, filters })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CompositeOperator {
+    And,
+    Or,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct CompositeFilter {
+    pub op: CompositeOperator,
+    pub filters: Vec<Filter>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PropertyOperator {
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    Equal,
+    In,
+    NotEqual,
+    HasAncestor,
+    NotIn,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct PropertyFilter {
+    pub property: PropertyReference,
+    pub op: PropertyOperator,
+    pub value: Value,
+}
+
+/// Aggregations over the results of a query, e.g. counting them. See
+/// https://cloud.google.com/datastore/docs/reference/rest/v1/AggregationQuery
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregationQuery {
+    pub nested_query: Query,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aggregations: Vec<Aggregation>,
+}
+
+impl AggregationQuery {
+    /// Creates an aggregation over the results of `query`, which respects its filters, offset
+    /// and limit.
+    pub fn new(query: Query) -> AggregationQuery {
+        AggregationQuery { nested_query: query, aggregations: vec![] }
+    }
+
+    /// Counts the results.
+    pub fn count<A: Into<String>>(self, alias: A) -> AggregationQuery {
+        self.with(alias, AggregationOperator::Count(Count::default()))
+    }
+
+    /// Counts the results, stopping at `up_to`.
+    pub fn count_up_to<A: Into<String>>(self, alias: A, up_to: i64) -> AggregationQuery {
+        self.with(alias, AggregationOperator::Count(Count { up_to: Some(up_to.into()) }))
+    }
+
+    /// Sums up the numeric values of a property. The sum is an integer if all values are integers
+    /// and it does not overflow, and a double otherwise.
+    pub fn sum<A: Into<String>, P: Into<String>>(self, alias: A, property: P) -> AggregationQuery {
+        self.with(alias, AggregationOperator::Sum { property: PropertyReference::new(property) })
+    }
+
+    /// Averages the numeric values of a property as a double, null if there are none.
+    pub fn avg<A: Into<String>, P: Into<String>>(self, alias: A, property: P) -> AggregationQuery {
+        self.with(alias, AggregationOperator::Avg { property: PropertyReference::new(property) })
+    }
+
+    fn with<A: Into<String>>(mut self, alias: A, operator: AggregationOperator) -> AggregationQuery {
+        self.aggregations.push(Aggregation { operator, alias: alias.into() });
+        self
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Aggregation {
+    #[serde(flatten)]
+    pub operator: AggregationOperator,
+
+    /// Name of the result, must be unique within the query. Generated by the API if empty.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub alias: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum AggregationOperator {
+    Count(Count),
+    Sum { property: PropertyReference },
+    Avg { property: PropertyReference },
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Count {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up_to: Option<Int>,
+}
+
+/// The results of the aggregations of a query, by alias.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregationResult {
+    #[serde(default)]
+    pub aggregate_properties: HashMap<String, Value>,
+}
+
+impl AggregationResult {
+    pub fn get(&self, alias: &str) -> Option<&Value> {
+        self.aggregate_properties.get(alias)
+    }
+
+    /// The result of a count, or of a sum over integers.
+    pub fn integer(&self, alias: &str) -> Option<i64> {
+        match self.get(alias) {
+            Some(Value::Integer { integer_value, .. }) => integer_value.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The result of an average or of a sum, converting integer sums. `None` for the average
+    /// of no values.
+    pub fn double(&self, alias: &str) -> Option<f64> {
+        match self.get(alias) {
+            Some(&Value::Double { double_value, .. }) => Some(double_value),
+            Some(Value::Integer { integer_value, .. }) => integer_value.parse::<i64>().ok().map(|i| i as f64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregationResultBatch {
+    #[serde(default)]
+    pub aggregation_results: Vec<AggregationResult>,
+
+    #[serde(default)]
+    pub more_results: MoreResultsType,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_time: Option<DateTime<Utc>>,
+}
+
+/// A query in the Google Query Language, see
+/// https://cloud.google.com/datastore/docs/reference/gql_reference
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GqlQuery {
+    pub query_string: String,
+
+    #[serde(default)]
+    pub allow_literals: bool,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub named_bindings: HashMap<String, GqlQueryParameter>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub positional_bindings: Vec<GqlQueryParameter>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum GqlQueryParameter {
+    Value(Value),
+    Cursor(Blob),
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityResult {
+    pub entity: Entity,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Int>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<Blob>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ResultType {
+    #[default]
+    ResultTypeUnspecified,
+    Full,
+    Projection,
+    KeyOnly,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MoreResultsType {
+    #[default]
+    MoreResultsTypeUnspecified,
+    NotFinished,
+    MoreResultsAfterLimit,
+    MoreResultsAfterCursor,
+    NoMoreResults,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryResultBatch {
+    #[serde(default)]
+    pub skipped_results: i32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped_cursor: Option<Blob>,
+
+    #[serde(default)]
+    pub entity_result_type: ResultType,
+
+    #[serde(default)]
+    pub entity_results: Vec<EntityResult>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_cursor: Option<Blob>,
+
+    #[serde(default)]
+    pub more_results: MoreResultsType,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_version: Option<Int>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_time: Option<DateTime<Utc>>,
+}
+
+impl QueryResultBatch {
+    /// The keys of the results, e.g. of a keys-only query.
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        self.entity_results.iter().filter_map(|result| result.entity.key.as_ref())
+    }
+}
@@ -0,0 +1,245 @@
+// Request and response messages of the Datastore API methods.
+//
+// The project ID is part of the URL in the REST API and part of the message in the gRPC API, it is
+// included in the messages here so that both transports can use them.
+//
+// See https://cloud.google.com/datastore/docs/reference/rest/v1/projects
+
+use chrono::{DateTime, Utc};
+
+use crate::datastore::{Blob, Entity, Int, Key, PartitionId};
+use crate::datastore::query::{AggregationQuery, AggregationResultBatch, EntityResult, GqlQuery, Query,
+                              QueryResultBatch};
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReadConsistency {
+    Strong,
+    Eventual,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadOptions {
+    ReadConsistency(ReadConsistency),
+    Transaction(Blob),
+    NewTransaction(TransactionOptions),
+    ReadTime(DateTime<Utc>),
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionOptions {
+    ReadWrite(ReadWrite),
+    ReadOnly(ReadOnly),
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadWrite {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_transaction: Option<Blob>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadOnly {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupRequest {
+    pub project_id: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_options: Option<ReadOptions>,
+
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupResponse {
+    #[serde(default)]
+    pub found: Vec<EntityResult>,
+
+    #[serde(default)]
+    pub missing: Vec<EntityResult>,
+
+    #[serde(default)]
+    pub deferred: Vec<Key>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<Blob>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_time: Option<DateTime<Utc>>,
+}
+
+/// Exactly one of `query` and `gql_query` must be set.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RunQueryRequest {
+    pub project_id: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_id: Option<PartitionId>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_options: Option<ReadOptions>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<Query>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gql_query: Option<GqlQuery>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RunQueryResponse {
+    pub batch: QueryResultBatch,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<Query>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<Blob>,
+}
+
+/// Exactly one of `aggregation_query` and `gql_query` must be set.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RunAggregationQueryRequest {
+    pub project_id: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_id: Option<PartitionId>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_options: Option<ReadOptions>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregation_query: Option<AggregationQuery>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gql_query: Option<GqlQuery>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RunAggregationQueryResponse {
+    pub batch: AggregationResultBatch,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<AggregationQuery>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<Blob>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginTransactionRequest {
+    pub project_id: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_options: Option<TransactionOptions>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginTransactionResponse {
+    pub transaction: Blob,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CommitMode {
+    Transactional,
+    NonTransactional,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum Mutation {
+    Insert(Entity),
+    Update(Entity),
+    Upsert(Entity),
+    Delete(Key),
+}
+
+/// `transaction` must be set if and only if the mode is `Transactional`.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitRequest {
+    pub project_id: String,
+
+    pub mode: CommitMode,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<Blob>,
+
+    pub mutations: Vec<Mutation>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MutationResult {
+    /// Only set for insertions of entities with incomplete keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<Key>,
+
+    pub version: Int,
+
+    #[serde(default)]
+    pub conflict_detected: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitResponse {
+    #[serde(default)]
+    pub mutation_results: Vec<MutationResult>,
+
+    #[serde(default)]
+    pub index_updates: i32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RollbackRequest {
+    pub project_id: String,
+    pub transaction: Blob,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct RollbackResponse {}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AllocateIdsRequest {
+    pub project_id: String,
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct AllocateIdsResponse {
+    #[serde(default)]
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReserveIdsRequest {
+    pub project_id: String,
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct ReserveIdsResponse {}
@@ -0,0 +1,195 @@
+// Statistics entities.
+//
+// Datastore periodically writes statistics about the stored entities as entities of the built-in
+// `__Stat_*__` kinds, one snapshot per run with all entities of a snapshot sharing the same
+// `timestamp`. Each kind has a struct here that is read via `serde_ds`. Statistics of the default
+// namespace cover the whole project, statistics within other namespaces use the `__Stat_Ns_*__`
+// kinds, see `stat_kind`.
+//
+// Sizes are in bytes. Index sizes and counts are missing from older snapshots and read as 0.
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+
+/// A struct for the entities of one statistics kind.
+pub trait Stat: DeserializeOwned {
+    /// The kind in the default namespace.
+    const KIND: &'static str;
+
+    /// When the snapshot was taken.
+    fn timestamp(&self) -> DateTime<Utc>;
+}
+
+/// The kind of the statistics `kind` within a namespace, e.g. `__Stat_Ns_Kind__` for
+/// `__Stat_Kind__` in any namespace other than the default one.
+pub fn stat_kind(kind: &str, namespace: &str) -> String {
+    if namespace.is_empty() {
+        kind.to_string()
+    } else {
+        kind.replacen("__Stat_", "__Stat_Ns_", 1)
+    }
+}
+
+macro_rules! stat {
+    ($name:ident, $kind:expr) => {
+        impl Stat for $name {
+            const KIND: &'static str = $kind;
+
+            fn timestamp(&self) -> DateTime<Utc> {
+                self.timestamp
+            }
+        }
+    };
+}
+
+/// Totals of all entities, `__Stat_Total__`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatTotal {
+    pub count: i64,
+    pub bytes: i64,
+    pub entity_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_count: i64,
+    #[serde(default)]
+    pub composite_index_bytes: i64,
+    #[serde(default)]
+    pub composite_index_count: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatTotal, "__Stat_Total__");
+
+/// Totals per namespace, `__Stat_Namespace__`. Only exists in the default namespace.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatNamespace {
+    /// The namespace, empty for the default namespace.
+    #[serde(default)]
+    pub subject_namespace: String,
+    pub count: i64,
+    pub bytes: i64,
+    pub entity_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_count: i64,
+    #[serde(default)]
+    pub composite_index_bytes: i64,
+    #[serde(default)]
+    pub composite_index_count: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatNamespace, "__Stat_Namespace__");
+
+/// Totals per kind, `__Stat_Kind__`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatKind {
+    pub kind_name: String,
+    pub count: i64,
+    pub bytes: i64,
+    pub entity_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_count: i64,
+    #[serde(default)]
+    pub composite_index_bytes: i64,
+    #[serde(default)]
+    pub composite_index_count: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatKind, "__Stat_Kind__");
+
+/// Totals of root entities per kind, `__Stat_Kind_IsRootEntity__`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatKindIsRootEntity {
+    pub kind_name: String,
+    pub count: i64,
+    pub bytes: i64,
+    pub entity_bytes: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatKindIsRootEntity, "__Stat_Kind_IsRootEntity__");
+
+/// Totals of entities with a parent per kind, `__Stat_Kind_NotRootEntity__`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatKindNotRootEntity {
+    pub kind_name: String,
+    pub count: i64,
+    pub bytes: i64,
+    pub entity_bytes: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatKindNotRootEntity, "__Stat_Kind_NotRootEntity__");
+
+/// Totals per property type, e.g. `String` or `Date/Time`, `__Stat_PropertyType__`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatPropertyType {
+    pub property_type: String,
+    pub count: i64,
+    pub bytes: i64,
+    pub entity_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_count: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatPropertyType, "__Stat_PropertyType__");
+
+/// Totals per property type and kind, `__Stat_PropertyType_Kind__`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatPropertyTypeKind {
+    pub property_type: String,
+    pub kind_name: String,
+    pub count: i64,
+    pub bytes: i64,
+    pub entity_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_count: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatPropertyTypeKind, "__Stat_PropertyType_Kind__");
+
+/// Totals per property and kind, `__Stat_PropertyName_Kind__`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatPropertyNameKind {
+    pub property_name: String,
+    pub kind_name: String,
+    pub count: i64,
+    pub entity_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_count: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatPropertyNameKind, "__Stat_PropertyName_Kind__");
+
+/// Totals per property type, property and kind, `__Stat_PropertyType_PropertyName_Kind__`.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct StatPropertyTypePropertyNameKind {
+    pub property_type: String,
+    pub property_name: String,
+    pub kind_name: String,
+    pub count: i64,
+    pub bytes: i64,
+    pub entity_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_bytes: i64,
+    #[serde(default)]
+    pub builtin_index_count: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+stat!(StatPropertyTypePropertyNameKind, "__Stat_PropertyType_PropertyName_Kind__");
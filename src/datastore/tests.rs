@@ -1,5 +1,4 @@
-use datastore::*;
-use serde_json;
+use crate::datastore::*;
 use chrono::{TimeZone, Utc};
 use std::fs::File;
 use std::path::{Path, PathBuf};
@@ -27,6 +26,20 @@ fn test_path_element_serialisation() {
     );
 }
 
+#[test]
+fn test_path_element_deserialisation() {
+    let parse = |json: &str| serde_json::from_str::<PathElement>(json);
+
+    assert_eq!(PathElement::from_id("User", 42), parse(r#"{"kind":"User","id":42}"#).unwrap());
+    assert_eq!(PathElement::from_id("User", 42), parse(r#"{"kind":"User","id":"42"}"#).unwrap());
+    assert_eq!(PathElement::from_name("User", "ann"), parse(r#"{"kind":"User","name":"ann"}"#).unwrap());
+    assert_eq!(PathElement::incomplete("User"), parse(r#"{"kind":"User"}"#).unwrap());
+
+    assert!(parse(r#"{"kind":"User","id":"42","name":"ann"}"#).is_err());
+    assert!(parse(r#"{"kind":"User","nmae":"ann"}"#).is_err());
+    assert!(parse(r#"{"id":"42"}"#).is_err());
+}
+
 #[test]
 fn test_blob_serialisation() {
     let blob = Blob(vec![82, 117, 115, 116, 33]);
@@ -88,16 +101,274 @@ fn test_entity_deserialisation() {
     let properties = hashmap!(
         "email".to_string() => Value::from("mags@mag"),
         "companyCountry".to_string() => Value::from("NO"),
-        "status".to_string() => Value::from( Entity { properties: expected_cl_options } ),
+        "status".to_string() => Value::from( Entity { key: None, properties: expected_cl_options } ),
         "signingId".to_string() => Value::from(()),
         "created".to_string() => Value::from(expected_time),
         "availableProducts".to_string() => expected_products,
     );
 
-    let expected = Entity { properties };
+    let expected = Entity { key: None, properties };
     assert_eq!(
         expected,
         deserialised,
         "Deserialised value should match expectations"
     );
 }
+
+#[test]
+fn test_key_wire_format() {
+    use crate::datastore::rpc::LookupResponse;
+
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("resources/lookup-response.json");
+    let file = File::open(d.as_ref() as &Path).expect("Could not open test file");
+    let payload: serde_json::Value = serde_json::from_reader(file).expect("Could not parse test file");
+    let response: LookupResponse = serde_json::from_value(payload.clone()).expect("Deserialisation failed");
+
+    // The default namespace is omitted from the partition, others are kept.
+    let user = response.found[0].entity.key.as_ref().expect("Entity without key");
+    assert_eq!(&PartitionId::new("sample-project", ""), user.partition_id());
+    assert_eq!(Some(5629499534213120), user.path()[1].id());
+    let manager = Key::new(
+        PartitionId::new("sample-project", "tenant-a"),
+        vec![PathElement::from_id("User", 5066549580791808)],
+    );
+    assert_eq!(Some(&Value::from(manager)), response.found[0].entity.properties.get("manager"));
+    let ann = response.found[1].entity.key.as_ref().expect("Entity without key");
+    assert_eq!("tenant-a", ann.partition_id().namespace_id());
+
+    let serialised = serde_json::to_value(&response.found).expect("Serialisation failed");
+    assert_eq!(payload["found"], serialised, "Serialised entities should match the API payload");
+}
+
+#[test]
+fn test_value_meta_serialisation() {
+    let json = r#"{"stringValue":"long text","meaning":15,"excludeFromIndexes":true}"#;
+    let expected = Value::from("long text").with_meaning(15).unindexed();
+
+    let deserialised: Value = serde_json::from_str(json).expect("Deserialisation failed");
+    assert_eq!(expected, deserialised, "Meaning and index exclusion should be kept");
+
+    let serialised = serde_json::to_string(&expected).expect("Serialisation failed");
+    assert_eq!(json, serialised, "Serialised value should match input");
+
+    let plain = serde_json::to_string(&Value::from(true)).expect("Serialisation failed");
+    assert_eq!(r#"{"booleanValue":true}"#, plain, "Default metadata should be omitted");
+}
+
+#[test]
+fn test_rpc_serialisation() {
+    use crate::datastore::query::*;
+    use crate::datastore::rpc::*;
+
+    let request = RunQueryRequest {
+        project_id: "test-project".to_string(),
+        query: Some(Query::new("User")
+            .with_filter(Filter::property("age", PropertyOperator::GreaterThan, 18))
+            .with_order("age", Direction::Descending)
+            .with_limit(10)),
+        ..RunQueryRequest::default()
+    };
+
+    let expected = json!({
+        "projectId": "test-project",
+        "query": {
+            "kind": [{"name": "User"}],
+            "filter": {
+                "propertyFilter": {
+                    "property": {"name": "age"},
+                    "op": "GREATER_THAN",
+                    "value": {"integerValue": "18"}
+                }
+            },
+            "order": [{"property": {"name": "age"}, "direction": "DESCENDING"}],
+            "limit": 10
+        }
+    });
+    assert_eq!(expected, serde_json::to_value(&request).expect("Serialisation failed"));
+
+    let commit = CommitRequest {
+        project_id: "test-project".to_string(),
+        mode: CommitMode::NonTransactional,
+        transaction: None,
+        mutations: vec![Mutation::Delete(Key::new(
+            PartitionId::new("test-project", ""),
+            vec![PathElement::from_id("User", 1)],
+        ))],
+    };
+    let expected = json!({
+        "projectId": "test-project",
+        "mode": "NON_TRANSACTIONAL",
+        "mutations": [{
+            "delete": {
+                "partitionId": {"projectId": "test-project"},
+                "path": [{"kind": "User", "id": "1"}]
+            }
+        }]
+    });
+    assert_eq!(expected, serde_json::to_value(&commit).expect("Serialisation failed"));
+
+    let response = r#"{
+        "batch": {
+            "entityResultType": "FULL",
+            "entityResults": [{"entity": {"properties": {}}, "version": "3", "cursor": "AQI="}],
+            "endCursor": "AQI=",
+            "moreResults": "NO_MORE_RESULTS"
+        }
+    }"#;
+    let response: RunQueryResponse = serde_json::from_str(response).expect("Deserialisation failed");
+    assert_eq!(MoreResultsType::NoMoreResults, response.batch.more_results);
+    assert_eq!(Some(Blob(vec![1, 2])), response.batch.end_cursor);
+    assert_eq!(Some(3), response.batch.entity_results[0].version.as_ref().map(|v| v.parse().unwrap()));
+}
+
+#[test]
+fn test_aggregation_json() {
+    use crate::datastore::query::*;
+    use crate::datastore::rpc::*;
+
+    let query = AggregationQuery::new(Query::new("Sale"))
+        .count_up_to("n", 10)
+        .avg("a", "units");
+    let expected = json!({
+        "nestedQuery": {"kind": [{"name": "Sale"}]},
+        "aggregations": [
+            {"count": {"upTo": "10"}, "alias": "n"},
+            {"avg": {"property": {"name": "units"}}, "alias": "a"}
+        ]
+    });
+    assert_eq!(expected, serde_json::to_value(&query).expect("Serialisation failed"));
+
+    let response = r#"{
+        "batch": {
+            "aggregationResults": [{"aggregateProperties": {"n": {"integerValue": "3"}}}],
+            "moreResults": "NO_MORE_RESULTS"
+        }
+    }"#;
+    let response: RunAggregationQueryResponse = serde_json::from_str(response)
+        .expect("Deserialisation failed");
+    assert_eq!(Some(3), response.batch.aggregation_results[0].integer("n"));
+}
+
+#[test]
+fn test_operation_json() {
+    use crate::datastore::admin::*;
+
+    let filter = EntityFilter::all().with_kinds(vec!["User"]);
+    assert_eq!(json!({"kinds": ["User"]}), serde_json::to_value(&filter).expect("Serialisation failed"));
+
+    let operation = r#"{
+        "name": "projects/p/operations/op1",
+        "metadata": {
+            "@type": "type.googleapis.com/google.datastore.admin.v1.ExportEntitiesMetadata",
+            "common": {
+                "startTime": "2024-01-02T03:04:05Z",
+                "operationType": "EXPORT_ENTITIES",
+                "state": "PROCESSING"
+            },
+            "progressEntities": {"workCompleted": "25", "workEstimated": "100"},
+            "entityFilter": {"namespaceIds": [""]},
+            "outputUrlPrefix": "gs://bucket"
+        }
+    }"#;
+    let operation: Operation = serde_json::from_str(operation).expect("Deserialisation failed");
+    assert!(!operation.done);
+    assert_eq!(State::Processing, operation.state());
+    assert!(!operation.state().is_final());
+    assert_eq!(Some(0.25), operation.progress().unwrap().fraction());
+    let metadata = operation.metadata.as_ref().unwrap();
+    assert_eq!(OperationType::ExportEntities, metadata.common.operation_type);
+    assert_eq!(vec![String::new()], metadata.entity_filter.as_ref().unwrap().namespace_ids);
+    assert_eq!(None, operation.output_url());
+}
+
+#[test]
+fn test_index_json() {
+    use crate::datastore::admin::*;
+    use crate::datastore::query::Direction;
+    use crate::index;
+
+    let definition = index::Index::new("Order").with_ancestor().with_property("created", Direction::Descending);
+    let expected = json!({
+        "kind": "Order",
+        "ancestor": "ALL_ANCESTORS",
+        "properties": [{"name": "created", "direction": "DESCENDING"}]
+    });
+    assert_eq!(expected, serde_json::to_value(Index::new(&definition)).expect("Serialisation failed"));
+
+    let response = r#"{
+        "indexes": [{
+            "projectId": "p",
+            "indexId": "CICAgJiUpoMK",
+            "kind": "Order",
+            "ancestor": "NONE",
+            "properties": [{"name": "status", "direction": "ASCENDING"}],
+            "state": "READY"
+        }],
+        "nextPageToken": "next"
+    }"#;
+    let response: ListIndexesResponse = serde_json::from_str(response).expect("Deserialisation failed");
+    assert_eq!("next", response.next_page_token);
+    let index = &response.indexes[0];
+    assert_eq!(IndexState::Ready, index.state);
+    assert_eq!(index::Index::new("Order").with_property("status", Direction::Ascending), index.definition());
+}
+
+#[test]
+fn test_key_text() {
+    let key = Key::new(PartitionId::new("", "tenant"), vec![
+        PathElement::from_id("Kind", 123),
+        PathElement::from_name("Child", "name"),
+    ]);
+    assert_eq!(r#"[ns:tenant] Kind:123 / Child:"name""#, key.to_string());
+    assert_eq!(key, r#"[ns:tenant] Kind:123 / Child:"name""#.parse().unwrap());
+    assert_eq!(key, r#"  [ ns:"tenant" ]Kind:123/Child:"name"  "#.parse().unwrap());
+
+    // Numeric names stay distinct from IDs.
+    let name: Key = r#"Kind:"123""#.parse().unwrap();
+    let id: Key = "Kind:123".parse().unwrap();
+    assert_eq!(Some("123"), name.path()[0].name());
+    assert_eq!(Some(123), id.path()[0].id());
+    assert_eq!("Kind:123", id.to_string());
+
+    // Everything round-trips, including separators and escapes in names, kinds and partitions.
+    let keys = [
+        Key::new(PartitionId::new("google.com:project", "a b]"), vec![
+            PathElement::from_name("Kind / with: separators", "a/b:\"c\" \\ [d]\n\t\u{1}"),
+            PathElement::from_id("__Stat_Kind__", -5),
+            PathElement::from_name("Empty", ""),
+            PathElement::incomplete("Child"),
+        ]),
+        Key::new(PartitionId::new("p", ""), vec![PathElement::from_name("ünïcødé", "名前")]),
+        Key::new(PartitionId::new("", ""), vec![PathElement::incomplete("Kind")]),
+        Key::new(PartitionId::new("p", "tenant"), vec![]),
+        Key::new(PartitionId::new("", ""), vec![]),
+    ];
+    for key in &keys {
+        let text = key.to_string();
+        assert_eq!(*key, text.parse::<Key>().unwrap(), "{}", text);
+    }
+    assert_eq!(r#"[project:"google.com:project" ns:"a b]"] "Kind / with: separators":"a/b:\"c\" \\ [d]\n\t\u{1}" / __Stat_Kind__:-5 / Empty:"" / Child"#,
+               keys[0].to_string());
+    assert_eq!("[project:p ns:tenant]", keys[3].to_string());
+    assert_eq!("[]", keys[4].to_string());
+
+    let invalid = [
+        ("", 0),
+        ("Kind:abc", 5),
+        ("Kind:1 Child:2", 7),
+        ("Kind:1 /", 8),
+        (r#"Kind:"abc"#, 5),
+        (r#"Kind:"\q""#, 7),
+        ("[ns:a ns:b] Kind:1", 6),
+        ("[db:a] Kind:1", 1),
+        ("[ns:a Kind:1", 6),
+        ("[ns:a] /", 7),
+    ];
+    for &(text, position) in &invalid {
+        match text.parse::<Key>() {
+            Err(err) => assert_eq!(position, err.position(), "{}: {}", text, err),
+            Ok(key) => panic!("Parsed {:?} as {:?}", text, key),
+        }
+    }
+}
@@ -0,0 +1,368 @@
+// Structural diffs of entities.
+//
+// `diff` compares the properties of two entities and lists what was added, removed and replaced,
+// descending into embedded entities and arrays. Values of different types are never equal, so an
+// integer that became a string is a replacement even if both read `42`. Arrays are compared
+// element by element, elements past the end of the shorter array are added or removed.
+//
+// A `Diff` prints as text for review, one change per line:
+//
+//   + address.zip: "10115"
+//   - nickname: "bo"
+//   ~ age: 30 -> "30" (integer -> string)
+//
+// and serialises to a patch in the style of JSON Patch (RFC 6902), whose paths are JSON pointers
+// and whose values are in the JSON of the API. `Diff::apply` applies a patch to an entity after
+// checking that the entity still has the values the patch replaces or removes. Keys are not
+// compared.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display, Write};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::datastore::{Entity, Value};
+use crate::schema::ValueType;
+
+#[cfg(test)]
+mod tests;
+
+/// A patch could not be applied, because the entity differs from the one it was made for.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Error {
+    pub path: PropertyPath,
+    pub message: String,
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "can not apply change to {}: {}", self.path, self.message)
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PathSegment {
+    Property(String),
+    Index(usize),
+}
+
+/// The path of a value within an entity, e.g. `lines[0].qty`.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct PropertyPath(pub Vec<PathSegment>);
+
+impl PropertyPath {
+    fn child(&self, segment: PathSegment) -> PropertyPath {
+        let mut path = self.clone();
+        path.0.push(segment);
+        path
+    }
+
+    /// The path as a JSON pointer, e.g. `/lines/0/qty`.
+    pub fn to_pointer(&self) -> String {
+        let mut pointer = String::new();
+        for segment in &self.0 {
+            match *segment {
+                PathSegment::Property(ref name) => {
+                    let _ = write!(pointer, "/{}", name.replace('~', "~0").replace('/', "~1"));
+                }
+                PathSegment::Index(index) => {
+                    let _ = write!(pointer, "/{}", index);
+                }
+            }
+        }
+        pointer
+    }
+
+    /// Reads a JSON pointer. Tokens that are numbers without leading zeros are read as array
+    /// indexes, `apply` treats them as property names where they refer to a property.
+    pub fn from_pointer(pointer: &str) -> Option<PropertyPath> {
+        if pointer.is_empty() {
+            return Some(PropertyPath::default());
+        }
+        let tokens = pointer.strip_prefix('/')?.split('/');
+        let segments = tokens.map(|token| {
+            let is_index = token == "0" || (!token.starts_with('0') && !token.is_empty()
+                && token.chars().all(|c| c.is_ascii_digit()));
+            match token.parse() {
+                Ok(index) if is_index => PathSegment::Index(index),
+                _ => PathSegment::Property(token.replace("~1", "/").replace("~0", "~")),
+            }
+        });
+        Some(PropertyPath(segments.collect()))
+    }
+}
+
+impl Display for PropertyPath {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match *segment {
+                PathSegment::Property(ref name) if i == 0 => fmt.write_str(name)?,
+                PathSegment::Property(ref name) => write!(fmt, ".{}", name)?,
+                PathSegment::Index(index) => write!(fmt, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for PropertyPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_pointer())
+    }
+}
+
+impl<'de> Deserialize<'de> for PropertyPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Self, D::Error> {
+        let pointer = String::deserialize(deserializer)?;
+        PropertyPath::from_pointer(&pointer)
+            .ok_or_else(|| de::Error::custom(format!("invalid JSON pointer `{}`", pointer)))
+    }
+}
+
+/// A change of a single value. `old` holds the value that a removal or replacement expects.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Change {
+    Add { path: PropertyPath, value: Value },
+    Remove { path: PropertyPath, old: Value },
+    Replace { path: PropertyPath, old: Value, value: Value },
+}
+
+impl Change {
+    pub fn path(&self) -> &PropertyPath {
+        match *self {
+            Change::Add { ref path, .. } | Change::Remove { ref path, .. } | Change::Replace { ref path, .. } => path,
+        }
+    }
+
+    /// Whether a replacement changes the type of the value.
+    pub fn is_type_change(&self) -> bool {
+        match *self {
+            Change::Replace { ref old, ref value, .. } => ValueType::of(old) != ValueType::of(value),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Add { ref path, ref value } => write!(fmt, "+ {}: {}", path, ValueText(value)),
+            Change::Remove { ref path, ref old } => write!(fmt, "- {}: {}", path, ValueText(old)),
+            Change::Replace { ref path, ref old, ref value } => {
+                write!(fmt, "~ {}: {} -> {}", path, ValueText(old), ValueText(value))?;
+                if self.is_type_change() {
+                    write!(fmt, " ({} -> {})", ValueType::of(old), ValueType::of(value))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The changes that turn one entity into another, in the order in which they apply.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(transparent)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Applies the changes to `entity`. Fails without changing the entity if a value to remove or
+    /// replace is not the expected one, or if a value to add already exists.
+    pub fn apply(&self, entity: &mut Entity) -> Result<()> {
+        let mut patched = entity.properties.clone();
+        for change in &self.changes {
+            apply(&mut patched, change)?;
+        }
+        entity.properties = patched;
+        Ok(())
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(fmt, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the properties of two entities.
+pub fn diff(old: &Entity, new: &Entity) -> Diff {
+    let mut changes = vec![];
+    diff_properties(&PropertyPath::default(), &old.properties, &new.properties, &mut changes);
+    Diff { changes }
+}
+
+fn diff_properties(path: &PropertyPath, old: &HashMap<String, Value>, new: &HashMap<String, Value>,
+                   changes: &mut Vec<Change>) {
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    for name in names {
+        let path = path.child(PathSegment::Property(name.clone()));
+        match (old.get(name), new.get(name)) {
+            (Some(old), Some(new)) => diff_values(path, old, new, changes),
+            (Some(old), None) => changes.push(Change::Remove { path, old: old.clone() }),
+            (None, Some(new)) => changes.push(Change::Add { path, value: new.clone() }),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+fn diff_values(path: PropertyPath, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    if old == new {
+        return;
+    }
+    // Values are only descended into if they differ in their contents alone.
+    if old.meta() == new.meta() {
+        match (old, new) {
+            (Value::EntityValue { entity_value: old, .. }, Value::EntityValue { entity_value: new, .. })
+                if old.key == new.key => {
+                return diff_properties(&path, &old.properties, &new.properties, changes);
+            }
+            (Value::Array { array_value: old, .. }, Value::Array { array_value: new, .. }) => {
+                let (old, new) = (&old.values, &new.values);
+                for (i, (old, new)) in old.iter().zip(new).enumerate() {
+                    diff_values(path.child(PathSegment::Index(i)), old, new, changes);
+                }
+                for (i, value) in new.iter().enumerate().skip(old.len()) {
+                    changes.push(Change::Add { path: path.child(PathSegment::Index(i)), value: value.clone() });
+                }
+                // Removed from the end, so that the indexes of the remaining elements stay valid.
+                for (i, old) in old.iter().enumerate().skip(new.len()).rev() {
+                    changes.push(Change::Remove { path: path.child(PathSegment::Index(i)), old: old.clone() });
+                }
+                return;
+            }
+            _ => (),
+        }
+    }
+    changes.push(Change::Replace { path, old: old.clone(), value: new.clone() });
+}
+
+// Where a change applies: the properties of an entity or the elements of an array.
+enum Container<'a> {
+    Properties(&'a mut HashMap<String, Value>),
+    Elements(&'a mut Vec<Value>),
+}
+
+fn apply(properties: &mut HashMap<String, Value>, change: &Change) -> Result<()> {
+    let path = change.path();
+    let conflict = |message: &str| Error { path: path.clone(), message: message.to_string() };
+    let (last, parent) = path.0.split_last().ok_or_else(|| conflict("empty path"))?;
+    let container = resolve(Container::Properties(properties), parent)
+        .ok_or_else(|| conflict("no such entity or array"))?;
+
+    match container {
+        Container::Properties(properties) => {
+            let name = match *last {
+                PathSegment::Property(ref name) => name.clone(),
+                PathSegment::Index(index) => index.to_string(),
+            };
+            match *change {
+                Change::Add { ref value, .. } if !properties.contains_key(&name) => {
+                    properties.insert(name, value.clone());
+                }
+                Change::Add { .. } => return Err(conflict("property exists already")),
+                Change::Remove { ref old, .. } if properties.get(&name) == Some(old) => {
+                    properties.remove(&name);
+                }
+                Change::Replace { ref old, ref value, .. } if properties.get(&name) == Some(old) => {
+                    properties.insert(name, value.clone());
+                }
+                _ => return Err(conflict("value differs from the expected one")),
+            }
+        }
+        Container::Elements(elements) => {
+            let index = match *last {
+                PathSegment::Index(index) => index,
+                PathSegment::Property(_) => return Err(conflict("expected an array index")),
+            };
+            match *change {
+                Change::Add { ref value, .. } if index <= elements.len() => elements.insert(index, value.clone()),
+                Change::Add { .. } => return Err(conflict("index out of bounds")),
+                Change::Remove { ref old, .. } if elements.get(index) == Some(old) => {
+                    elements.remove(index);
+                }
+                Change::Replace { ref old, ref value, .. } if elements.get(index) == Some(old) => {
+                    elements[index] = value.clone();
+                }
+                _ => return Err(conflict("value differs from the expected one")),
+            }
+        }
+    }
+    Ok(())
+}
+
+// The container that `path` leads to, starting at `container`.
+fn resolve<'a>(container: Container<'a>, path: &[PathSegment]) -> Option<Container<'a>> {
+    let (first, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Some(container),
+    };
+    let value = match (container, first) {
+        (Container::Properties(properties), PathSegment::Property(name)) => properties.get_mut(name)?,
+        (Container::Properties(properties), PathSegment::Index(index)) => properties.get_mut(&index.to_string())?,
+        (Container::Elements(elements), PathSegment::Index(index)) => elements.get_mut(*index)?,
+        (Container::Elements(_), PathSegment::Property(_)) => return None,
+    };
+    let inner = match *value {
+        Value::EntityValue { ref mut entity_value, .. } => Container::Properties(&mut entity_value.properties),
+        Value::Array { ref mut array_value, .. } => Container::Elements(&mut array_value.values),
+        _ => return None,
+    };
+    resolve(inner, rest)
+}
+
+// A short text form of a value for the text of a diff.
+struct ValueText<'a>(&'a Value);
+
+impl<'a> Display for ValueText<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            Value::Null { .. } => fmt.write_str("null"),
+            Value::Boolean { boolean_value, .. } => write!(fmt, "{}", boolean_value),
+            Value::Integer { ref integer_value, .. } => match integer_value.parse::<i64>() {
+                Ok(integer) => write!(fmt, "{}", integer),
+                Err(_) => fmt.write_str("<invalid integer>"),
+            },
+            Value::Double { double_value, .. } => write!(fmt, "{:?}", double_value),
+            Value::Timestamp { ref timestamp_value, .. } => write!(fmt, "{}", timestamp_value.to_rfc3339()),
+            Value::KeyValue { ref key_value, .. } => write!(fmt, "key({})", key_value),
+            Value::String { ref string_value, .. } => write!(fmt, "{:?}", string_value),
+            Value::Blob { ref blob_value, .. } => write!(fmt, "blob({} bytes)", blob_value.0.len()),
+            Value::GeoPoint { ref geo_point_value, .. } =>
+                write!(fmt, "({}, {})", geo_point_value.latitude(), geo_point_value.longitude()),
+            Value::Array { ref array_value, .. } => {
+                fmt.write_str("[")?;
+                for (i, value) in array_value.values.iter().enumerate() {
+                    if i > 0 {
+                        fmt.write_str(", ")?;
+                    }
+                    write!(fmt, "{}", ValueText(value))?;
+                }
+                fmt.write_str("]")
+            }
+            Value::EntityValue { ref entity_value, .. } => {
+                let names: BTreeSet<&String> = entity_value.properties.keys().collect();
+                fmt.write_str("{")?;
+                for (i, name) in names.into_iter().enumerate() {
+                    if i > 0 {
+                        fmt.write_str(", ")?;
+                    }
+                    write!(fmt, "{}: {}", name, ValueText(&entity_value.properties[name]))?;
+                }
+                fmt.write_str("}")
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::datastore::*;
+use crate::diff::*;
+
+fn before() -> Entity {
+    Entity {
+        key: None,
+        properties: hashmap! {
+            "name".to_string() => Value::from("Bob"),
+            "age".to_string() => Value::from(30),
+            "nickname".to_string() => Value::from("bo"),
+            "address".to_string() => Value::from(Entity {
+                key: None,
+                properties: hashmap! { "city".to_string() => Value::from("Paris") },
+            }),
+            "tags".to_string() => Value::from(vec!["a", "b", "c"]),
+            "scores".to_string() => Value::from(vec![1]),
+        },
+    }
+}
+
+fn after() -> Entity {
+    Entity {
+        key: None,
+        properties: hashmap! {
+            "name".to_string() => Value::from("Bob"),
+            "age".to_string() => Value::from("30"),
+            "address".to_string() => Value::from(Entity {
+                key: None,
+                properties: hashmap! {
+                    "city".to_string() => Value::from("Paris"),
+                    "zip".to_string() => Value::from("75001"),
+                },
+            }),
+            "tags".to_string() => Value::from(vec!["a"]),
+            "scores".to_string() => Value::from(vec![1, 2, 3]),
+            "active".to_string() => Value::from(true).unindexed(),
+        },
+    }
+}
+
+#[test]
+fn test_diff() {
+    let diff = diff(&before(), &after());
+    let expected = "\
++ active: true
++ address.zip: \"75001\"
+~ age: 30 -> \"30\" (integer -> string)
+- nickname: \"bo\"
++ scores[1]: 2
++ scores[2]: 3
+- tags[2]: \"c\"
+- tags[1]: \"b\"
+";
+    assert_eq!(expected, diff.to_string());
+    assert!(diff.changes.iter().filter(|change| change.is_type_change()).eq([&diff.changes[2]]));
+    assert_eq!("address.zip", diff.changes[1].path().to_string());
+
+    assert!(super::diff(&before(), &before()).is_empty());
+
+    // Changed index exclusion replaces the whole value.
+    let address = Entity { key: None, properties: Default::default() };
+    let unindexed = Entity {
+        key: None,
+        properties: hashmap! { "address".to_string() => Value::from(address).unindexed() },
+    };
+    let address = Entity { key: None, properties: hashmap! { "city".to_string() => Value::from("Rome") } };
+    let indexed = Entity { key: None, properties: hashmap! { "address".to_string() => Value::from(address) } };
+    assert_eq!("~ address: {} -> {city: \"Rome\"}\n", super::diff(&unindexed, &indexed).to_string());
+}
+
+#[test]
+fn test_apply() {
+    let diff = diff(&before(), &after());
+    let mut entity = before();
+    diff.apply(&mut entity).unwrap();
+    assert_eq!(after(), entity);
+
+    // The patch only applies to the entity it was made for.
+    let mut changed = before();
+    changed.properties.insert("age".to_string(), Value::from(31));
+    let err = diff.apply(&mut changed).unwrap_err();
+    assert_eq!("age", err.path.to_string());
+    assert_eq!(Some(&Value::from(31)), changed.properties.get("age"));
+    assert!(matches!(diff.apply(&mut after()), Err(Error { .. })));
+}
+
+#[test]
+fn test_patch_json() {
+    let diff = diff(&before(), &after());
+    let patch = serde_json::to_value(&diff).unwrap();
+    assert_eq!(json!({"op": "replace", "path": "/age", "old": {"integerValue": "30"}, "value": {"stringValue": "30"}}),
+               patch[2]);
+    assert_eq!(json!({"op": "remove", "path": "/tags/2", "old": {"stringValue": "c"}}), patch[6]);
+
+    let read: Diff = serde_json::from_value(patch).unwrap();
+    assert_eq!(diff, read);
+    let mut entity = before();
+    read.apply(&mut entity).unwrap();
+    assert_eq!(after(), entity);
+}
+
+#[test]
+fn test_pointers() {
+    let path = PropertyPath(vec![
+        PathSegment::Property("a/b~c".to_string()),
+        PathSegment::Index(10),
+        PathSegment::Property("007".to_string()),
+    ]);
+    assert_eq!("/a~1b~0c/10/007", path.to_pointer());
+    assert_eq!(Some(path), PropertyPath::from_pointer("/a~1b~0c/10/007"));
+    assert_eq!(None, PropertyPath::from_pointer("a"));
+
+    // Numeric property names read as indexes still apply to properties.
+    let nested = |value: i64| {
+        let inner = Entity { key: None, properties: hashmap! { "0".to_string() => Value::from(value) } };
+        Entity { key: None, properties: hashmap! { "2".to_string() => Value::from(inner) } }
+    };
+    let (old, new) = (nested(1), nested(2));
+    let patch = serde_json::to_string(&diff(&old, &new)).unwrap();
+    let mut patched = old.clone();
+    serde_json::from_str::<Diff>(&patch).unwrap().apply(&mut patched).unwrap();
+    assert_eq!(new, patched);
+}
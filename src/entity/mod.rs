@@ -0,0 +1,197 @@
+// Typed entities.
+//
+// `DatastoreEntity` ties a Rust struct to an entity kind and knows how to build the entity's key
+// from the struct's fields. It is usually derived:
+//
+//     #[derive(DatastoreEntity)]
+//     #[datastore(kind = "User")]
+//     struct User {
+//         #[datastore(key)]
+//         id: i64,
+//         name: String,
+//         #[datastore(exclude_from_indexes)]
+//         bio: String,
+//     }
+//
+// after which `client.put(&user)` and `client.get::<User>(42)` work without spelling out keys.
+
+use std::collections::HashMap;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::de::DeserializeOwned;
+
+use crate::datastore::{meaning, Entity, Key, PartitionId, PathElement, Value};
+use crate::serde_ds::{self, Error, Result};
+
+pub use datastore_derive::DatastoreEntity;
+
+#[cfg(test)]
+mod tests;
+
+/// A struct that is stored as an entity of a fixed kind.
+///
+/// The derive macro supports these attributes:
+///
+/// * `#[datastore(kind = "User")]` on the struct sets the kind, which defaults to the struct name.
+/// * `#[datastore(key)]` marks the field holding the numeric ID (`i64`) or name (`String`) of the
+///   key. Exactly one field must be marked.
+/// * `#[datastore(parent)]` marks a `Key` or `Option<Key>` field holding the parent key.
+/// * `#[datastore(exclude_from_indexes)]` stores a property without indexing it. For arrays the
+///   elements are excluded.
+/// * `#[datastore(rename = "name")]` sets the property name, which defaults to the field name.
+/// * `#[datastore(crate = "path")]` on the struct sets the path of this crate if it is not
+///   reachable as `::datastore`.
+///
+/// All other fields are converted via `serde_ds`. Missing properties are read as null, so they
+/// are only accepted for fields that can be null such as `Option`.
+pub trait DatastoreEntity: Sized {
+    /// The kind of the entities.
+    const KIND: &'static str;
+
+    /// The type of the last element of the key, `i64` or `String`.
+    type Id: KeyId;
+
+    /// The ID or name of this entity.
+    fn id(&self) -> &Self::Id;
+
+    /// The key of this entity's parent, `None` for root entities.
+    fn parent(&self) -> Option<&Key> {
+        None
+    }
+
+    fn to_entity(&self) -> serde_ds::Result<Entity>;
+
+    fn from_entity(entity: Entity) -> serde_ds::Result<Self>;
+
+    /// The key of this entity.
+    fn key(&self) -> Key {
+        Self::key_for(self.parent(), self.id())
+    }
+
+    /// The key of the entity of this kind with the given parent and ID.
+    fn key_for(parent: Option<&Key>, id: &Self::Id) -> Key {
+        let element = id.to_path_element(Self::KIND);
+        match parent {
+            Some(parent) => parent.child(element),
+            None => Key::new(PartitionId::new("", ""), vec![element]),
+        }
+    }
+}
+
+/// Types that can identify an entity within its parent: numeric IDs and names.
+pub trait KeyId: Sized {
+    fn to_path_element(&self, kind: &str) -> PathElement;
+
+    /// Reads the ID from the last element of the key.
+    fn from_key(key: &Key) -> Result<Self>;
+}
+
+impl KeyId for i64 {
+    fn to_path_element(&self, kind: &str) -> PathElement {
+        PathElement::from_id(kind, *self)
+    }
+
+    fn from_key(key: &Key) -> Result<i64> {
+        key.path().last().and_then(PathElement::id).ok_or(Error::ExpectedType("key with numeric ID"))
+    }
+}
+
+impl KeyId for String {
+    fn to_path_element(&self, kind: &str) -> PathElement {
+        PathElement::from_name(kind, self.as_str())
+    }
+
+    fn from_key(key: &Key) -> Result<String> {
+        match key.path().last().and_then(PathElement::name) {
+            Some(name) => Ok(name.to_string()),
+            None => Err(Error::ExpectedType("key with name")),
+        }
+    }
+}
+
+/// Types of fields holding the parent key: `Key` for entities that always have a parent and
+/// `Option<Key>` for entities that may have one.
+pub trait ParentKey: Sized {
+    fn parent_key(&self) -> Option<&Key>;
+
+    fn from_parent_key(parent: Option<Key>) -> Result<Self>;
+}
+
+impl ParentKey for Key {
+    fn parent_key(&self) -> Option<&Key> {
+        Some(self)
+    }
+
+    fn from_parent_key(parent: Option<Key>) -> Result<Key> {
+        parent.ok_or(Error::ExpectedType("key with parent"))
+    }
+}
+
+impl ParentKey for Option<Key> {
+    fn parent_key(&self) -> Option<&Key> {
+        self.as_ref()
+    }
+
+    fn from_parent_key(parent: Option<Key>) -> Result<Option<Key>> {
+        Ok(parent)
+    }
+}
+
+/// Deserialises a result of a projection query via `serde_ds`, e.g. into a struct with a subset of
+/// an entity's fields. Projected timestamps arrive as integers with meaning `GD_WHEN` and are
+/// converted back to timestamps first.
+pub fn from_projection<T: DeserializeOwned>(mut entity: Entity) -> Result<T> {
+    entity.key = None;
+    for value in entity.properties.values_mut() {
+        if let Some(timestamp) = projected_timestamp(value) {
+            *value = Value::from(timestamp);
+        }
+    }
+    serde_ds::from_value(Value::from(entity))
+}
+
+fn projected_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match *value {
+        Value::Integer { ref integer_value, ref meta } if meta.meaning == Some(meaning::GD_WHEN) => {
+            let micros: i64 = integer_value.parse().ok()?;
+            Utc.timestamp_opt(micros.div_euclid(1_000_000), (micros.rem_euclid(1_000_000) * 1000) as u32)
+                .single()
+        }
+        _ => None,
+    }
+}
+
+// Functions used by the derived implementations:
+
+/// Splits an entity into its key and properties, checking that it is of the expected kind.
+pub fn split_entity<T: DatastoreEntity>(entity: Entity) -> Result<(Key, HashMap<String, Value>)> {
+    let key = entity.key.ok_or(Error::ExpectedType("entity with key"))?;
+    if key.kind() != Some(T::KIND) {
+        return Err(Error::DeserializationError(
+            format!("expected entity of kind {}, found {}", T::KIND, key.kind().unwrap_or("none"))));
+    }
+    Ok((key, entity.properties))
+}
+
+/// Removes a property and deserialises it, treating a missing property as null.
+pub fn take_property<T: DeserializeOwned>(properties: &mut HashMap<String, Value>, name: &str)
+                                          -> Result<T> {
+    match properties.remove(name) {
+        Some(value) => serde_ds::from_value(value).map_err(|err| {
+            Error::DeserializationError(format!("property {}: {}", name, err))
+        }),
+        None => serde_ds::from_value(Value::from(())).map_err(|_| {
+            Error::DeserializationError(format!("missing property {}", name))
+        }),
+    }
+}
+
+/// Excludes a value from indexes. Arrays themselves can not be excluded, so their elements are.
+pub fn exclude_from_indexes(value: Value) -> Value {
+    match value {
+        Value::Array { mut array_value, meta } => {
+            array_value.values = array_value.values.into_iter().map(exclude_from_indexes).collect();
+            Value::Array { array_value, meta }
+        }
+        value => value.unindexed(),
+    }
+}
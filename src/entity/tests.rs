@@ -0,0 +1,147 @@
+use crate::client::{Client, MemoryBackend};
+use crate::datastore::*;
+use crate::entity::*;
+
+#[derive(Debug, PartialEq, DatastoreEntity)]
+#[datastore(crate = "crate", kind = "User")]
+struct Account {
+    #[datastore(key)]
+    id: i64,
+
+    name: String,
+
+    #[datastore(rename = "mail")]
+    email: Option<String>,
+
+    #[datastore(exclude_from_indexes)]
+    bio: String,
+
+    #[datastore(exclude_from_indexes)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, DatastoreEntity)]
+#[datastore(crate = "crate")]
+struct Order {
+    #[datastore(parent)]
+    user: Key,
+
+    #[datastore(key)]
+    number: String,
+
+    total: i64,
+}
+
+fn alice() -> Account {
+    Account {
+        id: 42,
+        name: "alice".to_string(),
+        email: Some("alice@example.com".to_string()),
+        bio: "Likes cryptography".to_string(),
+        tags: vec!["admin".to_string()],
+    }
+}
+
+fn user_key(id: i64) -> Key {
+    Key::new(PartitionId::new("", ""), vec![PathElement::from_id("User", id)])
+}
+
+#[test]
+fn test_to_entity() {
+    let entity = alice().to_entity().unwrap();
+    assert_eq!(Some(user_key(42)), entity.key);
+
+    let mut tag = Value::from("admin");
+    tag.meta_mut().exclude_from_indexes = true;
+    let expected = hashmap! {
+        "name".to_string() => Value::from("alice"),
+        "mail".to_string() => Value::from("alice@example.com"),
+        "bio".to_string() => Value::from("Likes cryptography").unindexed(),
+        "tags".to_string() => Value::from(vec![tag]),
+    };
+    assert_eq!(expected, entity.properties);
+    assert!(!entity.properties["tags"].exclude_from_indexes());
+}
+
+#[test]
+fn test_from_entity() {
+    assert_eq!(alice(), Account::from_entity(alice().to_entity().unwrap()).unwrap());
+
+    // Missing properties are accepted for optional fields only.
+    let mut entity = alice().to_entity().unwrap();
+    entity.properties.remove("mail");
+    assert_eq!(None, Account::from_entity(entity).unwrap().email);
+
+    let mut entity = alice().to_entity().unwrap();
+    entity.properties.remove("name");
+    let err = Account::from_entity(entity).unwrap_err();
+    assert_eq!(serde_ds::Error::DeserializationError("missing property name".to_string()), err);
+
+    let mut entity = alice().to_entity().unwrap();
+    entity.properties.insert("name".to_string(), Value::from(7));
+    assert!(Account::from_entity(entity).is_err());
+}
+
+#[test]
+fn test_keys() {
+    assert_eq!("User", Account::KIND);
+    assert_eq!("Order", Order::KIND);
+
+    let order = Order { user: user_key(42), number: "A-1".to_string(), total: 1250 };
+    let key = order.key();
+    assert_eq!(vec![PathElement::from_id("User", 42), PathElement::from_name("Order", "A-1")],
+               key.path());
+    assert_eq!(Some(user_key(42)), key.parent());
+    assert_eq!(key, Order::key_for(Some(&user_key(42)), &"A-1".to_string()));
+
+    let entity = order.to_entity().unwrap();
+    assert_eq!(order, Order::from_entity(entity).unwrap());
+
+    // Keys must match the kind and the type of the key field.
+    let entity = Entity { key: Some(user_key(42)), properties: Default::default() };
+    assert!(Order::from_entity(entity).is_err());
+
+    let mut entity = alice().to_entity().unwrap();
+    entity.key = Some(Key::new(PartitionId::new("", ""), vec![PathElement::from_name("User", "a")]));
+    assert_eq!(serde_ds::Error::ExpectedType("key with numeric ID"),
+               Account::from_entity(entity).unwrap_err());
+}
+
+#[tokio::test]
+async fn test_client() {
+    let client = Client::new("test-project", MemoryBackend::new());
+    assert_eq!(None, client.get::<Account>(42).await.unwrap());
+
+    let key = client.put(&alice()).await.unwrap();
+    assert_eq!(user_key(42), key);
+    assert_eq!(Some(alice()), client.get::<Account>(42).await.unwrap());
+
+    let order = Order { user: key.clone(), number: "A-1".to_string(), total: 1250 };
+    let key = client.put(&order).await.unwrap();
+    let stored: Order = client.get_by_key(&key).await.unwrap().unwrap();
+    assert_eq!(order.number, stored.number);
+    assert_eq!(vec![PathElement::from_id("User", 42)], stored.user.path());
+}
+
+#[test]
+fn test_from_projection() {
+    use chrono::{TimeZone, Utc};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Listing {
+        email: String,
+        created: chrono::DateTime<Utc>,
+    }
+
+    let created = Utc.with_ymd_and_hms(2018, 3, 1, 12, 30, 0).unwrap();
+    let entity = Entity {
+        key: Some(user_key(42)),
+        properties: hashmap! {
+            "email".to_string() => Value::from("alice@example.com"),
+            "created".to_string() =>
+                Value::from(created.timestamp_micros()).with_meaning(meaning::GD_WHEN),
+        },
+    };
+    let expected = Listing { email: "alice@example.com".to_string(), created };
+    assert_eq!(expected, from_projection(entity).unwrap());
+}
@@ -0,0 +1,500 @@
+// Conversion between entities and the legacy `EntityProto` messages found in managed exports.
+//
+// The relevant parts of the message definitions (from the App Engine SDK's `entity.proto`) are:
+//
+// message EntityProto {
+//   required Reference key = 13;
+//   required Path entity_group = 16;
+//   repeated Property property = 14;      // indexed properties
+//   repeated Property raw_property = 15;  // unindexed properties
+// }
+//
+// message Reference { required string app = 13; optional string name_space = 20;
+//                     required Path path = 14; }
+// message Path { repeated group Element = 1 { required string type = 2; optional int64 id = 3;
+//                                             optional string name = 4; } }
+//
+// message Property { optional Meaning meaning = 1; required string name = 3;
+//                    required bool multiple = 4; required PropertyValue value = 5; }
+//
+// message PropertyValue {
+//   optional int64 int64Value = 1;
+//   optional bool booleanValue = 2;
+//   optional string stringValue = 3;
+//   optional double doubleValue = 4;
+//   optional group PointValue = 5 { required double x = 6; required double y = 7; }
+//   optional group UserValue = 8 { required string email = 9; required string auth_domain = 10;
+//                                  optional string obfuscated_gaiaid = 19; ... }
+//   optional group ReferenceValue = 12 { required string app = 13; optional string name_space = 20;
+//                                        repeated group PathElement = 14 { ... = 15, 16, 17 } }
+// }
+//
+// Array properties are stored as several properties of the same name with `multiple` set.
+
+use std::collections::HashMap;
+use std::str;
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::datastore::{meaning, Blob, Entity, Key, LatLng, PartitionId, PathElement, Value};
+use crate::export::{Error, Result};
+use crate::proto::{Field, Reader, Writer};
+
+/// Decodes a serialised `EntityProto` into an entity.
+pub fn decode_entity(bytes: &[u8]) -> Result<Entity> {
+    let mut key = None;
+    let mut names: Vec<String> = vec![];
+    let mut values: HashMap<String, (bool, Vec<Value>)> = HashMap::new();
+
+    for field in Reader::new(bytes) {
+        let (number, value) = field?;
+        let indexed = match number {
+            13 => {
+                key = decode_reference(value.as_bytes(13)?)?;
+                continue;
+            }
+            14 => true,
+            15 => false,
+            _ => continue,
+        };
+
+        let (name, multiple, value) = decode_property(value.as_bytes(number)?, indexed)?;
+        let entry = values.entry(name.clone()).or_insert_with(|| {
+            names.push(name);
+            (multiple, vec![])
+        });
+        entry.0 |= multiple;
+        entry.1.push(value);
+    }
+
+    let mut properties = HashMap::new();
+    for name in names {
+        let (multiple, mut vals) = values.remove(&name).unwrap();
+        let value = if multiple {
+            Value::from(vals)
+        } else {
+            // Duplicate single-valued properties are invalid, the last one wins.
+            vals.pop().unwrap()
+        };
+        properties.insert(name, value);
+    }
+
+    Ok(Entity { key, properties })
+}
+
+// Decodes a `Reference` message. Embedded entities commonly carry an empty reference, and
+// references with an incomplete final element can not be represented; both yield `None`.
+fn decode_reference(bytes: &[u8]) -> Result<Option<Key>> {
+    let mut app = "";
+    let mut namespace = "";
+    let mut path = vec![];
+
+    for field in Reader::new(bytes) {
+        let (number, value) = field?;
+        match number {
+            13 => app = value.as_str(13)?,
+            20 => namespace = value.as_str(20)?,
+            14 => {
+                for element in value.as_message(14)? {
+                    let (number, element) = element?;
+                    if number == 1 {
+                        match decode_path_element(element.as_group(1)?, 2, 3, 4)? {
+                            Some(element) => path.push(element),
+                            None => return Ok(None),
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if path.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Key::new(PartitionId::new(project_id(app), namespace), path)))
+}
+
+// App IDs carry a partition prefix such as `s~` or `e~` that is not part of the project ID.
+fn project_id(app: &str) -> &str {
+    match app.find('~') {
+        Some(idx) => &app[idx + 1..],
+        None => app,
+    }
+}
+
+fn decode_path_element(group: Reader, kind_field: u32, id_field: u32, name_field: u32)
+                       -> Result<Option<PathElement>> {
+    let mut kind = None;
+    let mut id = 0;
+    let mut name = None;
+
+    for field in group {
+        let (number, value) = field?;
+        if number == kind_field {
+            kind = Some(value.as_str(number)?);
+        } else if number == id_field {
+            id = value.as_i64(number)?;
+        } else if number == name_field {
+            name = Some(value.as_str(number)?);
+        }
+    }
+
+    let kind = kind.ok_or(Error::InvalidEntity("path element without kind"))?;
+    Ok(match (id, name) {
+        (_, Some(name)) => Some(PathElement::from_name(kind, name)),
+        (0, None) => None,
+        (id, None) => Some(PathElement::from_id(kind, id)),
+    })
+}
+
+fn decode_property(bytes: &[u8], indexed: bool) -> Result<(String, bool, Value)> {
+    let mut legacy_meaning = 0;
+    let mut name = None;
+    let mut multiple = false;
+    let mut value_bytes: &[u8] = &[];
+
+    for field in Reader::new(bytes) {
+        let (number, value) = field?;
+        match number {
+            1 => legacy_meaning = value.as_i32(1)?,
+            3 => name = Some(value.as_str(3)?.to_string()),
+            4 => multiple = value.as_bool(4)?,
+            5 => value_bytes = value.as_bytes(5)?,
+            _ => {}
+        }
+    }
+
+    let name = name.ok_or(Error::InvalidEntity("property without name"))?;
+    let mut value = decode_property_value(value_bytes, legacy_meaning)?;
+
+    // Meanings that are implied by the resulting value type are dropped, all others are kept so
+    // that they can be written back unchanged.
+    let implied = match value {
+        Value::Timestamp { .. } => legacy_meaning == meaning::GD_WHEN,
+        Value::GeoPoint { .. } => legacy_meaning == meaning::GEORSS_POINT,
+        Value::Blob { .. } =>
+            legacy_meaning == meaning::BLOB || legacy_meaning == meaning::BYTESTRING,
+        Value::String { .. } => legacy_meaning == meaning::TEXT,
+        Value::Array { .. } => legacy_meaning == meaning::EMPTY_LIST,
+        Value::EntityValue { .. } => legacy_meaning == meaning::ENTITY_PROTO,
+        _ => false,
+    };
+
+    if legacy_meaning != 0 && !implied {
+        value = value.with_meaning(legacy_meaning);
+    }
+
+    // Array values can not be excluded from indexes themselves, only their elements.
+    if !indexed && !matches!(value, Value::Array { .. }) {
+        value = value.unindexed();
+    }
+
+    Ok((name, multiple, value))
+}
+
+fn decode_property_value(bytes: &[u8], legacy_meaning: i32) -> Result<Value> {
+    let mut result = None;
+
+    for field in Reader::new(bytes) {
+        let (number, field) = field?;
+        let value = match number {
+            1 => {
+                let int = field.as_i64(1)?;
+                if legacy_meaning == meaning::GD_WHEN {
+                    timestamp_value(int)?
+                } else {
+                    Value::from(int)
+                }
+            }
+            2 => Value::from(field.as_bool(2)?),
+            3 => string_value(field.as_bytes(3)?, legacy_meaning)?,
+            4 => Value::from(field.as_f64(4)?),
+            5 => point_value(&field)?,
+            8 => user_value(&field)?,
+            12 => reference_value(&field)?,
+            _ => continue,
+        };
+        result = Some(value);
+    }
+
+    Ok(match result {
+        Some(value) => value,
+        None if legacy_meaning == meaning::EMPTY_LIST => Value::from(Vec::<Value>::new()),
+        None => Value::from(()),
+    })
+}
+
+// Legacy timestamps are stored as microseconds since the Unix epoch.
+fn timestamp_value(micros: i64) -> Result<Value> {
+    let secs = micros.div_euclid(1_000_000);
+    let nanos = (micros.rem_euclid(1_000_000) * 1000) as u32;
+
+    Utc.timestamp_opt(secs, nanos)
+        .single()
+        .map(Value::from)
+        .ok_or(Error::InvalidEntity("timestamp out of range"))
+}
+
+fn string_value(bytes: &[u8], legacy_meaning: i32) -> Result<Value> {
+    match legacy_meaning {
+        meaning::BLOB | meaning::BYTESTRING => Ok(Value::from(Blob::from(bytes))),
+        meaning::ENTITY_PROTO => decode_entity(bytes).map(Value::from),
+        _ => str::from_utf8(bytes)
+            .map(Value::from)
+            .map_err(|_| Error::InvalidEntity("string value is not valid UTF-8")),
+    }
+}
+
+fn point_value(field: &Field) -> Result<Value> {
+    let mut x = None;
+    let mut y = None;
+
+    for inner in field.as_group(5)? {
+        match inner? {
+            (6, v) => x = Some(v.as_f64(6)?),
+            (7, v) => y = Some(v.as_f64(7)?),
+            _ => {}
+        }
+    }
+
+    match (x, y) {
+        (Some(x), Some(y)) => Ok(Value::from(LatLng::new(x, y))),
+        _ => Err(Error::InvalidEntity("incomplete point value")),
+    }
+}
+
+// Users are represented as embedded entities, which is also what the v1 API does.
+fn user_value(field: &Field) -> Result<Value> {
+    let mut properties = HashMap::new();
+
+    for inner in field.as_group(8)? {
+        let (number, value) = inner?;
+        let name = match number {
+            9 => "email",
+            10 => "auth_domain",
+            19 => "user_id",
+            21 => "federated_identity",
+            22 => "federated_provider",
+            _ => continue,
+        };
+        properties.insert(name.to_string(), Value::from(value.as_str(number)?));
+    }
+
+    Ok(Value::from(Entity { key: None, properties }).with_meaning(meaning::USER))
+}
+
+fn reference_value(field: &Field) -> Result<Value> {
+    let mut app = "";
+    let mut namespace = "";
+    let mut path = vec![];
+
+    for inner in field.as_group(12)? {
+        let (number, value) = inner?;
+        match number {
+            13 => app = value.as_str(13)?,
+            20 => namespace = value.as_str(20)?,
+            14 => match decode_path_element(value.as_group(14)?, 15, 16, 17)? {
+                Some(element) => path.push(element),
+                None => return Err(Error::InvalidEntity("incomplete key in reference value")),
+            },
+            _ => {}
+        }
+    }
+
+    let key = Key::new(PartitionId::new(project_id(app), namespace), path);
+    Ok(Value::from(key))
+}
+
+/// Encodes an entity as a serialised `EntityProto`. The entity must have a complete key.
+pub fn encode_entity(entity: &Entity) -> Result<Vec<u8>> {
+    let key = entity.key.as_ref().ok_or(Error::InvalidEntity("entity has no key"))?;
+    if !key.is_complete() {
+        return Err(Error::InvalidEntity("entity key is incomplete"));
+    }
+    let mut writer = Writer::new();
+    write_entity(&mut writer, Some(key), entity)?;
+    Ok(writer.into_bytes())
+}
+
+fn write_entity(w: &mut Writer, key: Option<&Key>, entity: &Entity) -> Result<()> {
+    match key {
+        Some(key) => {
+            w.message(13, |w| write_reference(w, key));
+            w.message(16, |w| write_path(w, &key.path()[..1]));
+        }
+
+        // Embedded entities without a key still need the (required) key fields.
+        None => {
+            w.message(13, |w| {
+                w.string(13, "");
+                w.message(14, |_| {});
+            });
+            w.message(16, |_| {});
+        }
+    }
+
+    // Properties are written in a stable order to make the output reproducible.
+    let mut names: Vec<&String> = entity.properties.keys().collect();
+    names.sort();
+
+    for name in names {
+        match entity.properties[name] {
+            Value::Array { ref array_value, .. } if array_value.values.is_empty() => {
+                let empty = Value::from(()).with_meaning(meaning::EMPTY_LIST);
+                write_property(w, name, &empty, false)?;
+            }
+            Value::Array { ref array_value, .. } => {
+                for value in &array_value.values {
+                    write_property(w, name, value, true)?;
+                }
+            }
+            ref value => write_property(w, name, value, false)?,
+        }
+    }
+
+    Ok(())
+}
+
+fn write_reference(w: &mut Writer, key: &Key) {
+    w.string(13, key.partition_id().project_id());
+    if !key.partition_id().namespace_id().is_empty() {
+        w.string(20, key.partition_id().namespace_id());
+    }
+    w.message(14, |w| write_path(w, key.path()));
+}
+
+fn write_path(w: &mut Writer, path: &[PathElement]) {
+    for element in path {
+        w.group(1, |w| {
+            w.string(2, element.kind());
+            if let Some(id) = element.id() {
+                w.int64(3, id);
+            }
+            if let Some(name) = element.name() {
+                w.string(4, name);
+            }
+        });
+    }
+}
+
+fn write_property(w: &mut Writer, name: &str, value: &Value, multiple: bool) -> Result<()> {
+    let (implied_meaning, encoded) = encode_property_value(value)?;
+
+    // Embedded entities can only be stored as unindexed (raw) properties in this format. Users
+    // are the exception, as they have their own value type.
+    let is_user = value.meaning() == Some(meaning::USER);
+    let embedded = matches!(value, Value::EntityValue { .. }) && !is_user;
+    let indexed = !value.exclude_from_indexes() && !embedded;
+
+    let legacy_meaning = match (value.meaning(), value) {
+        (Some(meaning::USER), _) => 0,
+        (Some(m), _) => m,
+        (None, &Value::String { .. }) if !indexed => meaning::TEXT,
+        (None, &Value::Blob { .. }) if !indexed => meaning::BLOB,
+        (None, &Value::Blob { .. }) => meaning::BYTESTRING,
+        (None, _) => implied_meaning,
+    };
+
+    w.message(if indexed { 14 } else { 15 }, |w| {
+        if legacy_meaning != 0 {
+            w.int32(1, legacy_meaning);
+        }
+        w.string(3, name);
+        w.bool(4, multiple);
+        w.bytes(5, &encoded);
+    });
+
+    Ok(())
+}
+
+// Encodes a `PropertyValue` and returns it together with the meaning implied by the value type.
+fn encode_property_value(value: &Value) -> Result<(i32, Vec<u8>)> {
+    let mut w = Writer::new();
+    let mut implied_meaning = 0;
+
+    match *value {
+        Value::Null { .. } => {}
+        Value::Boolean { boolean_value, .. } => w.bool(2, boolean_value),
+        Value::String { ref string_value, .. } => w.string(3, string_value),
+        Value::Integer { ref integer_value, .. } => {
+            let int = integer_value.parse().map_err(|_| Error::InvalidEntity("invalid integer"))?;
+            w.int64(1, int);
+        }
+        Value::Double { double_value, .. } => w.double(4, double_value),
+        Value::Timestamp { ref timestamp_value, .. } => {
+            implied_meaning = meaning::GD_WHEN;
+            w.int64(1, timestamp_micros(timestamp_value));
+        }
+        Value::GeoPoint { ref geo_point_value, .. } => {
+            implied_meaning = meaning::GEORSS_POINT;
+            w.group(5, |w| {
+                w.double(6, geo_point_value.latitude());
+                w.double(7, geo_point_value.longitude());
+            });
+        }
+        Value::KeyValue { ref key_value, .. } => w.group(12, |w| {
+            w.string(13, key_value.partition_id().project_id());
+            if !key_value.partition_id().namespace_id().is_empty() {
+                w.string(20, key_value.partition_id().namespace_id());
+            }
+            for element in key_value.path() {
+                w.group(14, |w| {
+                    w.string(15, element.kind());
+                    if let Some(id) = element.id() {
+                        w.int64(16, id);
+                    }
+                    if let Some(name) = element.name() {
+                        w.string(17, name);
+                    }
+                });
+            }
+        }),
+        Value::Blob { ref blob_value, .. } => w.bytes(3, &blob_value.0),
+        Value::EntityValue { ref entity_value, .. } if value.meaning() == Some(meaning::USER) =>
+            write_user(&mut w, entity_value)?,
+        Value::EntityValue { ref entity_value, .. } => {
+            implied_meaning = meaning::ENTITY_PROTO;
+            let mut inner = Writer::new();
+            write_entity(&mut inner, entity_value.key.as_ref(), entity_value)?;
+            w.bytes(3, &inner.into_bytes());
+        }
+        Value::Array { .. } => return Err(Error::InvalidEntity("nested arrays are not supported")),
+    }
+
+    Ok((implied_meaning, w.into_bytes()))
+}
+
+fn write_user(w: &mut Writer, user: &Entity) -> Result<()> {
+    let field = |name: &str| match user.properties.get(name) {
+        Some(Value::String { string_value, .. }) => Ok(Some(string_value.as_str())),
+        None => Ok(None),
+        Some(_) => Err(Error::InvalidEntity("user properties must be strings")),
+    };
+
+    let email = field("email")?.ok_or(Error::InvalidEntity("user without email"))?;
+    let auth_domain = field("auth_domain")?.unwrap_or("");
+    let user_id = field("user_id")?;
+    let federated_identity = field("federated_identity")?;
+    let federated_provider = field("federated_provider")?;
+
+    w.group(8, |w| {
+        w.string(9, email);
+        w.string(10, auth_domain);
+        if let Some(user_id) = user_id {
+            w.string(19, user_id);
+        }
+        if let Some(identity) = federated_identity {
+            w.string(21, identity);
+        }
+        if let Some(provider) = federated_provider {
+            w.string(22, provider);
+        }
+    });
+
+    Ok(())
+}
+
+fn timestamp_micros(timestamp: &DateTime<Utc>) -> i64 {
+    timestamp.timestamp() * 1_000_000 + i64::from(timestamp.timestamp_subsec_micros())
+}
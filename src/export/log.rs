@@ -0,0 +1,250 @@
+// Implementation of the LevelDB log format, which is used for the `output-N` files of managed
+// exports.
+//
+// A log file is a sequence of 32KiB blocks. Each block contains physical records consisting of a
+// 7-byte header (masked CRC32C checksum, little-endian length and record type) and the payload.
+// Logical records that do not fit into the remainder of a block are split into FIRST, MIDDLE and
+// LAST fragments. Trailing block space that is too small for a header is zero-filled.
+//
+// See https://github.com/google/leveldb/blob/master/doc/log_format.md
+
+use std::io::{self, Read, Write};
+use crate::export::{Error, Result};
+
+pub const BLOCK_SIZE: usize = 32768;
+pub const HEADER_SIZE: usize = 7;
+
+pub const ZERO_TYPE: u8 = 0;
+pub const FULL_TYPE: u8 = 1;
+pub const FIRST_TYPE: u8 = 2;
+pub const MIDDLE_TYPE: u8 = 3;
+pub const LAST_TYPE: u8 = 4;
+
+const MASK_DELTA: u32 = 0xa282_ead8;
+
+// Lookup table for the CRC32C (Castagnoli) polynomial, generated at compile time.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc = CRC_TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Checksum of a physical record, covering the record type and the payload. LevelDB masks stored
+/// checksums because computing the CRC of data that contains embedded CRCs is problematic.
+pub fn masked_crc(record_type: u8, data: &[u8]) -> u32 {
+    let crc = crc32c_update(crc32c_update(0, &[record_type]), data);
+    crc.rotate_right(15).wrapping_add(MASK_DELTA)
+}
+
+/// Reads logical records from a LevelDB log.
+pub struct LogReader<R> {
+    reader: R,
+    block: Vec<u8>,
+    block_len: usize,
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> LogReader<R> {
+    pub fn new(reader: R) -> LogReader<R> {
+        LogReader {
+            reader,
+            block: vec![0; BLOCK_SIZE],
+            block_len: 0,
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    // Fills the block buffer with the next block. Only the final block of a file may be short.
+    fn read_block(&mut self) -> io::Result<()> {
+        let mut len = 0;
+        while len < BLOCK_SIZE {
+            match self.reader.read(&mut self.block[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.eof = len < BLOCK_SIZE;
+        self.block_len = len;
+        self.pos = 0;
+        Ok(())
+    }
+
+    // Returns the next physical record, or `None` at the end of the log.
+    fn read_physical(&mut self) -> Result<Option<(u8, &[u8])>> {
+        loop {
+            if self.block_len - self.pos < HEADER_SIZE {
+                if self.eof {
+                    // A truncated header at the end of the log is what a crashed writer leaves
+                    // behind, so it is not treated as corruption.
+                    return Ok(None);
+                }
+                self.read_block()?;
+                continue;
+            }
+
+            let header = &self.block[self.pos..self.pos + HEADER_SIZE];
+            let checksum = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let len = usize::from(u16::from_le_bytes([header[4], header[5]]));
+            let record_type = header[6];
+
+            // Zero-length records of type zero are written by preallocating writers, the rest of
+            // the block is unused.
+            if record_type == ZERO_TYPE && len == 0 {
+                self.pos = self.block_len;
+                continue;
+            }
+
+            let start = self.pos + HEADER_SIZE;
+            if start + len > self.block_len {
+                return Err(Error::Corruption("record length exceeds block"));
+            }
+
+            self.pos = start + len;
+            let data = &self.block[start..start + len];
+            if masked_crc(record_type, data) != checksum {
+                return Err(Error::Corruption("checksum mismatch"));
+            }
+
+            return Ok(Some((record_type, data)));
+        }
+    }
+
+    /// Reads the next logical record, returning `None` at the end of the log.
+    pub fn read_record(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut record: Option<Vec<u8>> = None;
+
+        loop {
+            let (record_type, data) = match self.read_physical()? {
+                Some(physical) => physical,
+                None => return match record {
+                    None => Ok(None),
+                    Some(_) => Err(Error::Corruption("log ends inside a fragmented record")),
+                },
+            };
+
+            match (record_type, record.as_mut()) {
+                (FULL_TYPE, None) => return Ok(Some(data.to_vec())),
+                (FIRST_TYPE, None) => record = Some(data.to_vec()),
+                (MIDDLE_TYPE, Some(buf)) => buf.extend_from_slice(data),
+                (LAST_TYPE, Some(buf)) => {
+                    buf.extend_from_slice(data);
+                    return Ok(record);
+                }
+                (FULL_TYPE, Some(_)) | (FIRST_TYPE, Some(_)) =>
+                    return Err(Error::Corruption("new record inside a fragmented record")),
+                (MIDDLE_TYPE, None) | (LAST_TYPE, None) =>
+                    return Err(Error::Corruption("fragment without a first record")),
+                _ => return Err(Error::Corruption("unknown record type")),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for LogReader<R> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => None,
+            Err(e) => {
+                // Resynchronising after corruption is not supported, reading stops here.
+                self.eof = true;
+                self.block_len = 0;
+                self.pos = 0;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Writes logical records to a LevelDB log.
+pub struct LogWriter<W> {
+    writer: W,
+    block_offset: usize,
+}
+
+impl<W: Write> LogWriter<W> {
+    pub fn new(writer: W) -> LogWriter<W> {
+        LogWriter { writer, block_offset: 0 }
+    }
+
+    /// Appends a record, fragmenting it across blocks where necessary.
+    pub fn add_record(&mut self, record: &[u8]) -> io::Result<()> {
+        let mut remaining = record;
+        let mut first = true;
+
+        // Empty records still produce a single, empty FULL record, hence the `loop`.
+        loop {
+            let left = BLOCK_SIZE - self.block_offset;
+            if left < HEADER_SIZE {
+                self.writer.write_all(&[0; HEADER_SIZE][..left])?;
+                self.block_offset = 0;
+            }
+
+            let available = BLOCK_SIZE - self.block_offset - HEADER_SIZE;
+            let len = remaining.len().min(available);
+            let last = len == remaining.len();
+
+            let record_type = match (first, last) {
+                (true, true) => FULL_TYPE,
+                (true, false) => FIRST_TYPE,
+                (false, false) => MIDDLE_TYPE,
+                (false, true) => LAST_TYPE,
+            };
+
+            self.write_physical(record_type, &remaining[..len])?;
+            remaining = &remaining[len..];
+            first = false;
+
+            if last {
+                return Ok(());
+            }
+        }
+    }
+
+    fn write_physical(&mut self, record_type: u8, data: &[u8]) -> io::Result<()> {
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(&masked_crc(record_type, data).to_le_bytes());
+        header[4..6].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        header[6] = record_type;
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        self.block_offset += HEADER_SIZE + data.len();
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
@@ -0,0 +1,152 @@
+// Encoding of the export metadata files.
+//
+// Both the overall and the per-kind metadata files contain a `Backup` message as defined by the
+// App Engine datastore admin's `backup.proto`:
+//
+// message Backup { optional BackupInfo backup_info = 1; repeated KindBackupInfo kind_info = 2; }
+// message BackupInfo { optional string backup_name = 1; optional int64 start_timestamp = 2;
+//                      optional int64 end_timestamp = 3; }
+// message KindBackupInfo { required string kind = 1; repeated string file = 2;
+//                          optional EntitySchema entity_schema = 3; optional bool is_partial = 4; }
+// message EntitySchema { optional string kind = 1; repeated Field field = 2; }
+// message EntitySchema.Field { required string name = 1; repeated Type type = 2; }
+// message EntitySchema.Type { optional bool is_list = 1; repeated PrimitiveType primitive_type = 2;
+//                             repeated EntitySchema embedded_schema = 3; }
+//
+// In a per-kind metadata file `file` lists the output files of that kind, relative to the
+// metadata file. In the overall metadata file it lists the per-kind metadata files instead.
+
+use std::collections::{BTreeMap, BTreeSet};
+use chrono::{DateTime, Utc};
+
+use crate::datastore::{meaning, Entity, Value};
+use crate::proto::Writer;
+
+// EntitySchema.PrimitiveType
+const FLOAT: i32 = 0;
+const INTEGER: i32 = 1;
+const BOOLEAN: i32 = 2;
+const STRING: i32 = 3;
+const DATE_TIME: i32 = 4;
+const RATING: i32 = 5;
+const TEXT: i32 = 13;
+const BLOB: i32 = 14;
+const SHORT_BLOB: i32 = 15;
+const USER: i32 = 16;
+const GEO_POINT: i32 = 17;
+const REFERENCE: i32 = 18;
+
+/// Property types observed across the entities of a kind.
+#[derive(Debug, Default)]
+pub struct Schema {
+    fields: BTreeMap<String, FieldSchema>,
+}
+
+#[derive(Debug, Default)]
+struct FieldSchema {
+    is_list: bool,
+    types: BTreeSet<i32>,
+    embedded: Option<Schema>,
+}
+
+impl Schema {
+    pub fn observe(&mut self, entity: &Entity) {
+        for (name, value) in &entity.properties {
+            let field = self.fields.entry(name.clone()).or_default();
+            match *value {
+                Value::Array { ref array_value, .. } => {
+                    field.is_list = true;
+                    for element in &array_value.values {
+                        field.observe(element);
+                    }
+                }
+                ref value => field.observe(value),
+            }
+        }
+    }
+
+    fn write(&self, w: &mut Writer, kind: Option<&str>) {
+        if let Some(kind) = kind {
+            w.string(1, kind);
+        }
+
+        for (name, field) in &self.fields {
+            w.message(2, |w| {
+                w.string(1, name);
+                w.message(2, |w| {
+                    w.bool(1, field.is_list);
+                    for primitive_type in &field.types {
+                        w.int32(2, *primitive_type);
+                    }
+                    if let Some(ref embedded) = field.embedded {
+                        w.message(3, |w| embedded.write(w, None));
+                    }
+                });
+            });
+        }
+    }
+}
+
+impl FieldSchema {
+    fn observe(&mut self, value: &Value) {
+        let unindexed = value.exclude_from_indexes();
+        let primitive_type = match *value {
+            Value::Null { .. } | Value::Array { .. } => return,
+            Value::Double { .. } => FLOAT,
+            Value::Integer { .. } if value.meaning() == Some(meaning::RATING) => RATING,
+            Value::Integer { .. } => INTEGER,
+            Value::Boolean { .. } => BOOLEAN,
+            Value::String { .. } if unindexed => TEXT,
+            Value::String { .. } => STRING,
+            Value::Timestamp { .. } => DATE_TIME,
+            Value::Blob { .. } if unindexed => BLOB,
+            Value::Blob { .. } => SHORT_BLOB,
+            Value::GeoPoint { .. } => GEO_POINT,
+            Value::KeyValue { .. } => REFERENCE,
+            Value::EntityValue { .. } if value.meaning() == Some(meaning::USER) => USER,
+            Value::EntityValue { ref entity_value, .. } => {
+                self.embedded.get_or_insert_with(Schema::default).observe(entity_value);
+                return;
+            }
+        };
+
+        self.types.insert(primitive_type);
+    }
+}
+
+fn write_backup_info(w: &mut Writer, name: &str, started: &DateTime<Utc>, ended: &DateTime<Utc>) {
+    w.message(1, |w| {
+        w.string(1, name);
+        w.int64(2, started.timestamp_millis() * 1000);
+        w.int64(3, ended.timestamp_millis() * 1000);
+    });
+}
+
+/// Encodes the metadata of a single kind, listing its output files.
+pub fn encode_kind_metadata(name: &str, started: &DateTime<Utc>, ended: &DateTime<Utc>,
+                            kind: &str, files: &[String], schema: &Schema) -> Vec<u8> {
+    let mut w = Writer::new();
+    write_backup_info(&mut w, name, started, ended);
+    w.message(2, |w| {
+        w.string(1, kind);
+        for file in files {
+            w.string(2, file);
+        }
+        w.message(3, |w| schema.write(w, Some(kind)));
+    });
+    w.into_bytes()
+}
+
+/// Encodes the overall metadata, listing the metadata file of each kind.
+pub fn encode_overall_metadata(name: &str, started: &DateTime<Utc>, ended: &DateTime<Utc>,
+                               kinds: &[(String, String)]) -> Vec<u8> {
+    let mut w = Writer::new();
+    write_backup_info(&mut w, name, started, ended);
+    for (kind, file) in kinds {
+        w.message(2, |w| {
+            w.string(1, kind);
+            w.string(2, file);
+        });
+    }
+    w.into_bytes()
+}
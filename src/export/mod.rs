@@ -0,0 +1,152 @@
+// Support for the file format of Datastore managed exports (`gcloud datastore export`).
+//
+// An export consists of metadata files and a set of `output-N` files per namespace/kind
+// combination. The output files are LevelDB logs in which every record is a serialised legacy
+// `EntityProto` message. Exports can be read as well as written, written exports can be loaded
+// with `gcloud datastore import`.
+
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::datastore::Entity;
+use crate::proto;
+
+mod log;
+mod entity_proto;
+mod metadata;
+mod writer;
+
+pub use self::log::{LogReader, LogWriter};
+pub use self::entity_proto::{decode_entity, encode_entity};
+pub use self::writer::{write_export, ExportWriter, DEFAULT_MAX_FILE_SIZE};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Corruption(&'static str),
+    Proto(proto::Error),
+    InvalidEntity(&'static str),
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<proto::Error> for Error {
+    fn from(err: proto::Error) -> Self {
+        Error::Proto(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(fmt, "I/O error: {}", err),
+            Error::Corruption(msg) => write!(fmt, "corrupt log file: {}", msg),
+            Error::Proto(ref err) => write!(fmt, "invalid entity protobuf: {}", err),
+            Error::InvalidEntity(msg) => write!(fmt, "invalid entity: {}", msg),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// Streams the entities stored in a single export output file.
+pub struct EntityReader<R> {
+    log: LogReader<R>,
+}
+
+impl<R: Read> EntityReader<R> {
+    pub fn new(reader: R) -> EntityReader<R> {
+        EntityReader { log: LogReader::new(reader) }
+    }
+}
+
+impl EntityReader<File> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<EntityReader<File>> {
+        Ok(EntityReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Iterator for EntityReader<R> {
+    type Item = Result<Entity>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.log.next().map(|record| record.and_then(|bytes| decode_entity(&bytes)))
+    }
+}
+
+/// Streams the entities of all output files in an export directory, see `output_files`.
+pub struct ExportReader {
+    files: ::std::vec::IntoIter<PathBuf>,
+    current: Option<EntityReader<File>>,
+}
+
+impl ExportReader {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<ExportReader> {
+        Ok(ExportReader {
+            files: output_files(dir)?.into_iter(),
+            current: None,
+        })
+    }
+}
+
+impl Iterator for ExportReader {
+    type Item = Result<Entity>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entity) = self.current.as_mut().and_then(Iterator::next) {
+                return Some(entity);
+            }
+
+            let path = self.files.next()?;
+            match EntityReader::open(path) {
+                Ok(reader) => self.current = Some(reader),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Finds all `output-N` files below an export directory. Files are ordered by directory and then
+/// by their numeric suffix.
+pub fn output_files<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    collect_output_files(dir.as_ref(), &mut files)?;
+
+    files.sort_by(|a, b| {
+        a.parent().cmp(&b.parent()).then(output_number(a).cmp(&output_number(b)))
+    });
+
+    Ok(files)
+}
+
+fn collect_output_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_output_files(&path, files)?;
+        } else if output_number(&path).is_some() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn output_number(path: &Path) -> Option<u64> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix("output-"))
+        .and_then(|n| n.parse().ok())
+}
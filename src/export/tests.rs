@@ -0,0 +1,298 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use chrono::{TimeZone, Utc};
+
+use crate::datastore::*;
+use crate::export::*;
+use crate::export::log::{self, BLOCK_SIZE, FIRST_TYPE, LAST_TYPE, MIDDLE_TYPE};
+
+fn fixture_dir() -> PathBuf {
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("resources/export");
+    d
+}
+
+fn fixture(name: &str) -> PathBuf {
+    let mut d = fixture_dir();
+    d.push("all_namespaces/all_kinds");
+    d.push(name);
+    d
+}
+
+// Encodes a physical log record, used to construct fragmented records by hand.
+fn physical_record(record_type: u8, data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    out.extend_from_slice(&log::masked_crc(record_type, data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    out.push(record_type);
+    out.extend_from_slice(data);
+    out
+}
+
+#[test]
+fn test_read_entity() {
+    let entities: Vec<Entity> = EntityReader::open(fixture("output-0"))
+        .expect("Could not open fixture")
+        .collect::<Result<_>>()
+        .expect("Reading entities failed");
+
+    assert_eq!(1, entities.len());
+    let entity = &entities[0];
+
+    let key = Key::new(
+        PartitionId::new("test-project", ""),
+        vec![PathElement::from_id("User", 123)],
+    );
+    assert_eq!(Some(&key), entity.key.as_ref());
+
+    let manager = Key::new(
+        PartitionId::new("test-project", ""),
+        vec![PathElement::from_id("User", 456)],
+    );
+
+    let address = Entity {
+        key: None,
+        properties: hashmap!("city".to_string() => Value::from("Oslo")),
+    };
+
+    let created = Utc.with_ymd_and_hms(2017, 9, 21, 5, 41, 33).unwrap()
+        + ::chrono::Duration::microseconds(123_456);
+
+    let expected = hashmap!(
+        "email".to_string() => Value::from("mags@mag"),
+        "token".to_string() => Value::from(Blob::from(&b"tok"[..])),
+        "location".to_string() => Value::from(LatLng::new(59.91, 10.75)),
+        "created".to_string() => Value::from(created),
+        "age".to_string() => Value::from(42),
+        "balance".to_string() => Value::from(-5),
+        "score".to_string() => Value::from(9.5),
+        "active".to_string() => Value::from(true),
+        "tags".to_string() => Value::from(vec!["a", "b"]),
+        "nothing".to_string() => Value::from(()),
+        "empty".to_string() => Value::from(Vec::<Value>::new()),
+        "manager".to_string() => Value::from(manager),
+        "rating".to_string() => Value::from(3).with_meaning(13),
+        "bio".to_string() => Value::from("A long biography").unindexed(),
+        "avatar".to_string() => Value::from(Blob(vec![0, 1, 2, 255])).unindexed(),
+        "address".to_string() => Value::from(address).unindexed(),
+    );
+
+    assert_eq!(expected, entity.properties);
+}
+
+#[test]
+fn test_read_export_directory() {
+    let files = output_files(fixture_dir()).expect("Listing output files failed");
+    assert_eq!(vec![fixture("output-0"), fixture("output-1")], files);
+
+    let entities: Vec<Entity> = ExportReader::open(fixture_dir())
+        .expect("Could not open export")
+        .collect::<Result<_>>()
+        .expect("Reading entities failed");
+
+    assert_eq!(2, entities.len());
+
+    let order = &entities[1];
+    let key = Key::new(
+        PartitionId::new("test-project", "tenant-42"),
+        vec![PathElement::from_id("User", 123), PathElement::from_name("Order", "abc")],
+    );
+    assert_eq!(Some(&key), order.key.as_ref());
+    assert_eq!(Some(&Value::from(vec![Value::from("fragile").unindexed()])),
+               order.properties.get("notes"));
+}
+
+#[test]
+fn test_fragmented_records() {
+    let record: Vec<u8> = (0..BLOCK_SIZE + 100).map(|i| i as u8).collect();
+
+    // The first fragment fills the first block entirely, the rest of the record follows in the
+    // second block.
+    let first_len = BLOCK_SIZE - 7;
+    let mut log = physical_record(FIRST_TYPE, &record[..first_len]);
+    log.extend(physical_record(MIDDLE_TYPE, &record[first_len..first_len + 50]));
+    log.extend(physical_record(LAST_TYPE, &record[first_len + 50..]));
+
+    let records: Vec<Vec<u8>> = LogReader::new(Cursor::new(log))
+        .collect::<Result<_>>()
+        .expect("Reading fragmented record failed");
+
+    assert_eq!(vec![record], records);
+}
+
+#[test]
+fn test_block_trailer_is_skipped() {
+    let first = vec![1u8; BLOCK_SIZE - 7 - 7 - 3];
+    let mut log = physical_record(log::FULL_TYPE, &first);
+    log.extend(physical_record(log::FULL_TYPE, b""));
+    log.extend(vec![0; 3]);
+    log.extend(physical_record(log::FULL_TYPE, b"second"));
+
+    let records: Vec<Vec<u8>> = LogReader::new(Cursor::new(log))
+        .collect::<Result<_>>()
+        .expect("Reading records failed");
+
+    assert_eq!(vec![first, vec![], b"second".to_vec()], records);
+}
+
+#[test]
+fn test_checksum_mismatch() {
+    let mut log = physical_record(log::FULL_TYPE, b"hello");
+    let last = log.len() - 1;
+    log[last] = b'O';
+
+    let result: Result<Vec<Vec<u8>>> = LogReader::new(Cursor::new(log)).collect();
+    match result {
+        Err(Error::Corruption(msg)) => assert_eq!("checksum mismatch", msg),
+        other => panic!("Expected corruption error, got {:?}", other),
+    }
+}
+
+// Creates an empty scratch directory for a test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = ::std::env::temp_dir().join(format!("datastore-export-{}-{}", name, ::std::process::id()));
+    let _ = ::std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn test_log_writer_roundtrip() {
+    let records = vec![
+        vec![1u8; BLOCK_SIZE - 7 - 7 - 3],
+        vec![],
+        (0..3 * BLOCK_SIZE).map(|i| i as u8).collect(),
+        b"last".to_vec(),
+    ];
+
+    let mut writer = LogWriter::new(vec![]);
+    for record in &records {
+        writer.add_record(record).expect("Writing record failed");
+    }
+
+    let read: Vec<Vec<u8>> = LogReader::new(Cursor::new(writer.into_inner()))
+        .collect::<Result<_>>()
+        .expect("Reading records failed");
+
+    assert_eq!(records, read);
+}
+
+#[test]
+fn test_entity_roundtrip() {
+    let entities: Vec<Entity> = ExportReader::open(fixture_dir())
+        .expect("Could not open export")
+        .collect::<Result<_>>()
+        .expect("Reading entities failed");
+
+    for entity in entities {
+        let bytes = encode_entity(&entity).expect("Encoding entity failed");
+        assert_eq!(entity, decode_entity(&bytes).expect("Decoding entity failed"));
+    }
+}
+
+#[test]
+fn test_encode_requires_key() {
+    let entity = Entity { key: None, properties: hashmap!() };
+    match encode_entity(&entity) {
+        Err(Error::InvalidEntity(_)) => {}
+        other => panic!("Expected invalid entity error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_encode_requires_complete_key() {
+    let partition = PartitionId::new("test-project", "");
+    let keys = vec![
+        Key::new(partition.clone(), vec![]),
+        Key::new(partition, vec![PathElement::incomplete("User")]),
+    ];
+    for key in keys {
+        let entity = Entity { key: Some(key), properties: hashmap!() };
+        match encode_entity(&entity) {
+            Err(Error::InvalidEntity(_)) => {}
+            other => panic!("Expected invalid entity error, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_write_export() {
+    let dir = scratch_dir("write");
+    let partition = PartitionId::new("test-project", "");
+
+    let users: Vec<Entity> = (1..=5).map(|id| Entity {
+        key: Some(Key::new(partition.clone(), vec![PathElement::from_id("User", id)])),
+        properties: hashmap!("name".to_string() => Value::from(format!("user {}", id))),
+    }).collect();
+    let order = Entity {
+        key: Some(Key::new(partition.clone(), vec![PathElement::from_name("Order", "abc")])),
+        properties: hashmap!("total".to_string() => Value::from(9.5)),
+    };
+
+    let mut writer = ExportWriter::create(&dir).expect("Creating export failed").max_file_size(1);
+    for entity in users.iter().chain(Some(&order)) {
+        writer.write(entity).expect("Writing entity failed");
+    }
+    let metadata = writer.finish().expect("Finishing export failed");
+
+    assert_eq!(dir.join(format!("{}.overall_export_metadata",
+                                dir.file_name().unwrap().to_str().unwrap())), metadata);
+    assert!(dir.join("all_namespaces/kind_User/all_namespaces_kind_User.export_metadata").is_file());
+    assert!(dir.join("all_namespaces/kind_Order/all_namespaces_kind_Order.export_metadata").is_file());
+
+    // Every entity ends up in its own file because of the tiny maximum file size.
+    let files = output_files(&dir).expect("Listing output files failed");
+    assert_eq!(6, files.len());
+
+    // The overall metadata lists the per-kind metadata files.
+    let bytes = ::std::fs::read(&metadata).expect("Reading metadata failed");
+    let mut kinds = vec![];
+    for field in crate::proto::Reader::new(&bytes) {
+        let (number, field) = field.expect("Invalid metadata");
+        if number == 2 {
+            for field in crate::proto::Reader::new(field.as_bytes(2).unwrap()) {
+                let (number, field) = field.expect("Invalid kind info");
+                if number == 1 {
+                    kinds.push(field.as_str(1).unwrap().to_string());
+                }
+            }
+        }
+    }
+    assert_eq!(vec!["Order", "User"], kinds);
+
+    let read: Vec<Entity> = ExportReader::open(&dir)
+        .expect("Could not open export")
+        .collect::<Result<_>>()
+        .expect("Reading entities failed");
+    let mut expected = vec![order];
+    expected.extend(users);
+    assert_eq!(expected, read);
+
+    ::std::fs::remove_dir_all(&dir).expect("Removing export failed");
+}
+
+#[test]
+fn test_kinds_are_escaped_in_paths() {
+    let dir = scratch_dir("escape");
+    let partition = PartitionId::new("test-project", "");
+    let entities: Vec<Entity> = vec!["../Up", "a%2Fb", "a/b"].into_iter().map(|kind| Entity {
+        key: Some(Key::new(partition.clone(), vec![PathElement::from_id(kind, 1)])),
+        properties: hashmap!(),
+    }).collect();
+
+    write_export(&dir, entities.clone()).expect("Writing export failed");
+
+    let namespaces = dir.join("all_namespaces");
+    assert!(namespaces.join("kind_..%2FUp/all_namespaces_kind_..%2FUp.export_metadata").is_file());
+    assert!(namespaces.join("kind_a%2Fb/output-0").is_file());
+    assert!(namespaces.join("kind_a%252Fb/output-0").is_file());
+    assert!(!dir.join("Up").exists());
+
+    let mut read: Vec<Entity> = ExportReader::open(&dir)
+        .expect("Could not open export")
+        .collect::<Result<_>>()
+        .expect("Reading entities failed");
+    read.sort_by(|a, b| a.key.as_ref().and_then(Key::kind).cmp(&b.key.as_ref().and_then(Key::kind)));
+    assert_eq!(entities, read);
+
+    ::std::fs::remove_dir_all(&dir).expect("Removing export failed");
+}
@@ -0,0 +1,176 @@
+// Writing of managed exports that can be loaded with `gcloud datastore import`.
+//
+// The layout matches the one produced by `gcloud datastore export` without filters:
+//
+// <dir>/<name>.overall_export_metadata
+// <dir>/all_namespaces/kind_<Kind>/all_namespaces_kind_<Kind>.export_metadata
+// <dir>/all_namespaces/kind_<Kind>/output-N
+//
+// Kinds may contain characters that are not valid in file names, such as `/`. These are
+// percent-encoded in the paths, the metadata files still name the kind as it is.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+
+use crate::datastore::Entity;
+use crate::export::{Error, Result};
+use crate::export::entity_proto::encode_entity;
+use crate::export::log::LogWriter;
+use crate::export::metadata::{self, Schema};
+
+/// Default size after which a new output file is started.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+struct KindWriter {
+    dir: PathBuf,
+    files: Vec<String>,
+    current: Option<LogWriter<BufWriter<File>>>,
+    current_size: u64,
+    schema: Schema,
+}
+
+impl KindWriter {
+    fn write(&mut self, record: &[u8], entity: &Entity, max_file_size: u64) -> Result<()> {
+        if self.current.is_some() && self.current_size >= max_file_size {
+            self.close()?;
+        }
+
+        if self.current.is_none() {
+            let name = format!("output-{}", self.files.len());
+            let file = File::create(self.dir.join(&name))?;
+            self.current = Some(LogWriter::new(BufWriter::new(file)));
+            self.current_size = 0;
+            self.files.push(name);
+        }
+
+        if let Some(ref mut log) = self.current {
+            log.add_record(record)?;
+        }
+        self.current_size += record.len() as u64;
+        self.schema.observe(entity);
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(mut log) = self.current.take() {
+            log.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes entities to an export directory. Entities may be written in any order, they are grouped
+/// by kind. The export is only complete once `finish` has written the metadata files.
+pub struct ExportWriter {
+    dir: PathBuf,
+    name: String,
+    max_file_size: u64,
+    started: DateTime<Utc>,
+    kinds: BTreeMap<String, KindWriter>,
+}
+
+impl ExportWriter {
+    /// Creates the export directory. The export is named after the directory.
+    pub fn create<P: AsRef<Path>>(dir: P) -> Result<ExportWriter> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let name = dir.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("export")
+            .to_string();
+
+        Ok(ExportWriter {
+            dir,
+            name,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            started: Utc::now(),
+            kinds: BTreeMap::new(),
+        })
+    }
+
+    /// Sets the approximate size after which a new `output-N` file is started.
+    pub fn max_file_size(mut self, size: u64) -> ExportWriter {
+        self.max_file_size = size;
+        self
+    }
+
+    /// Appends an entity. The entity must have a complete key.
+    pub fn write(&mut self, entity: &Entity) -> Result<()> {
+        let record = encode_entity(entity)?;
+        let kind = entity.key.as_ref()
+            .and_then(|key| key.kind())
+            .ok_or(Error::InvalidEntity("entity key has no kind"))?;
+
+        if !self.kinds.contains_key(kind) {
+            let dir = self.dir.join("all_namespaces").join(format!("kind_{}", path_segment(kind)));
+            fs::create_dir_all(&dir)?;
+            self.kinds.insert(kind.to_string(), KindWriter {
+                dir,
+                files: vec![],
+                current: None,
+                current_size: 0,
+                schema: Schema::default(),
+            });
+        }
+
+        let max_file_size = self.max_file_size;
+        self.kinds.get_mut(kind)
+            .expect("kind writer was just inserted")
+            .write(&record, entity, max_file_size)
+    }
+
+    /// Closes all output files and writes the metadata files, returning the path of the overall
+    /// metadata file that is passed to `gcloud datastore import`.
+    pub fn finish(mut self) -> Result<PathBuf> {
+        let ended = Utc::now();
+        let mut kind_files = vec![];
+
+        for (kind, writer) in &mut self.kinds {
+            writer.close()?;
+
+            let segment = path_segment(kind);
+            let file_name = format!("all_namespaces_kind_{}.export_metadata", segment);
+            let bytes = metadata::encode_kind_metadata(
+                &self.name, &self.started, &ended, kind, &writer.files, &writer.schema);
+            fs::write(writer.dir.join(&file_name), bytes)?;
+
+            kind_files.push((kind.clone(), format!("all_namespaces/kind_{}/{}", segment, file_name)));
+        }
+
+        let path = self.dir.join(format!("{}.overall_export_metadata", self.name));
+        let bytes = metadata::encode_overall_metadata(&self.name, &self.started, &ended, &kind_files);
+        fs::write(&path, bytes)?;
+
+        Ok(path)
+    }
+}
+
+// Percent-encodes a kind for use in a file name. `%` is encoded as well, so that distinct kinds
+// never share a directory.
+fn path_segment(kind: &str) -> String {
+    let mut encoded = String::with_capacity(kind.len());
+    for &b in kind.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Writes all entities to a new export directory, see `ExportWriter`.
+pub fn write_export<P, I>(dir: P, entities: I) -> Result<PathBuf>
+    where P: AsRef<Path>,
+          I: IntoIterator<Item = Entity>
+{
+    let mut writer = ExportWriter::create(dir)?;
+    for entity in entities {
+        writer.write(&entity)?;
+    }
+    writer.finish()
+}
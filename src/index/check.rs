@@ -0,0 +1,161 @@
+// Working out the composite indexes that a query needs.
+//
+// The built-in indexes serve queries with only ancestor and equality filters, by merging the
+// indexes of the single properties, and queries that filter or order on a single property. Other
+// queries need a composite index that lists the properties of the equality filters first, in any
+// order, followed by the sort orders and the properties of inequality filters that are not sorted
+// on. Projected properties must be in the index as well. Queries with OR filters are run as one
+// query per conjunction of their disjunctive normal form, each of which may need its own index.
+//
+// Only exact matches count as covering a query. The server can sometimes serve equality filters
+// by merging several composite indexes, which is not considered here.
+
+use std::collections::BTreeSet;
+
+use crate::datastore::query::*;
+use crate::index::{Index, IndexedProperty};
+
+/// The composite index that a query needs.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RequiredIndex {
+    pub kind: String,
+    pub ancestor: bool,
+
+    /// Properties with equality filters, which come first in the index in any order.
+    pub equality: Vec<String>,
+
+    /// Properties that follow the equality properties, in this order.
+    pub order: Vec<IndexedProperty>,
+}
+
+impl RequiredIndex {
+    /// The index to declare for the query, with the equality properties sorted by name.
+    pub fn index(&self) -> Index {
+        let equality = self.equality.iter()
+            .map(|name| IndexedProperty { name: name.clone(), direction: Direction::Ascending });
+        Index {
+            kind: self.kind.clone(),
+            ancestor: self.ancestor,
+            properties: equality.chain(self.order.iter().cloned()).collect(),
+        }
+    }
+
+    /// Whether `index` can serve the query.
+    pub fn is_satisfied_by(&self, index: &Index) -> bool {
+        if index.kind != self.kind || index.ancestor != self.ancestor
+            || index.properties.len() != self.equality.len() + self.order.len() {
+            return false;
+        }
+
+        let (prefix, suffix) = index.properties.split_at(self.equality.len());
+        let mut names: Vec<&str> = prefix.iter().map(|property| property.name.as_str()).collect();
+        names.sort_unstable();
+        names.iter().eq(self.equality.iter()) && suffix == &self.order[..]
+    }
+}
+
+/// Returns the composite indexes that `query` needs, none if the built-in indexes serve it.
+pub fn required_indexes(query: &Query) -> Vec<RequiredIndex> {
+    let kind = match query.kind.first() {
+        Some(kind) => &kind.name,
+        // Kindless queries can only filter on keys, which the built-in indexes serve.
+        None => return vec![],
+    };
+
+    let mut required = vec![];
+    for conjunction in disjunctive_normal_form(query.filter.as_ref()) {
+        if let Some(index) = required_index(kind, query, &conjunction) {
+            if !required.contains(&index) {
+                required.push(index);
+            }
+        }
+    }
+    required
+}
+
+/// Returns the indexes that `query` needs but that are not among `defined`, e.g. the indexes of
+/// an `index.yaml` file.
+pub fn missing_indexes(query: &Query, defined: &[Index]) -> Vec<Index> {
+    required_indexes(query).iter()
+        .filter(|required| !defined.iter().any(|index| required.is_satisfied_by(index)))
+        .map(RequiredIndex::index)
+        .collect()
+}
+
+fn disjunctive_normal_form(filter: Option<&Filter>) -> Vec<Vec<&PropertyFilter>> {
+    let composite = match filter {
+        None => return vec![vec![]],
+        Some(Filter::PropertyFilter(filter)) => return vec![vec![filter]],
+        Some(Filter::CompositeFilter(composite)) => composite,
+    };
+
+    let parts = composite.filters.iter().map(|filter| disjunctive_normal_form(Some(filter)));
+    match composite.op {
+        CompositeOperator::Or => parts.flatten().collect(),
+        CompositeOperator::And => parts.fold(vec![vec![]], |conjunctions, part| {
+            conjunctions.iter()
+                .flat_map(|conjunction| part.iter().map(move |other| {
+                    conjunction.iter().chain(other.iter()).cloned().collect()
+                }))
+                .collect()
+        }),
+    }
+}
+
+fn required_index(kind: &str, query: &Query, filters: &[&PropertyFilter]) -> Option<RequiredIndex> {
+    let mut ancestor = false;
+    let mut equality = BTreeSet::new();
+    let mut inequality = BTreeSet::new();
+    for filter in filters {
+        let name = filter.property.name.as_str();
+        match filter.op {
+            PropertyOperator::HasAncestor => ancestor = true,
+            // Every index ends with the key, which serves filters on it.
+            _ if name == KEY_PROPERTY => (),
+            PropertyOperator::Equal | PropertyOperator::In => {
+                equality.insert(name);
+            }
+            _ => {
+                inequality.insert(name);
+            }
+        }
+    }
+
+    let mut order: Vec<IndexedProperty> = vec![];
+    let mut add = |name: &str, direction| {
+        if !equality.contains(name) && !order.iter().any(|property| property.name == name) {
+            order.push(IndexedProperty { name: name.to_string(), direction });
+        }
+    };
+    for property_order in &query.order {
+        add(&property_order.property.name, property_order.direction);
+    }
+    for name in &inequality {
+        add(name, Direction::Ascending);
+    }
+    let projected = query.projection.iter().map(|projection| &projection.property)
+        .chain(query.distinct_on.iter())
+        .filter(|property| property.name != KEY_PROPERTY);
+    for property in projected {
+        add(&property.name, Direction::Ascending);
+    }
+
+    // Results are ordered by key last anyway.
+    let by_key = |property: &IndexedProperty| {
+        property.name == KEY_PROPERTY && property.direction == Direction::Ascending
+    };
+    if order.last().is_some_and(by_key) {
+        order.pop();
+    }
+
+    let built_in = order.is_empty() || (!ancestor && equality.is_empty() && order.len() == 1);
+    if built_in {
+        return None;
+    }
+    Some(RequiredIndex {
+        kind: kind.to_string(),
+        ancestor,
+        equality: equality.into_iter().map(str::to_string).collect(),
+        order,
+    })
+}
@@ -0,0 +1,96 @@
+// Composite index definitions.
+//
+// Queries that filter or order on more than what the built-in single-property indexes serve need a
+// composite index, which is declared in an `index.yaml` file and deployed before the query runs.
+// Otherwise the query fails with `FAILED_PRECONDITION`. This module reads and writes `index.yaml`
+// files and works out which composite indexes a `Query` needs, so that missing indexes can be
+// caught before a deploy. `diff` compares definitions with the indexes on the server, which
+// `client::AdminClient` lists and creates.
+//
+// See https://cloud.google.com/datastore/docs/tools/indexconfig
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::datastore::query::Direction;
+
+mod check;
+mod sync;
+mod yaml;
+
+pub use self::check::{missing_indexes, required_indexes, RequiredIndex};
+pub use self::sync::{diff, IndexDiff};
+pub use self::yaml::{from_yaml, suggested_indexes, to_yaml};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Syntax { line: usize, message: String },
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(fmt, "I/O error: {}", err),
+            Error::Syntax { line, ref message } => write!(fmt, "invalid index.yaml, line {}: {}", line, message),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// A composite index on the entities of a kind.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Index {
+    pub kind: String,
+
+    /// Whether the index serves queries with an ancestor filter.
+    pub ancestor: bool,
+
+    pub properties: Vec<IndexedProperty>,
+}
+
+impl Index {
+    pub fn new<K: Into<String>>(kind: K) -> Index {
+        Index { kind: kind.into(), ancestor: false, properties: vec![] }
+    }
+
+    pub fn with_ancestor(mut self) -> Index {
+        self.ancestor = true;
+        self
+    }
+
+    pub fn with_property<N: Into<String>>(mut self, name: N, direction: Direction) -> Index {
+        self.properties.push(IndexedProperty { name: name.into(), direction });
+        self
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+pub struct IndexedProperty {
+    pub name: String,
+    pub direction: Direction,
+}
+
+/// Reads the indexes declared in an `index.yaml` file.
+pub fn read_index_yaml<P: AsRef<Path>>(path: P) -> Result<Vec<Index>> {
+    from_yaml(&fs::read_to_string(path)?)
+}
+
+/// Writes indexes to an `index.yaml` file, replacing its contents.
+pub fn write_index_yaml<P: AsRef<Path>>(path: P, indexes: &[Index]) -> Result<()> {
+    Ok(fs::write(path, to_yaml(indexes))?)
+}
@@ -0,0 +1,45 @@
+// Comparing local index definitions with the indexes on the server.
+
+use crate::datastore::admin::{self, IndexState};
+use crate::index::Index;
+
+/// The differences between local index definitions, e.g. of an `index.yaml` file, and the indexes
+/// on the server.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct IndexDiff {
+    /// Definitions without an index on the server, which have to be created.
+    pub missing: Vec<Index>,
+
+    /// Indexes on the server without a definition, which may be deleted.
+    pub unused: Vec<admin::Index>,
+
+    /// Indexes on the server with a definition, in any state but `DELETING`.
+    pub existing: Vec<admin::Index>,
+}
+
+impl IndexDiff {
+    /// Whether the server has exactly the defined indexes.
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unused.is_empty()
+    }
+}
+
+/// Compares local definitions with the indexes on the server. Indexes that are being deleted
+/// count as absent.
+pub fn diff(local: &[Index], remote: &[admin::Index]) -> IndexDiff {
+    let mut diff = IndexDiff::default();
+    for index in remote.iter().filter(|index| index.state != IndexState::Deleting) {
+        if local.contains(&index.definition()) {
+            diff.existing.push(index.clone());
+        } else {
+            diff.unused.push(index.clone());
+        }
+    }
+    for definition in local {
+        let exists = diff.existing.iter().any(|index| index.definition() == *definition);
+        if !exists && !diff.missing.contains(definition) {
+            diff.missing.push(definition.clone());
+        }
+    }
+    diff
+}
@@ -0,0 +1,204 @@
+use crate::datastore::*;
+use crate::datastore::query::*;
+use crate::index::*;
+
+const INDEX_YAML: &str = "# Indexes of the shop.
+indexes:
+
+- kind: Order
+  ancestor: no
+  properties:
+  - name: status
+  - name: created
+    direction: desc
+
+# Order lines by product.
+- kind: Line
+  ancestor: yes
+  properties:
+    - name: 'product: id'  # quoted
+    - name: \"qty\"
+      direction: asc
+";
+
+fn orders() -> Index {
+    Index::new("Order")
+        .with_property("status", Direction::Ascending)
+        .with_property("created", Direction::Descending)
+}
+
+fn lines() -> Index {
+    Index::new("Line")
+        .with_ancestor()
+        .with_property("product: id", Direction::Ascending)
+        .with_property("qty", Direction::Ascending)
+}
+
+#[test]
+fn test_parse_index_yaml() {
+    let indexes = from_yaml(INDEX_YAML).expect("Parsing failed");
+    assert_eq!(vec![orders(), lines()], indexes);
+
+    assert_eq!(Vec::<Index>::new(), from_yaml("").unwrap());
+    assert_eq!(Vec::<Index>::new(), from_yaml("# none yet\nindexes:\n").unwrap());
+    assert_eq!(Vec::<Index>::new(), from_yaml("indexes: []").unwrap());
+}
+
+#[test]
+fn test_parse_errors() {
+    let cases = [
+        ("indexes:\n- kind: A\n  ancestor: maybe\n", 3),
+        ("indexes:\n- kind: A\n  properties:\n  - direction: desc\n", 4),
+        ("indexes:\n- ancestor: yes\n", 2),
+        ("indexes:\n- kind: A\n   properties: []\n", 3),
+        ("indexes:\n- kind: A\n  kind: B\n", 3),
+        ("indices:\n- kind: A\n", 1),
+        ("indexes:\n- kind: 'A\n", 2),
+    ];
+    for &(yaml, line) in &cases {
+        match from_yaml(yaml) {
+            Err(Error::Syntax { line: actual, .. }) => assert_eq!(line, actual, "{}", yaml),
+            result => panic!("Expected a syntax error for {:?}, got {:?}", yaml, result),
+        }
+    }
+}
+
+#[test]
+fn test_write_index_yaml() {
+    let indexes = vec![orders(), lines(), Index::new("yes").with_ancestor()];
+    let yaml = to_yaml(&indexes);
+    assert!(yaml.starts_with("indexes:\n\n- kind: Order\n  properties:\n  - name: status\n"));
+    assert!(yaml.contains("  - name: 'product: id'\n"));
+    assert!(yaml.contains("- kind: 'yes'\n  ancestor: yes\n"));
+    assert_eq!(indexes, from_yaml(&yaml).unwrap());
+    assert_eq!(Vec::<Index>::new(), from_yaml(&to_yaml(&[])).unwrap());
+
+    let path = std::env::temp_dir().join(format!("index-{}.yaml", std::process::id()));
+    write_index_yaml(&path, &indexes).unwrap();
+    assert_eq!(indexes, read_index_yaml(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_suggested_indexes() {
+    let message = "no matching index found. recommended index is:\n\
+                   - kind: Order\n  properties:\n  - name: status\n  - name: created\n    direction: desc\n";
+    assert_eq!(vec![orders()], suggested_indexes(message));
+    assert_eq!(Vec::<Index>::new(), suggested_indexes("the query was too slow"));
+}
+
+#[test]
+fn test_built_in_indexes() {
+    let parent = Key::new(PartitionId::new("", ""), vec![PathElement::from_id("User", 1)]);
+    let queries = vec![
+        Query::new("Order"),
+        Query::new("Order").with_order("created", Direction::Descending),
+        Query::new("Order").with_filter(Filter::and(vec![
+            Filter::property("status", PropertyOperator::Equal, "open"),
+            Filter::property("region", PropertyOperator::In, vec![Value::from("eu")]),
+            Filter::has_ancestor(parent.clone()),
+        ])),
+        Query::new("Order")
+            .with_filter(Filter::property("total", PropertyOperator::GreaterThan, 10))
+            .with_order("total", Direction::Descending),
+        // Ordering on a property with an equality filter is a no-op.
+        Query::new("Order")
+            .with_filter(Filter::property("status", PropertyOperator::Equal, "open"))
+            .with_order("status", Direction::Ascending)
+            .with_key_order(Direction::Ascending),
+        Query::new("Order")
+            .with_filter(Filter::and(vec![
+                Filter::property("status", PropertyOperator::Equal, "open"),
+                Filter::key(PropertyOperator::GreaterThan, parent),
+            ]))
+            .keys_only(),
+        Query::new("Order").project(&["total"]),
+        Query::default(),
+    ];
+    for query in &queries {
+        assert_eq!(Vec::<RequiredIndex>::new(), required_indexes(query), "{:?}", query);
+    }
+}
+
+#[test]
+fn test_required_indexes() {
+    let query = Query::new("Order")
+        .with_filter(Filter::property("status", PropertyOperator::Equal, "open"))
+        .with_order("created", Direction::Descending);
+    let required = required_indexes(&query);
+    assert_eq!(1, required.len());
+    assert_eq!(orders(), required[0].index());
+    assert!(required[0].is_satisfied_by(&orders()));
+    assert!(!required[0].is_satisfied_by(&orders().with_ancestor()));
+    assert!(!required[0].is_satisfied_by(&orders().with_property("total", Direction::Ascending)));
+
+    // Equality properties may come in any order, inequality properties follow the sort orders.
+    let query = Query::new("Order")
+        .with_filter(Filter::and(vec![
+            Filter::property("status", PropertyOperator::Equal, "open"),
+            Filter::property("region", PropertyOperator::Equal, "eu"),
+            Filter::property("total", PropertyOperator::LessThan, 100),
+        ]))
+        .with_order("created", Direction::Descending);
+    let expected = Index::new("Order")
+        .with_property("region", Direction::Ascending)
+        .with_property("status", Direction::Ascending)
+        .with_property("created", Direction::Descending)
+        .with_property("total", Direction::Ascending);
+    assert_eq!(vec![expected.clone()], missing_indexes(&query, &[orders()]));
+    let swapped = Index::new("Order")
+        .with_property("status", Direction::Descending)
+        .with_property("region", Direction::Ascending)
+        .with_property("created", Direction::Descending)
+        .with_property("total", Direction::Ascending);
+    assert_eq!(Vec::<Index>::new(), missing_indexes(&query, &[orders(), swapped]));
+
+    // Ancestor queries with an order and projections of several properties.
+    let parent = Key::new(PartitionId::new("", ""), vec![PathElement::from_id("User", 1)]);
+    let query = Query::new("Order")
+        .with_filter(Filter::has_ancestor(parent))
+        .with_order("created", Direction::Ascending);
+    let expected = Index::new("Order").with_ancestor().with_property("created", Direction::Ascending);
+    assert_eq!(vec![expected], missing_indexes(&query, &[]));
+
+    let query = Query::new("Order").project(&["status", "total"]).distinct_on(&["status"]);
+    let expected = Index::new("Order")
+        .with_property("status", Direction::Ascending)
+        .with_property("total", Direction::Ascending);
+    assert_eq!(vec![expected], missing_indexes(&query, &[]));
+}
+
+#[test]
+fn test_or_queries() {
+    // Each conjunction needs its own index, the first one is built in.
+    let query = Query::new("Order")
+        .with_filter(Filter::and(vec![
+            Filter::property("status", PropertyOperator::Equal, "open"),
+            Filter::or(vec![
+                Filter::property("region", PropertyOperator::Equal, "eu"),
+                Filter::property("total", PropertyOperator::GreaterThan, 100),
+            ]),
+        ]));
+    let expected = Index::new("Order")
+        .with_property("status", Direction::Ascending)
+        .with_property("total", Direction::Ascending);
+    assert_eq!(vec![expected.clone()], missing_indexes(&query, &[orders()]));
+    assert_eq!(Vec::<Index>::new(), missing_indexes(&query, &[expected]));
+}
+
+#[test]
+fn test_diff() {
+    use crate::datastore::admin::{self, IndexState};
+
+    let remote = |definition: &Index, state| admin::Index { state, ..admin::Index::new(definition) };
+    let other = Index::new("Order").with_property("total", Direction::Ascending);
+    let diff = diff(&[orders(), lines(), lines()], &[
+        remote(&orders(), IndexState::Ready),
+        remote(&lines(), IndexState::Deleting),
+        remote(&other, IndexState::Creating),
+    ]);
+    assert_eq!(vec![lines()], diff.missing);
+    assert_eq!(vec![other], diff.unused.iter().map(admin::Index::definition).collect::<Vec<_>>());
+    assert_eq!(vec![orders()], diff.existing.iter().map(admin::Index::definition).collect::<Vec<_>>());
+    assert!(!diff.is_empty());
+}
@@ -0,0 +1,348 @@
+// Reading and writing `index.yaml` files.
+//
+// The parser understands the subset of YAML that index files use: block mappings and sequences,
+// plain and quoted scalars, comments and empty flow collections. Sequences may be indented at the
+// level of their key, as in the files generated by the SDK tools.
+
+use crate::datastore::query::Direction;
+use crate::index::{Error, Index, IndexedProperty, Result};
+
+#[derive(Debug)]
+enum Node {
+    Null,
+    Scalar(String),
+    Sequence(Vec<(usize, Node)>),
+    Mapping(Vec<(usize, String, Node)>),
+}
+
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+fn syntax<M: Into<String>>(line: usize, message: M) -> Error {
+    Error::Syntax { line, message: message.into() }
+}
+
+/// Parses the contents of an `index.yaml` file. An empty file declares no indexes.
+pub fn from_yaml(text: &str) -> Result<Vec<Index>> {
+    let entries = match parse(text)? {
+        Node::Null => return Ok(vec![]),
+        Node::Mapping(entries) => entries,
+        _ => return Err(syntax(1, "expected a mapping with `indexes`")),
+    };
+
+    let mut indexes = vec![];
+    for (line, key, node) in entries {
+        match key.as_str() {
+            "indexes" => match node {
+                Node::Null => (),
+                Node::Sequence(items) => for (line, node) in items {
+                    indexes.push(index(line, node)?);
+                },
+                _ => return Err(syntax(line, "`indexes` must be a list")),
+            },
+            _ => return Err(syntax(line, format!("unknown key `{}`", key))),
+        }
+    }
+    Ok(indexes)
+}
+
+/// Extracts the indexes that the server recommends in the message of a `FAILED_PRECONDITION`
+/// error for a query without a matching index.
+pub fn suggested_indexes(message: &str) -> Vec<Index> {
+    match message.find("- kind:") {
+        Some(start) => from_yaml(&format!("indexes:\n{}", &message[start..])).unwrap_or_default(),
+        None => vec![],
+    }
+}
+
+/// Formats indexes as the contents of an `index.yaml` file.
+pub fn to_yaml(indexes: &[Index]) -> String {
+    if indexes.is_empty() {
+        return "indexes: []\n".to_string();
+    }
+
+    let mut out = String::from("indexes:\n");
+    for index in indexes {
+        out.push_str(&format!("\n- kind: {}\n", quote(&index.kind)));
+        if index.ancestor {
+            out.push_str("  ancestor: yes\n");
+        }
+        if !index.properties.is_empty() {
+            out.push_str("  properties:\n");
+        }
+        for property in &index.properties {
+            out.push_str(&format!("  - name: {}\n", quote(&property.name)));
+            if property.direction == Direction::Descending {
+                out.push_str("    direction: desc\n");
+            }
+        }
+    }
+    out
+}
+
+fn index(line: usize, node: Node) -> Result<Index> {
+    let entries = match node {
+        Node::Mapping(entries) => entries,
+        _ => return Err(syntax(line, "an index must be a mapping")),
+    };
+
+    let mut kind = None;
+    let mut index = Index::new("");
+    for (line, key, node) in entries {
+        match key.as_str() {
+            "kind" => kind = Some(scalar(line, node, "kind")?),
+            "ancestor" => index.ancestor = match scalar(line, node, "ancestor")?.to_lowercase().as_str() {
+                "yes" | "true" => true,
+                "no" | "false" => false,
+                value => return Err(syntax(line, format!("invalid ancestor `{}`, expected yes or no", value))),
+            },
+            "properties" => match node {
+                Node::Null => (),
+                Node::Sequence(items) => for (line, node) in items {
+                    index.properties.push(property(line, node)?);
+                },
+                _ => return Err(syntax(line, "`properties` must be a list")),
+            },
+            _ => return Err(syntax(line, format!("unknown key `{}` in index", key))),
+        }
+    }
+    index.kind = kind.ok_or_else(|| syntax(line, "index without kind"))?;
+    Ok(index)
+}
+
+fn property(line: usize, node: Node) -> Result<IndexedProperty> {
+    let entries = match node {
+        Node::Mapping(entries) => entries,
+        _ => return Err(syntax(line, "a property must be a mapping")),
+    };
+
+    let mut name = None;
+    let mut direction = Direction::Ascending;
+    for (line, key, node) in entries {
+        match key.as_str() {
+            "name" => name = Some(scalar(line, node, "name")?),
+            "direction" => direction = match scalar(line, node, "direction")?.as_str() {
+                "asc" | "ascending" => Direction::Ascending,
+                "desc" | "descending" => Direction::Descending,
+                value => return Err(syntax(line, format!("invalid direction `{}`, expected asc or desc", value))),
+            },
+            _ => return Err(syntax(line, format!("unknown key `{}` in property", key))),
+        }
+    }
+    let name = name.ok_or_else(|| syntax(line, "property without name"))?;
+    Ok(IndexedProperty { name, direction })
+}
+
+fn scalar(line: usize, node: Node, key: &str) -> Result<String> {
+    match node {
+        Node::Scalar(value) if !value.is_empty() => Ok(value),
+        _ => Err(syntax(line, format!("`{}` must be a non-empty string", key))),
+    }
+}
+
+// Property and kind names are written unquoted where YAML reads them back as the same string.
+fn quote(value: &str) -> String {
+    let plain = value.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || "_.-/".contains(c))
+        && !["yes", "no", "true", "false", "null", "on", "off"].contains(&value.to_lowercase().as_str());
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "''"))
+    }
+}
+
+fn parse(text: &str) -> Result<Node> {
+    let mut lines = vec![];
+    for (i, raw) in text.lines().enumerate() {
+        let number = i + 1;
+        let content = strip_comment(raw).trim_end();
+        let text = content.trim_start_matches(' ');
+        if text.starts_with('\t') {
+            return Err(syntax(number, "tabs are not allowed for indentation"));
+        }
+        if text.is_empty() || (lines.is_empty() && text == "---") {
+            continue;
+        }
+        lines.push(Line { number, indent: content.len() - text.len(), text: text.to_string() });
+    }
+
+    let mut parser = Parser { lines, pos: 0 };
+    let root = match parser.lines.first() {
+        Some(line) => {
+            let indent = line.indent;
+            parser.node(indent)?
+        }
+        None => Node::Null,
+    };
+    match parser.peek() {
+        Some(line) => Err(syntax(line.number, "unexpected content")),
+        None => Ok(root),
+    }
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Line> {
+        self.lines.get(self.pos)
+    }
+
+    // Parses the node that starts at the current line, which is indented by `indent`.
+    fn node(&mut self, indent: usize) -> Result<Node> {
+        let line = &self.lines[self.pos];
+        if is_item(&line.text) {
+            self.sequence(indent)
+        } else if split_key(&line.text).is_some() {
+            self.mapping(indent)
+        } else {
+            let node = Node::Scalar(unquote(line.number, &line.text)?);
+            self.pos += 1;
+            Ok(node)
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Node> {
+        let mut items = vec![];
+        while let Some(line) = self.peek() {
+            if line.indent < indent || !is_item(&line.text) {
+                break;
+            }
+            if line.indent > indent {
+                return Err(syntax(line.number, "unexpected indentation"));
+            }
+
+            let number = line.number;
+            let rest = &line.text[1..];
+            let text = rest.trim_start();
+            let item = if text.is_empty() {
+                self.pos += 1;
+                self.value(indent, false)?
+            } else {
+                // The item's content continues at the column after the dash.
+                let indent = indent + 1 + rest.len() - text.len();
+                self.lines[self.pos] = Line { number, indent, text: text.to_string() };
+                self.node(indent)?
+            };
+            items.push((number, item));
+        }
+        Ok(Node::Sequence(items))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Node> {
+        let mut entries: Vec<(usize, String, Node)> = vec![];
+        while let Some(line) = self.peek() {
+            if line.indent < indent || is_item(&line.text) {
+                break;
+            }
+            if line.indent > indent {
+                return Err(syntax(line.number, "unexpected indentation"));
+            }
+
+            let number = line.number;
+            let (key, value) = split_key(&line.text)
+                .ok_or_else(|| syntax(number, "expected `key: value`"))?;
+            let key = unquote(number, key)?;
+            let value = value.to_string();
+            if entries.iter().any(|entry| entry.1 == key) {
+                return Err(syntax(number, format!("duplicate key `{}`", key)));
+            }
+
+            self.pos += 1;
+            let node = match value.as_str() {
+                "" => self.value(indent, true)?,
+                "[]" => Node::Sequence(vec![]),
+                "{}" => Node::Mapping(vec![]),
+                _ => Node::Scalar(unquote(number, &value)?),
+            };
+            entries.push((number, key, node));
+        }
+        Ok(Node::Mapping(entries))
+    }
+
+    // Parses the value of a key or sequence item that continues on the next line. Sequences that
+    // are values of keys may be indented at the level of the key.
+    fn value(&mut self, indent: usize, sequence_at_indent: bool) -> Result<Node> {
+        match self.peek() {
+            Some(line) if line.indent > indent => {
+                let indent = line.indent;
+                self.node(indent)
+            }
+            Some(line) if sequence_at_indent && line.indent == indent && is_item(&line.text) =>
+                self.sequence(indent),
+            _ => Ok(Node::Null),
+        }
+    }
+}
+
+fn is_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+// Removes a comment, which starts with `#` at the beginning of the line or after whitespace,
+// outside of quoted scalars.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if (c == '\'' || c == '"') && " -:[,".contains(previous) => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &line[..i],
+            None => (),
+        }
+        previous = c;
+    }
+    line
+}
+
+// Splits `key: value` at the first colon outside of quotes that is followed by a space or ends
+// the line.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if i == 0 && (c == '\'' || c == '"') => quote = Some(c),
+            None if c == ':' && text[i + 1..].chars().next().is_none_or(|next| next == ' ') =>
+                return Some((text[..i].trim_end(), text[i + 1..].trim())),
+            None => (),
+        }
+    }
+    None
+}
+
+fn unquote(line: usize, text: &str) -> Result<String> {
+    let unterminated = || syntax(line, format!("unterminated string {}", text));
+    if let Some(inner) = text.strip_prefix('\'') {
+        let inner = inner.strip_suffix('\'').ok_or_else(unterminated)?;
+        return Ok(inner.replace("''", "'"));
+    }
+    if let Some(inner) = text.strip_prefix('"') {
+        let inner = inner.strip_suffix('"').ok_or_else(unterminated)?;
+        let mut value = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c @ ('\\' | '"' | '/')) => value.push(c),
+                _ => return Err(syntax(line, format!("invalid escape in {}", text))),
+            }
+        }
+        return Ok(value);
+    }
+    Ok(text.to_string())
+}
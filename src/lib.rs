@@ -6,9 +6,11 @@ extern crate serde_derive;
 extern crate maplit;
 
 extern crate serde;
+#[cfg_attr(test, macro_use)]
 extern crate serde_json;
 extern crate base64;
 extern crate chrono;
+extern crate datastore_derive;
 
 #[cfg(test)]
 extern crate serde_bytes;
@@ -16,3 +18,11 @@ extern crate serde_bytes;
 // TODO: Rename -> api
 pub mod datastore;
 pub mod serde_ds;
+pub mod proto;
+pub mod export;
+pub mod client;
+pub mod entity;
+pub mod index;
+pub mod schema;
+pub mod diff;
+pub mod migrate;
@@ -0,0 +1,391 @@
+// Versioned migrations of the entities of a kind.
+//
+// A `Migration` transforms every entity of a kind once, e.g. to backfill a property or to change
+// the type of one. Migrations of a kind are numbered by version and run in order. The `Migrator`
+// pages through the kind with cursors and writes the transformed entities of each page either in
+// a transaction, which reads the entities again so that concurrent writes are not lost, or as
+// plain upserts in one commit per page.
+//
+// The applied version of every kind and the progress of the migration that is running are kept
+// in one entity per kind of the `__migrations__` kind, named after the migrated kind, in the
+// namespace of the client. The progress is written in the same commit as the page it belongs to,
+// so a run that crashed resumes after the last page that was written. Production reserves kind
+// names that start and end with `__` for its own use, use `Migrator::with_record_kind` there.
+//
+// A dry run writes nothing and reports the diffs of the entities that the pending migrations
+// would change instead.
+
+use std::collections::BTreeMap;
+
+use crate::client::{Client, Code, Error, Result};
+use crate::datastore::{Blob, Entity, Key, PartitionId, PathElement, Value};
+use crate::datastore::query::{MoreResultsType, Query};
+use crate::datastore::rpc::{CommitMode, CommitRequest, Mutation, RunQueryRequest};
+use crate::diff::{self, Diff};
+
+#[cfg(test)]
+mod tests;
+
+/// The kind of the entities that record the migrations.
+pub const MIGRATIONS_KIND: &str = "__migrations__";
+
+/// A transformation of the entities of a kind, which returns `None` for entities that stay as
+/// they are. The key of the returned entity is ignored.
+pub type Transform = fn(Entity) -> Option<Entity>;
+
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub kind: String,
+    pub version: i64,
+    pub transform: Transform,
+}
+
+impl Migration {
+    pub fn new<K: Into<String>>(kind: K, version: i64, transform: Transform) -> Migration {
+        Migration { kind: kind.into(), version, transform }
+    }
+
+    // Applies the transform, returning the new entity if it differs.
+    fn apply(&self, entity: &Entity) -> Option<Entity> {
+        let mut transformed = (self.transform)(entity.clone())?;
+        transformed.key = entity.key.clone();
+        if transformed == *entity {
+            return None;
+        }
+        Some(transformed)
+    }
+}
+
+/// How the transformed entities are written.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Mode {
+    /// Upserts the changed entities of a page in one non-transactional commit.
+    #[default]
+    Batched,
+
+    /// Reads the changed entities of a page again in a transaction, transforms them and commits.
+    Transactional,
+}
+
+/// The state of the migrations of a kind, as recorded in the `__migrations__` kind.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct MigrationRecord {
+    /// The version of the last migration that completed, 0 if none did.
+    pub version: i64,
+
+    /// The version of the migration that is running or crashed.
+    pub running: Option<i64>,
+
+    /// The cursor after the last page that the running migration wrote.
+    pub cursor: Option<Blob>,
+
+    /// Entities read and changed by the running migration so far.
+    pub processed: i64,
+    pub changed: i64,
+}
+
+impl MigrationRecord {
+    fn from_entity(entity: &Entity) -> MigrationRecord {
+        let integer = |name: &str| match entity.properties.get(name) {
+            Some(Value::Integer { integer_value, .. }) => integer_value.parse().ok(),
+            _ => None,
+        };
+        MigrationRecord {
+            version: integer("version").unwrap_or(0),
+            running: integer("running"),
+            cursor: match entity.properties.get("cursor") {
+                Some(Value::Blob { blob_value, .. }) => Some(blob_value.clone()),
+                _ => None,
+            },
+            processed: integer("processed").unwrap_or(0),
+            changed: integer("changed").unwrap_or(0),
+        }
+    }
+
+    fn to_entity(&self, key: Key) -> Entity {
+        let mut properties = vec![
+            ("version", Value::from(self.version)),
+            ("processed", Value::from(self.processed)),
+            ("changed", Value::from(self.changed)),
+        ];
+        if let Some(running) = self.running {
+            properties.push(("running", Value::from(running)));
+        }
+        if let Some(ref cursor) = self.cursor {
+            // Cursors may be longer than indexed values can be.
+            properties.push(("cursor", Value::from(cursor.clone()).unindexed()));
+        }
+        Entity {
+            key: Some(key),
+            properties: properties.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        }
+    }
+}
+
+/// What a migration did, or would do in a dry run.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MigrationReport {
+    pub kind: String,
+    pub version: i64,
+
+    /// Entities read and changed, including those of an earlier run that was resumed.
+    pub processed: i64,
+    pub changed: i64,
+
+    /// The changes of every changed entity, only collected by dry runs.
+    pub diffs: Vec<(Key, Diff)>,
+}
+
+/// Runs the migrations that were not applied yet.
+pub struct Migrator {
+    client: Client,
+    migrations: Vec<Migration>,
+    mode: Mode,
+    page_size: i32,
+    record_kind: String,
+}
+
+impl Migrator {
+    pub fn new(client: Client) -> Migrator {
+        Migrator {
+            client,
+            migrations: vec![],
+            mode: Mode::default(),
+            page_size: 200,
+            record_kind: MIGRATIONS_KIND.to_string(),
+        }
+    }
+
+    pub fn with_migration(mut self, migration: Migration) -> Migrator {
+        self.migrations.push(migration);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> Migrator {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the number of entities per page, each of which is written in one commit together with
+    /// the progress. Commits are limited to 500 mutations.
+    pub fn with_page_size(mut self, page_size: i32) -> Migrator {
+        self.page_size = page_size.clamp(1, 499);
+        self
+    }
+
+    /// Records the migrations in another kind than `__migrations__`.
+    pub fn with_record_kind<K: Into<String>>(mut self, kind: K) -> Migrator {
+        self.record_kind = kind.into();
+        self
+    }
+
+    /// Reads the recorded state of the migrations of a kind.
+    pub async fn record(&self, kind: &str) -> Result<MigrationRecord> {
+        Ok(self.client.get_entity(&self.record_key(kind)).await?
+            .map(|entity| MigrationRecord::from_entity(&entity))
+            .unwrap_or_default())
+    }
+
+    /// The migrations whose version is above the applied version of their kind, in the order in
+    /// which they run.
+    pub async fn pending(&self) -> Result<Vec<&Migration>> {
+        let mut pending = vec![];
+        for (kind, migrations) in self.by_kind() {
+            let applied = self.record(kind).await?.version;
+            pending.extend(migrations.into_iter().filter(|migration| migration.version > applied));
+        }
+        Ok(pending)
+    }
+
+    /// Runs the pending migrations, resuming a migration that was interrupted from its last page.
+    pub async fn run(&self) -> Result<Vec<MigrationReport>> {
+        let mut reports = vec![];
+        for migration in self.pending().await? {
+            reports.push(self.migrate(migration).await?);
+        }
+        Ok(reports)
+    }
+
+    /// Reports what the pending migrations would change, without writing anything. The migrations
+    /// of a kind are applied one after the other to every entity, so that each one sees the
+    /// output of the ones before it.
+    pub async fn dry_run(&self) -> Result<Vec<MigrationReport>> {
+        let pending = self.pending().await?;
+        let mut reports: Vec<MigrationReport> = pending.iter()
+            .map(|migration| MigrationReport {
+                kind: migration.kind.clone(),
+                version: migration.version,
+                processed: 0,
+                changed: 0,
+                diffs: vec![],
+            })
+            .collect();
+
+        let mut kinds: Vec<&str> = pending.iter().map(|migration| migration.kind.as_str()).collect();
+        kinds.dedup();
+        for kind in kinds {
+            let mut cursor = None;
+            loop {
+                let page = self.page(kind, cursor).await?;
+                for entity in page.entities {
+                    let mut current = entity;
+                    for (migration, report) in pending.iter().zip(reports.iter_mut()) {
+                        if migration.kind != kind {
+                            continue;
+                        }
+                        report.processed += 1;
+                        if let Some(transformed) = migration.apply(&current) {
+                            report.changed += 1;
+                            let key = current.key.clone().expect("query result without key");
+                            report.diffs.push((key, diff::diff(&current, &transformed)));
+                            current = transformed;
+                        }
+                    }
+                }
+                if page.last {
+                    break;
+                }
+                cursor = Some(page.cursor);
+            }
+        }
+        Ok(reports)
+    }
+
+    fn by_kind(&self) -> BTreeMap<&str, Vec<&Migration>> {
+        let mut by_kind: BTreeMap<&str, Vec<&Migration>> = BTreeMap::new();
+        for migration in &self.migrations {
+            by_kind.entry(&migration.kind).or_default().push(migration);
+        }
+        for migrations in by_kind.values_mut() {
+            migrations.sort_by_key(|migration| migration.version);
+        }
+        by_kind
+    }
+
+    fn record_key(&self, kind: &str) -> Key {
+        let namespace = self.client.namespace_id().unwrap_or("");
+        Key::new(PartitionId::new("", namespace), vec![PathElement::from_name(self.record_kind.as_str(), kind)])
+    }
+
+    async fn migrate(&self, migration: &Migration) -> Result<MigrationReport> {
+        let key = self.record_key(&migration.kind);
+        let mut record = self.record(&migration.kind).await?;
+        if record.running != Some(migration.version) {
+            record = MigrationRecord { version: record.version, running: Some(migration.version), ..MigrationRecord::default() };
+        }
+
+        loop {
+            let page = self.page(&migration.kind, record.cursor.clone()).await?;
+            if page.entities.is_empty() {
+                if page.last {
+                    break;
+                }
+                // Nothing to write, the cursor is saved with the next page.
+                record.cursor = Some(page.cursor);
+                continue;
+            }
+            record.processed += page.entities.len() as i64;
+            record.cursor = Some(page.cursor);
+            match self.mode {
+                Mode::Batched => {
+                    let mut mutations: Vec<Mutation> = page.entities.iter()
+                        .filter_map(|entity| migration.apply(entity))
+                        .map(Mutation::Upsert)
+                        .collect();
+                    record.changed += mutations.len() as i64;
+                    mutations.push(Mutation::Upsert(record.to_entity(key.clone())));
+                    self.client.commit(CommitRequest {
+                        project_id: String::new(),
+                        mode: CommitMode::NonTransactional,
+                        transaction: None,
+                        mutations,
+                    }).await?;
+                }
+                Mode::Transactional => {
+                    let keys: Vec<Key> = page.entities.iter()
+                        .filter(|entity| migration.apply(entity).is_some())
+                        .filter_map(|entity| entity.key.clone())
+                        .collect();
+                    // Aborted transactions are not retried by the client, they have to be run
+                    // again as a whole.
+                    let policy = self.client.retry_policy();
+                    let mut attempts = 1;
+                    record = loop {
+                        let err = match self.commit_page(migration, &keys, &record, &key).await {
+                            Ok(record) => break record,
+                            Err(err) => err,
+                        };
+                        if err.code() != Some(Code::Aborted) || attempts >= policy.max_attempts() {
+                            return Err(err);
+                        }
+                        policy.pause(attempts).await;
+                        attempts += 1;
+                    };
+                }
+            }
+            if page.last {
+                break;
+            }
+        }
+
+        let report = MigrationReport {
+            kind: migration.kind.clone(),
+            version: migration.version,
+            processed: record.processed,
+            changed: record.changed,
+            diffs: vec![],
+        };
+        let done = MigrationRecord { version: migration.version, ..MigrationRecord::default() };
+        self.client.put_entity(done.to_entity(key)).await?;
+        Ok(report)
+    }
+
+    // Reads the entities with the given keys in a transaction, transforms them again and commits
+    // them together with the progress, which is returned.
+    async fn commit_page(&self, migration: &Migration, keys: &[Key], record: &MigrationRecord, key: &Key)
+                         -> Result<MigrationRecord> {
+        let mut record = record.clone();
+        let mut transaction = self.client.transaction().await?;
+        let current = if keys.is_empty() { vec![] } else { transaction.get_entities(keys).await? };
+        for entity in current.into_iter().flatten() {
+            if let Some(transformed) = migration.apply(&entity) {
+                record.changed += 1;
+                transaction.upsert(transformed);
+            }
+        }
+        transaction.upsert(record.to_entity(key.clone()));
+        transaction.commit().await?;
+        Ok(record)
+    }
+
+    // Fetches the page of a kind that starts at `cursor`. Pages may be empty without being the
+    // last one, when the query stopped after scanning many entities.
+    async fn page(&self, kind: &str, cursor: Option<Blob>) -> Result<Page> {
+        let mut query = Query::new(kind).with_limit(self.page_size);
+        query.start_cursor = cursor;
+        let request = RunQueryRequest { query: Some(query), ..RunQueryRequest::default() };
+        let batch = self.client.run_query(request).await?.batch;
+        let last = match batch.more_results {
+            MoreResultsType::NoMoreResults => true,
+            MoreResultsType::MoreResultsAfterLimit => batch.entity_results.len() < self.page_size as usize,
+            _ => false,
+        };
+        let cursor = match batch.end_cursor {
+            Some(cursor) => cursor,
+            None if last => Blob(vec![]),
+            None => return Err(Error::UnexpectedResponse("unfinished query without cursor".to_string())),
+        };
+        let entities = batch.entity_results.into_iter().map(|result| result.entity).collect();
+        Ok(Page { entities, cursor, last })
+    }
+}
+
+struct Page {
+    entities: Vec<Entity>,
+
+    /// The cursor after the page.
+    cursor: Blob,
+
+    /// Whether no more entities follow.
+    last: bool,
+}
@@ -0,0 +1,176 @@
+use crate::client::*;
+use crate::datastore::*;
+use crate::migrate::*;
+
+fn user_key(id: i64) -> Key {
+    Key::new(PartitionId::new("", ""), vec![PathElement::from_id("User", id)])
+}
+
+async fn client_with_users(count: i64) -> Client {
+    client_with_users_in(MemoryBackend::new(), count).await
+}
+
+async fn client_with_users_in(backend: MemoryBackend, count: i64) -> Client {
+    let client = Client::new("test-project", backend);
+    for id in 1..=count {
+        let mut user = Entity { key: Some(user_key(id)), properties: Default::default() };
+        user.properties.insert("name".to_string(), Value::from(format!("user-{}", id)));
+        if id % 2 == 0 {
+            user.properties.insert("active".to_string(), Value::from(false));
+        }
+        client.put_entity(user).await.unwrap();
+    }
+    client
+}
+
+// Version 1 backfills `active`, version 2 renames `name` to `full_name`.
+fn backfill_active(mut entity: Entity) -> Option<Entity> {
+    if entity.properties.contains_key("active") {
+        return None;
+    }
+    entity.properties.insert("active".to_string(), Value::from(true));
+    Some(entity)
+}
+
+fn rename_name(mut entity: Entity) -> Option<Entity> {
+    let name = entity.properties.remove("name")?;
+    entity.properties.insert("full_name".to_string(), name);
+    Some(entity)
+}
+
+// Counts how often every entity was transformed, and crashes on entities marked as poisoned.
+fn count_visit(mut entity: Entity) -> Option<Entity> {
+    assert!(!entity.properties.contains_key("poisoned"), "poisoned entity");
+    let visits = match entity.properties.get("visits") {
+        Some(Value::Integer { integer_value, .. }) => integer_value.parse::<i64>().unwrap(),
+        _ => 0,
+    };
+    entity.properties.insert("visits".to_string(), Value::from(visits + 1));
+    Some(entity)
+}
+
+fn migrator(client: &Client) -> Migrator {
+    Migrator::new(client.clone())
+        .with_page_size(2)
+        .with_migration(Migration::new("User", 2, rename_name))
+        .with_migration(Migration::new("User", 1, backfill_active))
+        .with_migration(Migration::new("Order", 1, backfill_active))
+}
+
+#[tokio::test]
+async fn test_run() {
+    for mode in [Mode::Batched, Mode::Transactional] {
+        let client = client_with_users(5).await;
+        let migrator = migrator(&client).with_mode(mode);
+        let pending: Vec<(&str, i64)> = migrator.pending().await.unwrap().iter()
+            .map(|migration| (migration.kind.as_str(), migration.version))
+            .collect();
+        assert_eq!(vec![("Order", 1), ("User", 1), ("User", 2)], pending);
+
+        let reports = migrator.run().await.unwrap();
+        let counts: Vec<(i64, i64)> = reports.iter().map(|report| (report.processed, report.changed)).collect();
+        assert_eq!(vec![(0, 0), (5, 3), (5, 5)], counts, "{:?}", mode);
+
+        let user = client.get_entity(&user_key(1)).await.unwrap().unwrap();
+        assert_eq!(Some(&Value::from(true)), user.properties.get("active"));
+        assert_eq!(Some(&Value::from("user-1")), user.properties.get("full_name"));
+        assert!(!user.properties.contains_key("name"));
+        let user = client.get_entity(&user_key(2)).await.unwrap().unwrap();
+        assert_eq!(Some(&Value::from(false)), user.properties.get("active"));
+
+        let record = migrator.record("User").await.unwrap();
+        assert_eq!(MigrationRecord { version: 2, ..MigrationRecord::default() }, record);
+        assert!(migrator.pending().await.unwrap().is_empty());
+        assert!(migrator.run().await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_resume() {
+    let client = client_with_users(7).await;
+    let mut poisoned = client.get_entity(&user_key(4)).await.unwrap().unwrap();
+    poisoned.properties.insert("poisoned".to_string(), Value::from(true));
+    client.put_entity(poisoned.clone()).await.unwrap();
+
+    // The run crashes in the second page, after the first one was written.
+    let migrator = Migrator::new(client.clone()).with_page_size(2).with_record_kind("Migration")
+        .with_migration(Migration::new("User", 1, count_visit));
+    let crashed = tokio::spawn(async move { migrator.run().await });
+    assert!(crashed.await.is_err());
+
+    let migrator = Migrator::new(client.clone()).with_page_size(2).with_record_kind("Migration")
+        .with_migration(Migration::new("User", 1, count_visit));
+    let record = migrator.record("User").await.unwrap();
+    assert_eq!((0, Some(1), 2, 2), (record.version, record.running, record.processed, record.changed));
+    assert!(record.cursor.is_some());
+    assert!(client.get_entity(&Key::new(PartitionId::new("", ""), vec![PathElement::from_name(MIGRATIONS_KIND, "User")]))
+        .await.unwrap().is_none());
+
+    poisoned.properties.remove("poisoned");
+    client.put_entity(poisoned).await.unwrap();
+    let reports = migrator.run().await.unwrap();
+    assert_eq!((7, 7), (reports[0].processed, reports[0].changed));
+    for id in 1..=7 {
+        let user = client.get_entity(&user_key(id)).await.unwrap().unwrap();
+        assert_eq!(Some(&Value::from(1)), user.properties.get("visits"), "User {} was transformed again", id);
+    }
+    assert_eq!(1, migrator.record("User").await.unwrap().version);
+}
+
+#[tokio::test]
+async fn test_empty_batches() {
+    // The first page of every run comes back empty but unfinished.
+    let client = client_with_users_in(MemoryBackend::new().with_empty_batches(2), 5).await;
+    let migrator = Migrator::new(client.clone()).with_page_size(2)
+        .with_migration(Migration::new("User", 1, backfill_active));
+
+    let reports = migrator.dry_run().await.unwrap();
+    assert_eq!((5, 3), (reports[0].processed, reports[0].changed));
+
+    let reports = migrator.run().await.unwrap();
+    assert_eq!((5, 3), (reports[0].processed, reports[0].changed));
+    for id in 1..=5 {
+        let user = client.get_entity(&user_key(id)).await.unwrap().unwrap();
+        assert!(user.properties.contains_key("active"), "User {} was not migrated", id);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_transactional_retries() {
+    // Lookups of a page are deferred in part, and the first two commits are aborted.
+    let backend = MemoryBackend::new().with_lookup_limit(1).with_aborted_commits(2);
+    let client = client_with_users_in(backend, 5).await;
+    let migrator = Migrator::new(client.clone()).with_page_size(2).with_mode(Mode::Transactional)
+        .with_migration(Migration::new("User", 1, backfill_active));
+
+    let reports = migrator.run().await.unwrap();
+    assert_eq!((5, 3), (reports[0].processed, reports[0].changed));
+    for id in 1..=5 {
+        let user = client.get_entity(&user_key(id)).await.unwrap().unwrap();
+        assert!(user.properties.contains_key("active"), "User {} was not migrated", id);
+    }
+}
+
+#[tokio::test]
+async fn test_dry_run() {
+    let client = client_with_users(3).await;
+    let migrator = migrator(&client);
+    let reports = migrator.dry_run().await.unwrap();
+    assert_eq!(3, reports.len());
+
+    let backfill = &reports[1];
+    assert_eq!(("User", 1, 3, 2), (backfill.kind.as_str(), backfill.version, backfill.processed, backfill.changed));
+    assert_eq!(user_key(1).path(), backfill.diffs[0].0.path());
+    assert_eq!("+ active: true\n", backfill.diffs[0].1.to_string());
+
+    // Each migration sees the output of the one before it.
+    let rename = &reports[2];
+    assert_eq!(3, rename.changed);
+    assert_eq!("+ full_name: \"user-1\"\n- name: \"user-1\"\n", rename.diffs[0].1.to_string());
+    assert!(!rename.diffs[0].1.to_string().contains("active"));
+
+    // Nothing was written.
+    let user = client.get_entity(&user_key(1)).await.unwrap().unwrap();
+    assert_eq!(1, user.properties.len());
+    assert_eq!(MigrationRecord::default(), migrator.record("User").await.unwrap());
+}
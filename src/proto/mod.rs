@@ -0,0 +1,308 @@
+// Minimal, hand-rolled implementation of the protocol buffer wire format.
+//
+// Only the wire-level primitives live here. Messages are encoded and decoded by hand in the modules
+// that need them (e.g. `v1` for the Datastore API), which keeps the dependency footprint small and
+// avoids code generation.
+
+use std::fmt::{self, Display};
+use std::str;
+
+pub mod v1;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(test)]
+mod v1_tests;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    UnexpectedEof(),
+    VarintOverflow(),
+    InvalidWireType(u8),
+    InvalidUtf8(),
+    UnexpectedWireType(u32),
+    UnterminatedGroup(u32),
+    MissingField(&'static str),
+    InvalidValue(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnexpectedEof() => fmt.write_str("unexpected end of protobuf message"),
+            Error::VarintOverflow() => fmt.write_str("varint exceeds 64 bits"),
+            Error::InvalidWireType(t) => write!(fmt, "invalid wire type {}", t),
+            Error::InvalidUtf8() => fmt.write_str("string field is not valid UTF-8"),
+            Error::UnexpectedWireType(f) => write!(fmt, "unexpected wire type for field {}", f),
+            Error::UnterminatedGroup(f) => write!(fmt, "group {} is not terminated", f),
+            Error::MissingField(name) => write!(fmt, "required field missing: {}", name),
+            Error::InvalidValue(name) => write!(fmt, "invalid value in field: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const START_GROUP: u8 = 3;
+const END_GROUP: u8 = 4;
+const FIXED32: u8 = 5;
+
+/// Appends protobuf-encoded fields to a byte buffer.
+#[derive(Debug, Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer { buf: vec![] }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn raw_varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn tag(&mut self, field: u32, wire_type: u8) {
+        self.raw_varint((u64::from(field) << 3) | u64::from(wire_type));
+    }
+
+    pub fn uint64(&mut self, field: u32, v: u64) {
+        self.tag(field, VARINT);
+        self.raw_varint(v);
+    }
+
+    /// Negative values are encoded as ten-byte varints, as mandated for `int64` and `int32`.
+    pub fn int64(&mut self, field: u32, v: i64) {
+        self.uint64(field, v as u64);
+    }
+
+    pub fn int32(&mut self, field: u32, v: i32) {
+        self.int64(field, i64::from(v));
+    }
+
+    pub fn bool(&mut self, field: u32, v: bool) {
+        self.uint64(field, v as u64);
+    }
+
+    pub fn double(&mut self, field: u32, v: f64) {
+        self.tag(field, FIXED64);
+        self.buf.extend_from_slice(&v.to_bits().to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, field: u32, v: &[u8]) {
+        self.tag(field, LENGTH_DELIMITED);
+        self.raw_varint(v.len() as u64);
+        self.buf.extend_from_slice(v);
+    }
+
+    pub fn string(&mut self, field: u32, v: &str) {
+        self.bytes(field, v.as_bytes());
+    }
+
+    /// Writes an embedded, length-delimited message whose fields are written by `f`. The result
+    /// of `f` is passed through, which allows fallible encoders to use `?`.
+    pub fn message<T, F: FnOnce(&mut Writer) -> T>(&mut self, field: u32, f: F) -> T {
+        let mut inner = Writer::new();
+        let result = f(&mut inner);
+        self.bytes(field, &inner.buf);
+        result
+    }
+
+    /// Writes a (deprecated, but still used by legacy formats) group.
+    pub fn group<T, F: FnOnce(&mut Writer) -> T>(&mut self, field: u32, f: F) -> T {
+        self.tag(field, START_GROUP);
+        let result = f(self);
+        self.tag(field, END_GROUP);
+        result
+    }
+}
+
+/// A single field value as read from the wire.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    Bytes(&'a [u8]),
+    Group(&'a [u8]),
+}
+
+impl<'a> Field<'a> {
+    pub fn as_u64(&self, field: u32) -> Result<u64> {
+        match *self {
+            Field::Varint(v) => Ok(v),
+            _ => Err(Error::UnexpectedWireType(field)),
+        }
+    }
+
+    pub fn as_i64(&self, field: u32) -> Result<i64> {
+        self.as_u64(field).map(|v| v as i64)
+    }
+
+    pub fn as_i32(&self, field: u32) -> Result<i32> {
+        self.as_u64(field).map(|v| v as i32)
+    }
+
+    pub fn as_bool(&self, field: u32) -> Result<bool> {
+        self.as_u64(field).map(|v| v != 0)
+    }
+
+    pub fn as_f64(&self, field: u32) -> Result<f64> {
+        match *self {
+            Field::Fixed64(v) => Ok(f64::from_bits(v)),
+            _ => Err(Error::UnexpectedWireType(field)),
+        }
+    }
+
+    pub fn as_bytes(&self, field: u32) -> Result<&'a [u8]> {
+        match *self {
+            Field::Bytes(b) => Ok(b),
+            _ => Err(Error::UnexpectedWireType(field)),
+        }
+    }
+
+    pub fn as_str(&self, field: u32) -> Result<&'a str> {
+        str::from_utf8(self.as_bytes(field)?).map_err(|_| Error::InvalidUtf8())
+    }
+
+    /// Returns a reader over an embedded message.
+    pub fn as_message(&self, field: u32) -> Result<Reader<'a>> {
+        self.as_bytes(field).map(Reader::new)
+    }
+
+    /// Returns a reader over the contents of a group.
+    pub fn as_group(&self, field: u32) -> Result<Reader<'a>> {
+        match *self {
+            Field::Group(b) => Ok(Reader::new(b)),
+            _ => Err(Error::UnexpectedWireType(field)),
+        }
+    }
+}
+
+/// Iterates over the fields of an encoded message. Unknown fields can simply be ignored by the
+/// caller, groups are returned as a whole.
+#[derive(Clone, Debug)]
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf, pos: 0 }
+    }
+
+    fn raw_varint(&mut self) -> Result<u64> {
+        let mut result: u64 = 0;
+        for shift in 0..10 {
+            let byte = *self.buf.get(self.pos).ok_or(Error::UnexpectedEof())?;
+            self.pos += 1;
+
+            if shift == 9 && byte > 1 {
+                return Err(Error::VarintOverflow());
+            }
+
+            result |= u64::from(byte & 0x7f) << (shift * 7);
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+
+        Err(Error::VarintOverflow())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() - self.pos < len {
+            return Err(Error::UnexpectedEof());
+        }
+
+        let slice = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn tag(&mut self) -> Result<(u32, u8)> {
+        let tag = self.raw_varint()?;
+        Ok(((tag >> 3) as u32, (tag & 0x7) as u8))
+    }
+
+    // Reads the value of a field with the given wire type. End-group markers are handled by the
+    // callers, as their meaning depends on context.
+    fn value(&mut self, field: u32, wire_type: u8) -> Result<Field<'a>> {
+        match wire_type {
+            VARINT => self.raw_varint().map(Field::Varint),
+            FIXED64 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(self.take(8)?);
+                Ok(Field::Fixed64(u64::from_le_bytes(bytes)))
+            }
+            FIXED32 => {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(self.take(4)?);
+                Ok(Field::Fixed32(u32::from_le_bytes(bytes)))
+            }
+            LENGTH_DELIMITED => {
+                let len = self.raw_varint()? as usize;
+                self.take(len).map(Field::Bytes)
+            }
+            START_GROUP => {
+                let start = self.pos;
+                loop {
+                    let end = self.pos;
+                    if end >= self.buf.len() {
+                        return Err(Error::UnterminatedGroup(field));
+                    }
+
+                    let (inner_field, inner_type) = self.tag()?;
+                    if inner_type == END_GROUP {
+                        if inner_field != field {
+                            return Err(Error::UnterminatedGroup(field));
+                        }
+                        return Ok(Field::Group(&self.buf[start..end]));
+                    }
+
+                    self.value(inner_field, inner_type)?;
+                }
+            }
+            other => Err(Error::InvalidWireType(other)),
+        }
+    }
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<(u32, Field<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+
+        let result = self.tag().and_then(|(field, wire_type)| {
+            if wire_type == END_GROUP {
+                return Err(Error::UnterminatedGroup(field));
+            }
+            self.value(field, wire_type).map(|value| (field, value))
+        });
+
+        // Stop iterating after the first error, the position is meaningless afterwards.
+        if result.is_err() {
+            self.pos = self.buf.len();
+        }
+
+        Some(result)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::proto::*;
+
+#[test]
+fn test_varint_encoding() {
+    let mut writer = Writer::new();
+    writer.uint64(1, 150);
+    assert_eq!(vec![0x08, 0x96, 0x01], writer.into_bytes(), "Should match protobuf docs example");
+
+    let mut writer = Writer::new();
+    writer.int64(1, -1);
+    let bytes = writer.into_bytes();
+    assert_eq!(11, bytes.len(), "Negative int64 should use ten-byte varint");
+
+    let fields: Vec<_> = Reader::new(&bytes).collect::<Result<_>>().expect("decoding failed");
+    assert_eq!(vec![(1, Field::Varint(u64::MAX))], fields);
+    assert_eq!(-1, fields[0].1.as_i64(1).unwrap());
+}
+
+#[test]
+fn test_message_roundtrip() {
+    let mut writer = Writer::new();
+    writer.string(1, "hello");
+    writer.double(2, 1.5);
+    writer.message(3, |w| {
+        w.bool(1, true);
+        w.bytes(2, &[0, 255]);
+    });
+    writer.group(4, |w| w.int32(5, -7));
+    let bytes = writer.into_bytes();
+
+    let fields: Vec<_> = Reader::new(&bytes).collect::<Result<_>>().expect("decoding failed");
+    assert_eq!(4, fields.len());
+    assert_eq!("hello", fields[0].1.as_str(1).unwrap());
+    assert_eq!(1.5, fields[1].1.as_f64(2).unwrap());
+
+    let nested: Vec<_> = fields[2].1.as_message(3).unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(vec![(1, Field::Varint(1)), (2, Field::Bytes(&[0, 255]))], nested);
+
+    let group: Vec<_> = fields[3].1.as_group(4).unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(-7, group[0].1.as_i32(5).unwrap());
+}
+
+#[test]
+fn test_nested_groups() {
+    let mut writer = Writer::new();
+    writer.group(1, |w| {
+        w.group(2, |w| w.uint64(3, 1));
+        w.uint64(4, 2);
+    });
+    writer.uint64(5, 3);
+    let bytes = writer.into_bytes();
+
+    let fields: Vec<_> = Reader::new(&bytes).collect::<Result<_>>().expect("decoding failed");
+    assert_eq!(2, fields.len(), "Nested group should be consumed by outer group");
+    assert_eq!(Field::Varint(3), fields[1].1);
+}
+
+#[test]
+fn test_decoding_errors() {
+    let truncated = [0x0a, 0x05, b'h', b'i'];
+    let result: Result<Vec<_>> = Reader::new(&truncated).collect();
+    assert_eq!(Err(Error::UnexpectedEof()), result);
+
+    let unterminated = [0x0b, 0x10, 0x01];
+    let result: Result<Vec<_>> = Reader::new(&unterminated).collect();
+    assert_eq!(Err(Error::UnterminatedGroup(1)), result);
+
+    let field = Field::Varint(1);
+    assert_eq!(Err(Error::UnexpectedWireType(7)), field.as_str(7));
+}
@@ -0,0 +1,1365 @@
+// Protobuf encoding of the Datastore v1 API types, compatible with `google/datastore/v1/*.proto`.
+//
+// Messages are mapped to the same types that the REST API uses with serde, so that both
+// transports can share them. Fields that have their default value are omitted when encoding, as
+// proto3 requires.
+
+use std::collections::HashMap;
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::datastore::{ArrayValue, Blob, Entity, Int, Key, LatLng, PartitionId, PathElement, Value};
+use crate::datastore::query::*;
+use crate::datastore::rpc::*;
+use crate::proto::{Error, Field, Reader, Result, Writer};
+
+/// A message that can be converted to and from the protobuf wire format.
+pub trait Message: Sized {
+    fn encode(&self, w: &mut Writer) -> Result<()>;
+    fn decode(bytes: &[u8]) -> Result<Self>;
+}
+
+/// Encodes a message into a new buffer.
+pub fn encode<M: Message>(message: &M) -> Result<Vec<u8>> {
+    let mut w = Writer::new();
+    message.encode(&mut w)?;
+    Ok(w.into_bytes())
+}
+
+pub fn decode<M: Message>(bytes: &[u8]) -> Result<M> {
+    M::decode(bytes)
+}
+
+fn write<M: Message>(w: &mut Writer, field: u32, message: &M) -> Result<()> {
+    w.message(field, |w| message.encode(w))
+}
+
+fn read<M: Message>(value: &Field, field: u32) -> Result<M> {
+    M::decode(value.as_bytes(field)?)
+}
+
+fn read_string(value: &Field, field: u32) -> Result<String> {
+    value.as_str(field).map(str::to_string)
+}
+
+fn read_blob(value: &Field, field: u32) -> Result<Blob> {
+    value.as_bytes(field).map(Blob::from)
+}
+
+fn write_string(w: &mut Writer, field: u32, value: &str) {
+    if !value.is_empty() {
+        w.string(field, value);
+    }
+}
+
+fn write_int(w: &mut Writer, field: u32, value: &Int, name: &'static str) -> Result<()> {
+    let value: i64 = value.parse().map_err(|_| Error::InvalidValue(name))?;
+    w.int64(field, value);
+    Ok(())
+}
+
+// google.protobuf.Timestamp { int64 seconds = 1; int32 nanos = 2; }
+fn write_timestamp(w: &mut Writer, field: u32, timestamp: &DateTime<Utc>) {
+    w.message(field, |w| {
+        if timestamp.timestamp() != 0 {
+            w.int64(1, timestamp.timestamp());
+        }
+        if timestamp.timestamp_subsec_nanos() != 0 {
+            w.int32(2, timestamp.timestamp_subsec_nanos() as i32);
+        }
+    })
+}
+
+fn read_timestamp(value: &Field, field: u32) -> Result<DateTime<Utc>> {
+    let mut seconds = 0;
+    let mut nanos = 0;
+    for f in value.as_message(field)? {
+        match f? {
+            (1, v) => seconds = v.as_i64(1)?,
+            (2, v) => nanos = v.as_i32(2)?,
+            _ => {}
+        }
+    }
+
+    Utc.timestamp_opt(seconds, nanos as u32).single().ok_or(Error::InvalidValue("timestamp"))
+}
+
+impl Message for PartitionId {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write_string(w, 2, self.project_id());
+        write_string(w, 4, self.namespace_id());
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<PartitionId> {
+        let mut project_id = String::new();
+        let mut namespace_id = String::new();
+        for field in Reader::new(bytes) {
+            match field? {
+                (2, v) => project_id = read_string(&v, 2)?,
+                (4, v) => namespace_id = read_string(&v, 4)?,
+                _ => {}
+            }
+        }
+        Ok(PartitionId::new(project_id, namespace_id))
+    }
+}
+
+impl Message for PathElement {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        w.string(1, self.kind());
+        match *self {
+            PathElement::Id { .. } => {
+                let id = self.id().ok_or(Error::InvalidValue("id"))?;
+                w.int64(2, id);
+            }
+            PathElement::Name { ref name, .. } => w.string(3, name),
+            PathElement::Incomplete { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<PathElement> {
+        let mut kind = String::new();
+        let mut id = None;
+        let mut name = None;
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => kind = read_string(&v, 1)?,
+                (2, v) => id = Some(v.as_i64(2)?),
+                (3, v) => name = Some(read_string(&v, 3)?),
+                _ => {}
+            }
+        }
+
+        match (id, name) {
+            (_, Some(name)) => Ok(PathElement::from_name(kind, name)),
+            (Some(id), None) => Ok(PathElement::from_id(kind, id)),
+            (None, None) => Ok(PathElement::incomplete(kind)),
+        }
+    }
+}
+
+impl Message for Key {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write(w, 1, self.partition_id())?;
+        for element in self.path() {
+            write(w, 2, element)?;
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Key> {
+        let mut partition_id = PartitionId::new("", "");
+        let mut path = vec![];
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => partition_id = read(&v, 1)?,
+                (2, v) => path.push(read(&v, 2)?),
+                _ => {}
+            }
+        }
+        Ok(Key::new(partition_id, path))
+    }
+}
+
+// google.type.LatLng { double latitude = 1; double longitude = 2; }
+impl Message for LatLng {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        w.double(1, self.latitude());
+        w.double(2, self.longitude());
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<LatLng> {
+        let mut latitude = 0.0;
+        let mut longitude = 0.0;
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => latitude = v.as_f64(1)?,
+                (2, v) => longitude = v.as_f64(2)?,
+                _ => {}
+            }
+        }
+        Ok(LatLng::new(latitude, longitude))
+    }
+}
+
+impl Message for Value {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        match *self {
+            Value::Null { .. } => w.uint64(11, 0),
+            Value::Boolean { boolean_value, .. } => w.bool(1, boolean_value),
+            Value::Integer { ref integer_value, .. } => write_int(w, 2, integer_value, "integer_value")?,
+            Value::Double { double_value, .. } => w.double(3, double_value),
+            Value::Timestamp { ref timestamp_value, .. } => write_timestamp(w, 10, timestamp_value),
+            Value::KeyValue { ref key_value, .. } => write(w, 5, key_value)?,
+            Value::String { ref string_value, .. } => w.string(17, string_value),
+            Value::Blob { ref blob_value, .. } => w.bytes(18, &blob_value.0),
+            Value::GeoPoint { ref geo_point_value, .. } => write(w, 8, geo_point_value)?,
+            Value::EntityValue { ref entity_value, .. } => write(w, 6, entity_value)?,
+            Value::Array { ref array_value, .. } => w.message(9, |w| {
+                array_value.values.iter().try_for_each(|value| write(w, 1, value))
+            })?,
+        }
+
+        if let Some(meaning) = self.meaning() {
+            w.int32(14, meaning);
+        }
+        if self.exclude_from_indexes() {
+            w.bool(19, true);
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Value> {
+        let mut value = None;
+        let mut meaning = None;
+        let mut exclude_from_indexes = false;
+
+        for field in Reader::new(bytes) {
+            let (number, v) = field?;
+            value = Some(match number {
+                11 => Value::from(()),
+                1 => Value::from(v.as_bool(1)?),
+                2 => Value::from(v.as_i64(2)?),
+                3 => Value::from(v.as_f64(3)?),
+                10 => Value::from(read_timestamp(&v, 10)?),
+                5 => Value::from(read::<Key>(&v, 5)?),
+                17 => Value::from(read_string(&v, 17)?),
+                18 => Value::from(read_blob(&v, 18)?),
+                8 => Value::from(read::<LatLng>(&v, 8)?),
+                6 => Value::from(read::<Entity>(&v, 6)?),
+                9 => {
+                    let mut values = vec![];
+                    for element in v.as_message(9)? {
+                        if let (1, element) = element? {
+                            values.push(read(&element, 1)?);
+                        }
+                    }
+                    Value::Array { array_value: ArrayValue { values }, meta: Default::default() }
+                }
+                14 => {
+                    meaning = Some(v.as_i32(14)?);
+                    continue;
+                }
+                19 => {
+                    exclude_from_indexes = v.as_bool(19)?;
+                    continue;
+                }
+                _ => continue,
+            });
+        }
+
+        let mut value = value.ok_or(Error::MissingField("value_type"))?;
+        value.meta_mut().meaning = meaning;
+        value.meta_mut().exclude_from_indexes = exclude_from_indexes;
+        Ok(value)
+    }
+}
+
+impl Message for Entity {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        if let Some(ref key) = self.key {
+            write(w, 1, key)?;
+        }
+
+        write_properties(w, 3, &self.properties)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Entity> {
+        let mut key = None;
+        let mut properties = HashMap::new();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => key = Some(read(&v, 1)?),
+                (3, v) => {
+                    let (name, value) = read_map_entry(&v, 3)?;
+                    properties.insert(name, value.unwrap_or_else(|| Value::from(())));
+                }
+                _ => {}
+            }
+        }
+        Ok(Entity { key, properties })
+    }
+}
+
+// Writes a `map<string, Value>` field. Map entries are written in a stable order to make the output
+// reproducible.
+fn write_properties(w: &mut Writer, field: u32, properties: &HashMap<String, Value>) -> Result<()> {
+    let mut names: Vec<&String> = properties.keys().collect();
+    names.sort();
+    for name in names {
+        w.message(field, |w| {
+            w.string(1, name);
+            write(w, 2, &properties[name])
+        })?;
+    }
+    Ok(())
+}
+
+// Map entries are messages with the key in field 1 and the value in field 2.
+fn read_map_entry<M: Message>(value: &Field, field: u32) -> Result<(String, Option<M>)> {
+    let mut key = String::new();
+    let mut entry_value = None;
+    for f in value.as_message(field)? {
+        match f? {
+            (1, v) => key = read_string(&v, 1)?,
+            (2, v) => entry_value = Some(read(&v, 2)?),
+            _ => {}
+        }
+    }
+    Ok((key, entry_value))
+}
+
+// Query messages
+
+impl Message for PropertyReference {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write_string(w, 2, &self.name);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<PropertyReference> {
+        let mut name = String::new();
+        for field in Reader::new(bytes) {
+            if let (2, v) = field? {
+                name = read_string(&v, 2)?;
+            }
+        }
+        Ok(PropertyReference { name })
+    }
+}
+
+impl Message for KindExpression {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write_string(w, 1, &self.name);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<KindExpression> {
+        let mut name = String::new();
+        for field in Reader::new(bytes) {
+            if let (1, v) = field? {
+                name = read_string(&v, 1)?;
+            }
+        }
+        Ok(KindExpression { name })
+    }
+}
+
+impl Message for Projection {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write(w, 1, &self.property)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Projection> {
+        let mut property = PropertyReference::new("");
+        for field in Reader::new(bytes) {
+            if let (1, v) = field? {
+                property = read(&v, 1)?;
+            }
+        }
+        Ok(Projection { property })
+    }
+}
+
+impl Message for PropertyOrder {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write(w, 1, &self.property)?;
+        w.int32(2, match self.direction {
+            Direction::Ascending => 1,
+            Direction::Descending => 2,
+        });
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<PropertyOrder> {
+        let mut property = PropertyReference::new("");
+        let mut direction = Direction::Ascending;
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => property = read(&v, 1)?,
+                (2, v) => direction = match v.as_i32(2)? {
+                    0 | 1 => Direction::Ascending,
+                    2 => Direction::Descending,
+                    _ => return Err(Error::InvalidValue("direction")),
+                },
+                _ => {}
+            }
+        }
+        Ok(PropertyOrder { property, direction })
+    }
+}
+
+fn property_operator_code(op: PropertyOperator) -> i32 {
+    match op {
+        PropertyOperator::LessThan => 1,
+        PropertyOperator::LessThanOrEqual => 2,
+        PropertyOperator::GreaterThan => 3,
+        PropertyOperator::GreaterThanOrEqual => 4,
+        PropertyOperator::Equal => 5,
+        PropertyOperator::In => 6,
+        PropertyOperator::NotEqual => 9,
+        PropertyOperator::HasAncestor => 11,
+        PropertyOperator::NotIn => 13,
+    }
+}
+
+fn property_operator(code: i32) -> Result<PropertyOperator> {
+    Ok(match code {
+        1 => PropertyOperator::LessThan,
+        2 => PropertyOperator::LessThanOrEqual,
+        3 => PropertyOperator::GreaterThan,
+        4 => PropertyOperator::GreaterThanOrEqual,
+        5 => PropertyOperator::Equal,
+        6 => PropertyOperator::In,
+        9 => PropertyOperator::NotEqual,
+        11 => PropertyOperator::HasAncestor,
+        13 => PropertyOperator::NotIn,
+        _ => return Err(Error::InvalidValue("op")),
+    })
+}
+
+impl Message for Filter {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        match *self {
+            Filter::CompositeFilter(ref composite) => w.message(1, |w| {
+                w.int32(1, match composite.op {
+                    CompositeOperator::And => 1,
+                    CompositeOperator::Or => 2,
+                });
+                composite.filters.iter().try_for_each(|filter| write(w, 2, filter))
+            }),
+            Filter::PropertyFilter(ref property) => w.message(2, |w| {
+                write(w, 1, &property.property)?;
+                w.int32(2, property_operator_code(property.op));
+                write(w, 3, &property.value)
+            }),
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Filter> {
+        let mut filter = None;
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => {
+                    let mut op = CompositeOperator::And;
+                    let mut filters = vec![];
+                    for f in v.as_message(1)? {
+                        match f? {
+                            (1, v) => op = match v.as_i32(1)? {
+                                1 => CompositeOperator::And,
+                                2 => CompositeOperator::Or,
+                                _ => return Err(Error::InvalidValue("op")),
+                            },
+                            (2, v) => filters.push(read(&v, 2)?),
+                            _ => {}
+                        }
+                    }
+                    filter = Some(Filter::CompositeFilter(CompositeFilter { op, filters }));
+                }
+                (2, v) => {
+                    let mut property = None;
+                    let mut op = None;
+                    let mut value = None;
+                    for f in v.as_message(2)? {
+                        match f? {
+                            (1, v) => property = Some(read(&v, 1)?),
+                            (2, v) => op = Some(property_operator(v.as_i32(2)?)?),
+                            (3, v) => value = Some(read(&v, 3)?),
+                            _ => {}
+                        }
+                    }
+                    filter = Some(Filter::PropertyFilter(PropertyFilter {
+                        property: property.ok_or(Error::MissingField("property"))?,
+                        op: op.ok_or(Error::MissingField("op"))?,
+                        value: value.ok_or(Error::MissingField("value"))?,
+                    }));
+                }
+                _ => {}
+            }
+        }
+        filter.ok_or(Error::MissingField("filter_type"))
+    }
+}
+
+impl Message for Query {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        for projection in &self.projection {
+            write(w, 2, projection)?;
+        }
+        for kind in &self.kind {
+            write(w, 3, kind)?;
+        }
+        if let Some(ref filter) = self.filter {
+            write(w, 4, filter)?;
+        }
+        for order in &self.order {
+            write(w, 5, order)?;
+        }
+        for property in &self.distinct_on {
+            write(w, 6, property)?;
+        }
+        if let Some(ref cursor) = self.start_cursor {
+            w.bytes(7, &cursor.0);
+        }
+        if let Some(ref cursor) = self.end_cursor {
+            w.bytes(8, &cursor.0);
+        }
+        if self.offset != 0 {
+            w.int32(10, self.offset);
+        }
+        // The limit is a google.protobuf.Int32Value wrapper to distinguish zero from unset.
+        if let Some(limit) = self.limit {
+            w.message(12, |w| if limit != 0 { w.int32(1, limit) });
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Query> {
+        let mut query = Query::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (2, v) => query.projection.push(read(&v, 2)?),
+                (3, v) => query.kind.push(read(&v, 3)?),
+                (4, v) => query.filter = Some(read(&v, 4)?),
+                (5, v) => query.order.push(read(&v, 5)?),
+                (6, v) => query.distinct_on.push(read(&v, 6)?),
+                (7, v) => query.start_cursor = Some(read_blob(&v, 7)?),
+                (8, v) => query.end_cursor = Some(read_blob(&v, 8)?),
+                (10, v) => query.offset = v.as_i32(10)?,
+                (12, v) => {
+                    let mut limit = 0;
+                    for f in v.as_message(12)? {
+                        if let (1, v) = f? {
+                            limit = v.as_i32(1)?;
+                        }
+                    }
+                    query.limit = Some(limit);
+                }
+                _ => {}
+            }
+        }
+        Ok(query)
+    }
+}
+
+impl Message for GqlQueryParameter {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        match *self {
+            GqlQueryParameter::Value(ref value) => write(w, 2, value),
+            GqlQueryParameter::Cursor(ref cursor) => {
+                w.bytes(3, &cursor.0);
+                Ok(())
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<GqlQueryParameter> {
+        let mut parameter = None;
+        for field in Reader::new(bytes) {
+            match field? {
+                (2, v) => parameter = Some(GqlQueryParameter::Value(read(&v, 2)?)),
+                (3, v) => parameter = Some(GqlQueryParameter::Cursor(read_blob(&v, 3)?)),
+                _ => {}
+            }
+        }
+        parameter.ok_or(Error::MissingField("parameter_type"))
+    }
+}
+
+impl Message for GqlQuery {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write_string(w, 1, &self.query_string);
+        if self.allow_literals {
+            w.bool(2, true);
+        }
+        for parameter in &self.positional_bindings {
+            write(w, 4, parameter)?;
+        }
+
+        let mut names: Vec<&String> = self.named_bindings.keys().collect();
+        names.sort();
+        for name in names {
+            w.message(5, |w| {
+                w.string(1, name);
+                write(w, 2, &self.named_bindings[name])
+            })?;
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<GqlQuery> {
+        let mut query = GqlQuery::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => query.query_string = read_string(&v, 1)?,
+                (2, v) => query.allow_literals = v.as_bool(2)?,
+                (4, v) => query.positional_bindings.push(read(&v, 4)?),
+                (5, v) => {
+                    let (name, parameter) = read_map_entry(&v, 5)?;
+                    let parameter = parameter.ok_or(Error::MissingField("named_bindings"))?;
+                    query.named_bindings.insert(name, parameter);
+                }
+                _ => {}
+            }
+        }
+        Ok(query)
+    }
+}
+
+impl Message for EntityResult {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write(w, 1, &self.entity)?;
+        if let Some(ref cursor) = self.cursor {
+            w.bytes(3, &cursor.0);
+        }
+        if let Some(ref version) = self.version {
+            write_int(w, 4, version, "version")?;
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<EntityResult> {
+        let mut entity = None;
+        let mut version = None;
+        let mut cursor = None;
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => entity = Some(read(&v, 1)?),
+                (3, v) => cursor = Some(read_blob(&v, 3)?),
+                (4, v) => version = Some(Int::from(v.as_i64(4)?)),
+                _ => {}
+            }
+        }
+
+        Ok(EntityResult {
+            entity: entity.ok_or(Error::MissingField("entity"))?,
+            version,
+            cursor,
+        })
+    }
+}
+
+fn write_more_results(w: &mut Writer, field: u32, more_results: MoreResultsType) {
+    let code = match more_results {
+        MoreResultsType::MoreResultsTypeUnspecified => 0,
+        MoreResultsType::NotFinished => 1,
+        MoreResultsType::MoreResultsAfterLimit => 2,
+        MoreResultsType::NoMoreResults => 3,
+        MoreResultsType::MoreResultsAfterCursor => 4,
+    };
+    if code != 0 {
+        w.int32(field, code);
+    }
+}
+
+fn read_more_results(value: &Field, field: u32) -> Result<MoreResultsType> {
+    Ok(match value.as_i32(field)? {
+        0 => MoreResultsType::MoreResultsTypeUnspecified,
+        1 => MoreResultsType::NotFinished,
+        2 => MoreResultsType::MoreResultsAfterLimit,
+        3 => MoreResultsType::NoMoreResults,
+        4 => MoreResultsType::MoreResultsAfterCursor,
+        _ => return Err(Error::InvalidValue("more_results")),
+    })
+}
+
+impl Message for QueryResultBatch {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        let result_type = match self.entity_result_type {
+            ResultType::ResultTypeUnspecified => 0,
+            ResultType::Full => 1,
+            ResultType::Projection => 2,
+            ResultType::KeyOnly => 3,
+        };
+        if result_type != 0 {
+            w.int32(1, result_type);
+        }
+        for result in &self.entity_results {
+            write(w, 2, result)?;
+        }
+        if let Some(ref cursor) = self.skipped_cursor {
+            w.bytes(3, &cursor.0);
+        }
+        if let Some(ref cursor) = self.end_cursor {
+            w.bytes(4, &cursor.0);
+        }
+        write_more_results(w, 5, self.more_results);
+        if self.skipped_results != 0 {
+            w.int32(6, self.skipped_results);
+        }
+        if let Some(ref version) = self.snapshot_version {
+            write_int(w, 7, version, "snapshot_version")?;
+        }
+        if let Some(ref read_time) = self.read_time {
+            write_timestamp(w, 8, read_time);
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<QueryResultBatch> {
+        let mut batch = QueryResultBatch::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => batch.entity_result_type = match v.as_i32(1)? {
+                    0 => ResultType::ResultTypeUnspecified,
+                    1 => ResultType::Full,
+                    2 => ResultType::Projection,
+                    3 => ResultType::KeyOnly,
+                    _ => return Err(Error::InvalidValue("entity_result_type")),
+                },
+                (2, v) => batch.entity_results.push(read(&v, 2)?),
+                (3, v) => batch.skipped_cursor = Some(read_blob(&v, 3)?),
+                (4, v) => batch.end_cursor = Some(read_blob(&v, 4)?),
+                (5, v) => batch.more_results = read_more_results(&v, 5)?,
+                (6, v) => batch.skipped_results = v.as_i32(6)?,
+                (7, v) => batch.snapshot_version = Some(Int::from(v.as_i64(7)?)),
+                (8, v) => batch.read_time = Some(read_timestamp(&v, 8)?),
+                _ => {}
+            }
+        }
+        Ok(batch)
+    }
+}
+
+impl Message for Aggregation {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        match self.operator {
+            // The limit is a google.protobuf.Int64Value wrapper.
+            AggregationOperator::Count(ref count) => w.message(1, |w| match count.up_to {
+                Some(ref up_to) => w.message(1, |w| write_int(w, 1, up_to, "up_to")),
+                None => Ok(()),
+            })?,
+            AggregationOperator::Sum { ref property } => w.message(2, |w| write(w, 1, property))?,
+            AggregationOperator::Avg { ref property } => w.message(3, |w| write(w, 1, property))?,
+        }
+        write_string(w, 7, &self.alias);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Aggregation> {
+        let mut operator = None;
+        let mut alias = String::new();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => {
+                    let mut count = Count::default();
+                    for f in v.as_message(1)? {
+                        if let (1, v) = f? {
+                            let mut up_to = 0;
+                            for f in v.as_message(1)? {
+                                if let (1, v) = f? {
+                                    up_to = v.as_i64(1)?;
+                                }
+                            }
+                            count.up_to = Some(Int::from(up_to));
+                        }
+                    }
+                    operator = Some(AggregationOperator::Count(count));
+                }
+                (2, v) => operator = Some(AggregationOperator::Sum { property: read_aggregated(&v, 2)? }),
+                (3, v) => operator = Some(AggregationOperator::Avg { property: read_aggregated(&v, 3)? }),
+                (7, v) => alias = read_string(&v, 7)?,
+                _ => {}
+            }
+        }
+        Ok(Aggregation { operator: operator.ok_or(Error::MissingField("operator"))?, alias })
+    }
+}
+
+// Sum { PropertyReference property = 1; } and Avg { PropertyReference property = 1; }
+fn read_aggregated(value: &Field, field: u32) -> Result<PropertyReference> {
+    let mut property = None;
+    for f in value.as_message(field)? {
+        if let (1, v) = f? {
+            property = Some(read(&v, 1)?);
+        }
+    }
+    property.ok_or(Error::MissingField("property"))
+}
+
+impl Message for AggregationQuery {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write(w, 1, &self.nested_query)?;
+        for aggregation in &self.aggregations {
+            write(w, 3, aggregation)?;
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<AggregationQuery> {
+        let mut query = AggregationQuery::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => query.nested_query = read(&v, 1)?,
+                (3, v) => query.aggregations.push(read(&v, 3)?),
+                _ => {}
+            }
+        }
+        Ok(query)
+    }
+}
+
+impl Message for AggregationResult {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write_properties(w, 2, &self.aggregate_properties)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<AggregationResult> {
+        let mut result = AggregationResult::default();
+        for field in Reader::new(bytes) {
+            if let (2, v) = field? {
+                let (alias, value) = read_map_entry(&v, 2)?;
+                result.aggregate_properties.insert(alias, value.unwrap_or_else(|| Value::from(())));
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl Message for AggregationResultBatch {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        for result in &self.aggregation_results {
+            write(w, 1, result)?;
+        }
+        write_more_results(w, 2, self.more_results);
+        if let Some(ref read_time) = self.read_time {
+            write_timestamp(w, 3, read_time);
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<AggregationResultBatch> {
+        let mut batch = AggregationResultBatch::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => batch.aggregation_results.push(read(&v, 1)?),
+                (2, v) => batch.more_results = read_more_results(&v, 2)?,
+                (3, v) => batch.read_time = Some(read_timestamp(&v, 3)?),
+                _ => {}
+            }
+        }
+        Ok(batch)
+    }
+}
+
+// RPC messages
+
+impl Message for TransactionOptions {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        match *self {
+            TransactionOptions::ReadWrite(ref options) => w.message(1, |w| {
+                if let Some(ref transaction) = options.previous_transaction {
+                    w.bytes(1, &transaction.0);
+                }
+            }),
+            TransactionOptions::ReadOnly(ref options) => w.message(2, |w| {
+                if let Some(ref read_time) = options.read_time {
+                    write_timestamp(w, 1, read_time);
+                }
+            }),
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<TransactionOptions> {
+        let mut options = None;
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => {
+                    let mut read_write = ReadWrite::default();
+                    for f in v.as_message(1)? {
+                        if let (1, v) = f? {
+                            read_write.previous_transaction = Some(read_blob(&v, 1)?);
+                        }
+                    }
+                    options = Some(TransactionOptions::ReadWrite(read_write));
+                }
+                (2, v) => {
+                    let mut read_only = ReadOnly::default();
+                    for f in v.as_message(2)? {
+                        if let (1, v) = f? {
+                            read_only.read_time = Some(read_timestamp(&v, 1)?);
+                        }
+                    }
+                    options = Some(TransactionOptions::ReadOnly(read_only));
+                }
+                _ => {}
+            }
+        }
+        options.ok_or(Error::MissingField("mode"))
+    }
+}
+
+impl Message for ReadOptions {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        match *self {
+            ReadOptions::ReadConsistency(consistency) => w.int32(1, match consistency {
+                ReadConsistency::Strong => 1,
+                ReadConsistency::Eventual => 2,
+            }),
+            ReadOptions::Transaction(ref transaction) => w.bytes(2, &transaction.0),
+            ReadOptions::NewTransaction(ref options) => write(w, 3, options)?,
+            ReadOptions::ReadTime(ref read_time) => write_timestamp(w, 4, read_time),
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<ReadOptions> {
+        let mut options = None;
+        for field in Reader::new(bytes) {
+            options = Some(match field? {
+                (1, v) => ReadOptions::ReadConsistency(match v.as_i32(1)? {
+                    1 => ReadConsistency::Strong,
+                    2 => ReadConsistency::Eventual,
+                    _ => return Err(Error::InvalidValue("read_consistency")),
+                }),
+                (2, v) => ReadOptions::Transaction(read_blob(&v, 2)?),
+                (3, v) => ReadOptions::NewTransaction(read(&v, 3)?),
+                (4, v) => ReadOptions::ReadTime(read_timestamp(&v, 4)?),
+                _ => continue,
+            });
+        }
+        options.ok_or(Error::MissingField("consistency_type"))
+    }
+}
+
+impl Message for LookupRequest {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        if let Some(ref options) = self.read_options {
+            write(w, 1, options)?;
+        }
+        for key in &self.keys {
+            write(w, 3, key)?;
+        }
+        write_string(w, 8, &self.project_id);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<LookupRequest> {
+        let mut request = LookupRequest::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => request.read_options = Some(read(&v, 1)?),
+                (3, v) => request.keys.push(read(&v, 3)?),
+                (8, v) => request.project_id = read_string(&v, 8)?,
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+impl Message for LookupResponse {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        for result in &self.found {
+            write(w, 1, result)?;
+        }
+        for result in &self.missing {
+            write(w, 2, result)?;
+        }
+        for key in &self.deferred {
+            write(w, 3, key)?;
+        }
+        if let Some(ref transaction) = self.transaction {
+            w.bytes(5, &transaction.0);
+        }
+        if let Some(ref read_time) = self.read_time {
+            write_timestamp(w, 7, read_time);
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<LookupResponse> {
+        let mut response = LookupResponse::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => response.found.push(read(&v, 1)?),
+                (2, v) => response.missing.push(read(&v, 2)?),
+                (3, v) => response.deferred.push(read(&v, 3)?),
+                (5, v) => response.transaction = Some(read_blob(&v, 5)?),
+                (7, v) => response.read_time = Some(read_timestamp(&v, 7)?),
+                _ => {}
+            }
+        }
+        Ok(response)
+    }
+}
+
+impl Message for RunQueryRequest {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        if let Some(ref options) = self.read_options {
+            write(w, 1, options)?;
+        }
+        if let Some(ref partition_id) = self.partition_id {
+            write(w, 2, partition_id)?;
+        }
+        if let Some(ref query) = self.query {
+            write(w, 3, query)?;
+        }
+        if let Some(ref query) = self.gql_query {
+            write(w, 7, query)?;
+        }
+        write_string(w, 8, &self.project_id);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<RunQueryRequest> {
+        let mut request = RunQueryRequest::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => request.read_options = Some(read(&v, 1)?),
+                (2, v) => request.partition_id = Some(read(&v, 2)?),
+                (3, v) => request.query = Some(read(&v, 3)?),
+                (7, v) => request.gql_query = Some(read(&v, 7)?),
+                (8, v) => request.project_id = read_string(&v, 8)?,
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+impl Message for RunQueryResponse {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write(w, 1, &self.batch)?;
+        if let Some(ref query) = self.query {
+            write(w, 2, query)?;
+        }
+        if let Some(ref transaction) = self.transaction {
+            w.bytes(5, &transaction.0);
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<RunQueryResponse> {
+        let mut response = RunQueryResponse::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => response.batch = read(&v, 1)?,
+                (2, v) => response.query = Some(read(&v, 2)?),
+                (5, v) => response.transaction = Some(read_blob(&v, 5)?),
+                _ => {}
+            }
+        }
+        Ok(response)
+    }
+}
+
+impl Message for RunAggregationQueryRequest {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        if let Some(ref options) = self.read_options {
+            write(w, 1, options)?;
+        }
+        if let Some(ref partition_id) = self.partition_id {
+            write(w, 2, partition_id)?;
+        }
+        if let Some(ref query) = self.aggregation_query {
+            write(w, 3, query)?;
+        }
+        if let Some(ref query) = self.gql_query {
+            write(w, 7, query)?;
+        }
+        write_string(w, 8, &self.project_id);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<RunAggregationQueryRequest> {
+        let mut request = RunAggregationQueryRequest::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => request.read_options = Some(read(&v, 1)?),
+                (2, v) => request.partition_id = Some(read(&v, 2)?),
+                (3, v) => request.aggregation_query = Some(read(&v, 3)?),
+                (7, v) => request.gql_query = Some(read(&v, 7)?),
+                (8, v) => request.project_id = read_string(&v, 8)?,
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+impl Message for RunAggregationQueryResponse {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write(w, 1, &self.batch)?;
+        if let Some(ref query) = self.query {
+            write(w, 2, query)?;
+        }
+        if let Some(ref transaction) = self.transaction {
+            w.bytes(5, &transaction.0);
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<RunAggregationQueryResponse> {
+        let mut response = RunAggregationQueryResponse::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => response.batch = read(&v, 1)?,
+                (2, v) => response.query = Some(read(&v, 2)?),
+                (5, v) => response.transaction = Some(read_blob(&v, 5)?),
+                _ => {}
+            }
+        }
+        Ok(response)
+    }
+}
+
+impl Message for BeginTransactionRequest {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        write_string(w, 8, &self.project_id);
+        if let Some(ref options) = self.transaction_options {
+            write(w, 10, options)?;
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<BeginTransactionRequest> {
+        let mut request = BeginTransactionRequest::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (8, v) => request.project_id = read_string(&v, 8)?,
+                (10, v) => request.transaction_options = Some(read(&v, 10)?),
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+impl Message for BeginTransactionResponse {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        w.bytes(1, &self.transaction.0);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<BeginTransactionResponse> {
+        let mut transaction = Blob(vec![]);
+        for field in Reader::new(bytes) {
+            if let (1, v) = field? {
+                transaction = read_blob(&v, 1)?;
+            }
+        }
+        Ok(BeginTransactionResponse { transaction })
+    }
+}
+
+impl Message for Mutation {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        match *self {
+            Mutation::Insert(ref entity) => write(w, 4, entity),
+            Mutation::Update(ref entity) => write(w, 5, entity),
+            Mutation::Upsert(ref entity) => write(w, 6, entity),
+            Mutation::Delete(ref key) => write(w, 7, key),
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Mutation> {
+        let mut mutation = None;
+        for field in Reader::new(bytes) {
+            mutation = Some(match field? {
+                (4, v) => Mutation::Insert(read(&v, 4)?),
+                (5, v) => Mutation::Update(read(&v, 5)?),
+                (6, v) => Mutation::Upsert(read(&v, 6)?),
+                (7, v) => Mutation::Delete(read(&v, 7)?),
+                _ => continue,
+            });
+        }
+        mutation.ok_or(Error::MissingField("operation"))
+    }
+}
+
+impl Message for CommitRequest {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        if let Some(ref transaction) = self.transaction {
+            w.bytes(1, &transaction.0);
+        }
+        w.int32(5, match self.mode {
+            CommitMode::Transactional => 1,
+            CommitMode::NonTransactional => 2,
+        });
+        for mutation in &self.mutations {
+            write(w, 6, mutation)?;
+        }
+        write_string(w, 8, &self.project_id);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<CommitRequest> {
+        let mut request = CommitRequest {
+            project_id: String::new(),
+            mode: CommitMode::Transactional,
+            transaction: None,
+            mutations: vec![],
+        };
+
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => request.transaction = Some(read_blob(&v, 1)?),
+                (5, v) => request.mode = match v.as_i32(5)? {
+                    0 | 1 => CommitMode::Transactional,
+                    2 => CommitMode::NonTransactional,
+                    _ => return Err(Error::InvalidValue("mode")),
+                },
+                (6, v) => request.mutations.push(read(&v, 6)?),
+                (8, v) => request.project_id = read_string(&v, 8)?,
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+impl Message for MutationResult {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        if let Some(ref key) = self.key {
+            write(w, 3, key)?;
+        }
+        write_int(w, 4, &self.version, "version")?;
+        if self.conflict_detected {
+            w.bool(5, true);
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<MutationResult> {
+        let mut result = MutationResult { key: None, version: Int::from(0), conflict_detected: false };
+        for field in Reader::new(bytes) {
+            match field? {
+                (3, v) => result.key = Some(read(&v, 3)?),
+                (4, v) => result.version = Int::from(v.as_i64(4)?),
+                (5, v) => result.conflict_detected = v.as_bool(5)?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl Message for CommitResponse {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        for result in &self.mutation_results {
+            write(w, 3, result)?;
+        }
+        if self.index_updates != 0 {
+            w.int32(4, self.index_updates);
+        }
+        if let Some(ref commit_time) = self.commit_time {
+            write_timestamp(w, 8, commit_time);
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<CommitResponse> {
+        let mut response = CommitResponse::default();
+        for field in Reader::new(bytes) {
+            match field? {
+                (3, v) => response.mutation_results.push(read(&v, 3)?),
+                (4, v) => response.index_updates = v.as_i32(4)?,
+                (8, v) => response.commit_time = Some(read_timestamp(&v, 8)?),
+                _ => {}
+            }
+        }
+        Ok(response)
+    }
+}
+
+impl Message for RollbackRequest {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        w.bytes(1, &self.transaction.0);
+        write_string(w, 8, &self.project_id);
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<RollbackRequest> {
+        let mut request = RollbackRequest { project_id: String::new(), transaction: Blob(vec![]) };
+        for field in Reader::new(bytes) {
+            match field? {
+                (1, v) => request.transaction = read_blob(&v, 1)?,
+                (8, v) => request.project_id = read_string(&v, 8)?,
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+impl Message for RollbackResponse {
+    fn encode(&self, _: &mut Writer) -> Result<()> {
+        Ok(())
+    }
+
+    fn decode(_: &[u8]) -> Result<RollbackResponse> {
+        Ok(RollbackResponse {})
+    }
+}
+
+// AllocateIdsRequest and ReserveIdsRequest have the same layout.
+fn encode_keys_request(w: &mut Writer, project_id: &str, keys: &[Key]) -> Result<()> {
+    for key in keys {
+        write(w, 1, key)?;
+    }
+    write_string(w, 8, project_id);
+    Ok(())
+}
+
+fn decode_keys_request(bytes: &[u8]) -> Result<(String, Vec<Key>)> {
+    let mut project_id = String::new();
+    let mut keys = vec![];
+    for field in Reader::new(bytes) {
+        match field? {
+            (1, v) => keys.push(read(&v, 1)?),
+            (8, v) => project_id = read_string(&v, 8)?,
+            _ => {}
+        }
+    }
+    Ok((project_id, keys))
+}
+
+impl Message for AllocateIdsRequest {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        encode_keys_request(w, &self.project_id, &self.keys)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<AllocateIdsRequest> {
+        let (project_id, keys) = decode_keys_request(bytes)?;
+        Ok(AllocateIdsRequest { project_id, keys })
+    }
+}
+
+impl Message for AllocateIdsResponse {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        for key in &self.keys {
+            write(w, 1, key)?;
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<AllocateIdsResponse> {
+        let (_, keys) = decode_keys_request(bytes)?;
+        Ok(AllocateIdsResponse { keys })
+    }
+}
+
+impl Message for ReserveIdsRequest {
+    fn encode(&self, w: &mut Writer) -> Result<()> {
+        encode_keys_request(w, &self.project_id, &self.keys)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<ReserveIdsRequest> {
+        let (project_id, keys) = decode_keys_request(bytes)?;
+        Ok(ReserveIdsRequest { project_id, keys })
+    }
+}
+
+impl Message for ReserveIdsResponse {
+    fn encode(&self, _: &mut Writer) -> Result<()> {
+        Ok(())
+    }
+
+    fn decode(_: &[u8]) -> Result<ReserveIdsResponse> {
+        Ok(ReserveIdsResponse {})
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{TimeZone, Utc};
+
+use crate::datastore::*;
+use crate::datastore::query::*;
+use crate::datastore::rpc::*;
+use crate::proto::Error;
+use crate::proto::v1::*;
+
+fn user_key(id: i64) -> Key {
+    Key::new(PartitionId::new("p", ""), vec![PathElement::from_id("User", id)])
+}
+
+fn roundtrip<M: Message + PartialEq + ::std::fmt::Debug>(message: M) {
+    let bytes = encode(&message).expect("Encoding failed");
+    let decoded: M = decode(&bytes).expect("Decoding failed");
+    assert_eq!(message, decoded);
+}
+
+#[test]
+fn test_key_encoding() {
+    let expected = vec![
+        0x0a, 0x03, 0x12, 0x01, b'p',
+        0x12, 0x08, 0x0a, 0x04, b'U', b's', b'e', b'r', 0x10, 0x7b,
+    ];
+
+    assert_eq!(expected, encode(&user_key(123)).unwrap());
+    assert_eq!(user_key(123), decode(&expected).unwrap());
+}
+
+#[test]
+fn test_value_meta_encoding() {
+    let value = Value::from("hi").unindexed();
+    let expected = vec![0x8a, 0x01, 0x02, b'h', b'i', 0x98, 0x01, 0x01];
+    assert_eq!(expected, encode(&value).unwrap());
+    assert_eq!(value, decode(&expected).unwrap());
+}
+
+#[test]
+fn test_value_roundtrip() {
+    let nested = Entity {
+        key: None,
+        properties: hashmap!("city".to_string() => Value::from("Oslo")),
+    };
+
+    let values = vec![
+        Value::from(()),
+        Value::from(false),
+        Value::from(-42),
+        Value::from(0.5),
+        Value::from(Utc.with_ymd_and_hms(2017, 9, 21, 5, 41, 33).unwrap()),
+        Value::from(user_key(7)),
+        Value::from(""),
+        Value::from(Blob(vec![0, 1, 255])),
+        Value::from(LatLng::new(59.91, 10.75)),
+        Value::from(nested).unindexed(),
+        Value::from(vec![Value::from(1), Value::from("a").unindexed()]),
+        Value::from("long text").with_meaning(meaning::TEXT).unindexed(),
+    ];
+
+    for value in values {
+        roundtrip(value);
+    }
+}
+
+#[test]
+fn test_invalid_integer() {
+    let value: Value = ::serde_json::from_str(r#"{"integerValue": "twelve"}"#).unwrap();
+    assert_eq!(Err(Error::InvalidValue("integer_value")), encode(&value));
+}
+
+#[test]
+fn test_entity_roundtrip() {
+    roundtrip(Entity {
+        key: Some(Key::new(
+            PartitionId::new("p", "tenant"),
+            vec![PathElement::from_id("User", 1), PathElement::from_name("Order", "abc")],
+        )),
+        properties: hashmap!(
+            "total".to_string() => Value::from(9.5),
+            "items".to_string() => Value::from(vec!["a", "b"]),
+        ),
+    });
+}
+
+#[test]
+fn test_run_query_roundtrip() {
+    let query = Query::new("User")
+        .with_filter(Filter::and(vec![
+            Filter::property("age", PropertyOperator::GreaterThanOrEqual, 18),
+            Filter::has_ancestor(user_key(1)),
+        ]))
+        .with_order("age", Direction::Descending)
+        .with_offset(5)
+        .with_limit(0)
+        .with_start_cursor(Blob(vec![1, 2, 3]));
+
+    roundtrip(RunQueryRequest {
+        project_id: "p".to_string(),
+        partition_id: Some(PartitionId::new("p", "tenant")),
+        read_options: Some(ReadOptions::ReadConsistency(ReadConsistency::Eventual)),
+        query: Some(query),
+        gql_query: None,
+    });
+
+    roundtrip(RunQueryRequest {
+        project_id: "p".to_string(),
+        gql_query: Some(GqlQuery {
+            query_string: "SELECT * FROM User WHERE age > @age".to_string(),
+            allow_literals: false,
+            named_bindings: hashmap!("age".to_string() => GqlQueryParameter::Value(Value::from(18))),
+            positional_bindings: vec![],
+        }),
+        ..RunQueryRequest::default()
+    });
+
+    roundtrip(RunQueryResponse {
+        batch: QueryResultBatch {
+            entity_result_type: ResultType::Full,
+            entity_results: vec![EntityResult {
+                entity: Entity { key: Some(user_key(1)), properties: hashmap!() },
+                version: Some(Int::from(17)),
+                cursor: Some(Blob(vec![9])),
+            }],
+            end_cursor: Some(Blob(vec![9])),
+            more_results: MoreResultsType::NoMoreResults,
+            ..QueryResultBatch::default()
+        },
+        query: None,
+        transaction: None,
+    });
+}
+
+#[test]
+fn test_commit_roundtrip() {
+    roundtrip(CommitRequest {
+        project_id: "p".to_string(),
+        mode: CommitMode::Transactional,
+        transaction: Some(Blob(b"tx".to_vec())),
+        mutations: vec![
+            Mutation::Upsert(Entity { key: Some(user_key(1)), properties: hashmap!() }),
+            Mutation::Delete(user_key(2)),
+        ],
+    });
+
+    roundtrip(CommitResponse {
+        mutation_results: vec![MutationResult {
+            key: None,
+            version: Int::from(3),
+            conflict_detected: false,
+        }],
+        index_updates: 2,
+        commit_time: Some(Utc.with_ymd_and_hms(2017, 9, 21, 5, 41, 33).unwrap()),
+    });
+}
+
+#[test]
+fn test_lookup_and_transaction_roundtrip() {
+    roundtrip(LookupRequest {
+        project_id: "p".to_string(),
+        read_options: Some(ReadOptions::NewTransaction(TransactionOptions::ReadWrite(ReadWrite {
+            previous_transaction: Some(Blob(vec![1])),
+        }))),
+        keys: vec![user_key(1), user_key(2)],
+    });
+
+    roundtrip(LookupResponse {
+        found: vec![EntityResult {
+            entity: Entity { key: Some(user_key(1)), properties: hashmap!() },
+            version: Some(Int::from(1)),
+            cursor: None,
+        }],
+        missing: vec![],
+        deferred: vec![user_key(2)],
+        transaction: Some(Blob(vec![1])),
+        read_time: None,
+    });
+
+    roundtrip(BeginTransactionRequest {
+        project_id: "p".to_string(),
+        transaction_options: Some(TransactionOptions::ReadOnly(ReadOnly::default())),
+    });
+    roundtrip(RollbackRequest { project_id: "p".to_string(), transaction: Blob(vec![1]) });
+    roundtrip(AllocateIdsResponse { keys: vec![user_key(5)] });
+}
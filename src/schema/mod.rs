@@ -0,0 +1,225 @@
+// Inferring the schema of a kind from a sample of its entities.
+//
+// Datastore does not enforce a schema, so the only documentation of a legacy kind is often the
+// entities themselves. `infer` collects, for every property including the properties of embedded
+// entities and the elements of arrays, which value types were observed how often and whether the
+// property was ever missing. `Schema::to_rust` turns the result into suggested Rust structs that
+// `serde_ds::from_value` can read.
+//
+// A sample only shows what the sampled entities contain. Types and properties that are rare may
+// be missing from it, so the generated structs are a starting point to be reviewed.
+
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use crate::datastore::{Entity, Value};
+
+mod rust;
+
+#[cfg(test)]
+mod tests;
+
+/// The type of a value, as far as the schema is concerned.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum ValueType {
+    Null,
+    Boolean,
+    Integer,
+    Double,
+    Timestamp,
+    Key,
+    String,
+    Blob,
+    GeoPoint,
+    Array,
+    Entity,
+}
+
+impl ValueType {
+    pub fn of(value: &Value) -> ValueType {
+        match *value {
+            Value::Null { .. } => ValueType::Null,
+            Value::Boolean { .. } => ValueType::Boolean,
+            Value::Integer { .. } => ValueType::Integer,
+            Value::Double { .. } => ValueType::Double,
+            Value::Timestamp { .. } => ValueType::Timestamp,
+            Value::KeyValue { .. } => ValueType::Key,
+            Value::String { .. } => ValueType::String,
+            Value::Blob { .. } => ValueType::Blob,
+            Value::GeoPoint { .. } => ValueType::GeoPoint,
+            Value::Array { .. } => ValueType::Array,
+            Value::EntityValue { .. } => ValueType::Entity,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ValueType::Null => "null",
+            ValueType::Boolean => "boolean",
+            ValueType::Integer => "integer",
+            ValueType::Double => "double",
+            ValueType::Timestamp => "timestamp",
+            ValueType::Key => "key",
+            ValueType::String => "string",
+            ValueType::Blob => "blob",
+            ValueType::GeoPoint => "geo point",
+            ValueType::Array => "array",
+            ValueType::Entity => "entity",
+        }
+    }
+}
+
+impl Display for ValueType {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+/// What was observed about a property, or about the elements of an array.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct PropertySchema {
+    /// How many values of each type were observed.
+    pub types: BTreeMap<ValueType, u64>,
+
+    /// How many of the entities that could have had the property lacked it. Always 0 for the
+    /// elements of arrays.
+    pub absent: u64,
+
+    /// The properties of the embedded entities among the values.
+    pub properties: BTreeMap<String, PropertySchema>,
+
+    /// The elements of the arrays among the values, `None` if no array had any.
+    pub elements: Option<Box<PropertySchema>>,
+}
+
+impl PropertySchema {
+    /// The number of values that were observed.
+    pub fn count(&self) -> u64 {
+        self.types.values().sum()
+    }
+
+    /// The share of the values that were of type `value_type`, between 0 and 1.
+    pub fn frequency(&self, value_type: ValueType) -> f64 {
+        match self.count() {
+            0 => 0.0,
+            count => self.types.get(&value_type).copied().unwrap_or(0) as f64 / count as f64,
+        }
+    }
+
+    /// Whether some values were null.
+    pub fn is_nullable(&self) -> bool {
+        self.types.contains_key(&ValueType::Null)
+    }
+
+    /// Whether some entities lacked the property.
+    pub fn is_optional(&self) -> bool {
+        self.absent > 0
+    }
+
+    fn add(&mut self, value: &Value) {
+        let value_type = ValueType::of(value);
+        let seen = self.types.get(&value_type).copied().unwrap_or(0);
+        *self.types.entry(value_type).or_insert(0) += 1;
+        match *value {
+            Value::EntityValue { ref entity_value, .. } => add_entity(&mut self.properties, seen, entity_value),
+            Value::Array { ref array_value, .. } => for element in &array_value.values {
+                self.elements.get_or_insert_with(Default::default).add(element);
+            },
+            _ => (),
+        }
+    }
+}
+
+/// The schema of a sample of entities.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Schema {
+    /// The number of entities in the sample.
+    pub entities: u64,
+
+    pub properties: BTreeMap<String, PropertySchema>,
+}
+
+impl Schema {
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    /// Adds an entity of the sample.
+    pub fn add(&mut self, entity: &Entity) {
+        add_entity(&mut self.properties, self.entities, entity);
+        self.entities += 1;
+    }
+
+    /// All properties by their path, in order. Properties of embedded entities are separated from
+    /// their entity property by a dot, the elements of arrays are written as `[]`, e.g. `tags[]`
+    /// or `lines[].product`.
+    pub fn paths(&self) -> Vec<(String, &PropertySchema)> {
+        let mut paths = vec![];
+        collect_paths("", &self.properties, &mut paths);
+        paths
+    }
+}
+
+/// Infers the schema of a sample of entities.
+pub fn infer<I>(entities: I) -> Schema
+    where I: IntoIterator, I::Item: Borrow<Entity>
+{
+    let mut schema = Schema::new();
+    for entity in entities {
+        schema.add(entity.borrow());
+    }
+    schema
+}
+
+// Adds the properties of an entity to the properties of the `seen` entities before it.
+fn add_entity(properties: &mut BTreeMap<String, PropertySchema>, seen: u64, entity: &Entity) {
+    for (name, property) in properties.iter_mut() {
+        if !entity.properties.contains_key(name) {
+            property.absent += 1;
+        }
+    }
+    for (name, value) in &entity.properties {
+        properties.entry(name.clone())
+            .or_insert_with(|| PropertySchema { absent: seen, ..PropertySchema::default() })
+            .add(value);
+    }
+}
+
+fn collect_paths<'a>(prefix: &str, properties: &'a BTreeMap<String, PropertySchema>,
+                     paths: &mut Vec<(String, &'a PropertySchema)>) {
+    for (name, property) in properties {
+        let path = format!("{}{}", prefix, name);
+        paths.push((path.clone(), property));
+        collect_nested(&path, property, paths);
+    }
+}
+
+fn collect_nested<'a>(path: &str, property: &'a PropertySchema, paths: &mut Vec<(String, &'a PropertySchema)>) {
+    collect_paths(&format!("{}.", path), &property.properties, paths);
+    if let Some(ref elements) = property.elements {
+        let path = format!("{}[]", path);
+        paths.push((path.clone(), elements));
+        collect_nested(&path, elements, paths);
+    }
+}
+
+/// One line per property path with the observed types and their share, e.g.
+/// `address.zip: string 80%, integer 20% (sometimes absent)`.
+impl Display for Schema {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (path, property) in self.paths() {
+            let mut types: Vec<(&ValueType, &u64)> = property.types.iter().collect();
+            types.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let types: Vec<String> = types.into_iter()
+                .map(|(value_type, _)| format!("{} {:.0}%", value_type, property.frequency(*value_type) * 100.0))
+                .collect();
+            write!(fmt, "{}: {}", path, types.join(", "))?;
+            if property.is_optional() {
+                write!(fmt, " (sometimes absent)")?;
+            }
+            writeln!(fmt)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+// Generating Rust structs from a schema.
+//
+// Each property becomes a field of the type of its values. Fields of properties that were null or
+// absent in some entities are `Option`s, arrays that were absent default to an empty `Vec`.
+// Embedded entities become structs of their own, named after the enclosing struct and the
+// property. Properties that `serde_ds` can not read into a single type, because their values had
+// several types or were keys or geo points, are left out with a comment; `serde_ds` skips them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::schema::{PropertySchema, Schema, ValueType};
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+    "try", "typeof", "unsized", "virtual", "yield",
+];
+
+impl Schema {
+    /// Suggests Rust structs for the entities, the first one named `name`. The structs derive
+    /// serde's `Deserialize` and `Serialize` and refer to `chrono` for timestamps and
+    /// `serde_bytes` for blobs.
+    pub fn to_rust(&self, name: &str) -> String {
+        let mut generator = Generator { structs: vec![], names: BTreeSet::new() };
+        let name = generator.unique_name(type_name(name));
+        generator.generate(name, &self.properties);
+        generator.structs.join("\n")
+    }
+}
+
+struct Generator {
+    structs: Vec<String>,
+    names: BTreeSet<String>,
+}
+
+impl Generator {
+    fn unique_name(&mut self, name: String) -> String {
+        let mut unique = name.clone();
+        let mut n = 1;
+        while !self.names.insert(unique.clone()) {
+            n += 1;
+            unique = format!("{}{}", name, n);
+        }
+        unique
+    }
+
+    // Adds the struct `name` and the structs of its embedded entities, in this order.
+    fn generate(&mut self, name: String, properties: &BTreeMap<String, PropertySchema>) {
+        let index = self.structs.len();
+        self.structs.push(String::new());
+
+        let mut out = format!("#[derive(Debug, Clone, Deserialize, Serialize)]\npub struct {} {{\n", name);
+        let mut fields = BTreeSet::new();
+        for (property, schema) in properties {
+            let field = match self.field_type(&name, property, schema, false) {
+                Ok(field) => field,
+                Err(reason) => {
+                    let _ = writeln!(out, "    // {}: {}", property, reason);
+                    continue;
+                }
+            };
+
+            let mut ident = field_name(property);
+            let base = ident.clone();
+            let mut n = 1;
+            while !fields.insert(ident.clone()) {
+                n += 1;
+                ident = format!("{}_{}", base, n);
+            }
+            if ident != *property {
+                let _ = writeln!(out, "    #[serde(rename = {:?})]", property);
+            }
+            let absent_array = schema.types.contains_key(&ValueType::Array) && schema.is_optional();
+            let ty = if absent_array && !schema.is_nullable() {
+                out.push_str("    #[serde(default)]\n");
+                field
+            } else if schema.is_nullable() || schema.is_optional() {
+                format!("Option<{}>", field)
+            } else {
+                field
+            };
+            let _ = writeln!(out, "    pub {}: {},", ident, ty);
+        }
+        out.push_str("}\n");
+        self.structs[index] = out;
+    }
+
+    // The type of the non-null values of a property, or why there is none.
+    fn field_type(&mut self, parent: &str, property: &str, schema: &PropertySchema, element: bool)
+                  -> Result<String, String> {
+        let types: Vec<ValueType> = schema.types.keys().copied()
+            .filter(|&value_type| value_type != ValueType::Null)
+            .collect();
+        let value_type = match types[..] {
+            [] if element => return Err("only null elements".to_string()),
+            [] => return Err("only null values".to_string()),
+            [value_type] => value_type,
+            _ => {
+                let names: Vec<&str> = types.iter().map(ValueType::as_str).collect();
+                return Err(format!("mixed types {}", names.join(", ")));
+            }
+        };
+
+        Ok(match value_type {
+            ValueType::Null => unreachable!(),
+            ValueType::Boolean => "bool".to_string(),
+            ValueType::Integer => "i64".to_string(),
+            ValueType::Double => "f64".to_string(),
+            ValueType::Timestamp => "chrono::DateTime<chrono::Utc>".to_string(),
+            ValueType::String => "String".to_string(),
+            ValueType::Blob => "serde_bytes::ByteBuf".to_string(),
+            ValueType::Key | ValueType::GeoPoint =>
+                return Err(format!("{} values are not supported by serde_ds", value_type)),
+            ValueType::Array => {
+                let elements = match schema.elements {
+                    Some(ref elements) => elements,
+                    None => return Err("only empty arrays".to_string()),
+                };
+                if elements.types.contains_key(&ValueType::Array) {
+                    return Err("nested arrays".to_string());
+                }
+                let element = self.field_type(parent, property, elements, true)?;
+                if elements.is_nullable() {
+                    format!("Vec<Option<{}>>", element)
+                } else {
+                    format!("Vec<{}>", element)
+                }
+            }
+            ValueType::Entity => {
+                let name = self.unique_name(format!("{}{}", parent, type_name(property)));
+                self.generate(name.clone(), &schema.properties);
+                name
+            }
+        })
+    }
+}
+
+// `firstName` and `first-name` become `first_name`, keywords get a trailing underscore.
+fn field_name(property: &str) -> String {
+    let mut name = String::new();
+    let mut previous: Option<char> = None;
+    for c in property.chars() {
+        if c.is_uppercase() {
+            if previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) {
+                name.push('_');
+            }
+            name.extend(c.to_lowercase());
+        } else if c.is_alphanumeric() {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+        previous = Some(c);
+    }
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    if KEYWORDS.contains(&name.as_str()) || name == "_" {
+        name.push('_');
+    }
+    name
+}
+
+// `line_items` and `line-items` become `LineItems`.
+fn type_name(name: &str) -> String {
+    let mut type_name = String::new();
+    let mut upper = true;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            upper = true;
+        } else if upper {
+            type_name.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            type_name.push(c);
+        }
+    }
+    if type_name.is_empty() || type_name.starts_with(|c: char| c.is_ascii_digit()) {
+        type_name.insert(0, 'T');
+    }
+    type_name
+}
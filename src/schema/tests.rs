@@ -0,0 +1,202 @@
+use chrono::{TimeZone, Utc};
+
+use crate::datastore::*;
+use crate::schema::*;
+use crate::serde_ds;
+
+fn users() -> Vec<Entity> {
+    let manager = Key::new(PartitionId::new("", ""), vec![PathElement::from_id("User", 1)]);
+    let created = Utc.with_ymd_and_hms(2018, 3, 1, 12, 0, 0).unwrap();
+    vec![
+        Entity {
+            key: None,
+            properties: hashmap! {
+                "name".to_string() => Value::from("Ann"),
+                "age".to_string() => Value::from(30),
+                "firstName".to_string() => Value::from("Ann"),
+                "address".to_string() => Value::from(Entity {
+                    key: None,
+                    properties: hashmap! {
+                        "city".to_string() => Value::from("Berlin"),
+                        "zip".to_string() => Value::from("10115"),
+                    },
+                }),
+                "tags".to_string() => Value::from(vec!["admin", "ops"]),
+                "created".to_string() => Value::from(created),
+                "avatar".to_string() => Value::from(Blob(vec![1, 2, 3])),
+                "manager".to_string() => Value::from(manager),
+            },
+        },
+        Entity {
+            key: None,
+            properties: hashmap! {
+                "name".to_string() => Value::from("Bob"),
+                "age".to_string() => Value::from("41"),
+                "firstName".to_string() => Value::from(()),
+                "address".to_string() => Value::from(Entity {
+                    key: None,
+                    properties: hashmap! { "city".to_string() => Value::from("Paris") },
+                }),
+                "created".to_string() => Value::from(created),
+                "type".to_string() => Value::from("guest"),
+            },
+        },
+        Entity {
+            key: None,
+            properties: hashmap! {
+                "name".to_string() => Value::from("Cy"),
+                "firstName".to_string() => Value::from("Cyrus"),
+                "address".to_string() => Value::from(Entity {
+                    key: None,
+                    properties: hashmap! {
+                        "city".to_string() => Value::from("Rome"),
+                        "zip".to_string() => Value::from(()),
+                    },
+                }),
+                "tags".to_string() => Value::from(Vec::<Value>::new()),
+                "created".to_string() => Value::from(created),
+                "type".to_string() => Value::from("admin"),
+            },
+        },
+    ]
+}
+
+#[test]
+fn test_infer() {
+    let schema = infer(users());
+    assert_eq!(3, schema.entities);
+
+    let name = &schema.properties["name"];
+    assert_eq!(3, name.count());
+    assert!(!name.is_nullable() && !name.is_optional());
+
+    let age = &schema.properties["age"];
+    assert_eq!(1, age.absent);
+    assert_eq!(0.5, age.frequency(ValueType::Integer));
+    assert_eq!(0.5, age.frequency(ValueType::String));
+
+    let first_name = &schema.properties["firstName"];
+    assert!(first_name.is_nullable() && !first_name.is_optional());
+
+    // Properties that first appear in a later entity were absent in the earlier ones.
+    assert_eq!(1, schema.properties["type"].absent);
+    assert_eq!(2, schema.properties["avatar"].absent);
+
+    let address = &schema.properties["address"];
+    assert_eq!(3, address.types[&ValueType::Entity]);
+    assert_eq!(0, address.properties["city"].absent);
+    let zip = &address.properties["zip"];
+    assert_eq!(1, zip.absent);
+    assert!(zip.is_nullable());
+
+    let tags = &schema.properties["tags"];
+    assert_eq!(2, tags.types[&ValueType::Array]);
+    assert_eq!(Some(2), tags.elements.as_ref().map(|elements| elements.types[&ValueType::String]));
+
+    let paths: Vec<String> = schema.paths().into_iter().map(|(path, _)| path).collect();
+    assert_eq!(vec!["address", "address.city", "address.zip", "age", "avatar", "created", "firstName",
+                    "manager", "name", "tags", "tags[]", "type"], paths);
+
+    let report = schema.to_string();
+    assert!(report.contains("age: integer 50%, string 50% (sometimes absent)\n"), "{}", report);
+    assert!(report.contains("firstName: string 67%, null 33%\n"), "{}", report);
+    assert!(report.contains("tags[]: string 100%\n"), "{}", report);
+}
+
+#[test]
+fn test_nested_arrays() {
+    let line = |name: &str, value: Value| Value::from(Entity {
+        key: None,
+        properties: hashmap! { name.to_string() => value },
+    });
+    let schema = infer(&[
+        Entity {
+            key: None,
+            properties: hashmap! {
+                "lines".to_string() => Value::from(vec![
+                    line("product", Value::from("apple")),
+                    line("product", Value::from("pear")),
+                ]),
+            },
+        },
+        Entity {
+            key: None,
+            properties: hashmap! { "lines".to_string() => Value::from(vec![line("qty", Value::from(2))]) },
+        },
+    ]);
+    let lines = schema.properties["lines"].elements.as_ref().unwrap();
+    assert_eq!(3, lines.types[&ValueType::Entity]);
+    assert_eq!(1, lines.properties["product"].absent);
+    assert_eq!(2, lines.properties["qty"].absent);
+    assert!(schema.paths().iter().any(|(path, _)| path == "lines[].product"));
+}
+
+const USER_RS: &str = "\
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct User {
+    pub address: UserAddress,
+    // age: mixed types integer, string
+    pub avatar: Option<serde_bytes::ByteBuf>,
+    pub created: chrono::DateTime<chrono::Utc>,
+    #[serde(rename = \"firstName\")]
+    pub first_name: Option<String>,
+    // manager: key values are not supported by serde_ds
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(rename = \"type\")]
+    pub type_: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UserAddress {
+    pub city: String,
+    pub zip: Option<String>,
+}
+";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct User {
+    pub address: UserAddress,
+    pub avatar: Option<serde_bytes::ByteBuf>,
+    pub created: chrono::DateTime<chrono::Utc>,
+    #[serde(rename = "firstName")]
+    pub first_name: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UserAddress {
+    pub city: String,
+    pub zip: Option<String>,
+}
+
+#[test]
+fn test_to_rust() {
+    let rust = infer(users()).to_rust("User");
+    assert_eq!(USER_RS, rust);
+
+    // The generated structs read every entity of the sample.
+    let users: Vec<User> = users().into_iter()
+        .map(|user| serde_ds::from_value(Value::from(user)).expect("Reading the sample failed"))
+        .collect();
+    assert_eq!(vec!["Ann", "Bob", "Cy"], users.iter().map(|user| user.name.as_str()).collect::<Vec<_>>());
+    assert_eq!(vec!["admin", "ops"], users[0].tags);
+    assert_eq!(Some(&[1u8, 2, 3][..]), users[0].avatar.as_ref().map(|avatar| &avatar[..]));
+    assert_eq!(None, users[1].first_name);
+    assert_eq!(Some("admin"), users[2].type_.as_deref());
+
+    let item = Entity { key: None, properties: Default::default() };
+    let order = Entity {
+        key: None,
+        properties: hashmap! { "line-items".to_string() => Value::from(vec![Value::from(item)]) },
+    };
+    let rust = infer(&[order]).to_rust("order");
+    assert!(rust.starts_with("#[derive(Debug, Clone, Deserialize, Serialize)]\npub struct Order {\n\
+                              \x20   #[serde(rename = \"line-items\")]\n    pub line_items: Vec<OrderLineItems>,\n}\n"),
+            "{}", rust);
+}
@@ -1,15 +1,18 @@
-use datastore::{Int, Value};
+use crate::datastore::{Int, Value};
 use serde::Deserialize;
 use serde::de::{self, Visitor, MapAccess, DeserializeSeed, SeqAccess};
-use serde_ds::{Result, Error};
+use crate::serde_ds::{Result, Error};
 use std;
 use std::vec;
 use std::collections::hash_map;
+use chrono::{DateTime, SecondsFormat, Utc};
 
 pub struct Deserializer {
     input: Value,
 }
 
+/// Deserialises a value. Properties of an entity that a struct has no field for are skipped, as
+/// serde formats commonly do, unless the struct is marked with `#[serde(deny_unknown_fields)]`.
 pub fn from_value<'de, T: Deserialize<'de>>(input: Value) -> Result<T> {
     let deserializer = Deserializer { input };
     T::deserialize(&deserializer)
@@ -17,11 +20,17 @@ pub fn from_value<'de, T: Deserialize<'de>>(input: Value) -> Result<T> {
 
 fn int_value<'de>(input: &'de Value) -> Result<&'de Int> {
     match *input {
-        Value::Integer { ref integer_value } => Ok(integer_value),
+        Value::Integer { ref integer_value, .. } => Ok(integer_value),
         _ => Err(Error::ExpectedType("integer"))
     }
 }
 
+// Timestamps are deserialised from their RFC 3339 representation, which is what e.g. chrono's
+// `DateTime` expects.
+fn rfc3339(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
 impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
     type Error = Error;
 
@@ -37,6 +46,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
             Value::Boolean { .. } => self.deserialize_bool(visitor),
             Value::Blob { .. } => self.deserialize_bytes(visitor),
             Value::Array { .. } => self.deserialize_seq(visitor),
+            Value::Timestamp { .. } => self.deserialize_string(visitor),
 
             // Non-primitive types (entity, key, geo types etc.) don't have an obvious match.
             _ => Err(Error::NonSelfDescribingType()),
@@ -48,7 +58,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
             V: Visitor<'de>,
     {
         match self.input {
-            Value::Boolean { boolean_value } => visitor.visit_bool(boolean_value),
+            Value::Boolean { boolean_value, .. } => visitor.visit_bool(boolean_value),
             _ => Err(Error::ExpectedType("bool")),
         }
     }
@@ -99,7 +109,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
             V: Visitor<'de>,
     {
         let f = match self.input {
-            Value::Double { ref double_value } => Ok(*double_value),
+            Value::Double { ref double_value, .. } => Ok(*double_value),
             _ => Err(Error::ExpectedType("double")),
         }?;
 
@@ -115,7 +125,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
             V: Visitor<'de>,
     {
         match self.input {
-            Value::Double { double_value } => visitor.visit_f64(double_value),
+            Value::Double { double_value, .. } => visitor.visit_f64(double_value),
             _ => Err(Error::ExpectedType("double")),
         }
     }
@@ -125,7 +135,8 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
             V: Visitor<'de>,
     {
         match self.input {
-            Value::String { ref string_value } => visitor.visit_str(string_value.as_ref()),
+            Value::String { ref string_value, .. } => visitor.visit_str(string_value.as_ref()),
+            Value::Timestamp { ref timestamp_value, .. } => visitor.visit_string(rfc3339(timestamp_value)),
             _ => Err(Error::ExpectedType("string")),
         }
     }
@@ -135,7 +146,8 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
             V: Visitor<'de>,
     {
         match self.input {
-            Value::String { ref string_value } => visitor.visit_string(string_value.clone()),
+            Value::String { ref string_value, .. } => visitor.visit_string(string_value.clone()),
+            Value::Timestamp { ref timestamp_value, .. } => visitor.visit_string(rfc3339(timestamp_value)),
             _ => Err(Error::ExpectedType("string")),
         }
     }
@@ -145,7 +157,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
             V: Visitor<'de>,
     {
         match self.input {
-            Value::Blob { ref blob_value } => visitor.visit_bytes(blob_value.0.as_ref()),
+            Value::Blob { ref blob_value, .. } => visitor.visit_bytes(blob_value.0.as_ref()),
             _ => Err(Error::ExpectedType("blob")),
         }
     }
@@ -155,7 +167,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
             V: Visitor<'de>,
     {
         match self.input {
-            Value::Blob { ref blob_value } => visitor.visit_byte_buf(blob_value.0.clone()),
+            Value::Blob { ref blob_value, .. } => visitor.visit_byte_buf(blob_value.0.clone()),
             _ => Err(Error::ExpectedType("blob")),
         }
     }
@@ -236,12 +248,12 @@ impl<'de, 'a> de::Deserializer<'de> for &'a Deserializer {
         Err(Error::NotYetImplemented("enum deserialization"))
     }
 
-    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
+    // Serde asks for ignored values when an entity has a property that a struct has no field for.
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
         where
             V: Visitor<'de>,
     {
-        // TODO: What is this even for?
-        Err(Error::NotYetImplemented("ignored value deserialization"))
+        visitor.visit_unit()
     }
 
     fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value>
@@ -278,7 +290,7 @@ struct ArrayAccess {
 impl ArrayAccess {
     fn new(v: &Value) -> Result<Self> {
         let array = match *v {
-            Value::Array { ref array_value } => Ok(array_value.clone().values),
+            Value::Array { ref array_value, .. } => Ok(array_value.clone().values),
             _ => Err(Error::ExpectedType("array")),
         }?;
 
@@ -311,7 +323,7 @@ struct EntityAccess {
 impl EntityAccess {
     fn new(v: &Value) -> Result<Self> {
         let entity = match *v {
-            Value::EntityValue { ref entity_value } => Ok(entity_value),
+            Value::EntityValue { ref entity_value, .. } => Ok(entity_value),
             _ => Err(Error::ExpectedType("entity")),
         }?;
 
@@ -1,8 +1,7 @@
-use std;
 use std::collections::HashMap;
-use datastore::{Entity, Value};
-use serde_ds::de;
-use serde_ds::Error;
+use crate::datastore::{Entity, Value};
+use crate::serde_ds::de;
+use crate::serde_ds::Error;
 
 #[test]
 fn test_deserialize_ints() {
@@ -62,6 +61,7 @@ fn test_float_overflow() {
 fn test_map_deserialization() {
     let one = Value::from(42);
     let entity_value = Entity {
+        key: None,
         properties: hashmap!(
             "one".to_string() => one,
         ),
@@ -89,7 +89,7 @@ fn test_struct_deserialization() {
         "strongly_typed".to_string() => Value::from(true),
     );
 
-    let input = Value::from(Entity { properties });
+    let input = Value::from(Entity { key: None, properties });
 
     let expected = Language {
         name: String::from("Rust"),
@@ -101,6 +101,34 @@ fn test_struct_deserialization() {
     assert_eq!(expected, result);
 }
 
+#[test]
+fn test_unknown_properties() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Language {
+        name: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct StrictLanguage {
+        name: String,
+    }
+
+    // Properties without a field are skipped, whatever their type.
+    let properties = hashmap!(
+        "name".to_string() => Value::from("Rust"),
+        "strongly_typed".to_string() => Value::from(true),
+        "first_release".to_string() => Value::from(vec![Value::from(2015)]),
+        "homepage".to_string() => Value::from(Entity { key: None, properties: HashMap::new() }),
+    );
+    let input = Value::from(Entity { key: None, properties });
+
+    let result: Language = de::from_value(input.clone()).expect("struct deserialization failed");
+    assert_eq!(Language { name: String::from("Rust") }, result);
+
+    assert!(de::from_value::<StrictLanguage>(input).is_err());
+}
+
 #[test]
 fn test_sequence_deserialization() {
     let input = Value::from(vec![Value::from("Hello"), Value::from("World!")]);
@@ -109,3 +137,16 @@ fn test_sequence_deserialization() {
 
     assert_eq!(expected, result)
 }
+
+#[test]
+fn test_timestamp_deserialization() {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    let timestamp = Utc.with_ymd_and_hms(2018, 3, 1, 12, 30, 0).unwrap();
+    let parsed: DateTime<Utc> = de::from_value(Value::from(timestamp))
+        .expect("timestamp deserialization failed");
+    assert_eq!(timestamp, parsed);
+
+    let text: String = de::from_value(Value::from(timestamp)).expect("string deserialization failed");
+    assert_eq!("2018-03-01T12:30:00Z", text);
+}
@@ -1,4 +1,3 @@
-use std;
 use std::fmt::{self, Display};
 use std::num;
 
@@ -37,12 +36,13 @@ impl de::Error for Error {
     }
 }
 
+#[allow(deprecated)]
 impl Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         // Import the 'Error' trait locally scoped to prevent naming conflicts and access the
         // 'description' method.
         use std::error::Error;
-        use serde_ds::Error::*;
+        use crate::serde_ds::Error::*;
 
         // Some errors carry extra information that may be relevant when 'Display' is called on
         // the error. This extends the simple error descriptions where appropriate.
@@ -1,7 +1,11 @@
 // Implementation of Serde Serialiser & Deserialiser for Datastore entities
 
 mod error;
+
+#[allow(clippy::multiple_bound_locations, clippy::needless_borrow, clippy::needless_lifetimes)]
 mod ser;
+
+#[allow(clippy::needless_lifetimes, clippy::legacy_numeric_constants)]
 mod de;
 
 /*
@@ -12,21 +16,26 @@ from the root module:
 * [x] A Result typedef which is equivalent to std::result::Result<T, Error>.
 * [x] A Serializer type which implements serde::Serializer.
 * [x] A Deserializer type which implements serde::Deserializer.
-* [ ] One or more to_abc functions depending on what types the format supports serializing to.
+* [x] One or more to_abc functions depending on what types the format supports serializing to.
       For example to_string which returns a String, to_bytes which returns a Vec<u8>, or to_writer
       which writes into an io::Write.
-* [ ] One or more from_xyz functions depending on what types the format supports deserializing from.
+* [x] One or more from_xyz functions depending on what types the format supports deserializing from.
       For example from_str which takes a &str, from_bytes which takes a &[u8], or from_reader which
       takes an io::Read.
 */
 
 pub use self::error::{Error, Result};
+pub use self::ser::{to_value, Serializer};
+pub use self::de::{from_value, Deserializer};
 
 #[cfg(test)]
+#[allow(redundant_semicolons, clippy::unnecessary_cast, clippy::char_lit_as_u8)]
 mod ser_tests;
 
 #[cfg(test)]
+#[allow(redundant_semicolons, clippy::legacy_numeric_constants)]
 mod de_tests;
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_cast)]
 mod roundtrip_tests;
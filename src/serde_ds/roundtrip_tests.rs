@@ -1,7 +1,7 @@
 use std::collections;
 use std::fmt::Debug;
 use serde::{Serialize, Deserialize};
-use serde_ds::{de, ser};
+use crate::serde_ds::{de, ser};
 
 // These tests perform roundtrip serialisation of a type and check whether "the same thing" came out
 // at the other end.
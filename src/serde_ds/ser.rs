@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use serde::ser::{self, Serialize};
-use serde_ds::error::{Error, Result};
-use datastore::{Value, Entity, Blob};
+use crate::serde_ds::error::{Error, Result};
+use crate::datastore::{Value, Entity, Blob};
 
 #[derive(Copy, Clone)]
 pub struct Serializer;
@@ -222,7 +222,7 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
     {
         let key_value: Value = key.serialize(self.ser)?;
         match key_value {
-            Value::String { string_value } => {
+            Value::String { string_value, .. } => {
                 self.key = Option::Some(string_value);
                 Ok(())
             }
@@ -253,7 +253,7 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
             V: ? Sized + Serialize,
     {
         let key_str = match key.serialize(self.ser)? {
-            Value::String { string_value } => {
+            Value::String { string_value, .. } => {
                 Ok(string_value)
             }
             _ => Err(Error::UnsupportedKeyType()),
@@ -265,7 +265,7 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
     }
 
     fn end(self) -> Result<Self::Ok> {
-        let entity = Entity { properties: self.map };
+        let entity = Entity { key: None, properties: self.map };
         Ok(Value::from(entity))
     }
 
@@ -286,7 +286,7 @@ impl<'a> ser::SerializeStruct for MapSerializer<'a> {
     }
 
     fn end(self) -> Result<Self::Ok> {
-        let entity = Entity { properties: self.map };
+        let entity = Entity { key: None, properties: self.map };
 
         Ok(Value::from(entity))
     }
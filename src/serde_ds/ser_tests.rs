@@ -1,7 +1,7 @@
 use std::collections::HashMap;
-use serde_ds::ser;
+use crate::serde_ds::ser;
 use serde_bytes;
-use datastore::{Blob, Value, Entity};
+use crate::datastore::{Blob, Value, Entity};
 
 // Tests for simple value serialisation
 #[test]
@@ -117,7 +117,7 @@ fn test_serialize_map() {
         Value::from("value"),
     );
 
-    let expected = Value::from(Entity { properties: expected_properties });
+    let expected = Value::from(Entity { key: None, properties: expected_properties });
 
     assert_eq!(expected, result);
 }
@@ -156,7 +156,7 @@ fn test_serialize_struct() {
         "strongly_typed".to_string() => Value::from(true),
     };
 
-    let expected = Value::from(Entity { properties });
+    let expected = Value::from(Entity { key: None, properties });
 
     assert_eq!(expected, serialized);
 }